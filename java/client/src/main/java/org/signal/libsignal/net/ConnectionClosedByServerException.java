@@ -0,0 +1,27 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+package org.signal.libsignal.net;
+
+/**
+ * Indicates that the server closed the chat connection with a close frame.
+ *
+ * <p>The {@link #code} and {@link #reason} are the ones the server sent, which can tell the app
+ * what to do next. See also {@link ConnectedElsewhereException} and {@link
+ * ConnectionInvalidatedException}, which are used instead for the close codes they represent.
+ */
+public class ConnectionClosedByServerException extends ChatServiceException {
+  /** The websocket close code sent by the server. */
+  public final int code;
+
+  /** The reason sent by the server along with the close code, which may be empty. */
+  public final String reason;
+
+  public ConnectionClosedByServerException(String message, int code, String reason) {
+    super(message);
+    this.code = code;
+    this.reason = reason;
+  }
+}
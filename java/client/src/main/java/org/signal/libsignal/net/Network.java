@@ -175,6 +175,16 @@ public class Network {
     connectionManager.guardedRun(Native::ConnectionManager_on_network_change);
   }
 
+  /**
+   * Abandons any chat connection attempts in progress.
+   *
+   * <p>The attempts fail with a {@link java.util.concurrent.CancellationException}; ones started
+   * afterwards are unaffected.
+   */
+  public void cancelConnectionAttempts() {
+    connectionManager.guardedRun(Native::ConnectionManager_cancel_connection_attempts);
+  }
+
   public CompletableFuture<CdsiLookupResponse> cdsiLookup(
       String username, String password, CdsiLookupRequest request, Consumer<byte[]> tokenConsumer)
       throws IOException, InterruptedException, ExecutionException {
@@ -0,0 +1,16 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+package org.signal.libsignal.net;
+
+/**
+ * Exception thrown when a read or write on an established connection makes no progress for too
+ * long, for example because the server stopped reading what was being sent.
+ */
+public class OperationTimeoutException extends NetworkException {
+  public OperationTimeoutException(String message) {
+    super(message);
+  }
+}
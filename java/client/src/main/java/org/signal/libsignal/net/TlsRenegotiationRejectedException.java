@@ -0,0 +1,18 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+package org.signal.libsignal.net;
+
+/**
+ * Exception thrown when the server asks to renegotiate TLS.
+ *
+ * <p>libsignal never agrees to renegotiate, so the connection is closed instead. Signal's servers
+ * don't ask for renegotiation, so this points to something between the client and the server.
+ */
+public class TlsRenegotiationRejectedException extends ChatServiceException {
+  public TlsRenegotiationRejectedException(String message) {
+    super(message);
+  }
+}
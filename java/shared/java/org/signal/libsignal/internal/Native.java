@@ -239,6 +239,7 @@ public final class Native {
   public static native byte[] CdsiLookup_token(long lookup);
 
   public static native void ConnectionManager_Destroy(long handle);
+  public static native void ConnectionManager_cancel_connection_attempts(long connectionManager);
   public static native void ConnectionManager_clear_proxy(long connectionManager);
   public static native long ConnectionManager_new(int environment, String userAgent, long remoteConfig);
   public static native void ConnectionManager_on_network_change(long connectionManager);
@@ -52,12 +52,25 @@ export enum ErrorCode {
   DeviceDelinked,
   ConnectionInvalidated,
   ConnectedElsewhere,
+  ConnectionClosedByServer,
+  TlsRenegotiationRejected,
+  OperationTimeout,
 
   BackupValidation,
 
   Cancelled,
 }
 
+/** What the UI should suggest to the user after a network failure. */
+export type UserHint =
+  | 'check_connection'
+  | 'check_clock'
+  | 'sign_in_to_network'
+  | 'server_unavailable'
+  | 'update_app'
+  | 'reregister'
+  | 'no_action';
+
 export class LibSignalErrorBase extends Error {
   public readonly code: ErrorCode;
   public readonly operation: string;
@@ -219,6 +232,7 @@ export type InvalidUsernameLinkEncryptedData = LibSignalErrorCommon & {
 
 export type IoError = LibSignalErrorCommon & {
   code: ErrorCode.IoError;
+  readonly userHint?: UserHint;
 };
 
 export type CdsiInvalidTokenError = LibSignalErrorCommon & {
@@ -244,22 +258,42 @@ export type RateLimitedError = LibSignalErrorBase & {
 
 export type ChatServiceInactive = LibSignalErrorBase & {
   code: ErrorCode.ChatServiceInactive;
+  readonly userHint?: UserHint;
 };
 
 export type AppExpiredError = LibSignalErrorBase & {
   code: ErrorCode.AppExpired;
+  readonly userHint?: UserHint;
 };
 
 export type DeviceDelinkedError = LibSignalErrorBase & {
   code: ErrorCode.DeviceDelinked;
+  readonly userHint?: UserHint;
 };
 
 export type ConnectionInvalidatedError = LibSignalErrorBase & {
   code: ErrorCode.ConnectionInvalidated;
+  readonly userHint?: UserHint;
 };
 
 export type ConnectedElsewhereError = LibSignalErrorBase & {
   code: ErrorCode.ConnectedElsewhere;
+  readonly userHint?: UserHint;
+};
+
+export type ConnectionClosedByServerError = LibSignalErrorBase & {
+  code: ErrorCode.ConnectionClosedByServer;
+  readonly closeCode: number;
+  readonly closeReason: string;
+};
+
+export type TlsRenegotiationRejectedError = LibSignalErrorBase & {
+  code: ErrorCode.TlsRenegotiationRejected;
+  readonly userHint?: UserHint;
+};
+
+export type OperationTimeoutError = LibSignalErrorBase & {
+  code: ErrorCode.OperationTimeout;
 };
 
 export type SvrDataMissingError = LibSignalErrorBase & {
@@ -282,6 +316,7 @@ export type BackupValidationError = LibSignalErrorCommon & {
 
 export type CancellationError = LibSignalErrorCommon & {
   code: ErrorCode.Cancelled;
+  readonly userHint?: UserHint;
 };
 
 export type LibSignalError =
@@ -321,6 +356,9 @@ export type LibSignalError =
   | DeviceDelinkedError
   | ConnectionInvalidatedError
   | ConnectedElsewhereError
+  | ConnectionClosedByServerError
+  | TlsRenegotiationRejectedError
+  | OperationTimeoutError
   | RateLimitedError
   | BackupValidationError
   | CancellationError;
@@ -443,6 +443,16 @@ export class Net {
     Native.ConnectionManager_on_network_change(this._connectionManager);
   }
 
+  /**
+   * Abandons any chat connection attempts in progress.
+   *
+   * The attempts fail with an error whose code is `ErrorCode.Cancelled`;
+   * ones started afterwards are unaffected.
+   */
+  cancelConnectionAttempts(): void {
+    Native.ConnectionManager_cancel_connection_attempts(this._connectionManager);
+  }
+
   async cdsiLookup(
     auth: Readonly<ServiceAuth>,
     options: ReadonlyDeep<CDSRequestOptionsType>
@@ -154,6 +154,132 @@ pub unsafe extern "C" fn signal_error_get_tries_remaining(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_get_close_code(
+    err: *const SignalFfiError,
+    out: *mut u32,
+) -> *mut SignalFfiError {
+    let err = AssertUnwindSafe(err);
+    run_ffi_safe(|| {
+        let err = err.as_ref().ok_or(NullPointerError)?;
+        let value = err.provide_close_code().map_err(|_| {
+            SignalProtocolError::InvalidArgument(format!(
+                "cannot get close_code from error ({})",
+                err
+            ))
+        })?;
+        write_result_to(out, value)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_get_close_reason(
+    err: *const SignalFfiError,
+    out: *mut *const c_char,
+) -> *mut SignalFfiError {
+    let err = AssertUnwindSafe(err);
+    run_ffi_safe(|| {
+        let err = err.as_ref().ok_or(NullPointerError)?;
+        let value = err.provide_close_reason().map_err(|_| {
+            SignalProtocolError::InvalidArgument(format!(
+                "cannot get close_reason from error ({})",
+                err
+            ))
+        })?;
+        write_result_to(out, value)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_get_connect_category(
+    err: *const SignalFfiError,
+    out: *mut *const c_char,
+) -> *mut SignalFfiError {
+    let err = AssertUnwindSafe(err);
+    run_ffi_safe(|| {
+        let err = err.as_ref().ok_or(NullPointerError)?;
+        let value = err.provide_connect_category().map_err(|_| {
+            SignalProtocolError::InvalidArgument(format!(
+                "cannot get connect_category from error ({})",
+                err
+            ))
+        })?;
+        write_result_to(out, value)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_get_connect_retryable(
+    err: *const SignalFfiError,
+    out: *mut bool,
+) -> *mut SignalFfiError {
+    let err = AssertUnwindSafe(err);
+    run_ffi_safe(|| {
+        let err = err.as_ref().ok_or(NullPointerError)?;
+        let value = err.provide_connect_retryable().map_err(|_| {
+            SignalProtocolError::InvalidArgument(format!(
+                "cannot get connect_retryable from error ({})",
+                err
+            ))
+        })?;
+        write_result_to(out, value)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_get_connect_route(
+    err: *const SignalFfiError,
+    out: *mut *const c_char,
+) -> *mut SignalFfiError {
+    let err = AssertUnwindSafe(err);
+    run_ffi_safe(|| {
+        let err = err.as_ref().ok_or(NullPointerError)?;
+        let value = err.provide_connect_route().map_err(|_| {
+            SignalProtocolError::InvalidArgument(format!(
+                "cannot get connect_route from error ({})",
+                err
+            ))
+        })?;
+        write_result_to(out, value)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_get_connect_elapsed_millis(
+    err: *const SignalFfiError,
+    out: *mut u64,
+) -> *mut SignalFfiError {
+    let err = AssertUnwindSafe(err);
+    run_ffi_safe(|| {
+        let err = err.as_ref().ok_or(NullPointerError)?;
+        let value = err.provide_connect_elapsed_millis().map_err(|_| {
+            SignalProtocolError::InvalidArgument(format!(
+                "cannot get connect_elapsed_millis from error ({})",
+                err
+            ))
+        })?;
+        write_result_to(out, value)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_get_user_hint(
+    err: *const SignalFfiError,
+    out: *mut *const c_char,
+) -> *mut SignalFfiError {
+    let err = AssertUnwindSafe(err);
+    run_ffi_safe(|| {
+        let err = err.as_ref().ok_or(NullPointerError)?;
+        let value = err.provide_user_hint().map_err(|_| {
+            SignalProtocolError::InvalidArgument(format!(
+                "cannot get user_hint from error ({})",
+                err
+            ))
+        })?;
+        write_result_to(out, value)
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn signal_error_get_unknown_fields(
     err: *const SignalFfiError,
@@ -146,6 +146,11 @@ fn ConnectionManager_on_network_change(connection_manager: &ConnectionManager) {
     connection_manager.on_network_change(std::time::Instant::now())
 }
 
+#[bridge_fn]
+fn ConnectionManager_cancel_connection_attempts(connection_manager: &ConnectionManager) {
+    connection_manager.cancel_connection_attempts()
+}
+
 #[bridge_fn]
 fn CreateOTP(username: String, secret: &[u8]) -> String {
     Auth::otp(&username, secret, std::time::SystemTime::now())
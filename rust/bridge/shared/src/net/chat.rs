@@ -74,7 +74,7 @@ fn ChatConnectionInfo_description(connection_info: &ChatConnectionInfo) -> Strin
 #[bridge_io(TokioAsyncContext)]
 async fn UnauthenticatedChatConnection_connect(
     connection_manager: &ConnectionManager,
-) -> Result<UnauthenticatedChatConnection, ConnectError> {
+) -> Result<UnauthenticatedChatConnection, ChatConnectFailure> {
     UnauthenticatedChatConnection::connect(connection_manager).await
 }
 
@@ -126,7 +126,7 @@ async fn AuthenticatedChatConnection_connect(
     username: String,
     password: String,
     receive_stories: bool,
-) -> Result<AuthenticatedChatConnection, ConnectError> {
+) -> Result<AuthenticatedChatConnection, ChatConnectFailure> {
     AuthenticatedChatConnection::connect(
         connection_manager,
         Auth { username, password },
@@ -287,6 +287,7 @@ make_error_testing_enum! {
         AllAttemptsFailed => AllAttemptsFailed,
         InvalidConnectionConfiguration => InvalidConnectionConfiguration,
         RetryLater => RetryAfter42Seconds,
+        Cancelled => Cancelled,
     }
 }
 
@@ -304,13 +305,16 @@ fn TESTING_ChatConnectErrorConvert(
         TestingChatConnectError::AppExpired => ConnectError::AppExpired,
         TestingChatConnectError::DeviceDeregistered => ConnectError::DeviceDeregistered,
         TestingChatConnectError::Timeout => ConnectError::Timeout,
-        TestingChatConnectError::AllAttemptsFailed => ConnectError::AllAttemptsFailed,
+        TestingChatConnectError::AllAttemptsFailed => {
+            ConnectError::AllAttemptsFailed(Default::default())
+        }
         TestingChatConnectError::InvalidConnectionConfiguration => {
             ConnectError::InvalidConnectionConfiguration
         }
         TestingChatConnectError::RetryAfter42Seconds => ConnectError::RetryLater(RetryLater {
             retry_after_seconds: 42,
         }),
+        TestingChatConnectError::Cancelled => ConnectError::Cancelled,
     })
 }
 
@@ -318,6 +322,7 @@ make_error_testing_enum! {
     enum TestingChatSendError for SendError {
         RequestTimedOut => RequestTimedOut,
         Disconnected => Disconnected,
+        RequestOutcomeUnknown => RequestOutcomeUnknown,
         ConnectionInvalidated => ConnectionInvalidated,
         ConnectedElsewhere => ConnectedElsewhere,
         WebSocket => WebSocketConnectionReset,
@@ -334,6 +339,7 @@ fn TESTING_ChatSendErrorConvert(
     Err(match error_description.into_inner() {
         TestingChatSendError::RequestTimedOut => SendError::RequestTimedOut,
         TestingChatSendError::Disconnected => SendError::Disconnected,
+        TestingChatSendError::RequestOutcomeUnknown => SendError::RequestOutcomeUnknown,
         TestingChatSendError::ConnectionInvalidated => SendError::ConnectionInvalidated,
         TestingChatSendError::ConnectedElsewhere => SendError::ConnectedElsewhere,
         TestingChatSendError::WebSocketConnectionReset => {
@@ -94,7 +94,9 @@ impl ConnectChat for ConnectFakeChat {
         std::future::ready(
             self.1
                 .send(remote)
-                .map_err(|_| libsignal_net::chat::ConnectError::AllAttemptsFailed)
+                .map_err(|_| {
+                    libsignal_net::chat::ConnectError::AllAttemptsFailed(Default::default())
+                })
                 .map(|()| chat),
         )
         .boxed()
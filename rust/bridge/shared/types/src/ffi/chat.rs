@@ -9,7 +9,7 @@ use std::panic::UnwindSafe;
 use libsignal_net::chat::server_requests::DisconnectCause;
 
 use super::*;
-use crate::net::chat::{ChatListener, ServerMessageAck};
+use crate::net::chat::{ChatListener, ConnectionClosedByServer, ServerMessageAck};
 
 type ReceivedIncomingMessage = extern "C" fn(
     ctx: *mut c_void,
@@ -123,9 +123,15 @@ impl ChatListener for ChatListenerStruct {
 
     fn connection_interrupted(&mut self, disconnect_cause: DisconnectCause) {
         let error = match disconnect_cause {
-            DisconnectCause::LocalDisconnect => None,
-            DisconnectCause::Error(c) => Some(Box::new(SignalFfiError::from(c))),
-        };
+            DisconnectCause::RemoteClose { code, reason } => {
+                Some(SignalFfiError::from(ConnectionClosedByServer {
+                    code,
+                    reason,
+                }))
+            }
+            cause => cause.into_error().map(SignalFfiError::from),
+        }
+        .map(Box::new);
         (self.0.connection_interrupted)(
             self.0.ctx,
             error.map_or(std::ptr::null_mut(), Box::into_raw),
@@ -10,7 +10,8 @@ use attest::enclave::Error as EnclaveError;
 use attest::hsm_enclave::Error as HsmEnclaveError;
 use device_transfer::Error as DeviceTransferError;
 use libsignal_account_keys::Error as PinError;
-use libsignal_net::infra::errors::RetryLater;
+use libsignal_net::infra::errors::{RetryLater, TransportConnectError};
+use libsignal_net::infra::ws::{WebSocketConnectError, WebSocketServiceError};
 use libsignal_protocol::*;
 use signal_crypto::Error as SignalCryptoError;
 use usernames::{UsernameError, UsernameLinkError};
@@ -93,6 +94,8 @@ pub enum SignalErrorCode {
     ConnectionFailed = 148,
     ChatServiceInactive = 149,
     RequestTimedOut = 150,
+    TlsRenegotiationRejected = 151,
+    OperationTimeout = 152,
 
     SvrDataMissing = 160,
     SvrRestoreFailed = 161,
@@ -102,6 +105,7 @@ pub enum SignalErrorCode {
     DeviceDeregistered = 171,
     ConnectionInvalidated = 172,
     ConnectedElsewhere = 173,
+    ConnectionClosedByServer = 174,
 
     BackupValidation = 180,
 }
@@ -137,6 +141,27 @@ pub trait FfiError: UpcastAsAny + fmt::Debug + Send + 'static {
     fn provide_unknown_fields(&self) -> Result<Vec<String>, WrongErrorKind> {
         Err(WrongErrorKind)
     }
+    fn provide_close_code(&self) -> Result<u32, WrongErrorKind> {
+        Err(WrongErrorKind)
+    }
+    fn provide_close_reason(&self) -> Result<String, WrongErrorKind> {
+        Err(WrongErrorKind)
+    }
+    fn provide_connect_category(&self) -> Result<&'static str, WrongErrorKind> {
+        Err(WrongErrorKind)
+    }
+    fn provide_connect_retryable(&self) -> Result<bool, WrongErrorKind> {
+        Err(WrongErrorKind)
+    }
+    fn provide_connect_route(&self) -> Result<Option<String>, WrongErrorKind> {
+        Err(WrongErrorKind)
+    }
+    fn provide_connect_elapsed_millis(&self) -> Result<u64, WrongErrorKind> {
+        Err(WrongErrorKind)
+    }
+    fn provide_user_hint(&self) -> Result<&'static str, WrongErrorKind> {
+        Err(WrongErrorKind)
+    }
 }
 
 /// The top-level error type (opaquely) returned to C clients when something goes wrong.
@@ -466,6 +491,9 @@ impl FfiError for libsignal_net::cdsi::LookupError {
             Self::RateLimited { .. } => SignalErrorCode::RateLimited,
             Self::InvalidToken => SignalErrorCode::CdsiInvalidToken,
             Self::ConnectTransport(_) => SignalErrorCode::IoError,
+            Self::WebSocket(WebSocketServiceError::OperationTimeout) => {
+                SignalErrorCode::OperationTimeout
+            }
             Self::WebSocket(_) => SignalErrorCode::WebSocket,
             Self::ConnectionTimedOut => SignalErrorCode::ConnectionTimedOut,
             Self::InvalidArgument { .. } => SignalErrorCode::InvalidArgument,
@@ -486,12 +514,12 @@ impl FfiError for libsignal_net::chat::ConnectError {
     fn describe(&self) -> String {
         match self {
             Self::WebSocket(e) => format!("WebSocket error: {e}"),
-            Self::AllAttemptsFailed | Self::InvalidConnectionConfiguration => {
-                "Connection failed".to_owned()
-            }
+            Self::AllAttemptsFailed(failures) => format!("Connection failed{failures}"),
+            Self::InvalidConnectionConfiguration => "Connection failed".to_owned(),
             Self::Timeout => "Connect timed out".to_owned(),
             Self::AppExpired => "App expired".to_owned(),
             Self::DeviceDeregistered => "Device deregistered or delinked".to_owned(),
+            Self::Cancelled => "Connection attempt cancelled".to_owned(),
             Self::RetryLater(RetryLater {
                 retry_after_seconds,
             }) => format!("Rate limited; try again after {retry_after_seconds}s"),
@@ -500,6 +528,9 @@ impl FfiError for libsignal_net::chat::ConnectError {
 
     fn code(&self) -> SignalErrorCode {
         match self {
+            Self::WebSocket(WebSocketConnectError::Transport(
+                TransportConnectError::TlsRenegotiationRejected,
+            )) => SignalErrorCode::TlsRenegotiationRejected,
             Self::WebSocket(_) => SignalErrorCode::WebSocket,
             Self::AllAttemptsFailed { .. } | Self::InvalidConnectionConfiguration => {
                 SignalErrorCode::ConnectionFailed
@@ -507,6 +538,7 @@ impl FfiError for libsignal_net::chat::ConnectError {
             Self::Timeout => SignalErrorCode::ConnectionTimedOut,
             Self::AppExpired => SignalErrorCode::AppExpired,
             Self::DeviceDeregistered => SignalErrorCode::DeviceDeregistered,
+            Self::Cancelled => SignalErrorCode::Cancelled,
             Self::RetryLater { .. } => SignalErrorCode::RateLimited,
         }
     }
@@ -518,6 +550,44 @@ impl FfiError for libsignal_net::chat::ConnectError {
             _ => Err(WrongErrorKind),
         }
     }
+
+    fn provide_user_hint(&self) -> Result<&'static str, WrongErrorKind> {
+        Ok(self.user_hint().into())
+    }
+}
+
+impl FfiError for crate::net::chat::ChatConnectFailure {
+    fn describe(&self) -> String {
+        self.failure.error.describe()
+    }
+
+    fn code(&self) -> SignalErrorCode {
+        self.failure.error.code()
+    }
+
+    fn provide_retry_after_seconds(&self) -> Result<u32, WrongErrorKind> {
+        self.failure.error.provide_retry_after_seconds()
+    }
+
+    fn provide_connect_category(&self) -> Result<&'static str, WrongErrorKind> {
+        Ok(self.failure.category.into())
+    }
+
+    fn provide_connect_retryable(&self) -> Result<bool, WrongErrorKind> {
+        Ok(self.failure.retryable)
+    }
+
+    fn provide_connect_route(&self) -> Result<Option<String>, WrongErrorKind> {
+        Ok(self.route.clone())
+    }
+
+    fn provide_connect_elapsed_millis(&self) -> Result<u64, WrongErrorKind> {
+        Ok(self.elapsed.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+
+    fn provide_user_hint(&self) -> Result<&'static str, WrongErrorKind> {
+        self.failure.error.provide_user_hint()
+    }
 }
 
 impl FfiError for libsignal_net::chat::SendError {
@@ -530,18 +600,27 @@ impl FfiError for libsignal_net::chat::SendError {
             }
             Self::RequestTimedOut => "Request timed out".to_string(),
             Self::Disconnected => "Chat service disconnected".to_owned(),
+            Self::RequestOutcomeUnknown => {
+                "Chat service disconnected with request in flight".to_owned()
+            }
             Self::ConnectionInvalidated => "Connection invalidated".to_owned(),
             Self::ConnectedElsewhere => "Connected elsewhere".to_owned(),
+            Self::Draining => "Chat service draining".to_owned(),
         }
     }
 
     fn code(&self) -> SignalErrorCode {
         match self {
+            Self::WebSocket(WebSocketServiceError::TlsRenegotiationRejected) => {
+                SignalErrorCode::TlsRenegotiationRejected
+            }
             Self::WebSocket(_) => SignalErrorCode::WebSocket,
             Self::IncomingDataInvalid => SignalErrorCode::NetworkProtocol,
             Self::RequestHasInvalidHeader => SignalErrorCode::InternalError,
             Self::RequestTimedOut => SignalErrorCode::RequestTimedOut,
-            Self::Disconnected => SignalErrorCode::ChatServiceInactive,
+            Self::Disconnected | Self::RequestOutcomeUnknown | Self::Draining => {
+                SignalErrorCode::ChatServiceInactive
+            }
             Self::ConnectionInvalidated => SignalErrorCode::ConnectionInvalidated,
             Self::ConnectedElsewhere => SignalErrorCode::ConnectedElsewhere,
         }
@@ -549,6 +628,28 @@ impl FfiError for libsignal_net::chat::SendError {
     fn provide_retry_after_seconds(&self) -> Result<u32, WrongErrorKind> {
         Err(WrongErrorKind)
     }
+
+    fn provide_user_hint(&self) -> Result<&'static str, WrongErrorKind> {
+        Ok(self.user_hint().into())
+    }
+}
+
+impl FfiError for crate::net::chat::ConnectionClosedByServer {
+    fn describe(&self) -> String {
+        self.to_string()
+    }
+
+    fn code(&self) -> SignalErrorCode {
+        SignalErrorCode::ConnectionClosedByServer
+    }
+
+    fn provide_close_code(&self) -> Result<u32, WrongErrorKind> {
+        Ok(self.code.into())
+    }
+
+    fn provide_close_reason(&self) -> Result<String, WrongErrorKind> {
+        Ok(self.reason.clone())
+    }
 }
 
 impl FfiError for http::uri::InvalidUri {
@@ -11,7 +11,7 @@ use libsignal_net::chat::server_requests::DisconnectCause;
 use libsignal_net::chat::ChatConnection;
 
 use super::*;
-use crate::net::chat::{ChatListener, ServerMessageAck};
+use crate::net::chat::{ChatListener, ConnectionClosedByServer, ServerMessageAck};
 
 pub type JavaBridgeChatListener<'a> = JObject<'a>;
 
@@ -114,14 +114,19 @@ impl ChatListener for JniChatListener {
                 )?;
                 Ok(())
             };
-            match disconnect_cause {
-                DisconnectCause::LocalDisconnect => {
-                    throw_exception(env, listener, JObject::null().into())?
+            let disconnect_cause = match disconnect_cause {
+                DisconnectCause::RemoteClose { code, reason } => {
+                    Some(SignalJniError::from(ConnectionClosedByServer {
+                        code,
+                        reason,
+                    }))
                 }
-                DisconnectCause::Error(disconnect_cause) => convert_to_exception(
-                    env,
-                    SignalJniError::from(disconnect_cause),
-                    move |env, throwable, _error| {
+                cause => cause.into_error().map(SignalJniError::from),
+            };
+            match disconnect_cause {
+                None => throw_exception(env, listener, JObject::null().into())?,
+                Some(disconnect_cause) => {
+                    convert_to_exception(env, disconnect_cause, move |env, throwable, _error| {
                         throwable
                             .and_then(|throwable| throw_exception(env, listener, throwable))
                             .unwrap_or_else(|error| {
@@ -129,8 +134,8 @@ impl ChatListener for JniChatListener {
                                     "failed to call onConnectionInterrupted with cause: {error}"
                                 );
                             });
-                    },
-                ),
+                    })
+                }
             };
             Ok(())
         });
@@ -20,8 +20,8 @@ pub use jni::JNIEnv;
 use jni::JavaVM;
 use libsignal_account_keys::Error as PinError;
 use libsignal_net::chat::{ConnectError as ChatConnectError, SendError as ChatSendError};
-use libsignal_net::infra::errors::RetryLater;
-use libsignal_net::infra::ws::WebSocketServiceError;
+use libsignal_net::infra::errors::{RetryLater, TransportConnectError};
+use libsignal_net::infra::ws::{WebSocketConnectError, WebSocketServiceError};
 use libsignal_net::keytrans::Error as KeyTransNetError;
 use libsignal_protocol::*;
 use signal_crypto::Error as SignalCryptoError;
@@ -29,6 +29,7 @@ use usernames::{UsernameError, UsernameLinkError};
 use zkgroup::{ZkGroupDeserializationFailure, ZkGroupVerificationFailure};
 
 use crate::net::cdsi::CdsiError;
+use crate::net::chat::ChatConnectFailure;
 
 #[macro_use]
 mod args;
@@ -775,6 +776,9 @@ impl MessageOnlyExceptionJniError for WebSocketServiceError {
                 // assuming that *here*, though.
                 ClassName("org.signal.libsignal.net.NetworkProtocolException")
             }
+            WebSocketServiceError::OperationTimeout => {
+                ClassName("org.signal.libsignal.net.OperationTimeoutException")
+            }
 
             _ => ClassName("org.signal.libsignal.net.NetworkException"),
         }
@@ -797,9 +801,13 @@ impl JniError for ChatConnectError {
             ChatConnectError::DeviceDeregistered => {
                 ClassName("org.signal.libsignal.net.DeviceDeregisteredException")
             }
+            ChatConnectError::Cancelled => ClassName("java.util.concurrent.CancellationException"),
+            ChatConnectError::WebSocket(WebSocketConnectError::Transport(
+                TransportConnectError::TlsRenegotiationRejected,
+            )) => ClassName("org.signal.libsignal.net.TlsRenegotiationRejectedException"),
             ChatConnectError::WebSocket(_)
             | ChatConnectError::Timeout
-            | ChatConnectError::AllAttemptsFailed
+            | ChatConnectError::AllAttemptsFailed(_)
             | ChatConnectError::InvalidConnectionConfiguration => {
                 ClassName("org.signal.libsignal.net.ChatServiceException")
             }
@@ -808,10 +816,18 @@ impl JniError for ChatConnectError {
     }
 }
 
+impl JniError for ChatConnectFailure {
+    fn to_throwable<'a>(&self, env: &mut JNIEnv<'a>) -> Result<JThrowable<'a>, BridgeLayerError> {
+        self.failure.error.to_throwable(env)
+    }
+}
+
 impl MessageOnlyExceptionJniError for ChatSendError {
     fn exception_class(&self) -> ClassName<'static> {
         match self {
-            ChatSendError::Disconnected => {
+            ChatSendError::Disconnected
+            | ChatSendError::RequestOutcomeUnknown
+            | ChatSendError::Draining => {
                 ClassName("org.signal.libsignal.net.ChatServiceInactiveException")
             }
             ChatSendError::ConnectionInvalidated => {
@@ -820,6 +836,9 @@ impl MessageOnlyExceptionJniError for ChatSendError {
             ChatSendError::ConnectedElsewhere => {
                 ClassName("org.signal.libsignal.net.ConnectedElsewhereException")
             }
+            ChatSendError::WebSocket(WebSocketServiceError::TlsRenegotiationRejected) => {
+                ClassName("org.signal.libsignal.net.TlsRenegotiationRejectedException")
+            }
             ChatSendError::WebSocket(_)
             | ChatSendError::IncomingDataInvalid
             | ChatSendError::RequestHasInvalidHeader
@@ -851,6 +870,28 @@ impl MessageOnlyExceptionJniError for TestingError {
     }
 }
 
+impl JniError for crate::net::chat::ConnectionClosedByServer {
+    fn to_throwable<'a>(&self, env: &mut JNIEnv<'a>) -> Result<JThrowable<'a>, BridgeLayerError> {
+        let Self { code, reason } = self;
+        let message = env
+            .new_string(self.to_string())
+            .check_exceptions(env, "ConnectionClosedByServer::to_throwable")?;
+        let reason = env
+            .new_string(reason)
+            .check_exceptions(env, "ConnectionClosedByServer::to_throwable")?;
+        new_instance(
+            env,
+            ClassName("org.signal.libsignal.net.ConnectionClosedByServerException"),
+            jni_args!((
+                message => java.lang.String,
+                (*code).into() => int,
+                reason => java.lang.String
+            ) -> void),
+        )
+        .map(Into::into)
+    }
+}
+
 impl JniError for RetryLater {
     fn to_throwable<'a>(&self, env: &mut JNIEnv<'a>) -> Result<JThrowable<'a>, BridgeLayerError> {
         let Self {
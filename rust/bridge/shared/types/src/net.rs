@@ -16,11 +16,16 @@ use libsignal_net::enclave::{Cdsi, EnclaveEndpoint, EnclaveEndpointConnection, E
 use libsignal_net::env::{add_user_agent_header, Env, UserAgent};
 use libsignal_net::infra::connection_manager::MultiRouteConnectionManager;
 use libsignal_net::infra::dns::DnsResolver;
+use libsignal_net::infra::metrics::OpenMetricsRecorder;
 use libsignal_net::infra::route::ConnectionProxyConfig;
+use libsignal_net::infra::service::{ServiceStatusNotifier, ServiceStatusWatcher};
+use libsignal_net::infra::tcp_ssl::ipv6_suspicion::Ipv6Suspicion;
 use libsignal_net::infra::tcp_ssl::{InvalidProxyConfig, TcpSslConnector};
 use libsignal_net::infra::timeouts::ONE_ROUTE_CONNECTION_TIMEOUT;
+use libsignal_net::infra::utils::rng::RngProvider;
 use libsignal_net::infra::utils::NetworkChangeEvent;
-use libsignal_net::infra::{EnableDomainFronting, EndpointConnection};
+use libsignal_net::infra::ws::LongPollFallback;
+use libsignal_net::infra::{ConnectCancellation, EnableDomainFronting, EndpointConnection};
 
 use self::remote_config::{RemoteConfig, RemoteConfigKeys};
 use crate::*;
@@ -64,6 +69,7 @@ impl EndpointConnections {
         use_fallbacks: bool,
         enforce_minimum_tls: EnforceMinimumTls,
         network_change_event: &NetworkChangeEvent,
+        rng: &RngProvider,
     ) -> Self {
         log::info!(
             "Creating endpoint connections (fallbacks {}) for {} and others",
@@ -79,6 +85,7 @@ impl EndpointConnections {
             use_fallbacks,
             &enforce_minimum_tls,
             network_change_event,
+            rng,
         );
         let cdsi = Self::endpoint_connection(
             &env.cdsi,
@@ -86,6 +93,7 @@ impl EndpointConnections {
             use_fallbacks,
             &enforce_minimum_tls,
             network_change_event,
+            rng,
         );
         Self {
             chat,
@@ -104,6 +112,7 @@ impl EndpointConnections {
         include_fallback: bool,
         enforce_minimum_tls: &EnforceMinimumTls,
         network_change_event: &NetworkChangeEvent,
+        rng: &RngProvider,
     ) -> EnclaveEndpointConnection<E, MultiRouteConnectionManager> {
         let connection_config = match enforce_minimum_tls {
             EnforceMinimumTls::Yes => &endpoint.domain_config.connect,
@@ -113,7 +122,7 @@ impl EndpointConnections {
                 .config_with_permissive_min_tls_version(),
         };
         let params = if include_fallback {
-            connection_config.connection_params_with_fallback()
+            connection_config.connection_params_with_fallback(rng)
         } else {
             vec![endpoint.domain_config.connect.direct_connection_params()]
         };
@@ -138,6 +147,19 @@ pub struct ConnectionManager {
     // but we don't hold it for very long anyway (just enough to clone the Arc).
     endpoints: std::sync::Mutex<Arc<EndpointConnections>>,
     transport_connector: std::sync::Mutex<TcpSslConnector>,
+    /// Shared by `transport_connector` and the route resolver in `connect`.
+    ipv6_suspicion: Ipv6Suspicion,
+    /// Fed by chat connect attempts made through `connect` (including their
+    /// DNS lookups, through `dns_resolver`, and TLS handshakes), by the round
+    /// trips of the chat connections that result, and by `transport_connector`.
+    metrics: Arc<OpenMetricsRecorder>,
+    /// Follows the most recent authenticated chat connection.
+    authenticated_chat_status: Arc<ServiceStatusNotifier>,
+    /// Shared by every chat connection attempt, but only used while
+    /// [`RemoteConfigKeys::ChatLongPollFallback`] is enabled.
+    chat_long_poll_fallback: LongPollFallback,
+    /// Handed to each chat connection attempt, and replaced once cancelled.
+    connect_cancellation: std::sync::Mutex<ConnectCancellation>,
     most_recent_network_change: std::sync::Mutex<Instant>,
     network_change_event_tx: ::tokio::sync::watch::Sender<()>,
 }
@@ -162,10 +184,28 @@ impl ConnectionManager {
         let (network_change_event_tx, network_change_event_rx) = ::tokio::sync::watch::channel(());
         let user_agent = UserAgent::with_libsignal_version(user_agent);
 
+        let metrics = Arc::new(OpenMetricsRecorder::new());
         let dns_resolver =
             DnsResolver::new_with_static_fallback(env.static_fallback(), &network_change_event_rx);
-        let transport_connector =
-            std::sync::Mutex::new(TcpSslConnector::new_direct(dns_resolver.clone()));
+        dns_resolver.set_metrics(Some(metrics.clone()));
+        let ipv6_suspicion =
+            Ipv6Suspicion::new(Self::IPV6_DEPRIORITIZATION, &network_change_event_rx);
+        let mut transport_connector = TcpSslConnector::new_direct(dns_resolver.clone());
+        transport_connector.set_ipv6_suspicion(Some(ipv6_suspicion.clone()));
+        transport_connector.set_metrics(Some(metrics.clone()));
+        let connect = ConnectState::new_with_transport_connector(
+            SUGGESTED_CONNECT_CONFIG,
+            PreconnectingFactory::new(
+                DefaultConnectorFactory::with_metrics(metrics.clone()),
+                SUGGESTED_TLS_PRECONNECT_LIFETIME,
+            ),
+        );
+        {
+            let mut connect = connect.lock().expect("not poisoned");
+            connect.route_resolver.ipv6_suspicion = Some(ipv6_suspicion.clone());
+            connect.metrics = Some(metrics.clone());
+        }
+        let rng = connect.lock().expect("not poisoned").rng.clone();
         let remote_config = RemoteConfig::new(remote_config);
         let enforce_minimum_tls = if remote_config.is_enabled(RemoteConfigKeys::EnforceMinimumTls) {
             EnforceMinimumTls::Yes
@@ -179,6 +219,7 @@ impl ConnectionManager {
                 false,
                 enforce_minimum_tls,
                 &network_change_event_rx,
+                &rng,
             )
             .into(),
         );
@@ -187,20 +228,48 @@ impl ConnectionManager {
             endpoints,
             user_agent,
             remote_config: remote_config.into(),
-            connect: ConnectState::new_with_transport_connector(
-                SUGGESTED_CONNECT_CONFIG,
-                PreconnectingFactory::new(
-                    DefaultConnectorFactory,
-                    SUGGESTED_TLS_PRECONNECT_LIFETIME,
-                ),
-            ),
+            connect,
             dns_resolver,
-            transport_connector,
+            transport_connector: transport_connector.into(),
+            ipv6_suspicion,
+            metrics,
+            authenticated_chat_status: Default::default(),
+            chat_long_poll_fallback: LongPollFallback::new(
+                Self::LONG_POLL_FALLBACK_AFTER,
+                Self::LONG_POLL_RETRY_WEBSOCKET_AFTER,
+            ),
+            connect_cancellation: Default::default(),
             most_recent_network_change: Instant::now().into(),
             network_change_event_tx,
         }
     }
 
+    /// Renders what's been counted about connections so far, in the
+    /// OpenMetrics text format.
+    ///
+    /// See [`OpenMetricsRecorder`].
+    pub fn render_metrics(&self) -> String {
+        self.metrics.render()
+    }
+
+    /// Follows whether an authenticated chat connection is being made, is
+    /// connected, or has disconnected.
+    ///
+    /// Only the most recent connection is followed.
+    pub fn authenticated_chat_status(&self) -> ServiceStatusWatcher {
+        self.authenticated_chat_status.subscribe()
+    }
+
+    /// Abandons the chat connection attempts in progress, which then fail
+    /// with [`ConnectError::Cancelled`](libsignal_net::chat::ConnectError::Cancelled).
+    ///
+    /// Attempts started afterwards are unaffected.
+    pub fn cancel_connection_attempts(&self) {
+        let in_progress =
+            std::mem::take(&mut *self.connect_cancellation.lock().expect("not poisoned"));
+        in_progress.cancel();
+    }
+
     pub fn set_proxy(&self, proxy: ConnectionProxyConfig) {
         let mut guard = self.transport_connector.lock().expect("not poisoned");
         guard.set_proxy(proxy);
@@ -246,12 +315,14 @@ impl ConnectionManager {
         } else {
             EnforceMinimumTls::No
         };
+        let rng = self.connect.lock().expect("not poisoned").rng.clone();
         let new_endpoints = EndpointConnections::new(
             &self.env,
             &self.user_agent,
             enabled,
             enforce_minimum_tls,
             &self.network_change_event_tx.subscribe(),
+            &rng,
         );
         *self.endpoints.lock().expect("not poisoned") = Arc::new(new_endpoints);
     }
@@ -262,6 +333,17 @@ impl ConnectionManager {
 
     const NETWORK_CHANGE_DEBOUNCE: Duration = Duration::from_secs(1);
 
+    /// How long IPv4 is preferred after IPv6 is found to be broken, unless the
+    /// network changes first.
+    const IPV6_DEPRIORITIZATION: Duration = Duration::from_secs(5 * 60);
+
+    /// How many chat websocket upgrades in a row have to fail before
+    /// long-polling is used instead, if allowed.
+    const LONG_POLL_FALLBACK_AFTER: usize = 3;
+
+    /// How long long-polling is used before websockets are tried again.
+    const LONG_POLL_RETRY_WEBSOCKET_AFTER: Duration = Duration::from_secs(10 * 60);
+
     pub fn on_network_change(&self, now: Instant) {
         {
             let mut most_recent_change_guard = self
@@ -291,13 +373,18 @@ bridge_as_handle!(ConnectionProxyConfig);
 
 #[cfg(test)]
 mod test {
+    use std::num::NonZeroU16;
+
     use ::tokio; // otherwise ambiguous with the tokio submodule
     use assert_matches::assert_matches;
     use libsignal_net::chat::ConnectError;
+    use libsignal_net::infra::errors::ConnectErrorCategory;
+    use libsignal_net::infra::route::SIGNAL_TLS_PROXY_SCHEME;
+    use libsignal_net::infra::ConnectFailure;
     use test_case::test_case;
 
     use super::*;
-    use crate::net::chat::UnauthenticatedChatConnection;
+    use crate::net::chat::{ChatConnectFailure, UnauthenticatedChatConnection};
 
     #[test_case(Environment::Staging; "staging")]
     #[test_case(Environment::Prod; "prod")]
@@ -316,7 +403,74 @@ mod test {
             .await
             .map(|_| ())
             .expect_err("should fail to connect");
-        assert_matches!(err, ConnectError::InvalidConnectionConfiguration);
+        assert_matches!(
+            err,
+            ChatConnectFailure {
+                route: None,
+                elapsed: _,
+                failure: ConnectFailure {
+                    category: ConnectErrorCategory::Configuration,
+                    retryable: false,
+                    error: ConnectError::InvalidConnectionConfiguration,
+                },
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_attempts_are_counted_in_metrics() {
+        // A "proxy" that hangs up on everyone, so that the TLS handshake with chat fails.
+        let listener = tokio::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let port = listener.local_addr().expect("bound").port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                drop(stream);
+            }
+        });
+
+        let cm =
+            ConnectionManager::new(Environment::Staging, "test-user-agent", Default::default());
+        cm.set_proxy(
+            ConnectionProxyConfig::from_parts(
+                SIGNAL_TLS_PROXY_SCHEME,
+                "127.0.0.1",
+                NonZeroU16::new(port),
+                Some(("UNENCRYPTED_FOR_TESTING".to_owned(), "".to_owned())),
+            )
+            .expect("valid"),
+        );
+
+        let before = cm.render_metrics();
+        for line in [
+            "libsignal_net_connect_attempts_total 0",
+            "libsignal_net_tls_handshakes_total{result=\"failure\"} 0",
+        ] {
+            assert!(
+                before.lines().any(|l| l == line),
+                "{line} missing from\n{before}"
+            );
+        }
+
+        let _ = UnauthenticatedChatConnection::connect(&cm)
+            .await
+            .map(|_| ())
+            .expect_err("should fail to connect");
+
+        let after = cm.render_metrics();
+        assert!(
+            after
+                .lines()
+                .any(|l| l == "libsignal_net_connect_attempts_total 1"),
+            "{after}"
+        );
+        assert!(
+            !after
+                .lines()
+                .any(|l| l == "libsignal_net_tls_handshakes_total{result=\"failure\"} 0"),
+            "{after}"
+        );
     }
 
     #[test]
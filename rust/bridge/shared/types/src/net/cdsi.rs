@@ -101,6 +101,7 @@ impl CdsiLookup {
             dns_resolver,
             network_change_event: &network_change_event_tx.subscribe(),
             confirmation_header_name,
+            cancellation: None,
         };
 
         let connected = CdsiConnection::connect_with(
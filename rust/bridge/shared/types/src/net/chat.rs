@@ -10,7 +10,6 @@ use std::str::FromStr;
 use std::time::Duration;
 
 use atomic_take::AtomicTake;
-use futures_util::FutureExt as _;
 use http::status::InvalidStatusCode;
 use http::uri::{InvalidUri, PathAndQuery};
 use http::{HeaderMap, HeaderName, HeaderValue};
@@ -27,11 +26,13 @@ use libsignal_net::infra::route::{
     ConnectionProxyConfig, DirectOrProxyProvider, RouteProvider, RouteProviderExt,
     UnresolvedHttpsServiceRoute,
 };
+use libsignal_net::infra::service::ServiceStatus;
 use libsignal_net::infra::tcp_ssl::InvalidProxyConfig;
-use libsignal_net::infra::EnableDomainFronting;
+use libsignal_net::infra::{ConnectFailure, ConnectResult, EnableDomainFronting};
 use libsignal_protocol::Timestamp;
 use static_assertions::assert_impl_all;
 
+use crate::net::remote_config::RemoteConfigKeys;
 use crate::net::ConnectionManager;
 use crate::*;
 
@@ -76,7 +77,9 @@ enum MaybeChatConnection {
 assert_impl_all!(MaybeChatConnection: Send, Sync);
 
 impl UnauthenticatedChatConnection {
-    pub async fn connect(connection_manager: &ConnectionManager) -> Result<Self, ConnectError> {
+    pub async fn connect(
+        connection_manager: &ConnectionManager,
+    ) -> Result<Self, ChatConnectFailure> {
         let inner = establish_chat_connection("unauthenticated", connection_manager, None).await?;
         Ok(Self {
             inner: MaybeChatConnection::WaitingForListener(
@@ -93,7 +96,7 @@ impl AuthenticatedChatConnection {
         connection_manager: &ConnectionManager,
         auth: Auth,
         receive_stories: bool,
-    ) -> Result<Self, ConnectError> {
+    ) -> Result<Self, ChatConnectFailure> {
         let inner = establish_chat_connection(
             "authenticated",
             connection_manager,
@@ -125,6 +128,7 @@ impl AuthenticatedChatConnection {
             dns_resolver: &connection_manager.dns_resolver,
             network_change_event: &connection_manager.network_change_event_tx.subscribe(),
             confirmation_header_name: None,
+            cancellation: None,
         };
 
         log::info!("preconnecting chat");
@@ -275,7 +279,7 @@ async fn establish_chat_connection(
     auth_type: &'static str,
     connection_manager: &ConnectionManager,
     auth: Option<chat::AuthenticatedChatHeaders>,
-) -> Result<chat::PendingChatConnection, ConnectError> {
+) -> Result<chat::PendingChatConnection, ChatConnectFailure> {
     let ConnectionManager {
         env,
         dns_resolver,
@@ -283,6 +287,12 @@ async fn establish_chat_connection(
         user_agent,
         endpoints,
         network_change_event_tx,
+        ipv6_suspicion,
+        metrics,
+        authenticated_chat_status,
+        chat_long_poll_fallback,
+        connect_cancellation,
+        remote_config,
         ..
     } = connection_manager;
 
@@ -300,7 +310,14 @@ async fn establish_chat_connection(
         ..
     } = ws_config;
 
+    let long_poll_fallback = remote_config
+        .lock()
+        .expect("not poisoned")
+        .is_enabled(RemoteConfigKeys::ChatLongPollFallback)
+        .then_some(chat_long_poll_fallback);
+
     let chat_connect = &env.chat_domain_config.connect;
+    let cancellation = connect_cancellation.lock().expect("not poisoned").clone();
     let connection_resources = ConnectionResources {
         connect_state: connect,
         dns_resolver,
@@ -308,12 +325,21 @@ async fn establish_chat_connection(
         confirmation_header_name: chat_connect
             .confirmation_header_name
             .map(HeaderName::from_static),
+        cancellation: Some(&cancellation),
     };
     let route_provider = make_route_provider(connection_manager, enable_domain_fronting)?;
 
     log::info!("connecting {auth_type} chat");
+    let status = auth.is_some().then_some(authenticated_chat_status);
+    if let Some(status) = status {
+        status.set_status(ServiceStatus::Connecting);
+    }
 
-    ChatConnection::start_connect_with(
+    let ConnectResult {
+        route,
+        elapsed,
+        outcome,
+    } = ChatConnection::start_connect_with_result(
         connection_resources,
         route_provider,
         user_agent,
@@ -323,13 +349,42 @@ async fn establish_chat_connection(
             initial_request_id: 0,
         },
         auth,
+        long_poll_fallback,
         auth_type,
     )
-    .inspect(|r| match r {
-        Ok(_) => log::info!("successfully connected {auth_type} chat"),
-        Err(e) => log::warn!("failed to connect {auth_type} chat: {e}"),
-    })
-    .await
+    .await;
+
+    match outcome {
+        Ok(pending) => {
+            log::info!("successfully connected {auth_type} chat");
+            let pending = pending
+                .with_ipv6_suspicion(ipv6_suspicion.clone())
+                .with_metrics(metrics.clone());
+            Ok(match status {
+                Some(status) => pending.with_status_notifier(status.clone()),
+                None => pending,
+            })
+        }
+        Err(failure) => {
+            log::warn!(
+                "failed to connect {auth_type} chat after {elapsed:?}: {}",
+                failure.error
+            );
+            if let Some(status) = status {
+                status.set_status(match failure.error {
+                    ConnectError::AppExpired | ConnectError::DeviceDeregistered => {
+                        ServiceStatus::Failed
+                    }
+                    _ => ServiceStatus::Inactive,
+                });
+            }
+            Err(ChatConnectFailure {
+                route,
+                elapsed,
+                failure,
+            })
+        }
+    }
 }
 
 fn make_route_provider(
@@ -427,6 +482,59 @@ pub trait ChatListener: Send {
     fn connection_interrupted(&mut self, disconnect_cause: DisconnectCause);
 }
 
+/// A failed chat connect, reported to the app with the details of the attempt
+/// from [`ChatConnection::start_connect_with_result`].
+///
+/// The app sees the underlying [`ConnectError`] as usual; the details are
+/// available on the side, like through the FFI's error accessors.
+#[derive(Debug)]
+pub struct ChatConnectFailure {
+    /// See [`ConnectResult::route`].
+    pub route: Option<String>,
+    pub elapsed: Duration,
+    pub failure: ConnectFailure<ConnectError>,
+}
+
+/// For failures that happen before any connection attempt is made.
+impl From<ConnectError> for ChatConnectFailure {
+    fn from(error: ConnectError) -> Self {
+        Self {
+            route: None,
+            elapsed: Duration::ZERO,
+            failure: error.into(),
+        }
+    }
+}
+
+impl From<ChatConnectFailure> for ConnectError {
+    fn from(value: ChatConnectFailure) -> Self {
+        value.failure.error
+    }
+}
+
+impl std::fmt::Display for ChatConnectFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.failure.error.fmt(f)
+    }
+}
+
+/// A [`DisconnectCause::RemoteClose`], reported to the app with the code and
+/// reason from the server's close frame.
+///
+/// This is used instead of [`DisconnectCause::into_error`], which only keeps
+/// the fact that the connection was closed.
+#[derive(Debug)]
+pub struct ConnectionClosedByServer {
+    pub code: u16,
+    pub reason: String,
+}
+
+impl std::fmt::Display for ConnectionClosedByServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Chat service closed by server with code {}", self.code)
+    }
+}
+
 impl dyn ChatListener {
     /// A helper to translate from the libsignal-net enum to the separate callback methods in this
     /// trait.
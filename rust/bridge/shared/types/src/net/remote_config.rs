@@ -40,6 +40,8 @@ struct RemoteConfigKey {
 pub enum RemoteConfigKeys {
     /// Whether or not to enforce the hardcoded minimum TLS versions for Chat and CDSI endpoints.
     EnforceMinimumTls,
+    /// Whether chat connections may use HTTP long-polling while websocket upgrades keep failing.
+    ChatLongPollFallback,
 }
 
 pub enum RemoteConfigValue {
@@ -55,6 +57,9 @@ impl From<RemoteConfigKeys> for RemoteConfigKey {
             RemoteConfigKeys::EnforceMinimumTls => RemoteConfigKey {
                 raw_key: "enforceMinimumTls",
             },
+            RemoteConfigKeys::ChatLongPollFallback => RemoteConfigKey {
+                raw_key: "chatLongPollFallback",
+            },
         }
     }
 }
@@ -19,7 +19,7 @@ use neon::prelude::{Context, Finalize, JsObject, Object};
 use neon::result::NeonResult;
 use signal_neon_futures::call_method;
 
-use crate::net::chat::{ChatListener, ServerMessageAck};
+use crate::net::chat::{ChatListener, ConnectionClosedByServer, ServerMessageAck};
 use crate::net::ConnectionManager;
 use crate::node::{PersistentBorrowedJsBoxedBridgeHandle, ResultTypeInfo, SignalNodeError as _};
 
@@ -90,10 +90,6 @@ impl ChatListener for NodeChatListener {
     }
 
     fn connection_interrupted(&mut self, disconnect_cause: DisconnectCause) {
-        let disconnect_cause = match disconnect_cause {
-            DisconnectCause::LocalDisconnect => None,
-            DisconnectCause::Error(cause) => Some(cause),
-        };
         let roots_shared = self.roots.clone();
         self.js_channel.send(move |mut cx| {
             let Roots {
@@ -101,9 +97,19 @@ impl ChatListener for NodeChatListener {
                 module,
             } = &*roots_shared;
             let module = module.to_inner(&mut cx);
-            let cause = disconnect_cause
-                .map(|cause| cause.into_throwable(&mut cx, module, "connection_interrupted"))
-                .convert_into(&mut cx)?;
+            let cause = match disconnect_cause {
+                DisconnectCause::RemoteClose { code, reason } => {
+                    Some(ConnectionClosedByServer { code, reason }.into_throwable(
+                        &mut cx,
+                        module,
+                        "connection_interrupted",
+                    ))
+                }
+                cause => cause
+                    .into_error()
+                    .map(|cause| cause.into_throwable(&mut cx, module, "connection_interrupted")),
+            }
+            .convert_into(&mut cx)?;
 
             let callback = callback_object.to_inner(&mut cx);
             let _result = call_method(&mut cx, callback, "_connection_interrupted", [cause])?;
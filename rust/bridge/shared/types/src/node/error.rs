@@ -5,6 +5,8 @@
 
 use std::fmt;
 
+use libsignal_net::infra::errors::TransportConnectError;
+use libsignal_net::infra::ws::{WebSocketConnectError, WebSocketServiceError};
 #[cfg(feature = "signal-media")]
 use signal_media::sanitize::mp4::{Error as Mp4Error, ParseError as Mp4ParseError};
 #[cfg(feature = "signal-media")]
@@ -420,12 +422,16 @@ impl SignalNodeError for libsignal_net::chat::ConnectError {
         let name = match self {
             Self::AppExpired => "AppExpired",
             Self::DeviceDeregistered => "DeviceDelinked",
+            Self::Cancelled => "Cancelled",
             Self::RetryLater(retry_later) => {
                 return retry_later.into_throwable(cx, module, operation_name)
             }
+            Self::WebSocket(WebSocketConnectError::Transport(
+                TransportConnectError::TlsRenegotiationRejected,
+            )) => "TlsRenegotiationRejected",
             Self::WebSocket(_)
             | Self::Timeout
-            | Self::AllAttemptsFailed
+            | Self::AllAttemptsFailed(_)
             | Self::InvalidConnectionConfiguration =>
             // TODO: Distinguish retryable errors from proper failures?
             {
@@ -433,14 +439,27 @@ impl SignalNodeError for libsignal_net::chat::ConnectError {
             }
         };
         let message = self.to_string();
-        new_js_error(
-            cx,
-            module,
-            Some(name),
-            &message,
-            operation_name,
-            no_extra_properties,
-        )
+        let user_hint: &'static str = self.user_hint().into();
+        let properties = move |cx: &mut C| {
+            let props = cx.empty_object();
+            let user_hint = cx.string(user_hint);
+            props.set(cx, "userHint", user_hint)?;
+            Ok(props.upcast())
+        };
+        new_js_error(cx, module, Some(name), &message, operation_name, properties)
+    }
+}
+
+impl SignalNodeError for crate::net::chat::ChatConnectFailure {
+    fn into_throwable<'a, C: Context<'a>>(
+        self,
+        cx: &mut C,
+        module: Handle<'a, JsObject>,
+        operation_name: &str,
+    ) -> Handle<'a, JsError> {
+        self.failure
+            .error
+            .into_throwable(cx, module, operation_name)
     }
 }
 
@@ -452,9 +471,14 @@ impl SignalNodeError for libsignal_net::chat::SendError {
         operation_name: &str,
     ) -> Handle<'a, JsError> {
         let name = match self {
-            Self::Disconnected => Some("ChatServiceInactive"),
+            Self::Disconnected | Self::RequestOutcomeUnknown | Self::Draining => {
+                Some("ChatServiceInactive")
+            }
             Self::ConnectionInvalidated => Some("ConnectionInvalidated"),
             Self::ConnectedElsewhere => Some("ConnectedElsewhere"),
+            Self::WebSocket(WebSocketServiceError::TlsRenegotiationRejected) => {
+                Some("TlsRenegotiationRejected")
+            }
             Self::WebSocket(_)
             | Self::IncomingDataInvalid
             | Self::RequestHasInvalidHeader
@@ -465,13 +489,40 @@ impl SignalNodeError for libsignal_net::chat::SendError {
             }
         };
         let message = self.to_string();
+        let user_hint: &'static str = self.user_hint().into();
+        let properties = move |cx: &mut C| {
+            let props = cx.empty_object();
+            let user_hint = cx.string(user_hint);
+            props.set(cx, "userHint", user_hint)?;
+            Ok(props.upcast())
+        };
+        new_js_error(cx, module, name, &message, operation_name, properties)
+    }
+}
+
+impl SignalNodeError for crate::net::chat::ConnectionClosedByServer {
+    fn into_throwable<'a, C: Context<'a>>(
+        self,
+        cx: &mut C,
+        module: Handle<'a, JsObject>,
+        operation_name: &str,
+    ) -> Handle<'a, JsError> {
+        let message = self.to_string();
+        let properties = move |cx: &mut C| {
+            let props = cx.empty_object();
+            let close_code = cx.number(self.code);
+            props.set(cx, "closeCode", close_code)?;
+            let close_reason = cx.string(self.reason);
+            props.set(cx, "closeReason", close_reason)?;
+            Ok(props.upcast())
+        };
         new_js_error(
             cx,
             module,
-            name,
+            Some("ConnectionClosedByServer"),
             &message,
             operation_name,
-            no_extra_properties,
+            properties,
         )
     }
 }
@@ -535,6 +586,7 @@ impl SignalNodeError for libsignal_net::cdsi::LookupError {
             Self::AttestationError(e) => return e.into_throwable(cx, module, operation_name),
             Self::InvalidArgument { server_reason: _ } => None,
             Self::InvalidToken => Some("CdsiInvalidToken"),
+            Self::WebSocket(WebSocketServiceError::OperationTimeout) => Some("OperationTimeout"),
             Self::ConnectionTimedOut
             | Self::ConnectTransport(_)
             | Self::WebSocket(_)
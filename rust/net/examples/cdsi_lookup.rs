@@ -82,6 +82,7 @@ async fn main() {
             dns_resolver: &resolver,
             network_change_event: &no_network_change_events(),
             confirmation_header_name,
+            cancellation: None,
         };
 
         CdsiConnection::connect_with(
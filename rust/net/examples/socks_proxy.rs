@@ -126,7 +126,7 @@ async fn main() {
             .expect("failed to resolve");
         let connector =
             libsignal_net::infra::route::ComposedConnector::<_, _, TransportConnectError>::new(
-                libsignal_net::infra::tcp_ssl::StatelessTls,
+                libsignal_net::infra::tcp_ssl::StatelessTls::default(),
                 libsignal_net::infra::tcp_ssl::proxy::StatelessProxied,
             );
 
@@ -172,6 +172,8 @@ async fn main() {
             tcp_host: host,
             port,
             certs: root_certs,
+            dns_resolver: None,
+            alpn: None,
         };
         let StreamAndInfo(connection, info) = connector
             .connect(&connection_params, Alpn::Http1_1)
@@ -108,6 +108,7 @@ async fn main() {
         dns_resolver: &resolver,
         network_change_event: &no_network_change_events(),
         confirmation_header_name,
+        cancellation: None,
     };
 
     let params: EndpointParams<'_, LoggingNewHandshake<Svr2>> = cast_params(&env.params);
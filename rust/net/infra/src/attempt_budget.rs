@@ -0,0 +1,165 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! A limit on connection attempts in flight, shared between the layers that
+//! make them.
+//!
+//! During a storm of reconnects, a [`MultiRouteConnectionManager`] can start
+//! several routes at once, and each of them makes staggered attempts to every
+//! address its host resolves to. Giving the same [`AttemptBudget`] to the
+//! manager and to the [`DirectConnector`] it connects with bounds how many
+//! sockets are being connected at once across all of them.
+//!
+//! [`MultiRouteConnectionManager`]: crate::connection_manager::MultiRouteConnectionManager
+//! [`DirectConnector`]: crate::tcp_ssl::DirectConnector
+
+use std::sync::Arc;
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// How many connection attempts can be in flight at once.
+///
+/// Clones share the same permits.
+#[derive(Clone, Debug)]
+pub struct AttemptBudget(Arc<Semaphore>);
+
+impl AttemptBudget {
+    pub fn new(max_attempts_in_flight: usize) -> Self {
+        Self::from_semaphore(Arc::new(Semaphore::new(max_attempts_in_flight)))
+    }
+
+    /// Uses the permits of an existing semaphore, so that attempts share them
+    /// with whatever else does.
+    pub fn from_semaphore(semaphore: Arc<Semaphore>) -> Self {
+        Self(semaphore)
+    }
+
+    /// How many more attempts could start right now.
+    pub fn available(&self) -> usize {
+        self.0.available_permits()
+    }
+
+    /// Waits for room for another attempt, which counts as in flight until
+    /// the permit is dropped.
+    pub(crate) async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.0.acquire().await.expect("never closed")
+    }
+
+    /// Waits until another attempt could start, without counting one.
+    pub(crate) async fn wait_for_room(&self) {
+        drop(self.acquire().await)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv6Addr};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::connection_manager::{
+        ConnectionAttemptOutcome, ConnectionManager, MultiRouteConnectionManager,
+        SingleRouteThrottlingConnectionManager,
+    };
+    use crate::dns::DnsResolver;
+    use crate::tcp_ssl::testutil::{localhost_params, SERVER_HOSTNAME};
+    use crate::tcp_ssl::DirectConnector;
+    use crate::testutil::{no_network_change_events, TestError};
+    use crate::{Alpn, ConnectionParams, HttpRequestDecoratorSeq, RouteType};
+
+    const BUDGET: usize = 2;
+    const ROUTES: usize = 3;
+    const ADDRESSES: usize = 3;
+
+    #[tokio::test]
+    async fn attempts_across_routes_and_addresses_stay_within_the_budget() {
+        // The server doesn't answer the TLS handshake, and hangs up on each
+        // connection after a little while, so that every attempt stays in
+        // flight for some time and then fails.
+        let listener = TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let port = listener.local_addr().expect("bound").port();
+        let open = Arc::new(AtomicUsize::new(0));
+        let max_open = Arc::new(AtomicUsize::new(0));
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let _server_handle = tokio::spawn({
+            let (open, max_open, accepted) = (open.clone(), max_open.clone(), accepted.clone());
+            async move {
+                loop {
+                    let (stream, _) = listener.accept().await.expect("can accept");
+                    accepted.fetch_add(1, Ordering::SeqCst);
+                    let now_open = open.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_open.fetch_max(now_open, Ordering::SeqCst);
+                    let open = open.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        open.fetch_sub(1, Ordering::SeqCst);
+                        drop(stream);
+                    });
+                }
+            }
+        });
+
+        let budget = AttemptBudget::new(BUDGET);
+        let connector = DirectConnector {
+            race_tls_handshakes: true,
+            attempt_budget: Some(budget.clone()),
+            ..DirectConnector::new(DnsResolver::default())
+        };
+        let connection_params = ConnectionParams {
+            route_type: RouteType::Test,
+            http_host: SERVER_HOSTNAME.into(),
+            http_request_decorator: HttpRequestDecoratorSeq::default(),
+            connection_confirmation_header: None,
+            transport: localhost_params(port),
+        };
+        let routes = MultiRouteConnectionManager::new(
+            (0..ROUTES)
+                .map(|_| {
+                    SingleRouteThrottlingConnectionManager::new(
+                        connection_params.clone(),
+                        Duration::from_secs(10),
+                        &no_network_change_events(),
+                    )
+                })
+                .collect(),
+        )
+        .with_concurrent_attempts(ROUTES.try_into().expect("not zero"), Duration::ZERO)
+        .with_attempt_budget(budget.clone());
+
+        let addresses = [IpAddr::V6(Ipv6Addr::LOCALHOST); ADDRESSES];
+        let outcome: ConnectionAttemptOutcome<_, TestError> = routes
+            .connect_or_wait(|connection_params| {
+                let (connector, addresses) = (&connector, &addresses);
+                async move {
+                    connector
+                        .connect_with_addresses(
+                            &connection_params.transport,
+                            addresses,
+                            Alpn::Http1_1,
+                        )
+                        .await
+                        .map_err(|_| TestError::Expected)
+                }
+            })
+            .await;
+
+        assert!(matches!(
+            outcome,
+            ConnectionAttemptOutcome::AllRoutesFailed { .. }
+        ));
+        assert_eq!(budget.available(), BUDGET);
+        let (accepted, max_open) = (
+            accepted.load(Ordering::SeqCst),
+            max_open.load(Ordering::SeqCst),
+        );
+        assert!(accepted > ROUTES * ADDRESSES, "{accepted}");
+        assert!(max_open <= BUDGET, "{max_open}");
+    }
+}
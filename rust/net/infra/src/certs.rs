@@ -4,13 +4,19 @@
 //
 
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
 
 use boring_signal::error::ErrorStack;
+use boring_signal::hash::MessageDigest;
 use boring_signal::ssl::{SslAlert, SslConnectorBuilder, SslVerifyError, SslVerifyMode};
 use boring_signal::x509::store::X509StoreBuilder;
-use boring_signal::x509::X509;
+use boring_signal::x509::{X509Ref, X509};
 use rustls::client::danger::ServerCertVerifier;
 
+use crate::dns::dns_utils::log_safe_domain;
+use crate::errors::TransportConnectError;
 use crate::host::Host;
 
 #[derive(thiserror::Error, Debug, displaydoc::Display)]
@@ -66,6 +72,147 @@ impl RootCertificates {
     }
 }
 
+/// Named sets of trust roots to pick from at connect time.
+///
+/// This allows using a single [`TransportConnectionParams`] with endpoints
+/// that have different trust roots, see
+/// [`TransportConnector::connect_with_cert_profile`]. Selecting a profile
+/// clones its [`RootCertificates`], which doesn't allocate for static or
+/// borrowed certificates.
+///
+/// [`TransportConnectionParams`]: crate::TransportConnectionParams
+/// [`TransportConnector::connect_with_cert_profile`]: crate::TransportConnector::connect_with_cert_profile
+#[derive(Clone, Debug, Default)]
+pub struct CertProfiles {
+    profiles: HashMap<Arc<str>, RootCertificates>,
+}
+
+impl CertProfiles {
+    pub fn with_profile(mut self, name: impl Into<Arc<str>>, certs: RootCertificates) -> Self {
+        self.profiles.insert(name.into(), certs);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RootCertificates> {
+        self.profiles.get(name)
+    }
+}
+
+/// SHA-256 hash of a certificate's DER-encoded SubjectPublicKeyInfo.
+pub type SpkiHash = [u8; 32];
+
+/// Trust-on-first-use record of the public keys that hosts have presented.
+///
+/// This is checked in addition to normal certificate validation: after a
+/// successful connection to a host, later connections to the same host are
+/// expected to present a leaf certificate with the same public key. A
+/// different key is reported as [`TransportConnectError::CertificateChanged`]
+/// unless it's one of the known rotations given to [`Self::new`], in which case
+/// it replaces the recorded one.
+///
+/// Clones share the same record.
+#[derive(Clone, Debug, Default)]
+pub struct CertificateHistory {
+    seen: Arc<Mutex<HashMap<Arc<str>, SpkiHash>>>,
+    allowed_rotations: Arc<HashSet<SpkiHash>>,
+}
+
+impl CertificateHistory {
+    pub fn new(allowed_rotations: impl IntoIterator<Item = SpkiHash>) -> Self {
+        Self {
+            seen: Default::default(),
+            allowed_rotations: Arc::new(allowed_rotations.into_iter().collect()),
+        }
+    }
+
+    /// Computes the [`SpkiHash`] of a DER-encoded certificate.
+    pub fn spki_hash_of_der(certificate_der: &[u8]) -> Result<SpkiHash, Error> {
+        Self::spki_hash(&X509::from_der(certificate_der)?)
+    }
+
+    fn spki_hash(certificate: &X509Ref) -> Result<SpkiHash, Error> {
+        let spki = certificate.public_key()?.public_key_to_der()?;
+        let digest = boring_signal::hash::hash(MessageDigest::sha256(), &spki)?;
+        Ok((*digest).try_into().expect("SHA-256 output is 32 bytes"))
+    }
+
+    /// Checks the leaf certificate of a completed handshake with `host`
+    /// against the record, and records it if it's the first one seen.
+    pub(crate) fn check(
+        &self,
+        host: &str,
+        certificate: &X509Ref,
+    ) -> Result<(), TransportConnectError> {
+        let hash = Self::spki_hash(certificate)?;
+
+        let mut seen = self.seen.lock().expect("not poisoned");
+        match seen.get(host) {
+            Some(previous) if *previous == hash => {}
+            None => {
+                seen.insert(host.into(), hash);
+            }
+            Some(_) if self.allowed_rotations.contains(&hash) => {
+                log::info!(
+                    "certificate for [{}] changed to an expected rotation",
+                    log_safe_domain(host)
+                );
+                seen.insert(host.into(), hash);
+            }
+            Some(_) => {
+                log::warn!(
+                    "certificate for [{}] changed since the last connection",
+                    log_safe_domain(host)
+                );
+                return Err(TransportConnectError::CertificateChanged);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `certificate`, the leaf certificate of a completed handshake,
+/// is valid for `expected_name`, which can be a domain or an IP address.
+///
+/// This is independent of the hostname check done during the handshake,
+/// which uses the SNI. The name is matched against the certificate's subject
+/// alternative names, with a wildcard standing for a single leftmost label.
+pub(crate) fn check_certificate_name(
+    expected_name: &str,
+    certificate: &X509Ref,
+) -> Result<(), TransportConnectError> {
+    let expected_ip = expected_name.parse::<IpAddr>().ok();
+    let matches =
+        certificate
+            .subject_alt_names()
+            .into_iter()
+            .flatten()
+            .any(|name| match expected_ip {
+                Some(IpAddr::V4(ip)) => name.ipaddress() == Some(&ip.octets()[..]),
+                Some(IpAddr::V6(ip)) => name.ipaddress() == Some(&ip.octets()[..]),
+                None => name
+                    .dnsname()
+                    .is_some_and(|pattern| dns_name_matches(pattern, expected_name)),
+            });
+    if !matches {
+        log::warn!(
+            "certificate is not valid for [{}]",
+            log_safe_domain(expected_name)
+        );
+        return Err(TransportConnectError::CertNameMismatch);
+    }
+    Ok(())
+}
+
+fn dns_name_matches(pattern: &str, name: &str) -> bool {
+    let name = name.trim_end_matches('.');
+    match pattern.strip_prefix("*.") {
+        Some(parent) => name
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(parent)),
+        None => pattern.eq_ignore_ascii_case(name),
+    }
+}
+
 /// Configures [rustls_platform_verifier] as a BoringSSL [custom verify
 /// callback](boring::ssl::SslContextBuilder::set_custom_verify_callback).
 fn set_up_platform_verifier(
@@ -175,6 +322,7 @@ mod test {
     use assert_matches::assert_matches;
     use boring_signal::ssl::{ErrorCode, SslConnector, SslMethod};
     use rustls::RootCertStore;
+    use test_case::test_case;
     use tokio::net::TcpStream;
 
     use super::*;
@@ -183,6 +331,16 @@ mod test {
         localhost_http_server, make_http_request_response_over, SERVER_CERTIFICATE, SERVER_HOSTNAME,
     };
 
+    #[test_case("backend.signal.org", "backend.signal.org" => true)]
+    #[test_case("Backend.Signal.org", "backend.signal.org." => true; "case and trailing dot")]
+    #[test_case("*.signal.org", "backend.signal.org" => true)]
+    #[test_case("*.signal.org", "signal.org" => false; "wildcard needs a label")]
+    #[test_case("*.signal.org", "a.backend.signal.org" => false; "wildcard is one label")]
+    #[test_case("front.signal.org", "backend.signal.org" => false)]
+    fn certificate_name_matching(pattern: &str, name: &str) -> bool {
+        dns_name_matches(pattern, name)
+    }
+
     #[tokio::test]
     async fn verify_certificate_via_rustls() {
         let (addr, server) = localhost_http_server();
@@ -6,21 +6,30 @@
 use std::cmp::{max, min};
 use std::fmt::Debug;
 use std::future::Future;
+use std::num::NonZeroUsize;
 use std::ops::Add;
 use std::panic::RefUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
+use futures_util::stream::FuturesUnordered;
+use futures_util::{FutureExt as _, StreamExt as _};
 use itertools::Itertools;
 use tokio::sync::Mutex;
 use tokio::time::{timeout_at, Instant};
 
+use crate::attempt_budget::AttemptBudget;
 use crate::errors::LogSafeDisplay;
 use crate::timeouts::{CONNECTION_ROUTE_COOLDOWN_INTERVALS, CONNECTION_ROUTE_MAX_COOLDOWN};
+use crate::utils::rng::RngProvider;
 use crate::utils::NetworkChangeEvent;
 use crate::ConnectionParams;
 
+pub mod journal;
+use journal::{ConnectionEvent, ConnectionJournal};
+
 /// Represents the outcome of the connection attempt
 #[derive(Debug)]
 pub enum ConnectionAttemptOutcome<T, E> {
@@ -33,6 +42,19 @@ pub enum ConnectionAttemptOutcome<T, E> {
     /// Connection was not attempted because connection manager is in a cooldown state.
     /// Next attempt will happen no earlier than the `Instant` held by this variant.
     WaitUntil(Instant),
+    /// Every route was attempted, and the last attempt on each of them failed with an error.
+    ///
+    /// Only produced by [`MultiRouteConnectionManager`]. `routes` pairs the description of each
+    /// route with its error, in the order the routes gave up; `retry_at` is the earliest any of
+    /// them can be tried again.
+    ///
+    /// This is for callers of the manager itself. Chat and enclave connections are made through
+    /// [`route::connect`](crate::route::connect) instead, which reports the same kind of breakdown
+    /// in [`ConnectError::AllAttemptsFailed`](crate::route::ConnectError::AllAttemptsFailed).
+    AllRoutesFailed {
+        routes: Vec<(String, E)>,
+        retry_at: Instant,
+    },
 }
 
 /// Policy object that decides how and when to connect.
@@ -177,6 +199,32 @@ impl ThrottlingConnectionManagerState {
     }
 }
 
+/// A point-in-time view of the backoff of a [`SingleRouteThrottlingConnectionManager`], e.g. for
+/// showing "retrying in N seconds".
+///
+/// See [`SingleRouteThrottlingConnectionManager::cooldown`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CooldownSnapshot {
+    /// How many attempts in a row have failed.
+    ///
+    /// This stops counting once the longest cooldown interval is reached.
+    pub consecutive_failures: u16,
+    /// When the next attempt will be made, or `None` if one can be made right away.
+    pub next_attempt: Option<Instant>,
+}
+
+impl CooldownSnapshot {
+    pub fn is_in_cooldown(&self) -> bool {
+        self.next_attempt.is_some()
+    }
+
+    /// How long until the next attempt can be made, as of `now`.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.next_attempt
+            .map_or(Duration::ZERO, |next_attempt| next_attempt - now)
+    }
+}
+
 /// A connection manager that only attempts one route (i.e. one [ConnectionParams]).
 ///
 /// It keeps track of consecutive failed attempts and after each failure waits for a duration
@@ -192,15 +240,233 @@ pub struct SingleRouteThrottlingConnectionManager<C = ConnectionParams> {
 ///
 /// It iterates over them until it can find one that results in a successful connection attempt.
 /// If none did, it will return [ConnectionAttemptOutcome::WaitUntil] with the minimum possible
-/// cooldown time (based on cooldown times returned by all throttling connection managers).
+/// cooldown time (based on cooldown times returned by all throttling connection managers), or
+/// [ConnectionAttemptOutcome::AllRoutesFailed] if every route's last attempt failed with an error.
+///
+/// By default the routes are tried in the order they were given; see
+/// [`MultiRouteConnectionManager::with_weighted_random_selection`] for spreading connections
+/// across them instead, [`MultiRouteConnectionManager::with_concurrent_attempts`] for trying
+/// more than one at a time, [`MultiRouteConnectionManager::with_journal`] for keeping a
+/// record of what happened, [`MultiRouteConnectionManager::with_success_rate_tracking`] for
+/// keeping track of how reliable each route has been, and
+/// [`MultiRouteConnectionManager::with_attempt_budget`] for sharing a limit on attempts in flight.
 #[derive(Clone)]
 pub struct MultiRouteConnectionManager<M = SingleRouteThrottlingConnectionManager> {
     route_managers: Vec<M>,
+    weighted_selection: Option<WeightedSelection>,
+    concurrent_attempts: Option<ConcurrentAttempts>,
+    journaling: Option<Journaling>,
+    success_rates: Option<SuccessRates>,
+    attempt_budget: Option<AttemptBudget>,
+}
+
+/// How [`MultiRouteConnectionManager::with_success_rate_tracking`] keeps and uses success rates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SuccessRateTracking {
+    /// How much each outcome moves a route's success rate, between 0 (not at all) and 1 (the rate
+    /// is just the latest outcome).
+    pub smoothing: f64,
+    /// If `true`, routes that have been more reliable are tried before less reliable ones.
+    pub prefer_reliable_routes: bool,
+}
+
+#[derive(Clone)]
+struct SuccessRates {
+    tracking: SuccessRateTracking,
+    /// One for each route; `None` until an attempt on the route finishes.
+    rates: Arc<std::sync::Mutex<Vec<Option<f64>>>>,
+}
+
+impl SuccessRates {
+    fn record(&self, index: usize, succeeded: bool) {
+        let sample = if succeeded { 1.0 } else { 0.0 };
+        let mut rates = self.rates.lock().expect("not poisoned");
+        let rate = &mut rates[index];
+        *rate = Some(rate.map_or(sample, |rate| {
+            rate + self.tracking.smoothing * (sample - rate)
+        }));
+    }
+
+    fn snapshot(&self) -> Vec<Option<f64>> {
+        self.rates.lock().expect("not poisoned").clone()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ConcurrentAttempts {
+    limit: NonZeroUsize,
+    stagger: Duration,
+}
+
+#[derive(Clone)]
+struct WeightedSelection {
+    weights: Arc<[u32]>,
+    rng: RngProvider,
+}
+
+#[derive(Clone)]
+struct Journaling {
+    journal: ConnectionJournal,
+    has_connected: Arc<AtomicBool>,
 }
 
 impl<M> MultiRouteConnectionManager<M> {
     pub fn new(route_managers: Vec<M>) -> Self {
-        Self { route_managers }
+        Self {
+            route_managers,
+            weighted_selection: None,
+            concurrent_attempts: None,
+            journaling: None,
+            success_rates: None,
+            attempt_budget: None,
+        }
+    }
+
+    /// Keeps an exponentially weighted moving average of each route's connection success rate.
+    ///
+    /// A route's rate is updated each time an attempt on it connects, fails with an intermittent
+    /// error, or times out. Errors that say the server was reached but wouldn't accept the
+    /// connection, like fatal errors and retry-afters, aren't held against the route. Clones of
+    /// this manager share the same rates. See [`Self::success_rates`].
+    pub fn with_success_rate_tracking(self, tracking: SuccessRateTracking) -> Self {
+        Self {
+            success_rates: Some(SuccessRates {
+                tracking,
+                rates: Arc::new(std::sync::Mutex::new(vec![None; self.route_managers.len()])),
+            }),
+            ..self
+        }
+    }
+
+    /// The success rate of each route, between 0 and 1, in the order the routes were given.
+    ///
+    /// Routes without a finished attempt have no rate, and neither does any route unless
+    /// [tracking](Self::with_success_rate_tracking) is enabled.
+    pub fn success_rates(&self) -> Vec<Option<f64>> {
+        match &self.success_rates {
+            Some(success_rates) => success_rates.snapshot(),
+            None => vec![None; self.route_managers.len()],
+        }
+    }
+
+    /// Tries up to `limit` routes at the same time instead of one after another.
+    ///
+    /// Routes are still started in order, but each one only waits `stagger` for the attempts
+    /// before it (or until one of them fails) before starting. As soon as any attempt succeeds,
+    /// the others are cancelled.
+    pub fn with_concurrent_attempts(self, limit: NonZeroUsize, stagger: Duration) -> Self {
+        Self {
+            concurrent_attempts: Some(ConcurrentAttempts { limit, stagger }),
+            ..self
+        }
+    }
+
+    /// Holds off starting each route until `budget` has room for another attempt.
+    ///
+    /// The permits are taken by whatever makes the connections, like a
+    /// [`DirectConnector`](crate::tcp_ssl::DirectConnector) given the same budget, for each socket
+    /// it connects. This keeps routes from being started, and their connection timeouts from
+    /// running out, while all of them are in use.
+    pub fn with_attempt_budget(self, budget: AttemptBudget) -> Self {
+        Self {
+            attempt_budget: Some(budget),
+            ..self
+        }
+    }
+
+    /// Records the connection attempts made through this manager, their outcomes, and switches
+    /// between routes in `journal`.
+    pub fn with_journal(self, journal: ConnectionJournal) -> Self {
+        Self {
+            journaling: Some(Journaling {
+                journal,
+                has_connected: Default::default(),
+            }),
+            ..self
+        }
+    }
+
+    /// Picks the order of routes randomly for each connection attempt.
+    ///
+    /// The first route is chosen with probability proportional to its weight (the weight at the
+    /// same index in `weights`), the next among the remaining routes in the same way, and so on.
+    /// Since routes in cooldown are skipped, the route that ends up being used is effectively
+    /// chosen by weight among the currently healthy ones. Routes with a weight of zero, or
+    /// without a weight at all, are only tried after all the others, in their original order.
+    ///
+    /// Passing a [seeded](RngProvider::seeded) `rng` makes the choices reproducible.
+    pub fn with_weighted_random_selection(
+        self,
+        weights: impl IntoIterator<Item = u32>,
+        rng: RngProvider,
+    ) -> Self {
+        Self {
+            weighted_selection: Some(WeightedSelection {
+                weights: weights.into_iter().collect(),
+                rng,
+            }),
+            ..self
+        }
+    }
+
+    /// The indices of the route managers, in the order they should be tried.
+    ///
+    /// If reliable routes are preferred, routes are ordered by success rate, or with weighted
+    /// selection, their weights are scaled by it. Routes without a rate yet count as reliable so
+    /// that they get tried.
+    fn attempt_order(&self) -> Vec<usize> {
+        let reliability = self
+            .success_rates
+            .as_ref()
+            .filter(|success_rates| success_rates.tracking.prefer_reliable_routes)
+            .map(SuccessRates::snapshot);
+        let reliability = |index: usize| {
+            reliability
+                .as_ref()
+                .map(|rates| rates[index].unwrap_or(1.0))
+        };
+
+        let Some(WeightedSelection { weights, rng }) = &self.weighted_selection else {
+            let mut order = (0..self.route_managers.len()).collect_vec();
+            // The sort is stable, so routes that are equally reliable stay in the given order.
+            order.sort_by(|&a, &b| {
+                let rate = |index| reliability(index).unwrap_or(1.0);
+                rate(b).total_cmp(&rate(a))
+            });
+            return order;
+        };
+        let weight = |index: usize| {
+            let weight = u64::from(weights.get(index).copied().unwrap_or(0));
+            match reliability(index) {
+                None => weight,
+                // The rate is between 0 and 1, so this is at most 1000.
+                #[allow(clippy::cast_possible_truncation)]
+                Some(rate) => weight * (rate * 1000.0).round() as u64,
+            }
+        };
+
+        let mut remaining = (0..self.route_managers.len()).collect_vec();
+        let mut order = Vec::with_capacity(remaining.len());
+        while !remaining.is_empty() {
+            let total_weight: u64 = remaining.iter().copied().map(weight).sum();
+            if total_weight == 0 {
+                order.append(&mut remaining);
+                break;
+            }
+            let mut target = rng.random_range(0..total_weight);
+            let position = remaining
+                .iter()
+                .position(|&index| match target.checked_sub(weight(index)) {
+                    Some(rest) => {
+                        target = rest;
+                        false
+                    }
+                    None => true,
+                })
+                .expect("target is less than the total weight");
+            order.push(remaining.remove(position));
+        }
+        order
     }
 }
 
@@ -227,22 +493,107 @@ where
         Fun: Fn(&'a ConnectionParams) -> Fut + Send + Sync,
         Fut: Future<Output = Result<T, E>> + Send,
     {
+        let journal = self.journaling.as_ref().map(
+            |Journaling {
+                 journal,
+                 has_connected,
+             }| {
+                if has_connected.load(Ordering::Relaxed) {
+                    journal.record(ConnectionEvent::Reconnecting);
+                }
+                journal
+            },
+        );
+
         let mut wait_until = None;
-        for route_manager in self.route_managers.iter() {
-            match retry_connect_until_cooldown(route_manager, &connection_fn).await {
-                Ok(t) => return ConnectionAttemptOutcome::Attempted(Ok(t)),
-                Err(RetryError::WaitUntil(i)) => {
-                    wait_until = Some(
-                        wait_until.map_or(i, |earliest_retry| Instant::min(i, earliest_retry)),
-                    );
+        let mut previous_route: Option<Arc<str>> = None;
+        let mut start_attempt = |index: usize| {
+            let route_manager = &self.route_managers[index];
+            if let Some(journal) = journal {
+                let route: Arc<str> = route_manager.describe_for_logging().into();
+                if let Some(from) = previous_route.replace(Arc::clone(&route)) {
+                    journal.record(ConnectionEvent::RouteSwitched { from, to: route });
+                }
+            }
+            let success_rate = self.success_rates.as_ref().map(|rates| (rates, index));
+            retry_connect_until_cooldown(route_manager, &connection_fn, journal, success_rate)
+                .map(move |result| (index, result))
+        };
+        let mut failures = Vec::new();
+        let mut every_route_failed = true;
+        let mut handle_result = |(index, result): (usize, Result<T, RetryError<E>>)| match result {
+            Ok(t) => {
+                if let Some(Journaling { has_connected, .. }) = &self.journaling {
+                    has_connected.store(true, Ordering::Relaxed);
+                }
+                Some(ConnectionAttemptOutcome::Attempted(Ok(t)))
+            }
+            Err(RetryError::WaitUntil(i, last_error)) => {
+                wait_until =
+                    Some(wait_until.map_or(i, |earliest_retry| Instant::min(i, earliest_retry)));
+                match last_error {
+                    Some(e) => {
+                        failures.push((self.route_managers[index].describe_for_logging(), e))
+                    }
+                    None => every_route_failed = false,
+                }
+                None
+            }
+            Err(RetryError::Fatal(e)) => Some(ConnectionAttemptOutcome::Attempted(Err(e))),
+        };
+
+        let mut order = self.attempt_order().into_iter().peekable();
+        match self.concurrent_attempts {
+            None => {
+                for index in order {
+                    if let Some(budget) = &self.attempt_budget {
+                        budget.wait_for_room().await;
+                    }
+                    if let Some(outcome) = handle_result(start_attempt(index).await) {
+                        return outcome;
+                    }
+                }
+            }
+            Some(ConcurrentAttempts { limit, stagger }) => {
+                // Dropping the attempts still in flight when returning cancels them.
+                let mut in_flight = FuturesUnordered::new();
+                let mut next_start = Instant::now();
+                loop {
+                    let can_start = in_flight.len() < limit.get() && order.peek().is_some();
+                    let ready_to_start = async {
+                        tokio::time::sleep_until(next_start).await;
+                        if let Some(budget) = &self.attempt_budget {
+                            budget.wait_for_room().await;
+                        }
+                    };
+                    tokio::select! {
+                        () = ready_to_start, if can_start => {
+                            let index = order.next().expect("checked above");
+                            in_flight.push(start_attempt(index));
+                            next_start = Instant::now() + stagger;
+                        }
+                        Some(result) = in_flight.next() => {
+                            if let Some(outcome) = handle_result(result) {
+                                return outcome;
+                            }
+                            // Don't hold up the next route waiting for one that has failed.
+                            next_start = Instant::now();
+                        }
+                        else => break,
+                    }
                 }
-                Err(RetryError::Fatal(e)) => return ConnectionAttemptOutcome::Attempted(Err(e)),
             }
         }
-        wait_until.map_or(
-            ConnectionAttemptOutcome::TimedOut,
-            ConnectionAttemptOutcome::WaitUntil,
-        )
+        match wait_until {
+            None => ConnectionAttemptOutcome::TimedOut,
+            Some(retry_at) if every_route_failed && !failures.is_empty() => {
+                ConnectionAttemptOutcome::AllRoutesFailed {
+                    routes: failures,
+                    retry_at,
+                }
+            }
+            Some(wait_until) => ConnectionAttemptOutcome::WaitUntil(wait_until),
+        }
     }
 
     fn describe_for_logging(&self) -> String {
@@ -257,8 +608,9 @@ where
 }
 
 pub enum RetryError<E> {
-    /// Connection can be attempted again at a given Instant
-    WaitUntil(Instant),
+    /// Connection can be attempted again at a given Instant, along with the error the last
+    /// attempt failed with, if it did
+    WaitUntil(Instant, Option<E>),
     /// Connection failed due to an issue that retries will not solve
     Fatal(E),
 }
@@ -285,6 +637,8 @@ pub trait ErrorClassifier {
 async fn retry_connect_until_cooldown<'a, T, E, Fun, Fut>(
     route_manager: &'a impl ConnectionManager,
     connection_fn: &Fun,
+    journal: Option<&ConnectionJournal>,
+    success_rate: Option<(&SuccessRates, usize)>,
 ) -> Result<T, RetryError<E>>
 where
     T: Send,
@@ -292,13 +646,41 @@ where
     Fun: Fn(&'a ConnectionParams) -> Fut + Send + Sync,
     Fut: Future<Output = Result<T, E>> + Send,
 {
+    let route: Arc<str> = route_manager.describe_for_logging().into();
+    let record = |event: ConnectionEvent| {
+        if let Some(journal) = journal {
+            journal.record(event);
+        }
+    };
+    let record_outcome = |succeeded: bool| {
+        if let Some((success_rates, index)) = success_rate {
+            success_rates.record(index, succeeded);
+        }
+    };
+    let journaled_connection_fn = |connection_params: &'a ConnectionParams| {
+        record(ConnectionEvent::AttemptStarted {
+            route: Arc::clone(&route),
+        });
+        connection_fn(connection_params)
+    };
+    let mut last_error = None;
     loop {
-        let result = route_manager.connect_or_wait(connection_fn).await;
+        let result = route_manager
+            .connect_or_wait(&journaled_connection_fn)
+            .await;
         match result {
             ConnectionAttemptOutcome::Attempted(Ok(r)) => {
+                record(ConnectionEvent::Connected {
+                    route: Arc::clone(&route),
+                });
+                record_outcome(true);
                 return Ok(r);
             }
             ConnectionAttemptOutcome::Attempted(Err(e)) => {
+                record(ConnectionEvent::Failed {
+                    route: Arc::clone(&route),
+                    reason: e.to_string().into(),
+                });
                 let log_error = |e: &E, when: &'static str| {
                     log::debug!("Connection attempt failed with a non-fatal error: {e:?}, will retry {when}");
                     log::info!(
@@ -310,7 +692,9 @@ where
                 match e.classify() {
                     ErrorClass::Fatal => return Err(RetryError::Fatal(e)),
                     ErrorClass::Intermittent => {
+                        record_outcome(false);
                         log_error(&e, "immediately");
+                        last_error = Some(e);
                         continue;
                     }
                     ErrorClass::RetryAt(when) => {
@@ -321,22 +705,41 @@ where
                         // that gives up, and that higher-level operation won't be informed of the
                         // RetryAt. We can revisit that in the ws2 implementation.
                         tokio::time::sleep_until(when).await;
+                        last_error = Some(e);
                         continue;
                     }
                 }
             }
             ConnectionAttemptOutcome::TimedOut => {
+                record(ConnectionEvent::TimedOut {
+                    route: Arc::clone(&route),
+                });
+                record_outcome(false);
                 log::info!(
                     "Connection attempt timed out ({:?})",
                     route_manager.describe_for_logging()
                 );
+                last_error = None;
                 continue;
             }
-            ConnectionAttemptOutcome::WaitUntil(i) => return Err(RetryError::WaitUntil(i)),
+            ConnectionAttemptOutcome::WaitUntil(i) => {
+                return Err(RetryError::WaitUntil(i, last_error))
+            }
+            ConnectionAttemptOutcome::AllRoutesFailed { retry_at, .. } => {
+                return Err(RetryError::WaitUntil(retry_at, None))
+            }
         }
     }
 }
 
+impl<C> MultiRouteConnectionManager<SingleRouteThrottlingConnectionManager<C>> {
+    /// The backoff state of each route, in the order the routes were given.
+    pub async fn cooldowns(&self) -> Vec<CooldownSnapshot> {
+        futures_util::future::join_all(self.route_managers.iter().map(|manager| manager.cooldown()))
+            .await
+    }
+}
+
 impl<C> SingleRouteThrottlingConnectionManager<C> {
     pub fn new(
         connection_params: C,
@@ -356,6 +759,29 @@ impl<C> SingleRouteThrottlingConnectionManager<C> {
         }
     }
 
+    /// The current backoff state.
+    ///
+    /// A network change since the last attempt resets the cooldown, so it's reported as over even
+    /// though that's only recorded once the next attempt is made.
+    pub async fn cooldown(&self) -> CooldownSnapshot {
+        let now = Instant::now();
+        let guard = self.state.lock().await;
+        let network_changed = guard
+            .network_change_event
+            .has_changed()
+            .is_ok_and(|changed| changed);
+        if network_changed {
+            return CooldownSnapshot {
+                consecutive_failures: 0,
+                next_attempt: None,
+            };
+        }
+        CooldownSnapshot {
+            consecutive_failures: guard.consecutive_fails,
+            next_attempt: (guard.next_attempt > now).then_some(guard.next_attempt),
+        }
+    }
+
     pub(crate) async fn connect_or_wait<'a, T, E, Fun, Fut>(
         &'a self,
         connection_fn: Fun,
@@ -426,7 +852,7 @@ impl ConnectionManager for SingleRouteThrottlingConnectionManager {
     }
 
     fn describe_for_logging(&self) -> String {
-        self.connection_params.route_type.to_string()
+        self.connection_params.route_label()
     }
 }
 
@@ -454,6 +880,8 @@ mod test {
 
     const ROUTE_2: &str = "route2.signal.org";
 
+    const ROUTE_3: &str = "route3.signal.org";
+
     #[tokio::test]
     async fn single_route_successfull_attempts() {
         let manager = SingleRouteThrottlingConnectionManager::new(
@@ -552,6 +980,57 @@ mod test {
         assert_matches!(attempt_outcome, ConnectionAttemptOutcome::Attempted(Ok(())));
     }
 
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn single_route_manager_reports_cooldown() {
+        let manager = SingleRouteThrottlingConnectionManager::new(
+            example_connection_params("chat.staging.signal.org"),
+            TIMEOUT_DURATION,
+            &no_network_change_events(),
+        );
+        assert_eq!(
+            manager.cooldown().await,
+            CooldownSnapshot {
+                consecutive_failures: 0,
+                next_attempt: None,
+            }
+        );
+
+        // The first failure is retried right away; the backoff starts with the second.
+        for _ in 0..2 {
+            time::advance(TIME_ADVANCE_VALUE).await;
+            let _attempt_outcome: ConnectionAttemptOutcome<(), TestError> = manager
+                .connect_or_wait(|_| future::ready(Err(TestError::Expected)))
+                .await;
+        }
+        let cooldown = manager.cooldown().await;
+        assert!(cooldown.is_in_cooldown(), "{cooldown:?}");
+        assert_eq!(cooldown.consecutive_failures, 2);
+        let remaining = cooldown.remaining(Instant::now());
+        assert!(
+            remaining > Duration::ZERO && remaining <= CONNECTION_ROUTE_MAX_COOLDOWN,
+            "{remaining:?}"
+        );
+        assert_matches!(
+            manager
+                .connect_or_wait(|_| future::ready(Ok::<(), TestError>(())))
+                .await,
+            ConnectionAttemptOutcome::WaitUntil(next_attempt) if Some(next_attempt) == cooldown.next_attempt
+        );
+
+        time::advance(remaining).await;
+        assert!(!manager.cooldown().await.is_in_cooldown());
+        let attempt_outcome: ConnectionAttemptOutcome<(), TestError> =
+            manager.connect_or_wait(|_| future::ready(Ok(()))).await;
+        assert_matches!(attempt_outcome, ConnectionAttemptOutcome::Attempted(Ok(())));
+        assert_eq!(
+            manager.cooldown().await,
+            CooldownSnapshot {
+                consecutive_failures: 0,
+                next_attempt: None,
+            }
+        );
+    }
+
     #[tokio::test(flavor = "current_thread", start_paused = true)]
     async fn single_route_manager_resets_cooldown_on_network_changed() {
         let (network_change_tx, network_change_rx) = tokio::sync::watch::channel(());
@@ -738,6 +1217,56 @@ mod test {
         validate_expected_route(&multi_route_manager, true, ROUTE_1).await;
     }
 
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn multi_route_manager_concurrent_attempts_cancel_losers() {
+        const STAGGER: Duration = Duration::from_millis(100);
+        let managers = [ROUTE_1, ROUTE_2, ROUTE_3].map(|route| {
+            SingleRouteThrottlingConnectionManager::new(
+                example_connection_params(route),
+                TIMEOUT_DURATION,
+                &no_network_change_events(),
+            )
+        });
+        let multi_route_manager = MultiRouteConnectionManager::new(managers.into())
+            .with_concurrent_attempts(nonzero!(2usize), STAGGER);
+
+        let started = std::sync::Mutex::new(vec![]);
+        let completed = std::sync::Mutex::new(vec![]);
+        let start = Instant::now();
+        let attempt_outcome: ConnectionAttemptOutcome<Arc<str>, TestError> = multi_route_manager
+            .connect_or_wait(|connection_params| {
+                let (started, completed) = (&started, &completed);
+                async move {
+                    let route = Arc::clone(&connection_params.http_host);
+                    started.lock().unwrap().push(Arc::clone(&route));
+                    // The first route is slower than the second, even though the second one
+                    // starts later.
+                    let handshake_time = match &*route {
+                        ROUTE_1 => TIMEOUT_DURATION * 4 / 5,
+                        _ => TIMEOUT_DURATION * 3 / 10,
+                    };
+                    time::sleep(handshake_time).await;
+                    completed.lock().unwrap().push(Arc::clone(&route));
+                    Ok(route)
+                }
+            })
+            .await;
+
+        assert_matches!(
+            attempt_outcome,
+            ConnectionAttemptOutcome::Attempted(Ok(route)) if &*route == ROUTE_2
+        );
+        assert_eq!(start.elapsed(), STAGGER + TIMEOUT_DURATION * 3 / 10);
+
+        // Give the cancelled attempt long enough to have finished if it were still running.
+        time::advance(TIMEOUT_DURATION).await;
+        assert_eq!(
+            *started.lock().unwrap(),
+            [Arc::<str>::from(ROUTE_1), ROUTE_2.into()]
+        );
+        assert_eq!(*completed.lock().unwrap(), [Arc::<str>::from(ROUTE_2)]);
+    }
+
     #[derive(Clone, Debug)]
     struct CooldownAfterSomeAttempts {
         attempts_until_cooldown: u16,
@@ -869,7 +1398,7 @@ mod test {
                 simulate_connect(connection_params, Some(TestError::Expected))
             })
             .await;
-        assert_matches!(res, ConnectionAttemptOutcome::WaitUntil(_));
+        assert_matches!(res, ConnectionAttemptOutcome::AllRoutesFailed { .. });
         assert_eq!(
             first_manager_attempts_until_cooldown + 1,
             first_manager.attempts_made.load(Ordering::Relaxed)
@@ -880,6 +1409,39 @@ mod test {
         );
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn multi_route_manager_reports_how_each_route_failed() {
+        let first_manager = CooldownAfterSomeAttempts::new(2, example_connection_params(ROUTE_1));
+        let second_manager = CooldownAfterSomeAttempts::new(1, example_connection_params(ROUTE_2));
+        let multi_route_manager =
+            MultiRouteConnectionManager::new(vec![first_manager, second_manager]);
+
+        let now = Instant::now();
+        let res: ConnectionAttemptOutcome<(), TestError> = multi_route_manager
+            .connect_or_wait(|connection_params| {
+                future::ready(Err(TestError::Unexpected(
+                    match &*connection_params.http_host {
+                        ROUTE_1 => "first route failed",
+                        _ => "second route failed",
+                    },
+                )))
+            })
+            .await;
+
+        let (routes, retry_at) = assert_matches!(
+            res,
+            ConnectionAttemptOutcome::AllRoutesFailed { routes, retry_at } => (routes, retry_at)
+        );
+        assert_eq!(retry_at, now + CONNECTION_ROUTE_MAX_COOLDOWN);
+        assert_matches!(
+            &routes[..],
+            [
+                (first_route, TestError::Unexpected("first route failed")),
+                (second_route, TestError::Unexpected("second route failed")),
+            ] if first_route.contains(ROUTE_1) && second_route.contains(ROUTE_2)
+        );
+    }
+
     #[tokio::test(start_paused = true)]
     async fn multi_route_manager_propagates_post_connection_failure() {
         let connection_params = example_connection_params(ROUTE_1);
@@ -931,6 +1493,173 @@ mod test {
         assert_eq!(wait_until, now + SHORT_DELAY);
     }
 
+    fn weighted_route_manager(weights: [u32; 2], seed: u64) -> MultiRouteConnectionManager {
+        let managers = [ROUTE_1, ROUTE_2].map(|route| {
+            SingleRouteThrottlingConnectionManager::new(
+                example_connection_params(route),
+                TIMEOUT_DURATION,
+                &no_network_change_events(),
+            )
+        });
+        MultiRouteConnectionManager::new(managers.into())
+            .with_weighted_random_selection(weights, RngProvider::seeded(seed))
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn multi_route_manager_weighted_selection_follows_weights() {
+        const CONNECTS: usize = 1000;
+        let multi_route_manager = weighted_route_manager([1, 3], 0x5eed);
+
+        let mut route_1_count = 0;
+        for _ in 0..CONNECTS {
+            let attempt_outcome: ConnectionAttemptOutcome<&str, TestError> = multi_route_manager
+                .connect_or_wait(|connection_params| simulate_connect(connection_params, None))
+                .await;
+            let route = assert_matches!(attempt_outcome, ConnectionAttemptOutcome::Attempted(Ok(route)) => route);
+            if route == ROUTE_1 {
+                route_1_count += 1;
+            }
+        }
+
+        // The expected count is 250; anything this far off would be very unlikely.
+        assert!((200..300).contains(&route_1_count), "{route_1_count}");
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn multi_route_manager_weighted_selection_falls_back_to_other_routes() {
+        // Route 1 is heavily favored, but it's failing, so route 2 is always used.
+        let multi_route_manager = weighted_route_manager([100, 1], 0x5eed);
+        for _ in 0..MANY_ATTEMPTS {
+            validate_expected_route(&multi_route_manager, false, ROUTE_2).await;
+        }
+    }
+
+    #[test]
+    fn multi_route_manager_weighted_selection_tries_unweighted_routes_last() {
+        let multi_route_manager = MultiRouteConnectionManager::new(vec![(); 4])
+            .with_weighted_random_selection([0, 1], RngProvider::seeded(0));
+        for _ in 0..10 {
+            assert_eq!(multi_route_manager.attempt_order(), [1, 0, 2, 3]);
+        }
+
+        let ordered = MultiRouteConnectionManager::new(vec![(); 3]);
+        assert_eq!(ordered.attempt_order(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn multi_route_manager_orders_routes_by_success_rate() {
+        let outcomes: [&[bool]; 2] = [&[false, false], &[true, false, true]];
+        let manager_with = |prefer_reliable_routes| {
+            let multi_route_manager = MultiRouteConnectionManager::new(vec![(); 3])
+                .with_success_rate_tracking(SuccessRateTracking {
+                    smoothing: 0.5,
+                    prefer_reliable_routes,
+                });
+            let success_rates = multi_route_manager.success_rates.as_ref().expect("enabled");
+            for (index, outcomes) in outcomes.iter().enumerate() {
+                for &succeeded in *outcomes {
+                    success_rates.record(index, succeeded);
+                }
+            }
+            multi_route_manager
+        };
+
+        let preferring = manager_with(true);
+        assert_eq!(preferring.success_rates(), [Some(0.0), Some(0.75), None]);
+        // The last route hasn't been tried yet, so it isn't held back.
+        assert_eq!(preferring.attempt_order(), [2, 1, 0]);
+
+        let tracking_only = manager_with(false);
+        assert_eq!(tracking_only.success_rates(), [Some(0.0), Some(0.75), None]);
+        assert_eq!(tracking_only.attempt_order(), [0, 1, 2]);
+
+        let untracked = MultiRouteConnectionManager::new(vec![(); 2]);
+        assert_eq!(untracked.success_rates(), [None, None]);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn multi_route_manager_prefers_routes_that_have_been_reliable() {
+        let managers = [ROUTE_1, ROUTE_2].map(|route| {
+            SingleRouteThrottlingConnectionManager::new(
+                example_connection_params(route),
+                TIMEOUT_DURATION,
+                &no_network_change_events(),
+            )
+        });
+        let multi_route_manager = MultiRouteConnectionManager::new(managers.into())
+            .with_success_rate_tracking(SuccessRateTracking {
+                smoothing: 0.25,
+                prefer_reliable_routes: true,
+            });
+
+        validate_expected_route(&multi_route_manager, false, ROUTE_2).await;
+        assert_eq!(multi_route_manager.success_rates(), [Some(0.0), Some(1.0)]);
+
+        // Route 1 is out of its cooldown and would work now, but route 2 has
+        // been more reliable.
+        time::advance(CONNECTION_ROUTE_MAX_COOLDOWN).await;
+        validate_expected_route(&multi_route_manager, true, ROUTE_2).await;
+        assert_eq!(multi_route_manager.success_rates(), [Some(0.0), Some(1.0)]);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn multi_route_manager_journals_failure_and_recovery() {
+        let managers = [(ROUTE_1, RouteType::Direct), (ROUTE_2, RouteType::ProxyF)].map(
+            |(route, route_type)| {
+                SingleRouteThrottlingConnectionManager::new(
+                    ConnectionParams {
+                        route_type,
+                        ..example_connection_params(route)
+                    },
+                    TIMEOUT_DURATION,
+                    &no_network_change_events(),
+                )
+            },
+        );
+        let journal = ConnectionJournal::new(20);
+        let multi_route_manager =
+            MultiRouteConnectionManager::new(managers.into()).with_journal(journal.clone());
+
+        // route1 fails, so route2 is used...
+        validate_expected_route(&multi_route_manager, false, ROUTE_2).await;
+        // ...and then, once route1 is out of its cooldown, it's back in use.
+        time::advance(CONNECTION_ROUTE_MAX_COOLDOWN).await;
+        validate_expected_route(&multi_route_manager, true, ROUTE_1).await;
+
+        let direct: Arc<str> = format!("direct {ROUTE_1}").into();
+        let proxy: Arc<str> = format!("proxyf {ROUTE_2}").into();
+        let events = journal
+            .diagnostics_snapshot()
+            .into_iter()
+            .map(|entry| entry.event)
+            .collect_vec();
+        assert_eq!(
+            events,
+            [
+                ConnectionEvent::AttemptStarted {
+                    route: direct.clone()
+                },
+                ConnectionEvent::Failed {
+                    route: direct.clone(),
+                    reason: "expected error".into()
+                },
+                ConnectionEvent::RouteSwitched {
+                    from: direct.clone(),
+                    to: proxy.clone()
+                },
+                ConnectionEvent::AttemptStarted {
+                    route: proxy.clone()
+                },
+                ConnectionEvent::Connected { route: proxy },
+                ConnectionEvent::Reconnecting,
+                ConnectionEvent::AttemptStarted {
+                    route: direct.clone()
+                },
+                ConnectionEvent::Connected { route: direct },
+            ]
+        );
+    }
+
     async fn validate_expected_route(
         multi_route_manager: &MultiRouteConnectionManager,
         route1_healthy: bool,
@@ -987,6 +1716,8 @@ mod test {
                 tcp_host: Host::Domain(Arc::clone(&host)),
                 certs: RootCertificates::Native,
                 port: nonzero!(443u16),
+                dns_resolver: None,
+                alpn: None,
             },
             http_host: host,
             http_request_decorator: HttpRequestDecoratorSeq::default(),
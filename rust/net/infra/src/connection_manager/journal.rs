@@ -0,0 +1,146 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! An in-memory record of recent connection events.
+//!
+//! A [`ConnectionJournal`] keeps the last few events in a ring buffer so that
+//! an app can include them in network diagnostics or a bug report, without
+//! needing full logging to have been enabled when the problem happened.
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::LogSafeDisplay;
+
+/// Something that happened while connecting.
+///
+/// Routes are identified by their description (see
+/// [`ConnectionManager::describe_for_logging`](super::ConnectionManager::describe_for_logging)),
+/// and failure reasons are the [`LogSafeDisplay`] form of the error, so
+/// nothing here identifies the user.
+#[derive(Clone, Debug, PartialEq, Eq, displaydoc::Display)]
+pub enum ConnectionEvent {
+    /// starting a connection attempt over {route}
+    AttemptStarted { route: Arc<str> },
+    /// connected over {route}
+    Connected { route: Arc<str> },
+    /// connection over {route} failed: {reason}
+    Failed { route: Arc<str>, reason: Arc<str> },
+    /// connection over {route} timed out
+    TimedOut { route: Arc<str> },
+    /// moving on from {from} to {to}
+    RouteSwitched { from: Arc<str>, to: Arc<str> },
+    /// connecting again after an earlier connection
+    Reconnecting,
+}
+impl LogSafeDisplay for ConnectionEvent {}
+
+/// A [`ConnectionEvent`] and when it was recorded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub at: SystemTime,
+    pub event: ConnectionEvent,
+}
+
+impl LogSafeDisplay for JournalEntry {}
+impl Display for JournalEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { at, event } = self;
+        let since_epoch = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        write!(
+            f,
+            "[{}.{:03}] {event}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_millis()
+        )
+    }
+}
+
+/// A ring buffer of the most recent [`ConnectionEvent`]s.
+///
+/// Clones share the same buffer, so one journal can be given to several
+/// connection managers.
+#[derive(Clone, Debug)]
+pub struct ConnectionJournal {
+    entries: Arc<Mutex<VecDeque<JournalEntry>>>,
+    capacity: usize,
+}
+
+impl ConnectionJournal {
+    pub const DEFAULT_CAPACITY: usize = 128;
+
+    /// Creates a journal that keeps the last `capacity` events.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Adds an event, dropping the oldest one if the journal is full.
+    pub fn record(&self, event: ConnectionEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("not poisoned");
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(JournalEntry {
+            at: SystemTime::now(),
+            event,
+        });
+    }
+
+    /// The recorded events, oldest first.
+    pub fn diagnostics_snapshot(&self) -> Vec<JournalEntry> {
+        self.entries
+            .lock()
+            .expect("not poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for ConnectionJournal {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn journal_keeps_most_recent_events() {
+        let journal = ConnectionJournal::new(2);
+        for route in ["a", "b", "c"] {
+            journal.record(ConnectionEvent::AttemptStarted {
+                route: route.into(),
+            });
+        }
+
+        let events = journal
+            .diagnostics_snapshot()
+            .into_iter()
+            .map(|entry| entry.event)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                ConnectionEvent::AttemptStarted { route: "b".into() },
+                ConnectionEvent::AttemptStarted { route: "c".into() },
+            ]
+        );
+
+        let empty = ConnectionJournal::new(0);
+        empty.record(ConnectionEvent::Reconnecting);
+        assert_eq!(empty.diagnostics_snapshot(), []);
+    }
+}
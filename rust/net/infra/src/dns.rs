@@ -3,8 +3,10 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::net::{IpAddr, Ipv6Addr};
+use std::num::NonZeroU16;
 use std::str::FromStr as _;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
@@ -16,19 +18,22 @@ use tokio::time::Instant;
 use crate::certs::RootCertificates;
 use crate::dns::custom_resolver::CustomDnsResolver;
 use crate::dns::dns_errors::Error;
-use crate::dns::dns_lookup::{DnsLookup, DnsLookupRequest, StaticDnsMap, SystemDnsLookup};
+use crate::dns::dns_lookup::{
+    DnsLookup, DnsLookupRequest, SrvRecord, StaticDnsMap, SystemDnsLookup,
+};
 use crate::dns::dns_transport_doh::{DohTransportConnectorFactory, CLOUDFLARE_IPS};
 use crate::dns::dns_types::ResourceType;
 use crate::dns::dns_utils::log_safe_domain;
 use crate::dns::lookup_result::LookupResult;
 use crate::host::Host;
+use crate::metrics::ConnectionMetrics;
 use crate::route::{
     HttpRouteFragment, HttpsTlsRoute, TcpRoute, TlsRoute, TlsRouteFragment, DEFAULT_HTTPS_PORT,
 };
 use crate::timeouts::{DNS_SYSTEM_LOOKUP_TIMEOUT, DOH_FALLBACK_LOOKUP_TIMEOUT};
 use crate::utils::oneshot_broadcast::{self, Receiver};
 use crate::utils::NetworkChangeEvent;
-use crate::{utils, Alpn};
+use crate::{utils, Alpn, TransportConnectionParams};
 
 pub mod custom_resolver;
 mod dns_errors;
@@ -43,10 +48,28 @@ pub mod lookup_result;
 pub type DnsError = Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// A rule for which resolved addresses may be connected to.
+///
+/// See [`DnsResolver::add_address_filter`].
+pub type AddressFilter = Arc<dyn Fn(&IpAddr) -> bool + Send + Sync>;
+
 struct DnsResolverState {
     /// Controls if lookup results will contain IPv6 entries.
     ipv6_enabled: bool,
     in_flight_lookups: HashMap<String, Receiver<Result<LookupResult>>>,
+    /// Results saved by [`DnsResolver::prefetch_and_pin`].
+    pinned: HashMap<String, PinnedLookup>,
+    /// Filters added by [`DnsResolver::add_address_filter`].
+    address_filters: Vec<AddressFilter>,
+    /// Set by [`DnsResolver::set_metrics`].
+    metrics: Option<Arc<dyn ConnectionMetrics>>,
+}
+
+#[derive(Clone, Debug)]
+struct PinnedLookup {
+    result: LookupResult,
+    lifetime: Duration,
+    refresh_at: Instant,
 }
 
 impl std::fmt::Debug for DnsResolverState {
@@ -54,6 +77,9 @@ impl std::fmt::Debug for DnsResolverState {
         f.debug_struct("DnsResolverState")
             .field("ipv6_enabled", &self.ipv6_enabled)
             .field("in_flight_lookups", &self.in_flight_lookups.keys())
+            .field("pinned", &self.pinned.keys())
+            .field("address_filters", &self.address_filters.len())
+            .field("metrics", &self.metrics)
             .finish()
     }
 }
@@ -63,6 +89,9 @@ impl Default for DnsResolverState {
         Self {
             ipv6_enabled: true,
             in_flight_lookups: Default::default(),
+            pinned: Default::default(),
+            address_filters: Default::default(),
+            metrics: None,
         }
     }
 }
@@ -177,6 +206,18 @@ impl DnsResolver {
         }
     }
 
+    pub fn ipv6_enabled(&self) -> bool {
+        self.state.lock().expect("not poisoned").ipv6_enabled
+    }
+
+    /// How long each lookup strategy is given, in the order they're tried.
+    pub fn lookup_timeouts(&self) -> Vec<Duration> {
+        self.lookup_options
+            .iter()
+            .map(|option| option.timeout_after)
+            .collect()
+    }
+
     pub fn set_ipv6_enabled(&self, ipv6_enabled: bool) {
         let mut guard = self.state.lock().expect("not poisoned");
         if guard.ipv6_enabled != ipv6_enabled {
@@ -185,7 +226,62 @@ impl DnsResolver {
         }
     }
 
+    /// Adds a rule that resolved addresses must pass before they are
+    /// connected to.
+    ///
+    /// Addresses for which `filter` returns `false` are dropped from the
+    /// candidates for a connection. With several filters, an address must
+    /// pass all of them. This applies to all clones of this resolver.
+    pub fn add_address_filter(&self, filter: impl Fn(&IpAddr) -> bool + Send + Sync + 'static) {
+        self.state
+            .lock()
+            .expect("not poisoned")
+            .address_filters
+            .push(Arc::new(filter));
+    }
+
+    /// Reports the lookups made to connect over [routes](crate::route) to
+    /// `metrics`, or stops reporting them if `None`.
+    ///
+    /// This applies to all clones of this resolver.
+    pub fn set_metrics(&self, metrics: Option<Arc<dyn ConnectionMetrics>>) {
+        self.state.lock().expect("not poisoned").metrics = metrics;
+    }
+
+    /// The metrics set with [`Self::set_metrics`], if any.
+    pub(crate) fn metrics(&self) -> Option<Arc<dyn ConnectionMetrics>> {
+        self.state.lock().expect("not poisoned").metrics.clone()
+    }
+
+    /// Removes the addresses not allowed by the filters added with
+    /// [`Self::add_address_filter`].
+    pub(crate) fn apply_address_filters(&self, result: LookupResult) -> LookupResult {
+        let filters = self
+            .state
+            .lock()
+            .expect("not poisoned")
+            .address_filters
+            .clone();
+        if filters.is_empty() {
+            return result;
+        }
+        let allowed = |ip: IpAddr| filters.iter().all(|filter| filter(&ip));
+        let LookupResult {
+            source,
+            mut ipv4,
+            mut ipv6,
+        } = result;
+        ipv4.retain(|ip| allowed((*ip).into()));
+        ipv6.retain(|ip| allowed((*ip).into()));
+        LookupResult { source, ipv4, ipv6 }
+    }
+
+    /// Lets lookups know that the network has changed.
+    ///
+    /// Results pinned by [`Self::prefetch_and_pin`] were resolved on the old
+    /// network, so they are all dropped.
     pub fn on_network_change(&self, now: Instant) {
+        self.state.lock().expect("not poisoned").pinned.clear();
         for option in &self.lookup_options[..] {
             option.lookup.on_network_change(now);
         }
@@ -217,6 +313,157 @@ impl DnsResolver {
         }
     }
 
+    /// Discards whatever is known about `hostname` and resolves it again.
+    ///
+    /// Any in-flight lookup for the host is abandoned and every lookup option
+    /// is asked to [forget](DnsLookup::forget) its cached entry before a fresh
+    /// lookup is started. A result pinned by [`Self::prefetch_and_pin`] is
+    /// dropped too. Other hostnames are not affected.
+    pub async fn refresh(&self, hostname: &str) -> Result<LookupResult> {
+        {
+            let mut guard = self.state.lock().expect("not poisoned");
+            guard.in_flight_lookups.remove(hostname);
+            guard.pinned.remove(hostname);
+        }
+        for option in &self.lookup_options[..] {
+            option.lookup.forget(hostname);
+        }
+        self.lookup_ip(hostname).await
+    }
+
+    /// Resolves the hosts of all the given routes and pins the results.
+    ///
+    /// While a pin is fresh, [`Self::lookup_ip_preferring_pinned`] answers
+    /// from it without going to the network. Once `lifetime` has passed, the
+    /// next such lookup resolves the host again and re-pins the new result;
+    /// if that fails, the stale pin keeps being used. Routes that are
+    /// addressed by IP, and hosts that fail to resolve now, are not pinned.
+    pub async fn prefetch_and_pin<'a>(
+        &self,
+        routes: impl IntoIterator<Item = &'a TransportConnectionParams>,
+        lifetime: Duration,
+    ) {
+        let mut hostnames = routes
+            .into_iter()
+            .filter_map(|route| match &route.tcp_host {
+                Host::Domain(domain) => Some(Arc::clone(domain)),
+                Host::Ip(_) => None,
+            })
+            .collect::<Vec<_>>();
+        hostnames.sort_unstable();
+        hostnames.dedup();
+
+        futures_util::future::join_all(hostnames.iter().map(|hostname| async move {
+            match self.lookup_ip(hostname).await {
+                Ok(result) => self.pin(hostname, result, lifetime),
+                Err(error) => log::warn!(
+                    "Failed to prefetch domain [{}]: {}",
+                    log_safe_domain(hostname),
+                    error
+                ),
+            }
+        }))
+        .await;
+    }
+
+    /// Like [`Self::lookup_ip`], but answers from a pin saved by
+    /// [`Self::prefetch_and_pin`] when there is one.
+    pub async fn lookup_ip_preferring_pinned(&self, hostname: &str) -> Result<LookupResult> {
+        let Some((pinned, ipv6_enabled)) = self.pinned(hostname) else {
+            return self.lookup_ip(hostname).await;
+        };
+        let PinnedLookup {
+            result,
+            lifetime,
+            refresh_at,
+        } = pinned;
+
+        let result = if Instant::now() < refresh_at {
+            result
+        } else {
+            match self.lookup_ip(hostname).await {
+                Ok(refreshed) => {
+                    self.pin(hostname, refreshed.clone(), lifetime);
+                    return Ok(refreshed);
+                }
+                Err(error) => {
+                    log::warn!(
+                        "Failed to refresh pinned domain [{}], keeping stale result: {}",
+                        log_safe_domain(hostname),
+                        error
+                    );
+                    result
+                }
+            }
+        };
+
+        match ipv6_enabled {
+            true => Ok(result),
+            false if result.ipv4.is_empty() => Err(Error::RequestedIpTypeNotFound),
+            false => Ok(LookupResult {
+                ipv6: vec![],
+                ..result
+            }),
+        }
+    }
+
+    /// Returns the hosts and ports to connect to for the SRV `service`, in
+    /// the order they should be tried.
+    ///
+    /// Lookup options are asked in turn, and the first one with records wins.
+    /// Targets are ordered by ascending priority and then, unlike the
+    /// weighted random selection of RFC 2782, by descending weight, so that
+    /// the order is stable between attempts. If no lookup has any usable
+    /// records, the only candidate is `fallback`, to be resolved with plain
+    /// A/AAAA lookups like any other host.
+    pub async fn lookup_srv_candidates(
+        &self,
+        service: &str,
+        fallback: (Arc<str>, NonZeroU16),
+    ) -> Vec<(Arc<str>, NonZeroU16)> {
+        let service = Arc::<str>::from(service);
+        for option in &self.lookup_options[..] {
+            let records = match option.attempt_srv(Arc::clone(&service)).await {
+                Ok(records) => records,
+                Err(Error::NoData) => continue,
+                Err(error) => {
+                    log::debug!(
+                        "SRV lookup for [{}] failed: {}",
+                        log_safe_domain(&service),
+                        error
+                    );
+                    continue;
+                }
+            };
+            let candidates = srv_candidates(records);
+            if !candidates.is_empty() {
+                return candidates;
+            }
+        }
+        log::debug!(
+            "no SRV records for [{}], using the host directly",
+            log_safe_domain(&service)
+        );
+        vec![fallback]
+    }
+
+    fn pinned(&self, hostname: &str) -> Option<(PinnedLookup, bool)> {
+        let guard = self.state.lock().expect("not poisoned");
+        let pinned = guard.pinned.get(hostname)?.clone();
+        Some((pinned, guard.ipv6_enabled))
+    }
+
+    fn pin(&self, hostname: &str, result: LookupResult, lifetime: Duration) {
+        self.state.lock().expect("not poisoned").pinned.insert(
+            hostname.to_string(),
+            PinnedLookup {
+                result,
+                lifetime,
+                refresh_at: Instant::now() + lifetime,
+            },
+        );
+    }
+
     fn start_or_join_lookup(&self, hostname: &str) -> Receiver<Result<LookupResult>> {
         let mut guard = self.state.lock().expect("not poisoned");
         let ipv6_enabled = guard.ipv6_enabled;
@@ -280,6 +527,15 @@ impl DnsResolver {
 }
 
 impl LookupOption {
+    async fn attempt_srv(&self, service: Arc<str>) -> Result<Vec<SrvRecord>> {
+        utils::timeout(
+            self.timeout_after,
+            Error::Timeout,
+            self.lookup.srv_lookup(service),
+        )
+        .await
+    }
+
     async fn attempt(&self, request: DnsLookupRequest) -> Result<LookupResult> {
         let Self {
             lookup,
@@ -310,6 +566,24 @@ impl LookupOption {
     }
 }
 
+/// Orders SRV targets as described for [`DnsResolver::lookup_srv_candidates`].
+fn srv_candidates(mut records: Vec<SrvRecord>) -> Vec<(Arc<str>, NonZeroU16)> {
+    records.retain(|record| !matches!(&*record.target, "" | "."));
+    records.sort_by_key(|record| (record.priority, Reverse(record.weight)));
+    records
+        .into_iter()
+        .map(|SrvRecord { target, port, .. }| {
+            // Targets are fully-qualified, but the rest of the stack expects
+            // hostnames without the trailing dot.
+            let target = match target.strip_suffix('.') {
+                Some(stripped) => stripped.into(),
+                None => target,
+            };
+            (target, port)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
@@ -321,6 +595,7 @@ mod test {
     use assert_matches::assert_matches;
     use async_trait::async_trait;
     use const_str::ip_addr;
+    use nonzero_ext::nonzero;
 
     use super::*;
     use crate::dns::dns_lookup::DnsLookupRequest;
@@ -412,6 +687,42 @@ mod test {
         }
     }
 
+    /// Serves `response` for every domain but remembers the first answer it
+    /// gave for each one until told to forget it.
+    #[derive(Clone, Debug, Default)]
+    struct CachingTestLookup {
+        response: Arc<Mutex<Option<LookupResult>>>,
+        cache: Arc<Mutex<HashMap<String, LookupResult>>>,
+    }
+
+    impl CachingTestLookup {
+        fn set_response(&self, response: impl Into<LookupResult>) {
+            *self.response.lock().expect("not poisoned") = Some(response.into());
+        }
+    }
+
+    #[async_trait]
+    impl DnsLookup for CachingTestLookup {
+        async fn dns_lookup(&self, request: DnsLookupRequest) -> Result<LookupResult> {
+            let mut cache = self.cache.lock().expect("not poisoned");
+            if let Some(cached) = cache.get(request.hostname.as_ref()) {
+                return Ok(cached.clone());
+            }
+            let response = self
+                .response
+                .lock()
+                .expect("not poisoned")
+                .clone()
+                .ok_or(Error::LookupFailed)?;
+            cache.insert(request.hostname.to_string(), response.clone());
+            Ok(response)
+        }
+
+        fn forget(&self, hostname: &str) {
+            self.cache.lock().expect("not poisoned").remove(hostname);
+        }
+    }
+
     macro_rules! assert_empty {
         ($vec:expr) => {
             assert!($vec.is_empty(), "expected empty vec but have: {:?}", $vec)
@@ -801,4 +1112,146 @@ mod test {
         // making sure that the `test_lookup` have only seen one request
         assert_matches!(test_lookup.logged_requests().as_slice(), [_, _]);
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresh_replaces_cached_result() {
+        let test_lookup = CachingTestLookup::default();
+        test_lookup.set_response(IPV4);
+        let dns_resolver =
+            DnsResolver::new_custom(vec![(Box::new(test_lookup.clone()), ATTEMPT_TIMEOUT)]);
+
+        let result = dns_resolver.lookup_ip(CUSTOM_DOMAIN).await.unwrap();
+        assert_eq!(&[IPV4], result.ipv4.as_slice());
+
+        // The record changes, but the stale entry keeps getting served.
+        let updated_ip = ip_addr!(v4, "192.0.2.2");
+        test_lookup.set_response(updated_ip);
+        let result = dns_resolver.lookup_ip(CUSTOM_DOMAIN).await.unwrap();
+        assert_eq!(&[IPV4], result.ipv4.as_slice());
+
+        let result = dns_resolver.refresh(CUSTOM_DOMAIN).await.unwrap();
+        assert_eq!(&[updated_ip], result.ipv4.as_slice());
+
+        let result = dns_resolver.lookup_ip(CUSTOM_DOMAIN).await.unwrap();
+        assert_eq!(&[updated_ip], result.ipv4.as_slice());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresh_drops_pinned_result() {
+        let test_lookup = CachingTestLookup::default();
+        test_lookup.set_response(IPV4);
+        let dns_resolver =
+            DnsResolver::new_custom(vec![(Box::new(test_lookup.clone()), ATTEMPT_TIMEOUT)]);
+        dns_resolver.pin(CUSTOM_DOMAIN, IPV4.into(), Duration::from_secs(60));
+
+        let updated_ip = ip_addr!(v4, "192.0.2.2");
+        test_lookup.set_response(updated_ip);
+        let result = dns_resolver
+            .lookup_ip_preferring_pinned(CUSTOM_DOMAIN)
+            .await
+            .unwrap();
+        assert_eq!(&[IPV4], result.ipv4.as_slice());
+
+        let result = dns_resolver.refresh(CUSTOM_DOMAIN).await.unwrap();
+        assert_eq!(&[updated_ip], result.ipv4.as_slice());
+
+        let result = dns_resolver
+            .lookup_ip_preferring_pinned(CUSTOM_DOMAIN)
+            .await
+            .unwrap();
+        assert_eq!(&[updated_ip], result.ipv4.as_slice());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_network_change_drops_pinned_results() {
+        let test_lookup = CachingTestLookup::default();
+        let updated_ip = ip_addr!(v4, "192.0.2.2");
+        test_lookup.set_response(updated_ip);
+        let dns_resolver =
+            DnsResolver::new_custom(vec![(Box::new(test_lookup.clone()), ATTEMPT_TIMEOUT)]);
+        dns_resolver.pin(CUSTOM_DOMAIN, IPV4.into(), Duration::from_secs(60));
+        dns_resolver.pin(DUAL_STACK_DOMAIN, IPV4.into(), Duration::from_secs(60));
+
+        dns_resolver.on_network_change(Instant::now());
+
+        for domain in [CUSTOM_DOMAIN, DUAL_STACK_DOMAIN] {
+            let result = dns_resolver
+                .lookup_ip_preferring_pinned(domain)
+                .await
+                .unwrap();
+            assert_eq!(&[updated_ip], result.ipv4.as_slice(), "{domain}");
+        }
+    }
+
+    const SRV_SERVICE: &str = "_chat._tcp.signal.org";
+
+    /// Serves fixed SRV records for [`SRV_SERVICE`].
+    #[derive(Debug)]
+    struct SrvTestLookup(Vec<SrvRecord>);
+
+    #[async_trait]
+    impl DnsLookup for SrvTestLookup {
+        async fn dns_lookup(&self, _request: DnsLookupRequest) -> Result<LookupResult> {
+            Err(Error::LookupFailed)
+        }
+
+        async fn srv_lookup(&self, service: Arc<str>) -> Result<Vec<SrvRecord>> {
+            match &*service {
+                SRV_SERVICE => Ok(self.0.clone()),
+                _ => Err(Error::NoData),
+            }
+        }
+    }
+
+    fn srv(priority: u16, weight: u16, port: u16, target: &str) -> SrvRecord {
+        SrvRecord {
+            priority,
+            weight,
+            port: NonZeroU16::new(port).expect("non-zero"),
+            target: target.into(),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn srv_candidates_are_ordered_by_priority_then_weight() {
+        let dns_resolver = DnsResolver::new_custom(vec![
+            (
+                TestLookup::standard_responses(Duration::ZERO),
+                ATTEMPT_TIMEOUT,
+            ),
+            (
+                Box::new(SrvTestLookup(vec![
+                    srv(20, 100, 8443, "backup.signal.org."),
+                    srv(10, 10, 443, "light.signal.org."),
+                    srv(10, 90, 443, "heavy.signal.org."),
+                    srv(30, 0, 443, "."),
+                    srv(10, 50, 8080, "medium.signal.org"),
+                ])),
+                ATTEMPT_TIMEOUT,
+            ),
+        ]);
+
+        let fallback = (Arc::<str>::from(DUAL_STACK_DOMAIN), nonzero!(443u16));
+        let candidates = dns_resolver
+            .lookup_srv_candidates(SRV_SERVICE, fallback.clone())
+            .await;
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|(host, port)| (&**host, port.get()))
+                .collect::<Vec<_>>(),
+            [
+                ("heavy.signal.org", 443),
+                ("medium.signal.org", 8080),
+                ("light.signal.org", 443),
+                ("backup.signal.org", 8443),
+            ]
+        );
+
+        // Without any records, the host is used directly.
+        let candidates = dns_resolver
+            .lookup_srv_candidates("_other._tcp.signal.org", fallback.clone())
+            .await;
+        assert_eq!(candidates, [fallback]);
+    }
 }
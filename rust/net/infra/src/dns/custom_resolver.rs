@@ -18,7 +18,7 @@ use tokio::sync::oneshot;
 use tokio::time::Instant;
 
 use crate::dns::dns_errors::Error;
-use crate::dns::dns_lookup::DnsLookupRequest;
+use crate::dns::dns_lookup::{DnsLookupRequest, SrvRecord};
 use crate::dns::dns_types::Expiring;
 use crate::dns::dns_utils::log_safe_domain;
 use crate::dns::lookup_result::LookupResult;
@@ -61,6 +61,17 @@ pub trait DnsTransport: Debug + Sized + Send {
     ) -> impl Future<
         Output = dns::Result<impl Stream<Item = dns::Result<DnsQueryResult>> + Send + 'static>,
     > + Send;
+
+    /// Sends a query for the SRV records of `service`, like
+    /// `_chat._tcp.signal.org`.
+    ///
+    /// Transports that can't make SRV queries report [`Error::NoData`].
+    fn send_srv_query(
+        self,
+        _service: Arc<str>,
+    ) -> impl Future<Output = dns::Result<Vec<SrvRecord>>> + Send {
+        std::future::ready(Err(Error::NoData))
+    }
 }
 
 #[derive(Debug)]
@@ -134,6 +145,18 @@ where
         self.attempts_record.blocking_write().reset(now);
     }
 
+    /// Removes the cached entry for `hostname`, if there is one.
+    ///
+    /// Unlike [`Self::on_network_change`], this leaves the entries for other
+    /// hostnames untouched.
+    pub(crate) fn forget(&self, hostname: &str) {
+        self.cache
+            .lock()
+            .expect("not poisoned")
+            .map
+            .remove(hostname);
+    }
+
     pub async fn resolve(&self, request: DnsLookupRequest) -> dns::Result<LookupResult> {
         match self.cache_get(&request.hostname) {
             Some(res) => {
@@ -165,7 +188,39 @@ where
         }
     }
 
+    /// Looks up the SRV records for `service` over the transport `T`.
+    ///
+    /// Unlike address lookups, the results aren't cached: SRV records are only
+    /// looked up when deciding where to connect.
+    pub async fn resolve_srv(&self, service: Arc<str>) -> dns::Result<Vec<SrvRecord>> {
+        log::info!("Starting SRV lookup for {}", log_safe_domain(&service));
+        // There's no IPv6 preference to go by here; if the IPv6 routes to the
+        // DNS server don't work, the IPv4 ones will be tried as well.
+        let transport = self.connect_transport(true).await?;
+        transport.send_srv_query(service).await
+    }
+
     async fn lookup(&self, request: DnsLookupRequest) -> dns::Result<LookupResult> {
+        let transport = self.connect_transport(request.ipv6_enabled).await?;
+
+        let (ipv4_res_rx, ipv6_res_rx) = self.send_dns_queries(transport, request);
+        let (maybe_ipv4, maybe_ipv6) = results_within_interval(
+            ipv4_res_rx.map(Result::ok),
+            ipv6_res_rx.map(Result::ok),
+            DNS_RESOLUTION_DELAY,
+        )
+        .await;
+        let ipv4s = maybe_ipv4.map_or(vec![], |r| r.data);
+        let ipv6s = maybe_ipv6.map_or(vec![], |r| r.data);
+        match LookupResult::new(T::Connection::SOURCE, ipv4s, ipv6s) {
+            lookup_result if !lookup_result.is_empty() => Ok(lookup_result),
+            _ => Err(Error::LookupFailed),
+        }
+    }
+
+    /// Connects to the DNS server over one of the routes, skipping the IPv6
+    /// ones unless `ipv6_enabled`.
+    async fn connect_transport(&self, ipv6_enabled: bool) -> dns::Result<T::Connection> {
         let connector = InterfaceMonitor::new(
             self.connector_factory.make(),
             self.network_change_event.clone(),
@@ -175,7 +230,7 @@ where
         let routes = self
             .routes
             .iter()
-            .filter(|route| request.ipv6_enabled || route.immediate_target().is_ipv4())
+            .filter(|route| ipv6_enabled || route.immediate_target().is_ipv4())
             .cloned()
             .collect();
 
@@ -193,25 +248,11 @@ where
             .write()
             .await
             .apply_outcome_updates(updates.outcomes, updates.finished_at);
-        let transport = result.map_err(|e| match e {
+        result.map_err(|e| match e {
             crate::route::ConnectError::NoResolvedRoutes => dns::DnsError::TransportRestricted,
-            crate::route::ConnectError::AllAttemptsFailed
+            crate::route::ConnectError::AllAttemptsFailed(_)
             | crate::route::ConnectError::FatalConnect(_) => dns::DnsError::TransportFailure,
-        })?;
-
-        let (ipv4_res_rx, ipv6_res_rx) = self.send_dns_queries(transport, request);
-        let (maybe_ipv4, maybe_ipv6) = results_within_interval(
-            ipv4_res_rx.map(Result::ok),
-            ipv6_res_rx.map(Result::ok),
-            DNS_RESOLUTION_DELAY,
-        )
-        .await;
-        let ipv4s = maybe_ipv4.map_or(vec![], |r| r.data);
-        let ipv6s = maybe_ipv6.map_or(vec![], |r| r.data);
-        match LookupResult::new(T::Connection::SOURCE, ipv4s, ipv6s) {
-            lookup_result if !lookup_result.is_empty() => Ok(lookup_result),
-            _ => Err(Error::LookupFailed),
-        }
+        })
     }
 
     /// This method connects to the DNS server using the transport `T`,
@@ -389,6 +430,7 @@ pub(crate) mod test {
     use test_case::test_case;
 
     use super::*;
+    use crate::dns::dns_lookup::DnsLookup;
     use crate::route::testutils::ConnectFn;
     use crate::route::Connector;
     use crate::testutil::no_network_change_events;
@@ -456,6 +498,46 @@ pub(crate) mod test {
         }
     }
 
+    #[derive(Clone, Debug)]
+    struct TestSrvDnsTransport(Vec<SrvRecord>);
+
+    impl Connector<IpAddr, ()> for TestSrvDnsTransport {
+        type Connection = Self;
+        type Error = std::convert::Infallible;
+
+        fn connect_over(
+            &self,
+            _over: (),
+            _route: IpAddr,
+            _log_tag: Arc<str>,
+        ) -> impl Future<Output = Result<Self::Connection, Self::Error>> + Send {
+            std::future::ready(Ok(self.clone()))
+        }
+    }
+
+    impl DnsTransport for TestSrvDnsTransport {
+        const SOURCE: DnsSource = DnsSource::Test;
+
+        fn send_queries(
+            self,
+            _request: DnsLookupRequest,
+        ) -> impl Future<
+            Output = dns::Result<impl Stream<Item = dns::Result<DnsQueryResult>> + Send + 'static>,
+        > + Send {
+            panic!("not implemented");
+            #[allow(unreachable_code)] // needed for the compiler to infer the return type
+            std::future::ready(Ok(futures_util::stream::empty()))
+        }
+
+        fn send_srv_query(
+            self,
+            service: Arc<str>,
+        ) -> impl Future<Output = dns::Result<Vec<SrvRecord>>> + Send {
+            assert_eq!(&*service, "_chat._tcp.signal.org");
+            std::future::ready(Ok(self.0))
+        }
+    }
+
     pub(crate) type OneshotDnsQueryResultSender = oneshot::Sender<dns::Result<DnsQueryResult>>;
     pub(crate) type SenderHandlerFn<T> = dyn Fn(DnsLookupRequest, u32, T) + Send + Sync + 'static;
 
@@ -749,6 +831,30 @@ pub(crate) mod test {
         assert_lookup_result_content_equal(&result_3.unwrap(), IP_V4_LIST_2, IP_V6_LIST_2);
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn forgotten_results_are_looked_up_again() {
+        let (transport, resolver) =
+            TestDnsTransportWithTwoResponses::transport_and_custom_dns_resolver(|_, q_num, txs| {
+                let [tx_1, tx_2] = txs;
+                let (ipv4s, ipv6s) = if q_num == 1 {
+                    (IP_V4_LIST_1, IP_V6_LIST_1)
+                } else {
+                    (IP_V4_LIST_2, IP_V6_LIST_2)
+                };
+                tx_1.send(ok_query_result_ipv4(NORMAL_TTL, ipv4s)).unwrap();
+                tx_2.send(ok_query_result_ipv6(NORMAL_TTL, ipv6s)).unwrap();
+            });
+
+        let result_1 = resolver.resolve(test_request()).await;
+        resolver.forget(&test_request().hostname);
+        // the entry is still within its ttl, but should not be served anymore
+        let result_2 = resolver.resolve(test_request()).await;
+
+        assert_eq!(2, transport.queries_count());
+        assert_lookup_result_content_equal(&result_1.unwrap(), IP_V4_LIST_1, IP_V6_LIST_1);
+        assert_lookup_result_content_equal(&result_2.unwrap(), IP_V4_LIST_2, IP_V6_LIST_2);
+    }
+
     #[tokio::test(start_paused = true)]
     async fn cache_ttl_limited() {
         const LONG_TTL: Duration = MAX_CACHE_TTL.saturating_mul(10);
@@ -833,6 +939,32 @@ pub(crate) mod test {
         assert_matches!(result, Err(Error::LookupFailed));
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn srv_lookup_sent_over_transport() {
+        let records = vec![SrvRecord {
+            priority: 10,
+            weight: 5,
+            port: std::num::NonZeroU16::new(443).unwrap(),
+            target: "chat.signal.org".into(),
+        }];
+        let resolver = CustomDnsResolver::new(
+            vec![DNS_SERVER_IP],
+            MakeConnectorByCloning(TestSrvDnsTransport(records.clone())),
+            &no_network_change_events(),
+        );
+        let result = DnsLookup::srv_lookup(&resolver, "_chat._tcp.signal.org".into()).await;
+        assert_eq!(result.expect("has records"), records);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn srv_lookup_not_supported_by_default() {
+        let resolver = TestDnsTransportWithOneResponse::custom_dns_resolver(|_, _, _| {
+            panic!("no address queries should be sent")
+        });
+        let result = resolver.resolve_srv("_chat._tcp.signal.org".into()).await;
+        assert_matches!(result, Err(Error::NoData));
+    }
+
     #[test_case(false)]
     #[test_case(true)]
     #[tokio::test(start_paused = true)]
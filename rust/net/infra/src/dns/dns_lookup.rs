@@ -7,6 +7,7 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::net::SocketAddr;
+use std::num::NonZeroU16;
 use std::sync::Arc;
 
 use async_trait::async_trait;
@@ -26,10 +27,33 @@ pub struct DnsLookupRequest {
     pub ipv6_enabled: bool,
 }
 
+/// A single SRV record, as described in [RFC 2782](https://datatracker.ietf.org/doc/html/rfc2782).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SrvRecord {
+    /// Targets with lower priorities are tried first.
+    pub priority: u16,
+    /// Among targets with the same priority, the ones with higher weights are
+    /// tried first.
+    pub weight: u16,
+    pub port: NonZeroU16,
+    /// The host to connect to. A target of `.` means the service is not
+    /// available at this domain.
+    pub target: Arc<str>,
+}
+
 #[async_trait]
 pub trait DnsLookup: Debug + Send + Sync {
     async fn dns_lookup(&self, request: DnsLookupRequest) -> dns::Result<LookupResult>;
+    /// Looks up the SRV records for `service`, like `_chat._tcp.signal.org`.
+    ///
+    /// Lookups that don't support SRV records report [`Error::NoData`].
+    async fn srv_lookup(&self, _service: Arc<str>) -> dns::Result<Vec<SrvRecord>> {
+        Err(Error::NoData)
+    }
     fn on_network_change(&self, _now: Instant) {}
+    /// Drops any result for `hostname` that the lookup is holding on to, so
+    /// that the next request for it goes out to the network.
+    fn forget(&self, _hostname: &str) {}
 }
 
 /// Performs DNS lookup using system resolver
@@ -81,8 +105,17 @@ where
         self.resolve(request).await
     }
 
+    async fn srv_lookup(&self, service: Arc<str>) -> dns::Result<Vec<SrvRecord>> {
+        self.resolve_srv(service).await
+    }
+
     fn on_network_change(&self, now: Instant) {
         // Forward to the non-trait method.
         self.on_network_change(now);
     }
+
+    fn forget(&self, hostname: &str) {
+        // Forward to the non-trait method.
+        self.forget(hostname);
+    }
 }
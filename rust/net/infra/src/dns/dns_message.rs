@@ -7,6 +7,7 @@ use std::cmp::{max, min};
 use std::io;
 use std::io::Cursor;
 use std::net::{Ipv4Addr, Ipv6Addr};
+use std::num::NonZeroU16;
 use std::time::Duration;
 
 use bitstream_io::{
@@ -14,6 +15,7 @@ use bitstream_io::{
 };
 use tokio::time::Instant;
 
+use crate::dns::dns_lookup::SrvRecord;
 use crate::dns::dns_types::Expiring;
 use crate::dns::ResourceType;
 
@@ -120,6 +122,29 @@ pub fn parse_aaaa_record(bytes_vec: &[u8]) -> Result<Ipv6Addr> {
     Ok(Ipv6Addr::from(octets))
 }
 
+/// Parses the data of an SRV record.
+///
+/// [SRV RR format](https://datatracker.ietf.org/doc/html/rfc2782)
+pub fn parse_srv_record(bytes_vec: &[u8]) -> Result<SrvRecord> {
+    let mut reader = ByteReader::endian(Cursor::new(bytes_vec), BigEndian);
+    let priority = reader.read::<u16>()?;
+    let weight = reader.read::<u16>()?;
+    let port = NonZeroU16::new(reader.read::<u16>()?)
+        .ok_or(Error::ProtocolErrorFailedToParseResourceRecord)?;
+    // Name compression is not used for the target, so there are no preceding
+    // bytes for a pointer to refer to.
+    let mut target = vec![];
+    read_name_to_vec(&mut reader, &[], &mut target)?;
+    let target =
+        String::from_utf8(target).map_err(|_| Error::ProtocolErrorInvalidNameCharacters)?;
+    Ok(SrvRecord {
+        priority,
+        weight,
+        port,
+        target: target.into(),
+    })
+}
+
 pub fn parse_response<T>(
     message: &[u8],
     expected_type: ResourceType,
@@ -274,7 +299,7 @@ mod test {
     use assert_matches::assert_matches;
     use const_str::{concat_bytes, ip_addr};
     use hickory_proto::op::{MessageType, ResponseCode};
-    use hickory_proto::rr::rdata::{A, CNAME};
+    use hickory_proto::rr::rdata::{A, CNAME, SRV};
     use hickory_proto::rr::{Name, RData, RecordType};
     use hickory_proto::serialize::binary::BinEncodable;
     use itertools::Itertools;
@@ -557,6 +582,63 @@ mod test {
         assert_eq!(&[EXPECTED_IP], response.data.as_slice());
     }
 
+    #[test]
+    fn srv_response_parsed_correctly() {
+        let name = Name::from_str(VALID_DOMAIN).expect("valid name");
+        let response_message = response_bytes(RecordType::SRV, |message| {
+            for (priority, weight, port, target) in
+                [(10, 5, 443, "backend.example.net."), (20, 0, 8443, ".")]
+            {
+                let mut rr = hickory_proto::rr::Record::<RData>::new();
+                rr.set_name(name.clone())
+                    .set_record_type(RecordType::SRV)
+                    .set_ttl(100)
+                    .set_data(Some(RData::SRV(SRV::new(
+                        priority,
+                        weight,
+                        port,
+                        Name::from_str(target).expect("valid name"),
+                    ))));
+                message.add_answer(rr);
+            }
+        });
+
+        let response = parse_response(
+            response_message.as_slice(),
+            ResourceType::SRV,
+            parse_srv_record,
+        )
+        .expect("parsed result");
+
+        assert_eq!(
+            response.data,
+            [
+                SrvRecord {
+                    priority: 10,
+                    weight: 5,
+                    port: NonZeroU16::new(443).unwrap(),
+                    target: "backend.example.net".into(),
+                },
+                SrvRecord {
+                    priority: 20,
+                    weight: 0,
+                    port: NonZeroU16::new(8443).unwrap(),
+                    target: "".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn srv_record_with_compressed_target_rejected() {
+        // priority, weight, port, and then a pointer to the start of the message
+        let data = [0, 10, 0, 5, 1, 0xBB, POINTER_MASK, 0];
+        assert_matches!(
+            parse_srv_record(&data),
+            Err(Error::ProtocolErrorInvalidMessage)
+        );
+    }
+
     fn response_bytes<F>(record_type: RecordType, builder: F) -> Vec<u8>
     where
         F: FnOnce(&mut hickory_proto::op::message::Message),
@@ -15,12 +15,12 @@ use http::{HeaderValue, Method};
 
 use crate::dns::custom_resolver::{DnsQueryResult, DnsTransport};
 use crate::dns::dns_errors::Error;
-use crate::dns::dns_lookup::DnsLookupRequest;
+use crate::dns::dns_lookup::{DnsLookupRequest, SrvRecord};
 use crate::dns::dns_message;
-use crate::dns::dns_message::{parse_a_record, parse_aaaa_record};
+use crate::dns::dns_message::{parse_a_record, parse_aaaa_record, parse_srv_record};
 use crate::dns::dns_types::ResourceType;
 use crate::errors::{LogSafeDisplay, TransportConnectError};
-use crate::http_client::{AggregatingHttp2Client, Http2Connector};
+use crate::http_client::{AggregatingHttp2Client, HeaderLimits, Http2Connector};
 use crate::route::{
     Connector, ConnectorExt, ConnectorFactory, HttpsTlsRoute, TcpRoute, ThrottlingConnector,
     TlsRoute, VariableTlsTimeoutConnector,
@@ -51,16 +51,29 @@ pub struct DohTransportConnector {
         crate::tcp_ssl::StatelessTcp,
         TransportConnectError,
     >,
+    header_limits: HeaderLimits,
 }
 
 impl Default for DohTransportConnector {
     fn default() -> Self {
         Self {
             transport_connector: VariableTlsTimeoutConnector::new(
-                ThrottlingConnector::new(crate::tcp_ssl::StatelessTls, 1),
+                ThrottlingConnector::new(crate::tcp_ssl::StatelessTls::default(), 1),
                 crate::tcp_ssl::StatelessTcp,
                 MIN_TLS_HANDSHAKE_TIMEOUT,
             ),
+            header_limits: HeaderLimits::default(),
+        }
+    }
+}
+
+impl DohTransportConnector {
+    /// Applies `header_limits` to the resolver's responses instead of the
+    /// default ones.
+    pub fn with_header_limits(self, header_limits: HeaderLimits) -> Self {
+        Self {
+            header_limits,
+            ..self
         }
     }
 }
@@ -78,6 +91,7 @@ impl Connector<HttpsTlsRoute<TlsRoute<TcpRoute<IpAddr>>>, ()> for DohTransportCo
         let connector = Http2Connector {
             inner: &self.transport_connector,
             max_response_size: MAX_RESPONSE_SIZE,
+            header_limits: self.header_limits,
         };
         let http_client = connector
             .connect(route, log_tag.clone())
@@ -116,6 +130,13 @@ impl DnsTransport for DohTransport {
             .chain([self.send_request(request, ResourceType::A)]);
         Ok(FuturesUnordered::from_iter(futures))
     }
+
+    async fn send_srv_query(self, service: Arc<str>) -> dns::Result<Vec<SrvRecord>> {
+        let response_body = self.send_message(&service, ResourceType::SRV).await?;
+        let response =
+            dns_message::parse_response(&response_body, ResourceType::SRV, parse_srv_record)?;
+        Ok(response.data)
+    }
 }
 
 impl DohTransport {
@@ -124,11 +145,30 @@ impl DohTransport {
         request: DnsLookupRequest,
         resource_type: ResourceType,
     ) -> dns::Result<DnsQueryResult> {
+        let response_body = self.send_message(&request.hostname, resource_type).await?;
+        let result = match resource_type {
+            ResourceType::A => DnsQueryResult::Left(dns_message::parse_response(
+                &response_body,
+                ResourceType::A,
+                parse_a_record,
+            )?),
+            ResourceType::AAAA => DnsQueryResult::Right(dns_message::parse_response(
+                &response_body,
+                ResourceType::AAAA,
+                parse_aaaa_record,
+            )?),
+            ResourceType::SRV => unreachable!("SRV queries are sent by send_srv_query"),
+        };
+        Ok(result)
+    }
+
+    /// Sends a query for `name`'s records of the given type and returns the
+    /// body of the response.
+    async fn send_message(&self, name: &str, resource_type: ResourceType) -> dns::Result<Bytes> {
         // In DoH, responses are correlated with requests via HTTP,
         // so request ID should always be 0
         // https://datatracker.ietf.org/doc/html/rfc8484#section-4.1
-        let request_message =
-            dns_message::create_request_with_id(0, &request.hostname, resource_type)?;
+        let request_message = dns_message::create_request_with_id(0, name, resource_type)?;
 
         let (response_parts, response_body) = self
             .http_client
@@ -150,18 +190,6 @@ impl DohTransport {
         if response_parts.status.as_u16() != 200 {
             return Err(Error::DohRequestBadStatus(response_parts.status.as_u16()));
         }
-        let result = match resource_type {
-            ResourceType::A => DnsQueryResult::Left(dns_message::parse_response(
-                &response_body,
-                ResourceType::A,
-                parse_a_record,
-            )?),
-            ResourceType::AAAA => DnsQueryResult::Right(dns_message::parse_response(
-                &response_body,
-                ResourceType::AAAA,
-                parse_aaaa_record,
-            )?),
-        };
-        Ok(result)
+        Ok(response_body)
     }
 }
@@ -16,7 +16,8 @@ pub struct Expiring<T> {
 ///
 /// Values for the variants are assigned based on the Resource Record type values
 /// from [RFC1035](https://datatracker.ietf.org/doc/html/rfc1035#section-3.2.2)
-/// and [RFC3596](https://datatracker.ietf.org/doc/html/rfc3596#section-2.1)
+/// [RFC3596](https://datatracker.ietf.org/doc/html/rfc3596#section-2.1),
+/// and [RFC2782](https://datatracker.ietf.org/doc/html/rfc2782)
 #[repr(u16)]
 #[derive(Clone, Copy)]
 #[allow(clippy::upper_case_acronyms)]
@@ -29,4 +30,8 @@ pub enum ResourceType {
     ///
     /// https://datatracker.ietf.org/doc/html/rfc3596#section-2.1
     AAAA = 28,
+    /// A service location type
+    ///
+    /// https://datatracker.ietf.org/doc/html/rfc2782
+    SRV = 33,
 }
@@ -6,6 +6,7 @@
 use std::fmt::Display;
 use std::time::Duration;
 
+use boring_signal::x509::X509VerifyError;
 use http::{HeaderName, HeaderValue};
 use tokio_boring_signal::HandshakeError;
 
@@ -36,15 +37,160 @@ pub enum TransportConnectError {
     SslError(SslErrorReasons),
     /// Failed to load certificates
     CertError,
+    /// Server certificate changed since the last connection
+    CertificateChanged,
+    /// Server certificate is not valid for the expected name
+    CertNameMismatch,
     /// Failed to establish SSL connection: {0}
     SslFailedHandshake(FailedHandshakeReason),
     /// Proxy handshake failed
     ProxyProtocol,
     /// Abort due to local error
     ClientAbort,
+    /// Connection attempt aborted by a pre-connect hook
+    PreConnectAborted,
+    /// Connection attempt cancelled
+    Cancelled,
+    /// Too many connections are already open
+    TooManyConnections,
+    /// Server asked to renegotiate TLS, which isn't allowed
+    TlsRenegotiationRejected,
 }
 impl LogSafeDisplay for TransportConnectError {}
 
+/// The broad kind of problem behind a [`TransportConnectError`], or a higher-level connect error.
+///
+/// This is for callers, like platform-specific error types, that handle
+/// failures by kind rather than individually.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, strum::Display, strum::IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum ConnectErrorCategory {
+    /// The connection was set up incorrectly.
+    Configuration,
+    /// The server's name couldn't be resolved.
+    Dns,
+    /// The server couldn't be reached.
+    Network,
+    /// The TLS handshake failed.
+    Tls,
+    /// The server's certificate wasn't accepted.
+    Certificate,
+    /// A proxy didn't behave as expected.
+    Proxy,
+    /// The server turned the connection down.
+    Server,
+    /// The attempt was stopped locally before it finished.
+    Aborted,
+}
+
+impl TransportConnectError {
+    pub fn category(&self) -> ConnectErrorCategory {
+        match self {
+            Self::InvalidConfiguration => ConnectErrorCategory::Configuration,
+            Self::DnsError => ConnectErrorCategory::Dns,
+            Self::TcpConnectionFailed => ConnectErrorCategory::Network,
+            Self::SslError(_) | Self::SslFailedHandshake(_) | Self::TlsRenegotiationRejected => {
+                ConnectErrorCategory::Tls
+            }
+            Self::CertError | Self::CertificateChanged | Self::CertNameMismatch => {
+                ConnectErrorCategory::Certificate
+            }
+            Self::ProxyProtocol => ConnectErrorCategory::Proxy,
+            Self::ClientAbort
+            | Self::PreConnectAborted
+            | Self::Cancelled
+            | Self::TooManyConnections => ConnectErrorCategory::Aborted,
+        }
+    }
+
+    /// Whether the same connection attempt might succeed if made again later.
+    ///
+    /// Failures caused by the network are retryable; ones caused by
+    /// configuration, certificates, or a local decision to stop are not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::DnsError
+            | Self::TcpConnectionFailed
+            | Self::SslFailedHandshake(_)
+            | Self::ProxyProtocol
+            | Self::TooManyConnections => true,
+            Self::InvalidConfiguration
+            | Self::SslError(_)
+            | Self::CertError
+            | Self::CertificateChanged
+            | Self::CertNameMismatch
+            | Self::ClientAbort
+            | Self::PreConnectAborted
+            | Self::Cancelled
+            | Self::TlsRenegotiationRejected => false,
+        }
+    }
+
+    pub fn user_hint(&self) -> UserHint {
+        match self {
+            Self::TcpConnectionFailed | Self::DnsError | Self::ProxyProtocol => {
+                UserHint::CheckConnection
+            }
+            Self::SslFailedHandshake(reason) => match reason.cert_verify_failure() {
+                Some(CertVerifyFailure::OutsideValidityPeriod) => UserHint::CheckClock,
+                Some(CertVerifyFailure::Other) => UserHint::SignInToNetwork,
+                None => UserHint::CheckConnection,
+            },
+            // A certificate other than the one we expected is what a network
+            // that intercepts connections, like a captive portal, looks like.
+            Self::CertificateChanged | Self::CertNameMismatch => UserHint::SignInToNetwork,
+            // The cap on open streams is a local one, so the server may well be fine.
+            Self::TooManyConnections
+            | Self::InvalidConfiguration
+            | Self::SslError(_)
+            | Self::CertError
+            | Self::ClientAbort
+            | Self::PreConnectAborted
+            | Self::Cancelled
+            | Self::TlsRenegotiationRejected => UserHint::NoAction,
+        }
+    }
+}
+
+/// What a user could do about a failed connection.
+///
+/// This is for UIs that show guidance rather than the error itself. Errors
+/// map to hints without any outside state, so the same error always gets the
+/// same hint.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, strum::Display, strum::IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum UserHint {
+    /// The device's internet connection should be checked.
+    CheckConnection,
+    /// The device's clock is probably wrong.
+    CheckClock,
+    /// The network is intercepting connections, probably until the user
+    /// signs in to it, as with hotel or airport wifi.
+    SignInToNetwork,
+    /// The server is unavailable for now; retrying later should work.
+    ServerUnavailable,
+    /// The app is too old and needs to be updated.
+    UpdateApp,
+    /// The device is no longer registered, and needs to be set up again.
+    Reregister,
+    /// There's nothing useful to tell the user, because the problem is a
+    /// bug or the attempt was stopped on purpose.
+    NoAction,
+}
+
+impl UserHint {
+    /// The hint for an HTTP response that wasn't the one expected.
+    pub fn for_http_status(status: http::StatusCode) -> Self {
+        match status.as_u16() {
+            // 511 Network Authentication Required is meant for captive
+            // portals, and most others redirect instead.
+            511 | 300..=399 => Self::SignInToNetwork,
+            429 | 500..=599 => Self::ServerUnavailable,
+            _ => Self::NoAction,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SslErrorReasons(boring_signal::error::ErrorStack);
 
@@ -65,13 +211,49 @@ impl Display for SslErrorReasons {
 pub struct FailedHandshakeReason {
     io: Option<std::io::ErrorKind>,
     code: Option<boring_signal::ssl::ErrorCode>,
+    cert_verify: Option<CertVerifyFailure>,
+}
+
+/// Why the server's certificate was rejected during a TLS handshake.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CertVerifyFailure {
+    /// The certificate has expired or isn't valid yet, as far as the local
+    /// clock is concerned.
+    OutsideValidityPeriod,
+    /// Any other reason, like an unknown issuer.
+    Other,
 }
 
 impl FailedHandshakeReason {
     pub const TIMED_OUT: Self = Self {
         io: Some(std::io::ErrorKind::TimedOut),
         code: None,
+        cert_verify: None,
     };
+
+    /// A handshake that failed with an I/O error and no BoringSSL error code.
+    #[cfg_attr(not(feature = "rustls-tls"), allow(dead_code))]
+    pub(crate) fn io(kind: std::io::ErrorKind) -> Self {
+        Self {
+            io: Some(kind),
+            code: None,
+            cert_verify: None,
+        }
+    }
+
+    /// The kind of I/O error the handshake failed with, if any.
+    pub fn io_error_kind(&self) -> Option<std::io::ErrorKind> {
+        self.io
+    }
+
+    /// Why the server's certificate was rejected, if that's what failed the
+    /// handshake.
+    ///
+    /// This is only known when BoringSSL verified the certificate itself,
+    /// and not when verification was delegated to the platform.
+    pub fn cert_verify_failure(&self) -> Option<CertVerifyFailure> {
+        self.cert_verify
+    }
 }
 
 /// Error type for TLS handshake timeouts
@@ -79,12 +261,42 @@ impl FailedHandshakeReason {
 #[error("TLS handshake timed out")]
 pub struct TlsHandshakeTimeout;
 
+/// Error type for a read or write on an established stream that made no
+/// progress before its deadline.
+///
+/// This is carried inside an [`std::io::Error`] of kind
+/// [`TimedOut`](std::io::ErrorKind::TimedOut).
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("operation timed out")]
+pub struct OperationTimeout;
+impl LogSafeDisplay for OperationTimeout {}
+
 impl<S> From<HandshakeError<S>> for FailedHandshakeReason {
     fn from(value: HandshakeError<S>) -> Self {
         log::debug!("handshake error: {value}");
         let io = value.as_io_error().map(std::io::Error::kind);
         let code = value.code();
-        Self { io, code }
+        let cert_verify = value
+            .ssl()
+            .and_then(|ssl| ssl.verify_result().err())
+            .map(|error| {
+                let outside_validity_period = [
+                    X509VerifyError::CERT_HAS_EXPIRED,
+                    X509VerifyError::CERT_NOT_YET_VALID,
+                ]
+                .iter()
+                .any(|expected| expected.as_raw() == error.as_raw());
+                if outside_validity_period {
+                    CertVerifyFailure::OutsideValidityPeriod
+                } else {
+                    CertVerifyFailure::Other
+                }
+            });
+        Self {
+            io,
+            code,
+            cert_verify,
+        }
     }
 }
 
@@ -149,9 +361,15 @@ impl From<TransportConnectError> for std::io::Error {
             TransportConnectError::SslFailedHandshake(_)
             | TransportConnectError::SslError(_)
             | TransportConnectError::CertError
+            | TransportConnectError::CertificateChanged
+            | TransportConnectError::CertNameMismatch
             | TransportConnectError::ProxyProtocol => ErrorKind::InvalidData,
             TransportConnectError::DnsError => ErrorKind::NotFound,
-            TransportConnectError::ClientAbort => ErrorKind::ConnectionAborted,
+            TransportConnectError::ClientAbort
+            | TransportConnectError::PreConnectAborted
+            | TransportConnectError::Cancelled
+            | TransportConnectError::TlsRenegotiationRejected => ErrorKind::ConnectionAborted,
+            TransportConnectError::TooManyConnections => ErrorKind::Other,
         };
         Self::new(kind, value.to_string())
     }
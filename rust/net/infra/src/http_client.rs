@@ -3,22 +3,41 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use futures_util::{StreamExt as _, TryFutureExt as _, TryStreamExt as _};
 use http::response::Parts;
 use http::uri::PathAndQuery;
-use http::HeaderMap;
+use http::{HeaderMap, HeaderName, HeaderValue};
 use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Incoming;
 use hyper::client::conn::http2;
 use hyper_util::rt::{TokioExecutor, TokioIo};
 use static_assertions::assert_impl_all;
 
 use crate::errors::{LogSafeDisplay, TransportConnectError};
 use crate::route::{Connector, HttpRouteFragment, HttpsTlsRoute};
+use crate::utils::clock_offset::ServerClockOffset;
+use crate::utils::compression::CompressionPolicy;
+use crate::utils::oneshot_broadcast::{self, Receiver};
+use crate::utils::session_affinity::SessionAffinity;
+use crate::utils::timeout;
 use crate::{AsyncDuplexStream, Connection, TransportInfo};
 
-#[derive(displaydoc::Display, Debug)]
+pub mod auth_challenge;
+pub use auth_challenge::{auth_challenges, AuthChallenge};
+pub mod hedging;
+pub use hedging::{HedgingPolicy, RequestHedging};
+pub mod keep_warm;
+pub use keep_warm::{KeepWarm, KeepWarmConfig, Liveness};
+pub mod verified_download;
+pub use verified_download::{verify_digest, DigestAlgorithm, ExpectedDigest};
+
+#[derive(displaydoc::Display, Debug, Clone)]
 pub enum HttpError {
     /// SSL handshake failed
     SslHandshakeFailed,
@@ -36,53 +55,412 @@ pub enum HttpError {
     FailedToReadContentOfUnknownSize,
     /// Content larger than max size configured for the client
     ResponseTooLarge,
+    /// Too many redirects
+    TooManyRedirects,
+    /// Unexpected response status {0}
+    UnexpectedStatus(http::StatusCode),
+    /// `Content-Range` header is missing, invalid, or doesn't match the requested range
+    ContentRangeInvalid,
+    /// Failed while streaming the response body
+    FailedToStreamContent,
+    /// Timed out waiting for the response to start
+    FirstByteTimeout,
+    /// Timed out waiting for the whole response
+    ResponseTimeout,
+    /// Downloaded content didn't match its expected digest
+    IntegrityMismatch,
+    /// Response headers were larger than the configured limit
+    HeadersTooLarge,
+}
+
+/// Limits on the headers of a response.
+///
+/// These keep a misbehaving server from making the client hold on to
+/// arbitrarily large or numerous headers; a response that goes past them
+/// fails with [`HttpError::HeadersTooLarge`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HeaderLimits {
+    /// The most header bytes to accept, counting each header as its name and
+    /// value plus 32 bytes, the way HTTP/2's `SETTINGS_MAX_HEADER_LIST_SIZE`
+    /// does.
+    pub max_total_bytes: usize,
+    /// The most headers to accept.
+    pub max_count: usize,
+}
+
+impl Default for HeaderLimits {
+    fn default() -> Self {
+        Self {
+            max_total_bytes: 64 * 1024,
+            max_count: 100,
+        }
+    }
+}
+
+impl HeaderLimits {
+    fn check(&self, headers: &HeaderMap) -> Result<(), HttpError> {
+        let total_bytes: usize = headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len() + 32)
+            .sum();
+        if headers.len() > self.max_count || total_bytes > self.max_total_bytes {
+            log::warn!(
+                "response had {} headers totaling {total_bytes} bytes, over the limit",
+                headers.len()
+            );
+            return Err(HttpError::HeadersTooLarge);
+        }
+        Ok(())
+    }
+
+    /// The limit for the HTTP/2 layer to enforce while decoding.
+    ///
+    /// Header blocks past this are dropped as they're decoded rather than
+    /// buffered, and the request fails in a way that
+    /// [`is_oversized_headers`] recognizes.
+    fn max_header_list_size(&self) -> u32 {
+        self.max_total_bytes.try_into().unwrap_or(u32::MAX)
+    }
+}
+
+/// Whether a request failed because the response headers went past
+/// [`HeaderLimits::max_header_list_size`].
+///
+/// The HTTP/2 layer resets the stream itself with `REFUSED_STREAM` in that
+/// case; a reset sent by the server is left alone.
+fn is_oversized_headers(error: &hyper::Error) -> bool {
+    std::error::Error::source(error)
+        .and_then(|source| source.downcast_ref::<h2::Error>())
+        .is_some_and(|error| {
+            error.is_library() && error.reason() == Some(h2::Reason::REFUSED_STREAM)
+        })
 }
 
+/// A reasonable limit to pass to [`AggregatingHttp2Client::with_redirect_following`].
+pub const DEFAULT_MAX_REDIRECTS: usize = 5;
+
 #[derive(Debug, Clone)]
 pub struct AggregatingHttp2Client {
     service: http2::SendRequest<Full<Bytes>>,
     http_host: Arc<str>,
     max_response_size: usize,
+    header_limits: HeaderLimits,
     path_prefix: Arc<str>,
+    in_flight_gets: Option<Arc<Mutex<InFlightGets>>>,
+    max_redirects: Option<usize>,
+    first_byte_timeout: Option<Duration>,
+    overall_response_timeout: Option<Duration>,
+    clock_offset: Option<ServerClockOffset>,
+    session_affinity: Option<SessionAffinity>,
+    request_compression: Option<CompressionPolicy>,
+}
+
+type AggregateResponse = Result<(Parts, Bytes), HttpError>;
+
+/// A download started by [`AggregatingHttp2Client::download_resumable`].
+pub struct ResumableDownload {
+    /// The offset in the file that `body` starts at.
+    ///
+    /// This is the requested offset if the server honored the range, or 0 if
+    /// it ignored the range and is sending the whole file, in which case any
+    /// previously downloaded data should be discarded.
+    pub start: u64,
+    /// The size of the whole file, if the server reported it.
+    pub total_len: Option<u64>,
+    /// The file's contents from `start` on.
+    pub body: BoxStream<'static, Result<Bytes, HttpError>>,
+}
+
+impl std::fmt::Debug for ResumableDownload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResumableDownload")
+            .field("start", &self.start)
+            .field("total_len", &self.total_len)
+            .finish_non_exhaustive()
+    }
 }
 
+/// Identifies GET requests that can share a single response.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct GetRequestKey {
+    path_and_query: String,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+type InFlightGets = HashMap<GetRequestKey, Receiver<AggregateResponse>>;
+
 impl AggregatingHttp2Client {
+    /// Makes concurrent identical GET requests share a single request.
+    ///
+    /// While a GET request with no body is in progress, any other GET to the
+    /// same path with the same headers waits for it and receives a copy of
+    /// its result, whether that's a response (including an error status) or
+    /// an error. Clones of the returned client share the in-progress
+    /// requests.
+    pub fn with_get_coalescing(self) -> Self {
+        Self {
+            in_flight_gets: Some(Default::default()),
+            ..self
+        }
+    }
+
+    /// Follows redirect responses, up to `max_redirects` of them per request.
+    ///
+    /// A 303, or a 301 or 302 in response to a POST, is followed with a GET
+    /// with no body; other redirects repeat the original request. Redirects
+    /// are followed over the same connection, so only those to the same host
+    /// are followed. A redirect that can't be followed (because it's to a
+    /// different host, or isn't to an `https` URL) is returned as the
+    /// response, and going past the limit produces
+    /// [`HttpError::TooManyRedirects`].
+    ///
+    /// Without this, redirect responses are always returned as-is.
+    pub fn with_redirect_following(self, max_redirects: usize) -> Self {
+        Self {
+            max_redirects: Some(max_redirects),
+            ..self
+        }
+    }
+
+    /// Limits how long to wait for the status and headers of each response.
+    ///
+    /// Running out of time produces [`HttpError::FirstByteTimeout`]. This
+    /// also applies to [`Self::download_resumable`].
+    pub fn with_first_byte_timeout(self, timeout: Duration) -> Self {
+        Self {
+            first_byte_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Limits how long each request can take, from sending it to receiving
+    /// the last of the response body.
+    ///
+    /// Running out of time produces [`HttpError::ResponseTimeout`]. When
+    /// following redirects, each request gets the full amount of time. This
+    /// does not apply to [`Self::download_resumable`], whose body can take
+    /// as long as it needs.
+    pub fn with_overall_response_timeout(self, timeout: Duration) -> Self {
+        Self {
+            overall_response_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Feeds the `Date` header of every response to `clock_offset`.
+    pub fn with_clock_offset_tracking(self, clock_offset: ServerClockOffset) -> Self {
+        Self {
+            clock_offset: Some(clock_offset),
+            ..self
+        }
+    }
+
+    /// Sends the token recorded by `session_affinity` with every request, and
+    /// updates it from every response.
+    pub fn with_session_affinity(self, session_affinity: SessionAffinity) -> Self {
+        Self {
+            session_affinity: Some(session_affinity),
+            ..self
+        }
+    }
+
+    /// Gzips the body of each request that `policy` says is worth
+    /// compressing, and marks it with `Content-Encoding: gzip`.
+    ///
+    /// The body's content type comes from the request's `Content-Type`
+    /// header. Only use this with servers that accept compressed request
+    /// bodies.
+    pub fn with_request_compression(self, policy: CompressionPolicy) -> Self {
+        Self {
+            request_compression: Some(policy),
+            ..self
+        }
+    }
+
     pub async fn send_request_aggregate_response(
         &self,
         path_and_query: PathAndQuery,
         method: http::Method,
         headers: HeaderMap,
         body: Bytes,
-    ) -> Result<(Parts, Bytes), HttpError> {
-        let uri = format!(
-            "https://{}{}{}",
-            self.http_host, self.path_prefix, path_and_query
+    ) -> AggregateResponse {
+        let in_flight_gets = match &self.in_flight_gets {
+            Some(in_flight_gets) if method == http::Method::GET && body.is_empty() => {
+                in_flight_gets
+            }
+            _ => {
+                return self
+                    .send_request_uncoalesced(path_and_query, method, headers, body)
+                    .await
+            }
+        };
+
+        let key = GetRequestKey {
+            path_and_query: path_and_query.to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        };
+        let mut receiver = in_flight_gets
+            .lock()
+            .expect("not poisoned")
+            .entry(key)
+            .or_insert_with_key(|key| {
+                let (tx, rx) = oneshot_broadcast::channel();
+                let key = key.clone();
+                let client = self.clone();
+                let in_flight_gets = Arc::clone(in_flight_gets);
+                // The request runs on its own task so that it completes even
+                // if the caller that started it stops waiting.
+                tokio::spawn(async move {
+                    let result = client
+                        .send_request_uncoalesced(path_and_query, method, headers, body)
+                        .await;
+                    in_flight_gets.lock().expect("not poisoned").remove(&key);
+                    let _ignore_no_listeners = tx.send(result);
+                });
+                rx
+            })
+            .clone();
+
+        receiver
+            .val()
+            .await
+            .unwrap_or(Err(HttpError::SendRequestError))
+    }
+
+    /// Downloads the file at `path_and_query`, continuing from `offset`.
+    ///
+    /// This sends a GET with a `Range: bytes={offset}-` header and checks
+    /// that a `206 Partial Content` response covers the requested range. If
+    /// the server instead responds with `200 OK`, it is sending the whole
+    /// file, and the returned download starts at 0. Callers can include an
+    /// `If-Range` header in `headers` to have the server do this when the
+    /// file has changed since the earlier part was downloaded.
+    ///
+    /// Unlike [`Self::send_request_aggregate_response`], the body is streamed
+    /// rather than collected, and is not subject to the client's maximum
+    /// response size. Redirects are not followed.
+    pub async fn download_resumable(
+        &self,
+        path_and_query: PathAndQuery,
+        mut headers: HeaderMap,
+        offset: u64,
+    ) -> Result<ResumableDownload, HttpError> {
+        headers.insert(
+            http::header::RANGE,
+            HeaderValue::from_str(&format!("bytes={offset}-")).expect("valid header value"),
         );
-        let mut request_builder = http::Request::builder()
-            .method(method)
-            .uri(uri)
-            .version(http::Version::HTTP_2);
+        let request = Request {
+            host: Arc::clone(&self.http_host),
+            path_and_query: format!("{}{}", self.path_prefix, path_and_query),
+            method: http::Method::GET,
+            headers,
+            body: Bytes::new(),
+        };
+        let (parts, body) = self.send_single_request_streaming(&request).await?;
 
-        request_builder
-            .headers_mut()
-            // This can fail if the builder is invalid.
-            .ok_or(HttpError::FailedToCreateRequest)?
-            .extend(headers);
+        let (start, total_len) = match parts.status {
+            http::StatusCode::PARTIAL_CONTENT => {
+                let (start, total_len) = parts
+                    .headers
+                    .get(http::header::CONTENT_RANGE)
+                    .and_then(|value| parse_content_range(value.to_str().ok()?))
+                    .ok_or(HttpError::ContentRangeInvalid)?;
+                if start != offset {
+                    return Err(HttpError::ContentRangeInvalid);
+                }
+                (start, total_len)
+            }
+            http::StatusCode::OK => {
+                if offset != 0 {
+                    log::info!("server ignored range request; downloading from the start");
+                }
+                let total_len = parts
+                    .headers
+                    .get(http::header::CONTENT_LENGTH)
+                    .and_then(|value| value.to_str().ok()?.parse().ok());
+                (0, total_len)
+            }
+            status => return Err(HttpError::UnexpectedStatus(status)),
+        };
 
-        let content_length = body.len();
-        let request = request_builder
-            .header(http::header::CONTENT_LENGTH, content_length)
-            .body(Full::new(body))
-            .map_err(|_| HttpError::FailedToCreateRequest)?;
+        Ok(ResumableDownload {
+            start,
+            total_len,
+            body: body
+                .into_data_stream()
+                .map_err(|_: hyper::Error| HttpError::FailedToStreamContent)
+                .boxed(),
+        })
+    }
 
-        let res = self
-            .service
-            .clone()
-            .send_request(request)
-            .await
-            .map_err(|_| HttpError::SendRequestError)?;
+    /// Downloads the whole file at `path_and_query`, checking it against
+    /// `expected` as it streams in.
+    ///
+    /// The returned body ends with [`HttpError::IntegrityMismatch`] if the
+    /// content turns out not to match; see [`verify_digest`].
+    pub async fn download_verified(
+        &self,
+        path_and_query: PathAndQuery,
+        headers: HeaderMap,
+        expected: ExpectedDigest,
+    ) -> Result<BoxStream<'static, Result<Bytes, HttpError>>, HttpError> {
+        let ResumableDownload {
+            start,
+            total_len: _,
+            body,
+        } = self.download_resumable(path_and_query, headers, 0).await?;
+        debug_assert_eq!(start, 0);
+        Ok(verify_digest(body, expected))
+    }
+
+    async fn send_request_uncoalesced(
+        &self,
+        path_and_query: PathAndQuery,
+        method: http::Method,
+        mut headers: HeaderMap,
+        body: Bytes,
+    ) -> AggregateResponse {
+        let body = match &self.request_compression {
+            Some(policy) => policy.encode_request_body(&mut headers, body),
+            None => body,
+        };
+        let mut request = Request {
+            host: Arc::clone(&self.http_host),
+            path_and_query: format!("{}{}", self.path_prefix, path_and_query),
+            method,
+            headers,
+            body,
+        };
+        let mut redirects_followed = 0;
+        loop {
+            let response = self.send_single_request(&request).await?;
+            let Some(max_redirects) = self.max_redirects else {
+                return Ok(response);
+            };
+            let Some(next_request) = request.redirected_by(&response.0) else {
+                return Ok(response);
+            };
+            if redirects_followed == max_redirects {
+                return Err(HttpError::TooManyRedirects);
+            }
+            redirects_followed += 1;
+            request = next_request;
+        }
+    }
 
-        let (parts, body) = res.into_parts();
+    async fn send_single_request(&self, request: &Request) -> AggregateResponse {
+        let exchange = self.send_single_request_untimed(request);
+        match self.overall_response_timeout {
+            Some(duration) => timeout(duration, HttpError::ResponseTimeout, exchange).await,
+            None => exchange.await,
+        }
+    }
+
+    async fn send_single_request_untimed(&self, request: &Request) -> AggregateResponse {
+        let (parts, body) = self.send_single_request_streaming(request).await?;
 
         let content_length = parts
             .headers
@@ -112,14 +490,162 @@ impl AggregatingHttp2Client {
 
         Ok((parts, content))
     }
+
+    async fn send_single_request_streaming(
+        &self,
+        request: &Request,
+    ) -> Result<(Parts, Incoming), HttpError> {
+        let Request {
+            host,
+            path_and_query,
+            method,
+            headers,
+            body,
+        } = request;
+        let uri = format!("https://{host}{path_and_query}");
+        let mut request_builder = http::Request::builder()
+            .method(method.clone())
+            .uri(uri)
+            .version(http::Version::HTTP_2);
+
+        let request_headers = request_builder
+            .headers_mut()
+            // This can fail if the builder is invalid.
+            .ok_or(HttpError::FailedToCreateRequest)?;
+        request_headers.extend(headers.clone());
+        if let Some(session_affinity) = &self.session_affinity {
+            session_affinity.add_to_request(request_headers);
+        }
+
+        let content_length = body.len();
+        let request = request_builder
+            .header(http::header::CONTENT_LENGTH, content_length)
+            .body(Full::new(body.clone()))
+            .map_err(|_| HttpError::FailedToCreateRequest)?;
+
+        let response = self.service.clone().send_request(request).map_err(|e| {
+            if is_oversized_headers(&e) {
+                log::warn!("response headers were over the limit");
+                HttpError::HeadersTooLarge
+            } else {
+                HttpError::SendRequestError
+            }
+        });
+        let res = match self.first_byte_timeout {
+            Some(duration) => timeout(duration, HttpError::FirstByteTimeout, response).await?,
+            None => response.await?,
+        };
+        self.header_limits.check(res.headers())?;
+        if let Some(clock_offset) = &self.clock_offset {
+            clock_offset.observe_response_headers(res.headers());
+        }
+        if let Some(session_affinity) = &self.session_affinity {
+            session_affinity.observe_response_headers(res.headers());
+        }
+
+        Ok(res.into_parts())
+    }
 }
-pub(crate) struct Http2Connector<C> {
+
+/// Parses a `Content-Range` value of the form `bytes start-end/total`,
+/// returning the start and the total (if not `*`).
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let start: u64 = start.parse().ok()?;
+    let end: u64 = end.parse().ok()?;
+    let total = match total {
+        "*" => None,
+        total => Some(total.parse::<u64>().ok()?),
+    };
+    if end < start || total.is_some_and(|total| end >= total) {
+        return None;
+    }
+    Some((start, total))
+}
+
+/// A single request sent by [`AggregatingHttp2Client`], possibly as a result
+/// of following a redirect.
+#[derive(Clone, Debug)]
+struct Request {
+    host: Arc<str>,
+    path_and_query: String,
+    method: http::Method,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Request {
+    /// Returns the request to make next if `response` is a redirect that can
+    /// be followed.
+    fn redirected_by(&self, response: &Parts) -> Option<Self> {
+        use http::StatusCode;
+
+        let status = response.status;
+        let rewrite_as_get = match status {
+            StatusCode::SEE_OTHER => self.method != http::Method::HEAD,
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => self.method == http::Method::POST,
+            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => false,
+            _ => return None,
+        };
+
+        let location = response
+            .headers
+            .get(http::header::LOCATION)?
+            .to_str()
+            .ok()?;
+        if location.starts_with("//") {
+            // Scheme-relative URLs could point anywhere, including to plain
+            // HTTP; don't follow them.
+            return None;
+        }
+        let path_and_query = if location.starts_with('/') {
+            location.parse::<PathAndQuery>().ok()?
+        } else {
+            let uri = location.parse::<http::Uri>().ok()?;
+            if uri.scheme() != Some(&http::uri::Scheme::HTTPS) {
+                return None;
+            }
+            // The next request goes over the same connection, which was only
+            // established (and authenticated) for our own host.
+            if !uri.authority()?.as_str().eq_ignore_ascii_case(&self.host) {
+                log::debug!("not following {status} redirect to a different host");
+                return None;
+            }
+            uri.path_and_query()
+                .cloned()
+                .unwrap_or_else(|| PathAndQuery::from_static("/"))
+        };
+
+        let mut headers = self.headers.clone();
+
+        let (method, body) = if rewrite_as_get {
+            headers.remove(http::header::CONTENT_TYPE);
+            (http::Method::GET, Bytes::new())
+        } else {
+            (self.method.clone(), self.body.clone())
+        };
+
+        log::debug!("following {status} redirect");
+        Some(Self {
+            host: Arc::clone(&self.host),
+            path_and_query: path_and_query.to_string(),
+            method,
+            headers,
+            body,
+        })
+    }
+}
+/// Makes [`AggregatingHttp2Client`]s over connections made by `inner`.
+pub struct Http2Connector<C> {
     pub inner: C,
+    /// See [`HttpError::ResponseTooLarge`].
     pub max_response_size: usize,
+    pub header_limits: HeaderLimits,
 }
 
 #[derive(derive_more::From, displaydoc::Display)]
-pub(crate) enum HttpConnectError {
+pub enum HttpConnectError {
     /// {0}
     Transport(#[from] TransportConnectError),
     /// HTTP handshake failed
@@ -165,8 +691,27 @@ where
             .connect_over(over, tls_target, log_tag.clone())
             .await?;
         let info = ssl_stream.transport_info();
-        let io = TokioIo::new(ssl_stream);
-        let (sender, connection) = http2::handshake::<_, _, Full<Bytes>>(TokioExecutor::new(), io)
+        self.handshake(ssl_stream, info, host_header, path_prefix, log_tag)
+            .await
+    }
+}
+
+impl<C> Http2Connector<C> {
+    /// Makes an [`AggregatingHttp2Client`] over an already-established
+    /// `stream`.
+    async fn handshake(
+        &self,
+        stream: impl AsyncDuplexStream + 'static,
+        info: TransportInfo,
+        host_header: Arc<str>,
+        path_prefix: Arc<str>,
+        log_tag: Arc<str>,
+    ) -> Result<AggregatingHttp2Client, HttpConnectError> {
+        let io = TokioIo::new(stream);
+        let mut builder = http2::Builder::new(TokioExecutor::new());
+        builder.max_header_list_size(self.header_limits.max_header_list_size());
+        let (sender, connection) = builder
+            .handshake::<_, Full<Bytes>>(io)
             .await
             .map_err(|_: hyper::Error| HttpConnectError::HttpHandshake)?;
 
@@ -188,6 +733,14 @@ where
             http_host: host_header,
             path_prefix,
             max_response_size: self.max_response_size,
+            header_limits: self.header_limits,
+            in_flight_gets: None,
+            max_redirects: None,
+            first_byte_timeout: None,
+            overall_response_timeout: None,
+            clock_offset: None,
+            session_affinity: None,
+            request_compression: None,
         })
     }
 }
@@ -202,7 +755,9 @@ mod test {
     use std::time::Duration;
 
     use assert_matches::assert_matches;
+    use futures_util::TryStreamExt as _;
     use http::{HeaderName, HeaderValue, Method, StatusCode};
+    use test_case::test_case;
     use warp::Filter as _;
 
     use super::*;
@@ -219,18 +774,43 @@ mod test {
         HeaderValue::from_static("response-value"),
     );
     const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+    const FILE_CONTENTS: &[u8] = b"0123456789abcdef";
 
     struct RequestInfo {
         headers: warp::http::HeaderMap,
         method: warp::http::Method,
         version: warp::http::Version,
         path: String,
+        remote_addr: Option<SocketAddr>,
+    }
+
+    /// Serves `filter` over HTTPS on `::1`, presenting [`SERVER_CERTIFICATE`].
+    ///
+    /// Returns the address of the server and a [`Future`] that runs it.
+    fn localhost_https_server(
+        filter: impl warp::Filter<Extract: warp::Reply> + Clone + Send + Sync + 'static,
+    ) -> (SocketAddr, impl Future<Output = ()>) {
+        warp::serve(filter)
+            .tls()
+            .cert(SERVER_CERTIFICATE.cert.pem())
+            .key(SERVER_CERTIFICATE.key_pair.serialize_pem())
+            .bind_ephemeral((Ipv6Addr::LOCALHOST, 0))
     }
 
     fn localhost_https_server_with_fake_response(
         write_request_to: std::sync::mpsc::Sender<RequestInfo>,
     ) -> (SocketAddr, impl Future<Output = ()>) {
-        let filter = warp::any()
+        localhost_https_server(fake_response_filter(write_request_to))
+    }
+
+    fn fake_response_filter(
+        write_request_to: std::sync::mpsc::Sender<RequestInfo>,
+    ) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible>
+           + Clone
+           + Send
+           + Sync
+           + 'static {
+        warp::any()
             .map(|| {
                 warp::reply::with_header(
                     FAKE_RESPONSE,
@@ -244,14 +824,117 @@ mod test {
                     method: info.method().clone(),
                     path: info.path().to_string(),
                     version: info.version(),
+                    remote_addr: info.remote_addr(),
+                });
+            }))
+    }
+
+    /// Serves a few redirect chains that end at `/end`:
+    /// - `/start` → `/middle` → `/end`
+    /// - `/other-host` → `https://other-host.test/end`
+    /// - `/loop` → `/loop` → …
+    fn localhost_https_server_with_redirects(
+        write_request_to: std::sync::mpsc::Sender<RequestInfo>,
+    ) -> (SocketAddr, impl Future<Output = ()>) {
+        use warp::http::Uri;
+
+        let start = warp::path!("start").map(|| warp::redirect::found(Uri::from_static("/middle")));
+        let middle =
+            warp::path!("middle").map(|| warp::redirect::temporary(Uri::from_static("/end")));
+        let other_host = warp::path!("other-host")
+            .map(|| warp::redirect::see_other(Uri::from_static("https://other-host.test/end")));
+        let looping = warp::path!("loop").map(|| warp::redirect::found(Uri::from_static("/loop")));
+        let end = warp::path!("end").map(|| FAKE_RESPONSE);
+
+        let filter = start
+            .or(middle)
+            .or(other_host)
+            .or(looping)
+            .or(end)
+            .with(warp::log::custom(move |info| {
+                let _ignore_error = write_request_to.send(RequestInfo {
+                    headers: info.request_headers().clone(),
+                    method: info.method().clone(),
+                    path: info.path().to_string(),
+                    version: info.version(),
+                    remote_addr: info.remote_addr(),
                 });
             }));
-        let server = warp::serve(filter)
-            .tls()
-            .cert(SERVER_CERTIFICATE.cert.pem())
-            .key(SERVER_CERTIFICATE.key_pair.serialize_pem());
+        localhost_https_server(filter)
+    }
+
+    /// Serves [`FILE_CONTENTS`] at two paths:
+    /// - `/ranged`, which honors `Range: bytes=N-` requests
+    /// - `/unranged`, which always sends the whole file
+    fn localhost_https_server_with_file() -> (SocketAddr, impl Future<Output = ()>) {
+        use warp::http::{Response, StatusCode};
+
+        let ranged = warp::path!("ranged")
+            .and(warp::header::optional::<String>("range"))
+            .map(|range: Option<String>| {
+                let start = range.as_deref().and_then(|range| {
+                    range
+                        .strip_prefix("bytes=")?
+                        .strip_suffix('-')?
+                        .parse::<usize>()
+                        .ok()
+                });
+                match start {
+                    Some(start) => Response::builder()
+                        .status(StatusCode::PARTIAL_CONTENT)
+                        .header(
+                            "content-range",
+                            format!(
+                                "bytes {start}-{}/{}",
+                                FILE_CONTENTS.len() - 1,
+                                FILE_CONTENTS.len()
+                            ),
+                        )
+                        .body(&FILE_CONTENTS[start..])
+                        .unwrap(),
+                    None => Response::new(FILE_CONTENTS),
+                }
+            });
+        let unranged = warp::path!("unranged").map(|| Response::new(FILE_CONTENTS));
+
+        localhost_https_server(ranged.or(unranged))
+    }
 
-        server.bind_ephemeral((Ipv6Addr::LOCALHOST, 0))
+    async fn redirect_following_client(server_addr: SocketAddr) -> AggregatingHttp2Client {
+        localhost_client(server_addr)
+            .await
+            .with_redirect_following(DEFAULT_MAX_REDIRECTS)
+    }
+
+    async fn localhost_client(server_addr: SocketAddr) -> AggregatingHttp2Client {
+        http2_client(
+            [HttpsTlsRoute {
+                fragment: HttpRouteFragment {
+                    host_header: SERVER_HOSTNAME.into(),
+                    path_prefix: "".into(),
+                    front_name: None,
+                },
+                inner: TlsRoute {
+                    fragment: TlsRouteFragment {
+                        sni: Host::Domain(SERVER_HOSTNAME.into()),
+                        root_certs: crate::certs::RootCertificates::FromDer(Cow::Borrowed(
+                            SERVER_CERTIFICATE.cert.der(),
+                        )),
+                        alpn: None,
+                        min_protocol_version: None,
+                    },
+                    inner: TcpRoute {
+                        address: Ipv6Addr::LOCALHOST.into(),
+                        port: NonZeroU16::new(server_addr.port()).unwrap(),
+                    },
+                },
+            }],
+            &outcome_record_for_testing(),
+            MAX_RESPONSE_SIZE,
+            &"test".into(),
+        )
+        .await
+        .expect("can connect")
     }
 
     fn outcome_record_for_testing(
@@ -281,12 +964,13 @@ mod test {
     ) -> Result<AggregatingHttp2Client, HttpError> {
         let mut outcome_record_snapshot = outcome_record.read().await.clone();
         let tls_connector = crate::route::ComposedConnector::new(
-            ThrottlingConnector::new(crate::tcp_ssl::StatelessTls, 1),
+            ThrottlingConnector::new(crate::tcp_ssl::StatelessTls::default(), 1),
             crate::tcp_ssl::StatelessTcp,
         );
         let connector = Http2Connector {
             inner: tls_connector,
             max_response_size,
+            header_limits: HeaderLimits::default(),
         };
         let (result, updates) = crate::route::connect_resolved(
             targets.into_iter().collect(),
@@ -315,7 +999,7 @@ mod test {
             .apply_outcome_updates(updates.outcomes, updates.finished_at);
 
         result.map_err(|e| match e {
-            ConnectError::AllAttemptsFailed | ConnectError::NoResolvedRoutes => {
+            ConnectError::AllAttemptsFailed(_) | ConnectError::NoResolvedRoutes => {
                 HttpError::SslHandshakeFailed
             }
             ConnectError::FatalConnect(e) => e,
@@ -453,4 +1137,650 @@ mod test {
 
         assert_matches!(result, Err(HttpError::FailedToCreateRequest));
     }
+
+    #[tokio::test]
+    async fn identical_concurrent_gets_are_coalesced() {
+        let _ = env_logger::try_init();
+        let (request_info_send, request_info_recv) = std::sync::mpsc::channel();
+
+        let (server_addr, server) = localhost_https_server_with_fake_response(request_info_send);
+        tokio::spawn(server);
+
+        let client = localhost_client(server_addr).await.with_get_coalescing();
+
+        let responses = futures_util::future::join_all((0..3).map(|_| {
+            client.send_request_aggregate_response(
+                "/request/path".parse().unwrap(),
+                Method::GET,
+                HeaderMap::from_iter([(
+                    HeaderName::from_static("test-header"),
+                    HeaderValue::from_static("test-value"),
+                )]),
+                Bytes::new(),
+            )
+        }))
+        .await;
+
+        for response in responses {
+            let (parts, body) = response.expect("request should succeed");
+            assert_eq!(parts.status, StatusCode::OK);
+            assert_eq!(body, FAKE_RESPONSE);
+        }
+
+        // The server should have seen exactly one request.
+        let only_request = request_info_recv.recv().unwrap();
+        assert_eq!(only_request.method, warp::http::Method::GET);
+        assert_matches!(
+            request_info_recv.recv_timeout(Duration::from_millis(100)),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout)
+        );
+
+        // Once the shared request is done, a new one goes out to the server.
+        let _ = client
+            .send_request_aggregate_response(
+                "/request/path".parse().unwrap(),
+                Method::GET,
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+            .await
+            .expect("request should succeed");
+        let _second_request = request_info_recv.recv().unwrap();
+    }
+
+    #[tokio::test]
+    async fn redirect_chain_is_followed() {
+        let _ = env_logger::try_init();
+        let (request_info_send, request_info_recv) = std::sync::mpsc::channel();
+
+        let (server_addr, server) = localhost_https_server_with_redirects(request_info_send);
+        tokio::spawn(server);
+
+        let client = redirect_following_client(server_addr).await;
+        let (parts, body) = client
+            .send_request_aggregate_response(
+                "/start".parse().unwrap(),
+                Method::POST,
+                HeaderMap::new(),
+                Bytes::from_static(b"request body"),
+            )
+            .await
+            .expect("request should succeed");
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(body, FAKE_RESPONSE);
+
+        let requests = request_info_recv
+            .try_iter()
+            .map(|info| (info.method, info.path))
+            .collect::<Vec<_>>();
+        // The 302 turns the POST into a GET, which the 307 preserves.
+        assert_eq!(
+            requests,
+            [
+                (warp::http::Method::POST, "/start".to_owned()),
+                (warp::http::Method::GET, "/middle".to_owned()),
+                (warp::http::Method::GET, "/end".to_owned()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect_loop_stops_at_limit() {
+        let _ = env_logger::try_init();
+        let (request_info_send, request_info_recv) = std::sync::mpsc::channel();
+
+        let (server_addr, server) = localhost_https_server_with_redirects(request_info_send);
+        tokio::spawn(server);
+
+        let client = redirect_following_client(server_addr).await;
+        let result = client
+            .send_request_aggregate_response(
+                "/loop".parse().unwrap(),
+                Method::GET,
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+            .await;
+        assert_matches!(result, Err(HttpError::TooManyRedirects));
+
+        // The original request, plus one for each redirect followed.
+        assert_eq!(
+            request_info_recv.try_iter().count(),
+            DEFAULT_MAX_REDIRECTS + 1
+        );
+    }
+
+    #[tokio::test]
+    async fn cross_host_redirect_is_returned() {
+        let _ = env_logger::try_init();
+        let (request_info_send, request_info_recv) = std::sync::mpsc::channel();
+
+        let (server_addr, server) = localhost_https_server_with_redirects(request_info_send);
+        tokio::spawn(server);
+
+        let client = redirect_following_client(server_addr).await;
+        let (parts, _body) = client
+            .send_request_aggregate_response(
+                "/other-host".parse().unwrap(),
+                Method::PUT,
+                HeaderMap::from_iter([(
+                    http::header::AUTHORIZATION,
+                    HeaderValue::from_static("Basic c2VjcmV0"),
+                )]),
+                Bytes::new(),
+            )
+            .await
+            .expect("request should succeed");
+
+        // The redirect would have to be sent over the connection to this
+        // server, so it's handed back instead of being followed.
+        assert_eq!(parts.status, StatusCode::SEE_OTHER);
+        assert_eq!(
+            parts.headers.get(http::header::LOCATION),
+            Some(&HeaderValue::from_static("https://other-host.test/end"))
+        );
+        assert_eq!(request_info_recv.try_iter().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn download_resumes_from_offset() {
+        let _ = env_logger::try_init();
+        let (server_addr, server) = localhost_https_server_with_file();
+        tokio::spawn(server);
+
+        let client = localhost_client(server_addr).await;
+        let download = client
+            .download_resumable("/ranged".parse().unwrap(), HeaderMap::new(), 10)
+            .await
+            .expect("can start download");
+        assert_eq!(download.start, 10);
+        assert_eq!(download.total_len, Some(FILE_CONTENTS.len() as u64));
+
+        let body: Vec<Bytes> = download.body.try_collect().await.expect("can read body");
+        assert_eq!(body.concat(), &FILE_CONTENTS[10..]);
+    }
+
+    #[tokio::test]
+    async fn verified_download_checks_digest() {
+        let _ = env_logger::try_init();
+        let (server_addr, server) = localhost_https_server_with_file();
+        tokio::spawn(server);
+
+        let client = localhost_client(server_addr).await;
+        let digest =
+            boring_signal::hash::hash(boring_signal::hash::MessageDigest::sha256(), FILE_CONTENTS)
+                .expect("can hash")
+                .to_vec();
+        let download = |digest| {
+            client.download_verified(
+                "/ranged".parse().unwrap(),
+                HeaderMap::new(),
+                ExpectedDigest {
+                    algorithm: DigestAlgorithm::Sha256,
+                    digest,
+                },
+            )
+        };
+
+        let body: Vec<Bytes> = download(digest.clone())
+            .await
+            .expect("can start download")
+            .try_collect()
+            .await
+            .expect("digest matches");
+        assert_eq!(body.concat(), FILE_CONTENTS);
+
+        let mut wrong_digest = digest;
+        wrong_digest[0] ^= 1;
+        assert_matches!(
+            download(wrong_digest)
+                .await
+                .expect("can start download")
+                .try_collect::<Vec<Bytes>>()
+                .await,
+            Err(HttpError::IntegrityMismatch)
+        );
+    }
+
+    #[tokio::test]
+    async fn download_restarts_if_range_is_ignored() {
+        let _ = env_logger::try_init();
+        let (server_addr, server) = localhost_https_server_with_file();
+        tokio::spawn(server);
+
+        let client = localhost_client(server_addr).await;
+        let download = client
+            .download_resumable("/unranged".parse().unwrap(), HeaderMap::new(), 10)
+            .await
+            .expect("can start download");
+        assert_eq!(download.start, 0);
+        assert_eq!(download.total_len, Some(FILE_CONTENTS.len() as u64));
+
+        let body: Vec<Bytes> = download.body.try_collect().await.expect("can read body");
+        assert_eq!(body.concat(), FILE_CONTENTS);
+    }
+
+    #[test_case("bytes 10-15/16" => Some((10, Some(16))))]
+    #[test_case("bytes 0-99/*" => Some((0, None)))]
+    #[test_case("bytes 10-15/12" => None; "end past total")]
+    #[test_case("bytes 15-10/16" => None; "end before start")]
+    #[test_case("bytes */16" => None; "unsatisfied range")]
+    #[test_case("items 0-1/2" => None; "wrong unit")]
+    fn content_range_parsing(value: &str) -> Option<(u64, Option<u64>)> {
+        parse_content_range(value)
+    }
+
+    /// Serves two responses that are slow in different ways:
+    /// - `/slow-headers` waits before responding at all
+    /// - `/slow-body` sends its headers and part of the body right away, but
+    ///   waits before sending the rest
+    fn localhost_https_server_with_slow_responses(
+        delay: Duration,
+    ) -> (SocketAddr, impl Future<Output = ()>) {
+        let slow_headers = warp::path!("slow-headers").then(move || async move {
+            tokio::time::sleep(delay).await;
+            FAKE_RESPONSE
+        });
+        let slow_body = warp::path!("slow-body").map(move || {
+            let chunks =
+                futures_util::stream::iter([Duration::ZERO, delay]).then(|delay| async move {
+                    tokio::time::sleep(delay).await;
+                    Ok::<_, std::convert::Infallible>(FAKE_RESPONSE)
+                });
+            warp::reply::Response::new(warp::hyper::Body::wrap_stream(chunks))
+        });
+
+        localhost_https_server(slow_headers.or(slow_body))
+    }
+
+    async fn get_with_response_timeouts(path: &str) -> AggregateResponse {
+        let _ = env_logger::try_init();
+        let (server_addr, server) =
+            localhost_https_server_with_slow_responses(Duration::from_secs(10));
+        tokio::spawn(server);
+
+        let client = localhost_client(server_addr)
+            .await
+            .with_first_byte_timeout(Duration::from_millis(500))
+            .with_overall_response_timeout(Duration::from_secs(1));
+
+        client
+            .send_request_aggregate_response(
+                path.parse().unwrap(),
+                Method::GET,
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn slow_headers_trip_first_byte_timeout() {
+        assert_matches!(
+            get_with_response_timeouts("/slow-headers").await,
+            Err(HttpError::FirstByteTimeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_body_trips_overall_timeout() {
+        assert_matches!(
+            get_with_response_timeouts("/slow-body").await,
+            Err(HttpError::ResponseTimeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn clock_offset_is_estimated_from_date_header() {
+        let _ = env_logger::try_init();
+        const FUTURE_DATE: &str = "Fri, 01 Jan 2100 00:00:00 GMT";
+        const FUTURE_DATE_SINCE_EPOCH: Duration = Duration::from_secs(4102444800);
+
+        let filter =
+            warp::any().map(|| warp::reply::with_header(FAKE_RESPONSE, "date", FUTURE_DATE));
+        let (server_addr, server) = localhost_https_server(filter);
+        tokio::spawn(server);
+
+        let clock_offset = ServerClockOffset::new();
+        let client = localhost_client(server_addr)
+            .await
+            .with_clock_offset_tracking(clock_offset.clone());
+        let _ = client
+            .send_request_aggregate_response(
+                "/".parse().unwrap(),
+                Method::GET,
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+            .await
+            .expect("request should succeed");
+
+        let expected_offset = (std::time::UNIX_EPOCH + FUTURE_DATE_SINCE_EPOCH)
+            .duration_since(std::time::SystemTime::now())
+            .expect("it's not 2100 yet")
+            .as_secs_f64();
+        let offset = clock_offset.offset_secs().expect("has observed a response");
+        assert!(
+            (offset - expected_offset).abs() < 5.0,
+            "{offset} vs {expected_offset}"
+        );
+    }
+
+    #[tokio::test]
+    async fn session_affinity_token_is_echoed_back() {
+        use crate::utils::session_affinity::AffinityTokenLocation;
+
+        let _ = env_logger::try_init();
+        // Replies with the token it was sent, and hands out a new one.
+        let filter = warp::header::optional::<String>("x-backend").map(|sent: Option<String>| {
+            warp::reply::with_header(sent.unwrap_or_default(), "x-backend", "b12")
+        });
+        let (server_addr, server) = localhost_https_server(filter);
+        tokio::spawn(server);
+
+        let affinity = SessionAffinity::new(AffinityTokenLocation::Header(
+            HeaderName::from_static("x-backend"),
+        ));
+        let client = localhost_client(server_addr)
+            .await
+            .with_session_affinity(affinity.clone());
+        let send = || {
+            client.send_request_aggregate_response(
+                "/".parse().unwrap(),
+                Method::GET,
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+        };
+
+        let (_parts, first_body) = send().await.expect("request should succeed");
+        assert_eq!(first_body, "");
+        let (_parts, second_body) = send().await.expect("request should succeed");
+        assert_eq!(second_body, "b12");
+
+        // The token can also be carried over to a new connection.
+        assert_matches!(
+            affinity.decorator(),
+            Some(crate::HttpRequestDecorator::Headers(headers)) if headers["x-backend"] == "b12"
+        );
+    }
+
+    #[tokio::test]
+    async fn request_bodies_are_compressed_by_policy() {
+        use std::io::Read as _;
+
+        use rand::{RngCore as _, SeedableRng as _};
+
+        let _ = env_logger::try_init();
+        // Echoes the body it received, decompressed, along with how it was
+        // encoded.
+        let filter = warp::header::optional::<String>("content-encoding")
+            .and(warp::body::bytes())
+            .map(|encoding: Option<String>, body: bytes::Bytes| {
+                let encoding = encoding.unwrap_or_else(|| "identity".to_owned());
+                let mut decoded = vec![];
+                match &*encoding {
+                    "gzip" => {
+                        flate2::read::GzDecoder::new(&*body)
+                            .read_to_end(&mut decoded)
+                            .expect("valid gzip");
+                    }
+                    _ => decoded.extend_from_slice(&body),
+                }
+                warp::reply::with_header(decoded, "x-received-encoding", encoding)
+            });
+        let (server_addr, server) = localhost_https_server(filter);
+        tokio::spawn(server);
+
+        let client = localhost_client(server_addr)
+            .await
+            .with_request_compression(CompressionPolicy::default());
+        let send = |content_type: &'static str, body: Vec<u8>| {
+            client.send_request_aggregate_response(
+                "/".parse().unwrap(),
+                Method::POST,
+                HeaderMap::from_iter([(
+                    http::header::CONTENT_TYPE,
+                    HeaderValue::from_static(content_type),
+                )]),
+                body.into(),
+            )
+        };
+
+        let json = (0..20)
+            .map(|i| format!(r#"{{"id":{i},"name":"contact {i}","verified":false}}"#))
+            .collect::<Vec<_>>()
+            .join(",")
+            .into_bytes();
+        let (parts, received) = send("application/json", json.clone())
+            .await
+            .expect("request should succeed");
+        assert_eq!(parts.headers["x-received-encoding"], "gzip");
+        assert_eq!(received, json);
+
+        let mut blob = vec![0; 4096];
+        rand::rngs::StdRng::seed_from_u64(0).fill_bytes(&mut blob);
+        let (parts, received) = send("application/octet-stream", blob.clone())
+            .await
+            .expect("request should succeed");
+        assert_eq!(parts.headers["x-received-encoding"], "identity");
+        assert_eq!(received, blob);
+    }
+
+    #[tokio::test]
+    async fn hedged_request_uses_the_faster_copy() {
+        let _ = env_logger::try_init();
+        let (slow_dropped_tx, slow_dropped_rx) = tokio::sync::oneshot::channel::<()>();
+        let slow_dropped_tx = Arc::new(Mutex::new(Some(slow_dropped_tx)));
+        // The first request to arrive hangs; any later one is answered right
+        // away.
+        let filter = warp::any().then(move || {
+            let slow_dropped_tx = slow_dropped_tx.lock().expect("not poisoned").take();
+            async move {
+                if let Some(_dropped_when_cancelled) = slow_dropped_tx {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    return "slow";
+                }
+                "fast"
+            }
+        });
+        let (server_addr, server) = localhost_https_server(filter);
+        tokio::spawn(server);
+
+        let primary = localhost_client(server_addr).await;
+        let backup = localhost_client(server_addr).await;
+        let hedging = RequestHedging::new(HedgingPolicy {
+            percentile: 0.95,
+            initial_delay: Duration::from_millis(100),
+            min_delay: Duration::ZERO,
+        });
+        let (parts, body) = hedging
+            .send_request_aggregate_response(
+                &primary,
+                &backup,
+                "/".parse().unwrap(),
+                Method::GET,
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+            .await
+            .expect("request should succeed");
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(body, "fast");
+
+        // The slow request was cancelled rather than left to finish.
+        assert_matches!(
+            tokio::time::timeout(Duration::from_secs(10), slow_dropped_rx).await,
+            Ok(Err(_))
+        );
+    }
+
+    #[tokio::test]
+    async fn unauthorized_response_exposes_challenge() {
+        let _ = env_logger::try_init();
+        let filter = warp::any().map(|| {
+            warp::reply::with_status(
+                warp::reply::with_header(FAKE_RESPONSE, "www-authenticate", r#"Basic realm="x""#),
+                StatusCode::UNAUTHORIZED,
+            )
+        });
+        let (server_addr, server) = localhost_https_server(filter);
+        tokio::spawn(server);
+
+        let client = localhost_client(server_addr).await;
+        let (parts, _body) = client
+            .send_request_aggregate_response(
+                "/".parse().unwrap(),
+                Method::GET,
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+            .await
+            .expect("got a response");
+        assert_eq!(parts.status, StatusCode::UNAUTHORIZED);
+
+        let challenges = auth_challenges(&parts.headers);
+        assert_matches!(&challenges[..], [challenge] => {
+            assert_eq!(challenge.scheme, "Basic");
+            assert_eq!(challenge.realm(), Some("x"));
+        });
+    }
+
+    #[test_case("/many-headers"; "too many")]
+    #[test_case("/huge-header"; "too big")]
+    #[test_case("/enormous-header"; "more than twice too big")]
+    #[tokio::test]
+    async fn oversized_response_headers_are_rejected(path: &'static str) {
+        use warp::http::Response;
+
+        let _ = env_logger::try_init();
+        let many_headers = warp::path!("many-headers").map(|| {
+            let mut response = Response::builder();
+            for i in 0..=HeaderLimits::default().max_count {
+                response = response.header(format!("x-header-{i}"), "value");
+            }
+            response.body(FAKE_RESPONSE).unwrap()
+        });
+        let huge_header = warp::path!("huge-header").map(|| {
+            Response::builder()
+                .header(
+                    "x-huge",
+                    "a".repeat(HeaderLimits::default().max_total_bytes),
+                )
+                .body(FAKE_RESPONSE)
+                .unwrap()
+        });
+        let enormous_header = warp::path!("enormous-header").map(|| {
+            Response::builder()
+                .header(
+                    "x-enormous",
+                    "a".repeat(2 * HeaderLimits::default().max_total_bytes + 1),
+                )
+                .body(FAKE_RESPONSE)
+                .unwrap()
+        });
+        let (server_addr, server) =
+            localhost_https_server(many_headers.or(huge_header).or(enormous_header));
+        tokio::spawn(server);
+
+        let client = localhost_client(server_addr).await;
+        let result = client
+            .send_request_aggregate_response(
+                PathAndQuery::from_static(path),
+                Method::GET,
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+            .await;
+        assert_matches!(result, Err(HttpError::HeadersTooLarge));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keep_warm_requests_are_sent_on_schedule_over_the_same_connection() {
+        const INTERVAL: Duration = Duration::from_secs(10);
+        let (request_info_send, request_info_recv) = std::sync::mpsc::channel();
+
+        // The server is given just this one connection, so every request it sees came over it.
+        // Keeping it in memory also means the paused clock only moves on once each request has
+        // been answered.
+        let (client_stream, server_stream) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(
+            warp::serve(fake_response_filter(request_info_send)).run_incoming(
+                futures_util::stream::iter([Ok::<_, std::io::Error>(server_stream)]),
+            ),
+        );
+        let client = Http2Connector {
+            inner: (),
+            max_response_size: MAX_RESPONSE_SIZE,
+            header_limits: HeaderLimits::default(),
+        }
+        .handshake(
+            client_stream,
+            TransportInfo {
+                ip_version: crate::IpType::V6,
+                local_port: 0,
+            },
+            SERVER_HOSTNAME.into(),
+            "".into(),
+            "test".into(),
+        )
+        .await
+        .expect("can handshake");
+
+        let _ = client
+            .send_request_aggregate_response(
+                "/".parse().unwrap(),
+                Method::GET,
+                HeaderMap::new(),
+                Bytes::new(),
+            )
+            .await
+            .expect("request should succeed");
+        assert_eq!(request_info_recv.try_iter().count(), 1);
+
+        let (network_change_tx, network_change_event) = tokio::sync::watch::channel(());
+        let keep_warm = KeepWarm::start(
+            client,
+            KeepWarmConfig {
+                interval: INTERVAL,
+                path_and_query: PathAndQuery::from_static("/keep-warm"),
+            },
+            &network_change_event,
+        );
+        let keep_warm_requests = || {
+            let requests = Vec::from_iter(request_info_recv.try_iter());
+            for request in &requests {
+                assert_eq!(request.method, warp::http::Method::HEAD);
+                assert_eq!(request.path, "/keep-warm");
+            }
+            requests.len()
+        };
+
+        tokio::time::sleep(INTERVAL / 2).await;
+        assert_eq!(keep_warm_requests(), 0);
+        assert_eq!(keep_warm.liveness(), Liveness::default());
+
+        tokio::time::sleep(INTERVAL * 2).await;
+        assert_eq!(keep_warm_requests(), 2);
+        let liveness = keep_warm.liveness();
+        assert!(liveness.last_response_at.is_some());
+        assert_eq!(liveness.consecutive_failures, 0);
+
+        // Nothing is sent while paused...
+        keep_warm.pause();
+        tokio::time::sleep(INTERVAL * 2).await;
+        assert_eq!(keep_warm_requests(), 0);
+
+        // ...and the schedule starts over on resuming...
+        keep_warm.resume();
+        tokio::time::sleep(INTERVAL * 3 / 2).await;
+        assert_eq!(keep_warm_requests(), 1);
+
+        // ...or on a network change.
+        network_change_tx.send_replace(());
+        tokio::time::sleep(INTERVAL * 3 / 4).await;
+        assert_eq!(keep_warm_requests(), 0);
+        tokio::time::sleep(INTERVAL / 2).await;
+        assert_eq!(keep_warm_requests(), 1);
+    }
 }
@@ -0,0 +1,276 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Parsing of `WWW-Authenticate` challenges (RFC 9110 §11.6.1).
+//!
+//! A `401 Unauthorized` response says which authentication schemes the server
+//! accepts, and with what parameters. This turns those challenges into
+//! structured values so that the caller can build a matching `Authorization`
+//! header and retry.
+
+use http::HeaderMap;
+
+/// One authentication challenge from a `WWW-Authenticate` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthChallenge {
+    /// The authentication scheme, like `Basic` or `Bearer`, as the server
+    /// wrote it.
+    ///
+    /// Schemes are case-insensitive; see [`Self::has_scheme`].
+    pub scheme: String,
+    /// The challenge's parameters, in order, with lowercase names and
+    /// unquoted values.
+    pub params: Vec<(String, String)>,
+    /// The scheme-specific data of a challenge that has no parameters, like
+    /// `Negotiate` with a base64 token.
+    pub token68: Option<String>,
+}
+
+impl AuthChallenge {
+    pub fn has_scheme(&self, scheme: &str) -> bool {
+        self.scheme.eq_ignore_ascii_case(scheme)
+    }
+
+    /// The value of the parameter `name`, which is case-insensitive.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param_name, _)| param_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The protection space the credentials are for.
+    pub fn realm(&self) -> Option<&str> {
+        self.param("realm")
+    }
+}
+
+/// Parses every challenge in the `WWW-Authenticate` headers of a response.
+///
+/// A header can hold several challenges, and a response can have several of
+/// these headers; challenges are returned in the order they appear. Parsing
+/// of a header stops at the first thing that isn't well-formed, keeping the
+/// challenges before it.
+pub fn auth_challenges(headers: &HeaderMap) -> Vec<AuthChallenge> {
+    headers
+        .get_all(http::header::WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(parse_challenges)
+        .collect()
+}
+
+fn parse_challenges(value: &str) -> Vec<AuthChallenge> {
+    let mut parser = Parser {
+        input: value.as_bytes(),
+        pos: 0,
+    };
+    let mut challenges = vec![];
+
+    loop {
+        parser.skip_list_separators();
+        let Some(scheme) = parser.token() else {
+            break;
+        };
+        let mut challenge = AuthChallenge {
+            scheme: scheme.to_owned(),
+            params: vec![],
+            token68: None,
+        };
+        parser.skip_whitespace();
+
+        if let Some(token68) = parser.complete_token68() {
+            challenge.token68 = Some(token68.to_owned());
+            challenges.push(challenge);
+            continue;
+        }
+
+        loop {
+            let before_param = parser.pos;
+            parser.skip_list_separators();
+            let Some(name) = parser.token() else {
+                parser.pos = before_param;
+                break;
+            };
+            parser.skip_whitespace();
+            if !parser.eat(b'=') {
+                // This was the scheme of the next challenge.
+                parser.pos = before_param;
+                break;
+            }
+            parser.skip_whitespace();
+            let value = match parser.peek() {
+                Some(b'"') => parser.quoted_string(),
+                _ => parser.token().map(str::to_owned),
+            };
+            let Some(value) = value else {
+                challenges.push(challenge);
+                return challenges;
+            };
+            challenge.params.push((name.to_ascii_lowercase(), value));
+        }
+        challenges.push(challenge);
+    }
+
+    challenges
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let matches = self.peek() == Some(byte);
+        if matches {
+            self.pos += 1;
+        }
+        matches
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_list_separators(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b',')) {
+            self.pos += 1;
+        }
+    }
+
+    fn take_while(&mut self, pred: impl Fn(u8) -> bool) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(&pred) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.input[start..self.pos]).expect("only ASCII was taken")
+    }
+
+    fn token(&mut self) -> Option<&'a str> {
+        Some(self.take_while(is_tchar)).filter(|token| !token.is_empty())
+    }
+
+    /// Takes a token68 if that's all that's left of the challenge.
+    fn complete_token68(&mut self) -> Option<&'a str> {
+        let start = self.pos;
+        if !self.take_while(is_token68_char).is_empty() {
+            while self.eat(b'=') {}
+            let token68 =
+                std::str::from_utf8(&self.input[start..self.pos]).expect("only ASCII was taken");
+            self.skip_whitespace();
+            // Otherwise this is the name of the first parameter.
+            if matches!(self.peek(), None | Some(b',')) {
+                return Some(token68);
+            }
+        }
+        self.pos = start;
+        None
+    }
+
+    fn quoted_string(&mut self) -> Option<String> {
+        if !self.eat(b'"') {
+            return None;
+        }
+        let mut value = vec![];
+        loop {
+            match self.peek()? {
+                b'"' => {
+                    self.pos += 1;
+                    return String::from_utf8(value).ok();
+                }
+                b'\\' => {
+                    self.pos += 1;
+                    value.push(self.peek()?);
+                    self.pos += 1;
+                }
+                byte => {
+                    value.push(byte);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+fn is_tchar(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+fn is_token68_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"-._~+/".contains(&byte)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn challenge(scheme: &str, params: &[(&str, &str)]) -> AuthChallenge {
+        AuthChallenge {
+            scheme: scheme.to_owned(),
+            params: params
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            token68: None,
+        }
+    }
+
+    #[test_case(r#"Basic realm="x""#, vec![challenge("Basic", &[("realm", "x")])]; "basic")]
+    #[test_case(
+        r#"Bearer realm="signal", error="invalid_token", error_description="the \"token\" expired""#,
+        vec![challenge("Bearer", &[("realm", "signal"), ("error", "invalid_token"), ("error_description", r#"the "token" expired"#)])];
+        "bearer with escapes"
+    )]
+    #[test_case(
+        r#"Digest Realm=x, nonce="abc", Basic realm="y""#,
+        vec![challenge("Digest", &[("realm", "x"), ("nonce", "abc")]), challenge("Basic", &[("realm", "y")])];
+        "several challenges"
+    )]
+    #[test_case("Basic, Bearer", vec![challenge("Basic", &[]), challenge("Bearer", &[])]; "no params")]
+    #[test_case(
+        "Negotiate a87421==, Basic",
+        vec![AuthChallenge { token68: Some("a87421==".into()), ..challenge("Negotiate", &[]) }, challenge("Basic", &[])];
+        "token68"
+    )]
+    #[test_case(
+        r#"Basic realm="x", Bearer realm="unterminated"#,
+        vec![challenge("Basic", &[("realm", "x")]), challenge("Bearer", &[])];
+        "stops at malformed input"
+    )]
+    fn parsing(value: &str, expected: Vec<AuthChallenge>) {
+        assert_eq!(parse_challenges(value), expected);
+    }
+
+    #[test]
+    fn challenges_are_collected_across_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            http::header::WWW_AUTHENTICATE,
+            http::HeaderValue::from_static(r#"Bearer realm="a""#),
+        );
+        headers.append(
+            http::header::WWW_AUTHENTICATE,
+            http::HeaderValue::from_static(r#"Basic realm="b""#),
+        );
+        let challenges = auth_challenges(&headers);
+        assert_eq!(
+            challenges
+                .iter()
+                .map(|c| (c.scheme.as_str(), c.realm()))
+                .collect::<Vec<_>>(),
+            [("Bearer", Some("a")), ("Basic", Some("b"))]
+        );
+        assert!(challenges[1].has_scheme("basic"));
+    }
+}
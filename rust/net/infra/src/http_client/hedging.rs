@@ -0,0 +1,185 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Hedging slow requests by sending a second copy over another connection.
+//!
+//! Most responses arrive quickly, but a few take much longer, often because
+//! of the particular connection or backend they went to. Sending a copy of a
+//! request that's running late, and taking whichever answer comes back first,
+//! cuts off that tail at the cost of some extra requests.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::future::Either;
+use futures_util::pin_mut;
+use http::uri::PathAndQuery;
+use http::HeaderMap;
+use tokio::time::Instant;
+
+use super::{AggregateResponse, AggregatingHttp2Client};
+
+/// When to send the second copy of a request.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HedgingPolicy {
+    /// The percentile of recent response times, between 0 and 1, that a
+    /// request has to take longer than to be hedged.
+    pub percentile: f64,
+    /// How long to wait before hedging until there have been enough responses
+    /// to estimate the percentile.
+    pub initial_delay: Duration,
+    /// Requests are never hedged sooner than this.
+    pub min_delay: Duration,
+}
+
+/// Sends requests with [`HedgingPolicy`], keeping track of how long responses
+/// have been taking.
+///
+/// Clones share the same response times.
+#[derive(Clone, Debug)]
+pub struct RequestHedging {
+    policy: HedgingPolicy,
+    response_times: Arc<Mutex<VecDeque<Duration>>>,
+}
+
+impl RequestHedging {
+    /// How many of the most recent response times are kept.
+    const WINDOW: usize = 100;
+    /// How many response times are needed before the percentile is used.
+    const MIN_SAMPLES: usize = 10;
+
+    pub fn new(policy: HedgingPolicy) -> Self {
+        Self {
+            policy,
+            response_times: Default::default(),
+        }
+    }
+
+    /// How long the next request will wait for a response before being
+    /// hedged.
+    pub fn hedge_delay(&self) -> Duration {
+        let HedgingPolicy {
+            percentile,
+            initial_delay,
+            min_delay,
+        } = self.policy;
+        let response_times = self.response_times.lock().expect("not poisoned");
+        if response_times.len() < Self::MIN_SAMPLES {
+            return initial_delay.max(min_delay);
+        }
+        let mut sorted = Vec::from_iter(response_times.iter().copied());
+        sorted.sort_unstable();
+        // The percentile is clamped to [0, 1], so the rank is at most `sorted.len()`.
+        #[allow(clippy::cast_possible_truncation)]
+        let rank = (percentile.clamp(0.0, 1.0) * sorted.len() as f64).ceil() as usize;
+        sorted[rank.saturating_sub(1)].max(min_delay)
+    }
+
+    fn record_response_time(&self, response_time: Duration) {
+        let mut response_times = self.response_times.lock().expect("not poisoned");
+        if response_times.len() == Self::WINDOW {
+            response_times.pop_front();
+        }
+        response_times.push_back(response_time);
+    }
+
+    /// Sends a request over `primary`, and a copy over `backup` if there's no
+    /// response within [`Self::hedge_delay`].
+    ///
+    /// Whichever copy succeeds first is returned, and the other is cancelled;
+    /// if one fails, the other is waited for. Only idempotent requests are
+    /// hedged, since the server may well act on both copies. Others are sent
+    /// over `primary` alone.
+    pub async fn send_request_aggregate_response(
+        &self,
+        primary: &AggregatingHttp2Client,
+        backup: &AggregatingHttp2Client,
+        path_and_query: PathAndQuery,
+        method: http::Method,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> AggregateResponse {
+        if !method.is_idempotent() {
+            return primary
+                .send_request_aggregate_response(path_and_query, method, headers, body)
+                .await;
+        }
+
+        let delay = self.hedge_delay();
+        let primary_sent_at = Instant::now();
+        let first = primary.send_request_aggregate_response(
+            path_and_query.clone(),
+            method.clone(),
+            headers.clone(),
+            body.clone(),
+        );
+        pin_mut!(first);
+
+        let (result, sent_at) = match tokio::time::timeout(delay, &mut first).await {
+            Ok(result) => (result, primary_sent_at),
+            Err(_elapsed) => {
+                log::info!("no response after {delay:?}; sending a hedged request");
+                let backup_sent_at = Instant::now();
+                let second =
+                    backup.send_request_aggregate_response(path_and_query, method, headers, body);
+                pin_mut!(second);
+                // The losing request is cancelled when it's dropped on return.
+                match futures_util::future::select(first, second).await {
+                    Either::Left((Ok(response), _)) => (Ok(response), primary_sent_at),
+                    Either::Right((Ok(response), _)) => (Ok(response), backup_sent_at),
+                    Either::Left((Err(e), second)) => {
+                        log::info!("original request failed with {e}; waiting for hedged one");
+                        (second.await, backup_sent_at)
+                    }
+                    Either::Right((Err(e), first)) => {
+                        log::info!("hedged request failed with {e}; waiting for original one");
+                        (first.await, primary_sent_at)
+                    }
+                }
+            }
+        };
+
+        if result.is_ok() {
+            self.record_response_time(sent_at.elapsed());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const POLICY: HedgingPolicy = HedgingPolicy {
+        percentile: 0.9,
+        initial_delay: Duration::from_millis(500),
+        min_delay: Duration::from_millis(20),
+    };
+
+    #[test]
+    fn hedge_delay_follows_the_percentile_of_recent_response_times() {
+        let hedging = RequestHedging::new(POLICY);
+        assert_eq!(hedging.hedge_delay(), POLICY.initial_delay);
+
+        for millis in 1..=10 {
+            hedging.record_response_time(Duration::from_millis(millis * 10));
+        }
+        assert_eq!(hedging.hedge_delay(), Duration::from_millis(90));
+
+        // Fast responses don't bring the delay below the minimum...
+        for _ in 0..RequestHedging::WINDOW {
+            hedging.record_response_time(Duration::from_millis(1));
+        }
+        assert_eq!(hedging.hedge_delay(), POLICY.min_delay);
+
+        // ...and old ones fall out of the window.
+        for _ in 0..RequestHedging::WINDOW {
+            hedging.record_response_time(Duration::from_millis(300));
+        }
+        assert_eq!(hedging.hedge_delay(), Duration::from_millis(300));
+    }
+}
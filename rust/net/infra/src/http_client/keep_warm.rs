@@ -0,0 +1,176 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Keeping an idle HTTP connection open with periodic requests.
+//!
+//! Unlike a websocket, an HTTP/2 connection has no keep-alive of its own, so
+//! one that goes unused can be dropped by the server or by a NAT along the
+//! way, and the next request pays for a new handshake. Sending a cheap
+//! request now and then keeps the connection in use, and shows whether it's
+//! still working.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use http::uri::PathAndQuery;
+use http::HeaderMap;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use super::AggregatingHttp2Client;
+use crate::utils::NetworkChangeEvent;
+
+/// How to keep a connection warm.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeepWarmConfig {
+    /// How long to wait between requests.
+    pub interval: Duration,
+    /// Where to send the `HEAD` requests. This should be cheap for the server
+    /// to answer; any response, even an error status, counts as the
+    /// connection being alive.
+    pub path_and_query: PathAndQuery,
+}
+
+/// What the keep-warm requests have shown about the connection so far.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Liveness {
+    /// When the last response was received.
+    pub last_response_at: Option<Instant>,
+    /// How long the last response took to arrive.
+    pub last_round_trip: Option<Duration>,
+    /// How many requests in a row have failed since the last response.
+    pub consecutive_failures: u32,
+}
+
+/// Sends a `HEAD` request over an [`AggregatingHttp2Client`] every so often,
+/// as described by a [`KeepWarmConfig`].
+///
+/// Requests stop while [paused](Self::pause), which is meant for when the
+/// app is in the background, and stop for good when this is dropped. A
+/// network change, like the device going offline or switching networks, is
+/// treated like a pause that ends right away: the next request waits a full
+/// interval, by which time the connection has either recovered or been
+/// replaced.
+#[derive(Debug)]
+pub struct KeepWarm {
+    paused: watch::Sender<bool>,
+    liveness: Arc<Mutex<Liveness>>,
+    task: JoinHandle<()>,
+}
+
+impl KeepWarm {
+    /// Starts sending requests over `client`, the first one after
+    /// `config.interval`, and restarting the wait on each
+    /// `network_change_event`.
+    ///
+    /// Since clones of a client share its connection, this keeps warm the
+    /// connection used by all of them.
+    pub fn start(
+        client: AggregatingHttp2Client,
+        config: KeepWarmConfig,
+        network_change_event: &NetworkChangeEvent,
+    ) -> Self {
+        let (paused, paused_rx) = watch::channel(false);
+        let liveness = Arc::new(Mutex::new(Liveness::default()));
+        let task = tokio::spawn(Self::run(
+            client,
+            config,
+            paused_rx,
+            network_change_event.clone(),
+            Arc::clone(&liveness),
+        ));
+        Self {
+            paused,
+            liveness,
+            task,
+        }
+    }
+
+    /// Stops sending requests until [`Self::resume`] is called.
+    ///
+    /// A request that's already been sent is still waited for.
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    /// Starts sending requests again after [`Self::pause`], the first one
+    /// after a full interval.
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn liveness(&self) -> Liveness {
+        *self.liveness.lock().expect("not poisoned")
+    }
+
+    async fn run(
+        client: AggregatingHttp2Client,
+        config: KeepWarmConfig,
+        mut paused: watch::Receiver<bool>,
+        mut network_change_event: NetworkChangeEvent,
+        liveness: Arc<Mutex<Liveness>>,
+    ) {
+        let KeepWarmConfig {
+            interval,
+            path_and_query,
+        } = config;
+        loop {
+            if paused.wait_for(|paused| !paused).await.is_err() {
+                return;
+            }
+            let network_changed = async {
+                if network_change_event.changed().await.is_err() {
+                    // Nothing will ever be sent again.
+                    std::future::pending::<()>().await
+                }
+            };
+            tokio::select! {
+                () = tokio::time::sleep(interval) => {}
+                changed = paused.changed() => match changed {
+                    // Whether paused or resumed, start the wait over.
+                    Ok(()) => continue,
+                    Err(_) => return,
+                },
+                () = network_changed => {
+                    log::info!("network change; restarting the keep-warm interval");
+                    continue;
+                }
+            }
+
+            let sent_at = Instant::now();
+            let result = client
+                .send_request_aggregate_response(
+                    path_and_query.clone(),
+                    http::Method::HEAD,
+                    HeaderMap::new(),
+                    Bytes::new(),
+                )
+                .await;
+            let mut liveness = liveness.lock().expect("not poisoned");
+            match result {
+                Ok(_) => {
+                    let now = Instant::now();
+                    *liveness = Liveness {
+                        last_response_at: Some(now),
+                        last_round_trip: Some(now - sent_at),
+                        consecutive_failures: 0,
+                    };
+                }
+                Err(e) => {
+                    log::info!("keep-warm request failed: {e}");
+                    liveness.consecutive_failures += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for KeepWarm {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,169 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Checking downloaded content against a known digest as it streams in.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use boring_signal::sha::{Sha256, Sha512};
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use futures_util::{Stream, StreamExt as _};
+
+use super::HttpError;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+/// The digest that a download is expected to have, e.g. from a manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpectedDigest {
+    pub algorithm: DigestAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+/// Passes along the chunks of `body` while computing their digest, and fails
+/// with [`HttpError::IntegrityMismatch`] at the end if it isn't `expected`.
+///
+/// Since the digest can only be checked once everything has arrived, the
+/// content shouldn't be trusted (or, say, moved out of a temporary file)
+/// until the returned stream has ended without an error.
+pub fn verify_digest(
+    body: BoxStream<'static, Result<Bytes, HttpError>>,
+    expected: ExpectedDigest,
+) -> BoxStream<'static, Result<Bytes, HttpError>> {
+    VerifyingBody {
+        body,
+        digest: Some(RunningDigest::new(expected.algorithm)),
+        expected: expected.digest,
+    }
+    .boxed()
+}
+
+enum RunningDigest {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl RunningDigest {
+    fn new(algorithm: DigestAlgorithm) -> Self {
+        match algorithm {
+            DigestAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            DigestAlgorithm::Sha512 => Self::Sha512(Sha512::new()),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Sha256(digest) => digest.update(bytes),
+            Self::Sha512(digest) => digest.update(bytes),
+        }
+    }
+
+    fn matches(self, expected: &[u8]) -> bool {
+        match self {
+            Self::Sha256(digest) => digest.finish()[..] == *expected,
+            Self::Sha512(digest) => digest.finish()[..] == *expected,
+        }
+    }
+}
+
+struct VerifyingBody {
+    body: BoxStream<'static, Result<Bytes, HttpError>>,
+    /// Taken once the body ends and the digest has been checked.
+    digest: Option<RunningDigest>,
+    expected: Vec<u8>,
+}
+
+impl Stream for VerifyingBody {
+    type Item = Result<Bytes, HttpError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let Some(digest) = &mut this.digest else {
+            return Poll::Ready(None);
+        };
+        match std::task::ready!(this.body.poll_next_unpin(cx)) {
+            Some(Ok(chunk)) => {
+                digest.update(&chunk);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(e))),
+            None => {
+                let digest = this.digest.take().expect("checked above");
+                if digest.matches(&this.expected) {
+                    Poll::Ready(None)
+                } else {
+                    log::warn!("downloaded content doesn't match its expected digest");
+                    Poll::Ready(Some(Err(HttpError::IntegrityMismatch)))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use boring_signal::hash::MessageDigest;
+    use futures_util::TryStreamExt as _;
+    use test_case::test_case;
+
+    use super::*;
+
+    const CHUNKS: [&[u8]; 3] = [b"first chunk, ", b"second chunk, ", b"third chunk"];
+
+    fn chunked_body() -> BoxStream<'static, Result<Bytes, HttpError>> {
+        futures_util::stream::iter(CHUNKS.map(|chunk| Ok(Bytes::from_static(chunk)))).boxed()
+    }
+
+    fn digest_of_chunks(algorithm: DigestAlgorithm) -> Vec<u8> {
+        let message_digest = match algorithm {
+            DigestAlgorithm::Sha256 => MessageDigest::sha256(),
+            DigestAlgorithm::Sha512 => MessageDigest::sha512(),
+        };
+        boring_signal::hash::hash(message_digest, &CHUNKS.concat())
+            .expect("can hash")
+            .to_vec()
+    }
+
+    #[test_case(DigestAlgorithm::Sha256)]
+    #[test_case(DigestAlgorithm::Sha512)]
+    #[tokio::test]
+    async fn matching_digest_passes_chunks_through(algorithm: DigestAlgorithm) {
+        let expected = ExpectedDigest {
+            algorithm,
+            digest: digest_of_chunks(algorithm),
+        };
+        let chunks: Vec<Bytes> = verify_digest(chunked_body(), expected)
+            .try_collect()
+            .await
+            .expect("digest matches");
+        assert_eq!(chunks, CHUNKS);
+    }
+
+    #[tokio::test]
+    async fn mismatched_digest_fails_at_the_end() {
+        let mut digest = digest_of_chunks(DigestAlgorithm::Sha256);
+        digest[0] ^= 1;
+        let mut body = verify_digest(
+            chunked_body(),
+            ExpectedDigest {
+                algorithm: DigestAlgorithm::Sha256,
+                digest,
+            },
+        );
+
+        for chunk in CHUNKS {
+            assert_matches!(body.next().await, Some(Ok(received)) if received == chunk);
+        }
+        assert_matches!(body.next().await, Some(Err(HttpError::IntegrityMismatch)));
+        assert_matches!(body.next().await, None);
+    }
+}
@@ -16,24 +16,29 @@ use async_trait::async_trait;
 use http::{HeaderMap, HeaderName, HeaderValue};
 use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::certs::RootCertificates;
+use crate::certs::{CertProfiles, RootCertificates};
 use crate::connection_manager::{
     MultiRouteConnectionManager, SingleRouteThrottlingConnectionManager,
 };
-use crate::errors::{LogSafeDisplay, RetryLater, TransportConnectError};
+use crate::dns::DnsResolver;
+use crate::errors::{ConnectErrorCategory, LogSafeDisplay, RetryLater, TransportConnectError};
 use crate::host::Host;
-use crate::timeouts::{WS_KEEP_ALIVE_INTERVAL, WS_MAX_IDLE_INTERVAL};
+use crate::timeouts::{WS_KEEP_ALIVE_INTERVAL, WS_MAX_IDLE_INTERVAL, WS_UPGRADE_TIMEOUT};
 use crate::utils::NetworkChangeEvent;
 use crate::ws::WebSocketConfig;
 
+pub mod attempt_budget;
 pub mod certs;
 pub mod connection_manager;
 pub mod dns;
 pub mod errors;
 pub mod host;
 pub mod http_client;
+pub mod metrics;
 pub mod noise;
+pub mod open_streams;
 pub mod route;
+pub mod self_test;
 pub mod service;
 pub mod tcp_ssl;
 pub mod timeouts;
@@ -88,12 +93,52 @@ pub enum EnableDomainFronting {
 pub enum HttpRequestDecorator {
     /// Adds a collection of headers to the request
     Headers(http::header::HeaderMap),
+    /// Sets a header on the request, replacing any value given to it by an
+    /// earlier decorator.
+    ///
+    /// This is what headers that can only appear once, like `Authorization`,
+    /// should use, so that the last decorator wins instead of the request
+    /// carrying several conflicting values.
+    ReplaceHeader(HeaderName, HeaderValue),
     /// Prefixes the path portion of the request with the given string.
     PathPrefix(&'static str),
     /// Applies generic decoration logic.
     Generic(fn(http::request::Builder) -> http::request::Builder),
+    /// Sets a header computed anew for each request, like [`Self::ReplaceHeader`].
+    ///
+    /// This is for values that mustn't be reused, like a timestamp or nonce
+    /// that protects against replays.
+    Dynamic(DynamicHeader),
+}
+
+/// Produces the header for [`HttpRequestDecorator::Dynamic`].
+///
+/// Clones share the same function; they compare equal to each other only.
+#[derive(Clone)]
+pub struct DynamicHeader(Arc<dyn Fn() -> (HeaderName, HeaderValue) + Send + Sync>);
+
+impl DynamicHeader {
+    pub fn new(
+        make_header: impl Fn() -> (HeaderName, HeaderValue) + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(make_header))
+    }
+}
+
+impl std::fmt::Debug for DynamicHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DynamicHeader")
+    }
+}
+
+impl PartialEq for DynamicHeader {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
 }
 
+impl Eq for DynamicHeader {}
+
 #[derive(Clone, Debug, Default)]
 pub struct HttpRequestDecoratorSeq(Vec<HttpRequestDecorator>);
 
@@ -107,6 +152,24 @@ impl HttpRequestDecoratorSeq {
     pub fn add(&mut self, decorator: HttpRequestDecorator) {
         self.0.push(decorator)
     }
+
+    /// Describes each decorator, leaving out header values since those can
+    /// be credentials.
+    pub fn redacted_descriptions(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .flat_map(|decorator| match decorator {
+                HttpRequestDecorator::Headers(headers) => headers
+                    .keys()
+                    .map(|name| format!("header {name}"))
+                    .collect(),
+                HttpRequestDecorator::ReplaceHeader(name, _) => vec![format!("header {name}")],
+                HttpRequestDecorator::PathPrefix(prefix) => vec![format!("path prefix {prefix}")],
+                HttpRequestDecorator::Generic(_) => vec!["generic".to_owned()],
+                HttpRequestDecorator::Dynamic(_) => vec!["dynamic header".to_owned()],
+            })
+            .collect()
+    }
 }
 
 pub trait AsHttpHeader {
@@ -119,9 +182,11 @@ pub trait AsHttpHeader {
     }
 }
 
+/// The header produced by an [`AsHttpHeader`] value replaces any earlier value
+/// for that header.
 impl<T: AsHttpHeader> From<T> for HttpRequestDecorator {
     fn from(value: T) -> Self {
-        HttpRequestDecorator::header(T::HEADER_NAME, value.header_value())
+        HttpRequestDecorator::ReplaceHeader(T::HEADER_NAME, value.header_value())
     }
 }
 
@@ -155,6 +220,19 @@ impl ConnectionParams {
         self.connection_confirmation_header = Some(header);
         self
     }
+
+    /// Makes this route offer `alpn` instead of the protocol requested by the caller; see
+    /// [`TransportConnectionParams::alpn`].
+    pub fn with_alpn(mut self, alpn: Alpn) -> Self {
+        self.transport.alpn = Some(alpn);
+        self
+    }
+
+    /// A short description of this route, like `direct chat.signal.org`, for telling routes of
+    /// the same [`RouteType`] apart in logs and reported results.
+    pub fn route_label(&self) -> String {
+        format!("{} {}", self.route_type, self.transport.sni)
+    }
 }
 
 /// Contains all information required to establish a TLS connection to a remote endpoint.
@@ -168,6 +246,26 @@ pub struct TransportConnectionParams {
     pub port: NonZeroU16,
     /// Trusted certificates for this connection.
     pub certs: RootCertificates,
+    /// If present, used instead of the connector's resolver to look up `tcp_host`.
+    ///
+    /// This lets routes that are combined into one [`EndpointConnection`] resolve their hosts
+    /// differently, e.g. a fronting domain that can only be resolved over DoH next to a primary
+    /// host that uses the system resolver. Proxy hosts are still looked up with the connector's
+    /// resolver.
+    pub dns_resolver: Option<DnsResolver>,
+    /// If present, the ALPN protocol to offer on this route, instead of the one passed to
+    /// [`TransportConnector::connect`].
+    ///
+    /// This lets one route expect HTTP/2 while its fallback expects HTTP/1.1.
+    pub alpn: Option<Alpn>,
+}
+
+impl TransportConnectionParams {
+    /// The ALPN protocol to offer: the route's own [`Self::alpn`] if it has one, and `requested`
+    /// otherwise.
+    pub fn alpn_or(&self, requested: Alpn) -> Alpn {
+        self.alpn.unwrap_or(requested)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -184,6 +282,42 @@ pub struct ServiceConnectionInfo {
     /// If IP information is available, it's recommended to use [Host::Ip] and
     /// only use [Host::Domain] as a fallback.
     pub address: Host<Arc<str>>,
+
+    /// What was negotiated in the TLS handshake, if the connection has one
+    pub tls: Option<TlsSessionInfo>,
+}
+
+/// The protocol version and cipher suite of an established TLS session.
+///
+/// This is a snapshot taken right after the handshake, so it can outlive the
+/// stream it describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlsSessionInfo {
+    pub protocol_version: boring_signal::ssl::SslVersion,
+    /// The cipher suite name, in OpenSSL format (e.g. `ECDHE-ECDSA-AES128-GCM-SHA256`).
+    pub cipher_suite: &'static str,
+}
+
+impl TlsSessionInfo {
+    /// Builds the info for a session negotiated by rustls.
+    ///
+    /// Returns `None` for versions older than TLS 1.2, which rustls won't
+    /// negotiate anyway.
+    #[cfg(feature = "rustls-tls")]
+    pub(crate) fn from_rustls_session(
+        protocol_version: rustls::ProtocolVersion,
+        cipher_suite: &'static str,
+    ) -> Option<Self> {
+        let protocol_version = match protocol_version {
+            rustls::ProtocolVersion::TLSv1_2 => boring_signal::ssl::SslVersion::TLS1_2,
+            rustls::ProtocolVersion::TLSv1_3 => boring_signal::ssl::SslVersion::TLS1_3,
+            _ => return None,
+        };
+        Some(Self {
+            protocol_version,
+            cipher_suite,
+        })
+    }
 }
 
 /// Information about a currently- or previously-established connection to a
@@ -220,6 +354,8 @@ pub enum DnsSource {
     Static,
     /// The result came from delegating to a remote resource.
     Delegated,
+    /// The addresses were provided by the caller, and no lookup was made.
+    Provided,
     /// Test-only value
     #[cfg(any(test, feature = "test-util"))]
     Test,
@@ -284,6 +420,21 @@ impl HttpRequestDecorator {
                 .fold(request_builder, |builder, (name, value)| {
                     builder.header(name, value)
                 }),
+            Self::ReplaceHeader(name, value) => {
+                let mut request_builder = request_builder;
+                if let Some(headers) = request_builder.headers_mut() {
+                    headers.insert(name, value.clone());
+                }
+                request_builder
+            }
+            Self::Dynamic(DynamicHeader(make_header)) => {
+                let mut request_builder = request_builder;
+                if let Some(headers) = request_builder.headers_mut() {
+                    let (name, value) = make_header();
+                    headers.insert(name, value);
+                }
+                request_builder
+            }
             Self::PathPrefix(prefix) => {
                 let uri = request_builder.uri_ref().expect("request has URI set");
                 let mut parts = (*uri).clone().into_parts();
@@ -313,6 +464,69 @@ pub trait AsyncDuplexStream: AsyncRead + AsyncWrite + Unpin + Send {}
 
 impl<S: AsyncRead + AsyncWrite + Unpin + Send> AsyncDuplexStream for S {}
 
+/// How a connection attempt went, like one made by
+/// [`TransportConnector::connect_with_result`].
+#[derive(Debug)]
+pub struct ConnectResult<T, E = TransportConnectError> {
+    /// The label of the route that was used (see [`ConnectionParams::route_label`]), if the
+    /// attempt got far enough to settle on one.
+    pub route: Option<String>,
+    /// How long the attempt took, whether it succeeded or not.
+    pub elapsed: Duration,
+    pub outcome: Result<T, ConnectFailure<E>>,
+}
+
+/// Why a connection attempt failed, in a form that can be mapped to
+/// platform error types without looking at the error's message.
+#[derive(Debug)]
+pub struct ConnectFailure<E = TransportConnectError> {
+    pub category: ConnectErrorCategory,
+    /// Whether the same attempt might succeed if made again later; see
+    /// [`TransportConnectError::is_retryable`].
+    pub retryable: bool,
+    /// The underlying error, for callers that need to match on it.
+    pub error: E,
+}
+
+impl From<TransportConnectError> for ConnectFailure {
+    fn from(error: TransportConnectError) -> Self {
+        Self {
+            category: error.category(),
+            retryable: error.is_retryable(),
+            error,
+        }
+    }
+}
+
+/// A handle for abandoning connection attempts, like when the user gives up
+/// on one.
+///
+/// See [`TransportConnector::connect_cancellable`]. Clones share the same
+/// state, and once cancelled, a handle stays cancelled.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectCancellation(tokio_util::sync::CancellationToken);
+
+impl ConnectCancellation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the attempts using this handle, including any started later.
+    pub fn cancel(&self) {
+        self.0.cancel()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.is_cancelled()
+    }
+
+    /// Waits until this handle is cancelled, returning right away if it
+    /// already has been.
+    pub async fn cancelled(&self) {
+        self.0.cancelled().await
+    }
+}
+
 /// Establishes TCP/TLS connections to remote destinations.
 ///
 /// Given a destination in the form of [`TransportConnectionParams`],
@@ -322,11 +536,92 @@ impl<S: AsyncRead + AsyncWrite + Unpin + Send> AsyncDuplexStream for S {}
 pub trait TransportConnector: Clone + Send + Sync {
     type Stream: AsyncDuplexStream + 'static;
 
+    /// Connects to the destination in `connection_params`.
+    ///
+    /// Offers the route's own [`TransportConnectionParams::alpn`] if it has one, and `alpn`
+    /// otherwise.
     async fn connect(
         &self,
         connection_params: &TransportConnectionParams,
         alpn: Alpn,
     ) -> Result<StreamAndInfo<Self::Stream>, TransportConnectError>;
+
+    /// Like [`Self::connect`], but trusting the roots of certificate profile
+    /// `profile` instead of [`TransportConnectionParams::certs`].
+    ///
+    /// Fails with [`TransportConnectError::InvalidConfiguration`] if there is
+    /// no such profile.
+    async fn connect_with_cert_profile(
+        &self,
+        connection_params: &TransportConnectionParams,
+        cert_profiles: &CertProfiles,
+        profile: &str,
+        alpn: Alpn,
+    ) -> Result<StreamAndInfo<Self::Stream>, TransportConnectError> {
+        let Some(certs) = cert_profiles.get(profile) else {
+            log::warn!("no certificate profile named {profile}");
+            return Err(TransportConnectError::InvalidConfiguration);
+        };
+        let TransportConnectionParams {
+            sni,
+            tcp_host,
+            port,
+            certs: _,
+            dns_resolver,
+            alpn: route_alpn,
+        } = connection_params;
+        let connection_params = TransportConnectionParams {
+            sni: Arc::clone(sni),
+            tcp_host: tcp_host.clone(),
+            port: *port,
+            certs: certs.clone(),
+            dns_resolver: dns_resolver.clone(),
+            alpn: *route_alpn,
+        };
+        self.connect(&connection_params, alpn).await
+    }
+
+    /// Like [`Self::connect`], but for a whole route, and also reporting the route's label and
+    /// how long the attempt took, with a failure broken down into a [`ConnectFailure`].
+    async fn connect_with_result(
+        &self,
+        connection_params: &ConnectionParams,
+        alpn: Alpn,
+    ) -> ConnectResult<StreamAndInfo<Self::Stream>> {
+        let start = tokio::time::Instant::now();
+        let outcome = self
+            .connect(&connection_params.transport, alpn)
+            .await
+            .map_err(ConnectFailure::from);
+        ConnectResult {
+            route: Some(connection_params.route_label()),
+            elapsed: start.elapsed(),
+            outcome,
+        }
+    }
+
+    /// Like [`Self::connect`], but abandoned as soon as `cancellation` is
+    /// cancelled.
+    ///
+    /// Unlike a timeout, this stops the attempt right away: DNS lookups, TCP
+    /// connection attempts, and TLS handshakes in progress are all dropped,
+    /// closing their sockets, and the result is
+    /// [`TransportConnectError::Cancelled`].
+    async fn connect_cancellable(
+        &self,
+        connection_params: &TransportConnectionParams,
+        alpn: Alpn,
+        cancellation: &ConnectCancellation,
+    ) -> Result<StreamAndInfo<Self::Stream>, TransportConnectError> {
+        tokio::select! {
+            biased;
+            () = cancellation.cancelled() => {
+                log::info!("connection attempt cancelled");
+                Err(TransportConnectError::Cancelled)
+            }
+            result = self.connect(connection_params, alpn) => result,
+        }
+    }
 }
 
 /// A single ALPN list entry.
@@ -353,6 +648,11 @@ pub struct EndpointConnection<C> {
 }
 
 impl EndpointConnection<MultiRouteConnectionManager> {
+    /// Combines several routes to the same endpoint, tried in order.
+    ///
+    /// Each route keeps its own [`ConnectionParams`], so a route that sets
+    /// [`TransportConnectionParams::dns_resolver`] resolves its host with that resolver regardless
+    /// of the others.
     pub fn new_multi(
         connection_params: impl IntoIterator<Item = ConnectionParams>,
         one_route_connect_timeout: Duration,
@@ -387,6 +687,7 @@ pub fn make_ws_config(
         max_connection_time: connect_timeout,
         keep_alive_interval: WS_KEEP_ALIVE_INTERVAL,
         max_idle_time: WS_MAX_IDLE_INTERVAL,
+        upgrade_timeout: WS_UPGRADE_TIMEOUT,
     }
 }
 
@@ -411,23 +712,33 @@ pub mod testutil {
     use std::io;
     use std::io::Error as IoError;
     use std::pin::Pin;
-    use std::sync::LazyLock;
+    use std::sync::{Arc, LazyLock, Mutex};
     use std::task::{Context, Poll};
     use std::time::Duration;
 
     use async_trait::async_trait;
+    use boring_signal::pkey::PKey;
+    use boring_signal::ssl::{
+        AlpnError, SslAcceptor, SslAcceptorBuilder, SslMethod, SslRef, SslVersion,
+    };
+    use boring_signal::x509::X509;
     use displaydoc::Display;
     use futures_util::stream::FusedStream;
     use futures_util::{Sink, SinkExt as _, Stream};
-    use tokio::io::DuplexStream;
+    use tokio::io::{AsyncWriteExt as _, DuplexStream};
+    use tokio_boring_signal::SslStream;
     use tokio_util::sync::PollSender;
     use warp::{Filter, Reply};
 
     use crate::connection_manager::{ErrorClass, ErrorClassifier};
     use crate::errors::{LogSafeDisplay, TransportConnectError};
+    use crate::host::Host;
+    use crate::open_streams::{LimitOpenStreams, OpenStreams};
+    use crate::route::{Connector as _, TlsRouteFragment};
+    use crate::tcp_ssl::StatelessTls;
     use crate::utils::NetworkChangeEvent;
     use crate::{
-        Alpn, DnsSource, RouteType, ServiceConnectionInfo, StreamAndInfo,
+        Alpn, DnsSource, RouteType, ServiceConnectionInfo, StreamAndInfo, TlsSessionInfo,
         TransportConnectionParams, TransportConnector,
     };
 
@@ -495,6 +806,79 @@ pub mod testutil {
         SENDER_THAT_NEVER_SENDS.subscribe()
     }
 
+    /// Checks, when dropped, that a test didn't leave background tasks
+    /// running or streams open.
+    ///
+    /// Tasks are counted on the current tokio runtime, so every task spawned
+    /// after the check starts counts, whether by the test or by the code under
+    /// test. Streams are only counted if they come from a connector wrapped
+    /// with [`LeakCheck::track`].
+    ///
+    /// Tasks often take a moment to notice that they should exit, so call
+    /// [`LeakCheck::settle`] before the check is dropped.
+    pub struct LeakCheck {
+        runtime: tokio::runtime::Handle,
+        tasks_at_start: usize,
+        open_streams: OpenStreams,
+    }
+
+    impl LeakCheck {
+        /// How many times [`Self::settle`] yields to other tasks before giving
+        /// up on them exiting.
+        const SETTLE_YIELDS: usize = 100;
+
+        /// Starts counting tasks and streams from now.
+        ///
+        /// Panics if not called from within a tokio runtime.
+        pub fn start() -> Self {
+            let runtime = tokio::runtime::Handle::current();
+            Self {
+                tasks_at_start: runtime.metrics().num_alive_tasks(),
+                runtime,
+                open_streams: OpenStreams::new(),
+            }
+        }
+
+        /// Counts the streams produced by `connector` until they're dropped.
+        pub fn track<C>(&self, connector: C) -> LimitOpenStreams<C> {
+            LimitOpenStreams::new(connector, self.open_streams.clone())
+        }
+
+        /// Gives tasks that are finishing up, like ones that were just
+        /// aborted or whose streams were just closed, a chance to exit.
+        pub async fn settle(&self) {
+            for _ in 0..Self::SETTLE_YIELDS {
+                if self.leaks().is_none() {
+                    return;
+                }
+                tokio::task::yield_now().await;
+            }
+        }
+
+        fn leaks(&self) -> Option<String> {
+            let extra_tasks = self
+                .runtime
+                .metrics()
+                .num_alive_tasks()
+                .saturating_sub(self.tasks_at_start);
+            let open_streams = self.open_streams.count();
+            (extra_tasks != 0 || open_streams != 0).then(|| {
+                format!("{extra_tasks} tasks still running and {open_streams} streams still open")
+            })
+        }
+    }
+
+    impl Drop for LeakCheck {
+        fn drop(&mut self) {
+            if std::thread::panicking() {
+                return;
+            }
+            if let Some(leaks) = self.leaks() {
+                panic!("leaked at the end of the test: {leaks}");
+            }
+        }
+    }
+
     #[derive(Clone)]
     pub struct InMemoryWarpConnector<F> {
         filter: F,
@@ -531,11 +915,277 @@ pub mod testutil {
                     route_type: RouteType::Test,
                     dns_source: DnsSource::Test,
                     address: connection_params.tcp_host.clone(),
+                    tls: None,
                 },
             ))
         }
     }
 
+    /// Records the SNI and ALPN of every connection attempt, and hands back
+    /// one end of an in-memory stream that nothing is listening on.
+    #[derive(Clone, Debug, Default)]
+    pub struct RecordingConnector {
+        attempts: Arc<Mutex<Vec<(Arc<str>, Alpn)>>>,
+    }
+
+    impl RecordingConnector {
+        /// The SNI and ALPN offered by each attempt so far, oldest first.
+        pub fn attempts(&self) -> Vec<(Arc<str>, Alpn)> {
+            self.attempts.lock().expect("not poisoned").clone()
+        }
+    }
+
+    #[async_trait]
+    impl TransportConnector for RecordingConnector {
+        type Stream = DuplexStream;
+
+        async fn connect(
+            &self,
+            connection_params: &TransportConnectionParams,
+            alpn: Alpn,
+        ) -> Result<StreamAndInfo<Self::Stream>, TransportConnectError> {
+            self.attempts.lock().expect("not poisoned").push((
+                Arc::clone(&connection_params.sni),
+                connection_params.alpn_or(alpn),
+            ));
+            let (client, _server) = tokio::io::duplex(1);
+            Ok(StreamAndInfo(
+                client,
+                ServiceConnectionInfo {
+                    route_type: RouteType::Test,
+                    dns_source: DnsSource::Test,
+                    address: connection_params.tcp_host.clone(),
+                    tls: None,
+                },
+            ))
+        }
+    }
+
+    /// Like [`InMemoryWarpConnector`], but with a real BoringSSL handshake in
+    /// front of the server.
+    ///
+    /// The client side of the handshake is the same one used for real
+    /// connections, so the [`TransportConnectionParams::certs`] have to trust
+    /// the server's certificate, and the SNI has to match it.
+    #[derive(Clone)]
+    pub struct InMemoryTlsConnector<F> {
+        filter: F,
+        acceptor: Arc<SslAcceptor>,
+        stall_handshake: bool,
+        request_renegotiation: bool,
+    }
+
+    impl<F> InMemoryTlsConnector<F> {
+        /// Serves `filter` with the given DER-encoded certificate and private
+        /// key, agreeing to the first of `alpn` that the client offers.
+        ///
+        /// Panics if the certificate or key are invalid.
+        pub fn new(
+            filter: F,
+            certificate_der: &[u8],
+            private_key_der: &[u8],
+            alpn: &[Alpn],
+        ) -> Self {
+            let builder = Self::acceptor_builder(certificate_der, private_key_der, alpn);
+            Self {
+                filter,
+                acceptor: Arc::new(builder.build()),
+                stall_handshake: false,
+                request_renegotiation: false,
+            }
+        }
+
+        /// Like [`Self::new`], but the server asks the client to renegotiate
+        /// right after the handshake, for testing
+        /// [`RenegotiationPolicy`](crate::tcp_ssl::RenegotiationPolicy).
+        ///
+        /// Renegotiation only exists up to TLS 1.2, so the server speaks
+        /// nothing newer, and uses AES-128-GCM, the one suite the test server
+        /// knows how to encrypt the request with.
+        pub fn new_requesting_renegotiation(
+            filter: F,
+            certificate_der: &[u8],
+            private_key_der: &[u8],
+            alpn: &[Alpn],
+        ) -> Self {
+            let mut builder = Self::acceptor_builder(certificate_der, private_key_der, alpn);
+            builder
+                .set_max_proto_version(Some(SslVersion::TLS1_2))
+                .expect("can set version");
+            builder
+                .set_cipher_list("ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256")
+                .expect("can set cipher");
+            Self {
+                filter,
+                acceptor: Arc::new(builder.build()),
+                stall_handshake: false,
+                request_renegotiation: true,
+            }
+        }
+
+        fn acceptor_builder(
+            certificate_der: &[u8],
+            private_key_der: &[u8],
+            alpn: &[Alpn],
+        ) -> SslAcceptorBuilder {
+            let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())
+                .expect("can create builder");
+            builder
+                .set_certificate(&X509::from_der(certificate_der).expect("valid certificate"))
+                .expect("can set certificate");
+            builder
+                .set_private_key(
+                    &PKey::private_key_from_der(private_key_der).expect("valid private key"),
+                )
+                .expect("can set key");
+
+            let server_alpn = alpn
+                .iter()
+                .flat_map(|alpn| alpn.as_ref())
+                .copied()
+                .collect::<Vec<u8>>();
+            builder.set_alpn_select_callback(move |_ssl, client_alpn| {
+                boring_signal::ssl::select_next_proto(&server_alpn, client_alpn)
+                    .ok_or(AlpnError::NOACK)
+            });
+            builder
+        }
+
+        /// Makes the server accept connections but never answer the
+        /// handshake, for testing timeouts.
+        pub fn with_stalled_handshake(self) -> Self {
+            Self {
+                stall_handshake: true,
+                ..self
+            }
+        }
+    }
+
+    #[async_trait]
+    impl<F> TransportConnector for InMemoryTlsConnector<F>
+    where
+        F: Filter<Extract: Reply> + Clone + Send + Sync + 'static,
+    {
+        type Stream = SslStream<DuplexStream>;
+
+        async fn connect(
+            &self,
+            connection_params: &TransportConnectionParams,
+            alpn: Alpn,
+        ) -> Result<StreamAndInfo<Self::Stream>, TransportConnectError> {
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            let routes = self.filter.clone();
+            let acceptor = Arc::clone(&self.acceptor);
+            let stall_handshake = self.stall_handshake;
+            let request_renegotiation = self.request_renegotiation;
+            tokio::spawn(async move {
+                if stall_handshake {
+                    let _server = server;
+                    return std::future::pending().await;
+                }
+                let mut stream = match tokio_boring_signal::accept(&acceptor, server).await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::debug!("in-memory TLS server handshake failed: {e}");
+                        return;
+                    }
+                };
+                if request_renegotiation {
+                    let record = hello_request_record(stream.ssl());
+                    if let Err(e) = stream.get_mut().write_all(&record).await {
+                        log::debug!("in-memory TLS server couldn't ask to renegotiate: {e}");
+                        return;
+                    }
+                }
+                let one_element_iter = futures_util::stream::iter(vec![Ok::<_, io::Error>(stream)]);
+                warp::serve(routes).run_incoming(one_element_iter).await;
+            });
+
+            let route = TlsRouteFragment {
+                root_certs: connection_params.certs.clone(),
+                sni: Host::Domain(Arc::clone(&connection_params.sni)),
+                alpn: Some(connection_params.alpn_or(alpn)),
+                min_protocol_version: None,
+            };
+            let stream = StatelessTls::default()
+                .connect_over(client, route, "InMemoryTlsConnector".into())
+                .await?;
+            let tls = TlsSessionInfo::from_stream(&stream);
+            Ok(StreamAndInfo(
+                stream,
+                ServiceConnectionInfo {
+                    route_type: RouteType::Test,
+                    dns_source: DnsSource::Test,
+                    address: connection_params.tcp_host.clone(),
+                    tls,
+                },
+            ))
+        }
+    }
+
+    /// Builds a HelloRequest record, the server's request to renegotiate, for
+    /// `ssl` to send next.
+    ///
+    /// BoringSSL servers can't ask to renegotiate, so this encrypts the record
+    /// with the session's AES-128-GCM keys directly.
+    fn hello_request_record(ssl: &SslRef) -> Vec<u8> {
+        use boring_signal::symm::{encrypt_aead, Cipher};
+        use foreign_types::ForeignTypeRef as _;
+
+        const CONTENT_TYPE_HANDSHAKE: u8 = 22;
+        const TLS1_2: [u8; 2] = [3, 3];
+        // The message type (0, hello_request) and the length of its empty body.
+        const HELLO_REQUEST: [u8; 4] = [0, 0, 0, 0];
+
+        // The client's key, the server's key, the client's IV, and the
+        // server's IV.
+        let mut key_block = [0; 40];
+        // SAFETY: the pointer comes from a live `SslRef`, and the buffer is as
+        // long as the length given.
+        let result = unsafe {
+            boring_sys_signal::SSL_generate_key_block(
+                ssl.as_ptr(),
+                key_block.as_mut_ptr(),
+                key_block.len(),
+            )
+        };
+        assert_eq!(result, 1, "can generate key block");
+        let server_key = &key_block[16..32];
+        let server_iv = &key_block[36..40];
+
+        // The server's Finished message was the first record with these keys.
+        let sequence_number = 1u64.to_be_bytes();
+        let nonce = [server_iv, &sequence_number[..]].concat();
+        let plaintext_length = u16::try_from(HELLO_REQUEST.len()).unwrap().to_be_bytes();
+        let aad = [
+            &sequence_number[..],
+            &[CONTENT_TYPE_HANDSHAKE][..],
+            &TLS1_2[..],
+            &plaintext_length[..],
+        ]
+        .concat();
+        let mut tag = [0; 16];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_128_gcm(),
+            server_key,
+            Some(&nonce),
+            &aad,
+            &HELLO_REQUEST,
+            &mut tag,
+        )
+        .expect("can encrypt");
+
+        let fragment = [&sequence_number[..], &ciphertext[..], &tag[..]].concat();
+        let fragment_length = u16::try_from(fragment.len()).unwrap().to_be_bytes();
+        [
+            &[CONTENT_TYPE_HANDSHAKE][..],
+            &TLS1_2[..],
+            &fragment_length[..],
+            &fragment[..],
+        ]
+        .concat()
+    }
+
     /// Trivial [`Sink`] and [`Stream`] implementation over a pair of buffered channels.
     pub struct TestStream<T, E> {
         rx: tokio::sync::mpsc::Receiver<Result<T, E>>,
@@ -618,12 +1268,30 @@ pub mod testutil {
 
 #[cfg(test)]
 pub(crate) mod test {
+    use std::borrow::Cow;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use assert_matches::assert_matches;
     use const_str::ip_addr;
     use http::Request;
+    use nonzero_ext::nonzero;
+    use test_case::test_case;
+    use warp::Filter as _;
 
+    use crate::certs::{CertProfiles, RootCertificates};
+    use crate::errors::{ConnectErrorCategory, TransportConnectError};
     use crate::host::Host;
+    use crate::tcp_ssl::testutil::{localhost_params, SERVER_CERTIFICATE, SERVER_HOSTNAME};
+    use crate::testutil::{
+        InMemoryTlsConnector, InMemoryWarpConnector, LeakCheck, RecordingConnector,
+    };
     use crate::utils::basic_authorization;
-    use crate::{DnsSource, HttpRequestDecorator, RouteType, ServiceConnectionInfo};
+    use crate::{
+        Alpn, ConnectFailure, ConnectionParams, DnsSource, DynamicHeader, HttpRequestDecorator,
+        HttpRequestDecoratorSeq, RouteType, ServiceConnectionInfo, StreamAndInfo,
+        TransportConnectionParams, TransportConnector,
+    };
 
     #[test]
     fn connection_info_description() {
@@ -631,6 +1299,7 @@ pub(crate) mod test {
             address: Host::Domain("test.signal.org".into()),
             dns_source: DnsSource::SystemLookup,
             route_type: RouteType::Test,
+            tls: None,
         };
 
         assert_eq!(
@@ -679,4 +1348,224 @@ pub(crate) mod test {
             parts.headers.get(http::header::AUTHORIZATION).unwrap()
         );
     }
+
+    #[test]
+    fn later_authorization_decorator_replaces_earlier_one() {
+        struct TestAuth(&'static str);
+        impl crate::AsHttpHeader for TestAuth {
+            const HEADER_NAME: http::HeaderName = http::header::AUTHORIZATION;
+            fn header_value(&self) -> http::HeaderValue {
+                basic_authorization(self.0, "psswd")
+            }
+        }
+
+        let mut decorators =
+            HttpRequestDecoratorSeq::from(HttpRequestDecorator::from(TestAuth("first")));
+        decorators.add(TestAuth("second").into());
+        let builder = decorators.decorate_request(Request::get("https://chat.signal.org/"));
+        let (parts, _) = builder.body(()).unwrap().into_parts();
+        assert_eq!(
+            parts
+                .headers
+                .get_all(http::header::AUTHORIZATION)
+                .iter()
+                .collect::<Vec<_>>(),
+            [&basic_authorization("second", "psswd")]
+        );
+    }
+
+    #[test]
+    fn dynamic_decorator_computes_a_header_for_each_request() {
+        let counter = Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let decorators = HttpRequestDecoratorSeq::from(HttpRequestDecorator::Dynamic(
+            DynamicHeader::new(move || {
+                let nonce = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                (
+                    http::HeaderName::from_static("x-nonce"),
+                    nonce.to_string().try_into().expect("valid header value"),
+                )
+            }),
+        ));
+
+        let nonces = [(), ()].map(|()| {
+            let builder = decorators.decorate_request(Request::get("https://chat.signal.org/"));
+            let (parts, _) = builder.body(()).unwrap().into_parts();
+            parts.headers.get("x-nonce").expect("has nonce").clone()
+        });
+        assert_ne!(nonces[0], nonces[1]);
+        assert_eq!(decorators.redacted_descriptions(), ["dynamic header"]);
+    }
+
+    fn route(host: &'static str) -> ConnectionParams {
+        ConnectionParams {
+            route_type: RouteType::Test,
+            http_host: host.into(),
+            http_request_decorator: HttpRequestDecoratorSeq::default(),
+            connection_confirmation_header: None,
+            transport: TransportConnectionParams {
+                sni: host.into(),
+                tcp_host: Host::Domain(host.into()),
+                port: nonzero!(443u16),
+                certs: RootCertificates::Native,
+                dns_resolver: None,
+                alpn: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_with_cert_profile_keeps_the_routes_alpn() {
+        let connector = RecordingConnector::default();
+        let profiles = CertProfiles::default().with_profile("native", RootCertificates::Native);
+        for route in [
+            route("http1.signal.org.local").with_alpn(Alpn::Http1_1),
+            route("default.signal.org.local"),
+        ] {
+            connector
+                .connect_with_cert_profile(&route.transport, &profiles, "native", Alpn::Http2)
+                .await
+                .expect("can connect");
+        }
+
+        assert_matches!(
+            connector
+                .connect_with_cert_profile(
+                    &route("missing.signal.org.local").transport,
+                    &profiles,
+                    "missing",
+                    Alpn::Http2,
+                )
+                .await,
+            Err(TransportConnectError::InvalidConfiguration)
+        );
+        assert_eq!(
+            connector.attempts(),
+            [
+                ("http1.signal.org.local".into(), Alpn::Http1_1),
+                ("default.signal.org.local".into(), Alpn::Http2),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_connect_reports_category_and_retryability() {
+        let connector = crate::tcp_ssl::DirectConnector::new(
+            crate::dns::DnsResolver::new_from_static_map(Default::default()),
+        );
+        let result = connector
+            .connect_with_result(&route("unresolvable.signal.org.local"), Alpn::Http1_1)
+            .await;
+
+        assert_eq!(
+            result.route.as_deref(),
+            Some("test unresolvable.signal.org.local")
+        );
+        let failure = assert_matches!(result.outcome, Err(failure) => failure);
+        assert_matches!(
+            failure,
+            ConnectFailure {
+                category: ConnectErrorCategory::Dns,
+                retryable: true,
+                error: TransportConnectError::DnsError,
+            }
+        );
+    }
+
+    fn in_memory_tls_connector(
+        alpn: &[Alpn],
+    ) -> InMemoryTlsConnector<impl warp::Filter<Extract = (&'static str,)> + Clone> {
+        InMemoryTlsConnector::new(
+            warp::any().map(|| "hello"),
+            SERVER_CERTIFICATE.cert.der(),
+            SERVER_CERTIFICATE.key_pair.serialized_der(),
+            alpn,
+        )
+    }
+
+    fn in_memory_tls_params(certs: RootCertificates) -> TransportConnectionParams {
+        TransportConnectionParams {
+            certs,
+            ..localhost_params(443)
+        }
+    }
+
+    #[test_case(&[Alpn::Http2, Alpn::Http1_1], Some(&b"http/1.1"[..]); "agreed")]
+    #[test_case(&[Alpn::Http2], None; "not offered by the server")]
+    #[tokio::test]
+    async fn in_memory_tls_connector_negotiates_alpn(
+        server_alpn: &[Alpn],
+        expected: Option<&[u8]>,
+    ) {
+        let connector = in_memory_tls_connector(server_alpn);
+        let StreamAndInfo(stream, info) = connector
+            .connect(
+                &in_memory_tls_params(RootCertificates::FromDer(Cow::Borrowed(
+                    SERVER_CERTIFICATE.cert.der(),
+                ))),
+                Alpn::Http1_1,
+            )
+            .await
+            .expect("handshake succeeds");
+
+        assert_matches!(info.tls, Some(_));
+        assert_eq!(stream.ssl().selected_alpn_protocol(), expected);
+    }
+
+    #[tokio::test]
+    async fn in_memory_tls_connector_rejects_untrusted_certificate() {
+        let other_root = rcgen::generate_simple_self_signed([SERVER_HOSTNAME.to_string()])
+            .expect("can generate");
+        let connector = in_memory_tls_connector(&[Alpn::Http1_1]);
+        let result = connector
+            .connect(
+                &in_memory_tls_params(RootCertificates::FromDer(Cow::Owned(
+                    other_root.cert.der().to_vec(),
+                ))),
+                Alpn::Http1_1,
+            )
+            .await;
+        assert_matches!(result, Err(TransportConnectError::SslFailedHandshake(_)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn in_memory_tls_connector_can_stall_the_handshake() {
+        let connector = in_memory_tls_connector(&[Alpn::Http1_1]).with_stalled_handshake();
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            connector.connect(
+                &in_memory_tls_params(RootCertificates::FromDer(Cow::Borrowed(
+                    SERVER_CERTIFICATE.cert.der(),
+                ))),
+                Alpn::Http1_1,
+            ),
+        )
+        .await;
+        assert_matches!(result, Err(_));
+    }
+
+    #[tokio::test]
+    async fn leak_check_passes_once_everything_is_closed() {
+        let leak_check = LeakCheck::start();
+        let connector = leak_check.track(InMemoryWarpConnector::new(warp::any().map(|| "hello")));
+        let StreamAndInfo(stream, _info) = connector
+            .connect(
+                &in_memory_tls_params(RootCertificates::Native),
+                Alpn::Http1_1,
+            )
+            .await
+            .expect("can connect");
+        assert_eq!(connector.open_streams().count(), 1);
+
+        // Closing the stream lets the server task exit too.
+        drop(stream);
+        leak_check.settle().await;
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "1 tasks still running")]
+    async fn leak_check_catches_a_leaked_task() {
+        let leak_check = LeakCheck::start();
+        let _never_joined = tokio::spawn(std::future::pending::<()>());
+        leak_check.settle().await;
+    }
 }
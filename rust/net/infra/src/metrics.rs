@@ -0,0 +1,353 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Counting what happens while connecting, so apps can export it.
+//!
+//! [`ConnectionMetrics`] is told about each connection attempt and the steps
+//! along the way. [`OpenMetricsRecorder`] is an implementation that keeps
+//! running totals and renders them in the [OpenMetrics] text format, which
+//! Prometheus also accepts, for an app to serve from a `/metrics` endpoint.
+//!
+//! [OpenMetrics]: https://github.com/prometheus/OpenMetrics/blob/main/specification/OpenMetrics.md
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::errors::ConnectErrorCategory;
+
+/// Receives notice of connection events as they happen.
+pub trait ConnectionMetrics: std::fmt::Debug + Send + Sync {
+    /// A connection attempt was started.
+    fn attempt_started(&self);
+    /// A connection attempt produced a connection.
+    fn attempt_succeeded(&self);
+    /// A connection attempt failed with an error of the given `category`.
+    fn attempt_failed(&self, category: ConnectErrorCategory);
+    /// A DNS lookup finished after `duration`.
+    fn dns_lookup(&self, duration: Duration, succeeded: bool);
+    /// A TLS handshake finished after `duration`.
+    fn tls_handshake(&self, duration: Duration, succeeded: bool);
+    /// A request on an established connection got its response after `rtt`.
+    fn round_trip(&self, rtt: Duration);
+}
+
+/// A [`ConnectionMetrics`] that accumulates everything it's told and
+/// [renders](Self::render) it in the OpenMetrics text format.
+///
+/// The metrics, whose names won't change, are:
+///
+/// | Name                                  | Type      | Labels             |
+/// |---------------------------------------|-----------|--------------------|
+/// | `libsignal_net_connect_attempts`      | counter   |                    |
+/// | `libsignal_net_connect_successes`     | counter   |                    |
+/// | `libsignal_net_connect_failures`      | counter   | `reason`           |
+/// | `libsignal_net_dns_lookups`           | counter   | `result`           |
+/// | `libsignal_net_dns_lookup_seconds`    | histogram |                    |
+/// | `libsignal_net_tls_handshakes`        | counter   | `result`           |
+/// | `libsignal_net_tls_handshake_seconds` | histogram |                    |
+/// | `libsignal_net_round_trip_seconds`    | histogram |                    |
+///
+/// `reason` is the [`ConnectErrorCategory`] of the failure, in lowercase,
+/// and `result` is `success` or `failure`. Counters are rendered with the
+/// `_total` suffix that OpenMetrics requires, and a label value shows up only
+/// once it's been counted.
+///
+#[derive(Debug, Default)]
+pub struct OpenMetricsRecorder(Mutex<Totals>);
+
+#[derive(Debug, Default)]
+struct Totals {
+    attempts: u64,
+    successes: u64,
+    failures: BTreeMap<&'static str, u64>,
+    dns_lookups: Outcomes,
+    dns_lookup_duration: Histogram,
+    tls_handshakes: Outcomes,
+    tls_handshake_duration: Histogram,
+    round_trip: Histogram,
+}
+
+#[derive(Debug, Default)]
+struct Outcomes {
+    success: u64,
+    failure: u64,
+}
+
+impl Outcomes {
+    fn count(&mut self, succeeded: bool) {
+        if succeeded {
+            self.success += 1;
+        } else {
+            self.failure += 1;
+        }
+    }
+}
+
+/// Bucket upper bounds, in seconds, shared by all the histograms.
+const BUCKET_BOUNDS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// How many observations fell in each bucket, without the ones below it.
+    buckets: [u64; BUCKET_BOUNDS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = BUCKET_BOUNDS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+impl OpenMetricsRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renders the totals so far as an OpenMetrics text exposition, ending
+    /// with `# EOF`.
+    pub fn render(&self) -> String {
+        let Totals {
+            attempts,
+            successes,
+            failures,
+            dns_lookups,
+            dns_lookup_duration,
+            tls_handshakes,
+            tls_handshake_duration,
+            round_trip,
+        } = &*self.0.lock().expect("not poisoned");
+
+        let mut out = String::new();
+        write_counter(
+            &mut out,
+            "libsignal_net_connect_attempts",
+            "Connection attempts started.",
+            [("", *attempts)],
+        );
+        write_counter(
+            &mut out,
+            "libsignal_net_connect_successes",
+            "Connection attempts that produced a connection.",
+            [("", *successes)],
+        );
+        write_counter(
+            &mut out,
+            "libsignal_net_connect_failures",
+            "Connection attempts that failed, by the kind of failure.",
+            failures
+                .iter()
+                .map(|(reason, count)| (format!("{{reason=\"{reason}\"}}"), *count)),
+        );
+        write_counter(
+            &mut out,
+            "libsignal_net_dns_lookups",
+            "DNS lookups, by whether they succeeded.",
+            result_labeled(dns_lookups),
+        );
+        write_histogram(
+            &mut out,
+            "libsignal_net_dns_lookup_seconds",
+            "How long DNS lookups took.",
+            dns_lookup_duration,
+        );
+        write_counter(
+            &mut out,
+            "libsignal_net_tls_handshakes",
+            "TLS handshakes, by whether they succeeded.",
+            result_labeled(tls_handshakes),
+        );
+        write_histogram(
+            &mut out,
+            "libsignal_net_tls_handshake_seconds",
+            "How long TLS handshakes took.",
+            tls_handshake_duration,
+        );
+        write_histogram(
+            &mut out,
+            "libsignal_net_round_trip_seconds",
+            "How long requests on established connections took to get a response.",
+            round_trip,
+        );
+        out.push_str("# EOF\n");
+        out
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Totals)) {
+        f(&mut self.0.lock().expect("not poisoned"))
+    }
+}
+
+impl ConnectionMetrics for OpenMetricsRecorder {
+    fn attempt_started(&self) {
+        self.update(|totals| totals.attempts += 1)
+    }
+
+    fn attempt_succeeded(&self) {
+        self.update(|totals| totals.successes += 1)
+    }
+
+    fn attempt_failed(&self, category: ConnectErrorCategory) {
+        let reason: &'static str = category.into();
+        self.update(|totals| *totals.failures.entry(reason).or_default() += 1)
+    }
+
+    fn dns_lookup(&self, duration: Duration, succeeded: bool) {
+        self.update(|totals| {
+            totals.dns_lookups.count(succeeded);
+            totals.dns_lookup_duration.observe(duration);
+        })
+    }
+
+    fn tls_handshake(&self, duration: Duration, succeeded: bool) {
+        self.update(|totals| {
+            totals.tls_handshakes.count(succeeded);
+            totals.tls_handshake_duration.observe(duration);
+        })
+    }
+
+    fn round_trip(&self, rtt: Duration) {
+        self.update(|totals| totals.round_trip.observe(rtt))
+    }
+}
+
+fn result_labeled(outcomes: &Outcomes) -> [(&'static str, u64); 2] {
+    [
+        ("{result=\"success\"}", outcomes.success),
+        ("{result=\"failure\"}", outcomes.failure),
+    ]
+}
+
+/// Writes a counter family, with one sample for each set of labels.
+fn write_counter<L: std::fmt::Display>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (L, u64)>,
+) {
+    writeln!(out, "# TYPE {name} counter").expect("can write to a string");
+    writeln!(out, "# HELP {name} {help}").expect("can write to a string");
+    for (labels, value) in samples {
+        writeln!(out, "{name}_total{labels} {value}").expect("can write to a string");
+    }
+}
+
+fn write_histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    let Histogram {
+        buckets,
+        count,
+        sum,
+    } = histogram;
+    writeln!(out, "# TYPE {name} histogram").expect("can write to a string");
+    writeln!(out, "# HELP {name} {help}").expect("can write to a string");
+    let mut cumulative = 0;
+    for (bound, in_bucket) in BUCKET_BOUNDS.iter().zip(buckets) {
+        cumulative += in_bucket;
+        writeln!(out, "{name}_bucket{{le=\"{bound:?}\"}} {cumulative}")
+            .expect("can write to a string");
+    }
+    writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}").expect("can write to a string");
+    writeln!(out, "{name}_sum {sum:?}").expect("can write to a string");
+    writeln!(out, "{name}_count {count}").expect("can write to a string");
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::errors::{FailedHandshakeReason, TransportConnectError};
+
+    /// Checks the parts of the OpenMetrics format that a renderer could get
+    /// wrong: every sample belongs to a family declared before it, each
+    /// family is declared once, and the exposition ends with `# EOF`.
+    fn assert_valid_open_metrics(rendered: &str) {
+        let body = rendered.strip_suffix("# EOF\n").expect("ends with # EOF");
+        let mut families = HashSet::new();
+        let mut current: Option<(&str, &str)> = None;
+        for line in body.lines() {
+            if let Some(declaration) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = declaration.split_once(' ').expect("has a type");
+                assert!(families.insert(name), "{name} declared twice");
+                current = Some((name, kind));
+            } else if let Some(help) = line.strip_prefix("# HELP ") {
+                let (name, _) = help.split_once(' ').expect("has help text");
+                assert_eq!(Some(name), current.map(|(name, _)| name));
+            } else {
+                let (sample, value) = line.rsplit_once(' ').expect("has a value");
+                value.parse::<f64>().expect("value is a number");
+                let sample_name = sample.split('{').next().expect("not empty");
+                let (family, kind) = current.expect("family is declared first");
+                let suffixes: &[&str] = match kind {
+                    "counter" => &["_total"],
+                    "histogram" => &["_bucket", "_sum", "_count"],
+                    _ => panic!("unexpected type {kind}"),
+                };
+                assert!(
+                    suffixes
+                        .iter()
+                        .any(|suffix| sample_name == format!("{family}{suffix}")),
+                    "{line} doesn't belong to {family}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn render_produces_open_metrics_for_recorded_activity() {
+        let metrics = OpenMetricsRecorder::new();
+        for _ in 0..3 {
+            metrics.attempt_started();
+        }
+        metrics.dns_lookup(Duration::from_millis(20), true);
+        metrics.tls_handshake(Duration::from_millis(80), true);
+        metrics.attempt_succeeded();
+        metrics.tls_handshake(Duration::from_millis(300), false);
+        metrics.attempt_failed(
+            TransportConnectError::SslFailedHandshake(FailedHandshakeReason::TIMED_OUT).category(),
+        );
+        metrics.attempt_failed(TransportConnectError::DnsError.category());
+        metrics.round_trip(Duration::from_millis(40));
+        metrics.round_trip(Duration::from_secs(10));
+
+        let rendered = metrics.render();
+        assert_valid_open_metrics(&rendered);
+
+        let lines = Vec::from_iter(rendered.lines());
+        for expected in [
+            "# TYPE libsignal_net_connect_attempts counter",
+            "libsignal_net_connect_attempts_total 3",
+            "libsignal_net_connect_successes_total 1",
+            "libsignal_net_connect_failures_total{reason=\"dns\"} 1",
+            "libsignal_net_connect_failures_total{reason=\"tls\"} 1",
+            "libsignal_net_dns_lookups_total{result=\"success\"} 1",
+            "libsignal_net_dns_lookups_total{result=\"failure\"} 0",
+            "libsignal_net_tls_handshakes_total{result=\"failure\"} 1",
+            "libsignal_net_tls_handshake_seconds_bucket{le=\"0.1\"} 1",
+            "libsignal_net_tls_handshake_seconds_bucket{le=\"0.5\"} 2",
+            "libsignal_net_tls_handshake_seconds_count 2",
+            "# TYPE libsignal_net_round_trip_seconds histogram",
+            "libsignal_net_round_trip_seconds_bucket{le=\"0.025\"} 0",
+            "libsignal_net_round_trip_seconds_bucket{le=\"0.05\"} 1",
+            "libsignal_net_round_trip_seconds_bucket{le=\"5.0\"} 1",
+            "libsignal_net_round_trip_seconds_bucket{le=\"+Inf\"} 2",
+            "libsignal_net_round_trip_seconds_sum 10.04",
+            "libsignal_net_round_trip_seconds_count 2",
+        ] {
+            assert!(
+                lines.contains(&expected),
+                "missing {expected} in\n{rendered}"
+            );
+        }
+    }
+}
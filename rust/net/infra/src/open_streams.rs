@@ -0,0 +1,220 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Accounting for the streams produced by [`TransportConnector`]s.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use pin_project::pin_project;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::errors::TransportConnectError;
+use crate::{
+    Alpn, Connection, StreamAndInfo, TransportConnectionParams, TransportConnector, TransportInfo,
+};
+
+/// A count of open streams, with an optional cap on how many can be open at
+/// once.
+///
+/// Clones share the same count.
+#[derive(Clone, Debug, Default)]
+pub struct OpenStreams(Arc<OpenStreamsInner>);
+
+#[derive(Debug, Default)]
+struct OpenStreamsInner {
+    count: AtomicUsize,
+    limit: Option<usize>,
+}
+
+/// Keeps its stream counted in [`OpenStreams`] until dropped.
+#[derive(Debug)]
+pub struct OpenStreamPermit(OpenStreams);
+
+/// A stream that counts as open for as long as it exists.
+#[derive(Debug)]
+#[pin_project]
+pub struct CountedStream<S> {
+    #[pin]
+    inner: S,
+    _permit: OpenStreamPermit,
+}
+
+/// [`TransportConnector`] that keeps track of the streams produced by another.
+///
+/// When the count is at its limit, connecting fails with
+/// [`TransportConnectError::TooManyConnections`] without making an attempt.
+#[derive(Clone, Debug)]
+pub struct LimitOpenStreams<C> {
+    inner: C,
+    open_streams: OpenStreams,
+}
+
+impl OpenStreams {
+    /// Counts open streams without limiting them.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts open streams, allowing no more than `limit` at once.
+    pub fn with_limit(limit: usize) -> Self {
+        Self(Arc::new(OpenStreamsInner {
+            count: AtomicUsize::new(0),
+            limit: Some(limit),
+        }))
+    }
+
+    /// The number of streams that are currently open.
+    pub fn count(&self) -> usize {
+        self.0.count.load(Ordering::Relaxed)
+    }
+
+    /// Counts one more open stream, unless that would go over the limit.
+    pub fn try_open(&self) -> Result<OpenStreamPermit, TransportConnectError> {
+        let OpenStreamsInner { count, limit } = &*self.0;
+        count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |open| match limit {
+                Some(limit) if open >= *limit => None,
+                _ => Some(open + 1),
+            })
+            .map_err(|_| TransportConnectError::TooManyConnections)?;
+        Ok(OpenStreamPermit(self.clone()))
+    }
+}
+
+impl Drop for OpenStreamPermit {
+    fn drop(&mut self) {
+        self.0 .0.count.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<S> CountedStream<S> {
+    pub fn new(inner: S, permit: OpenStreamPermit) -> Self {
+        Self {
+            inner,
+            _permit: permit,
+        }
+    }
+}
+
+impl<C> LimitOpenStreams<C> {
+    pub fn new(inner: C, open_streams: OpenStreams) -> Self {
+        Self {
+            inner,
+            open_streams,
+        }
+    }
+
+    pub fn open_streams(&self) -> &OpenStreams {
+        &self.open_streams
+    }
+}
+
+#[async_trait]
+impl<C: TransportConnector> TransportConnector for LimitOpenStreams<C> {
+    type Stream = CountedStream<C::Stream>;
+
+    async fn connect(
+        &self,
+        connection_params: &TransportConnectionParams,
+        alpn: Alpn,
+    ) -> Result<StreamAndInfo<Self::Stream>, TransportConnectError> {
+        // Take the permit first so that concurrent attempts can't go over the
+        // limit together.
+        let permit = self.open_streams.try_open()?;
+        let StreamAndInfo(stream, info) = self.inner.connect(connection_params, alpn).await?;
+        Ok(StreamAndInfo(CountedStream::new(stream, permit), info))
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for CountedStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.project().inner.poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for CountedStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
+impl<S: Connection> Connection for CountedStream<S> {
+    fn transport_info(&self) -> TransportInfo {
+        self.inner.transport_info()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use nonzero_ext::nonzero;
+
+    use super::*;
+    use crate::certs::RootCertificates;
+    use crate::host::Host;
+    use crate::testutil::RecordingConnector;
+
+    #[tokio::test]
+    async fn connecting_past_the_limit_fails_until_a_stream_is_dropped() {
+        let connector =
+            LimitOpenStreams::new(RecordingConnector::default(), OpenStreams::with_limit(2));
+        let params = TransportConnectionParams {
+            sni: "localhost".into(),
+            tcp_host: Host::Domain("localhost".into()),
+            port: nonzero!(443u16),
+            certs: RootCertificates::Native,
+            dns_resolver: None,
+            alpn: None,
+        };
+        let open_streams = connector.open_streams().clone();
+
+        let first = connector
+            .connect(&params, Alpn::Http1_1)
+            .await
+            .expect("under limit");
+        let _second = connector
+            .connect(&params, Alpn::Http1_1)
+            .await
+            .expect("at limit");
+        assert_eq!(open_streams.count(), 2);
+
+        assert_matches!(
+            connector.connect(&params, Alpn::Http1_1).await,
+            Err(TransportConnectError::TooManyConnections)
+        );
+        assert_eq!(open_streams.count(), 2);
+
+        drop(first);
+        assert_eq!(open_streams.count(), 1);
+        let _third = connector
+            .connect(&params, Alpn::Http1_1)
+            .await
+            .expect("room was made");
+        assert_eq!(open_streams.count(), 2);
+    }
+}
@@ -199,16 +199,37 @@ impl_uses_transport!(HttpsServiceRoute, inner);
 impl_uses_transport!(UsePreconnect, inner);
 
 /// Error for [`connect()`].
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ConnectError<E> {
     /// The route provider did not produce any routes.
     NoResolvedRoutes,
     /// All attempts to connect failed, but none fatally.
-    AllAttemptsFailed,
+    ///
+    /// Carries how each attempt failed, as far as the connector could describe
+    /// it (see [`Connector::describe_failure`]).
+    AllAttemptsFailed(AttemptFailures),
     /// An attempt to connect failed fatally.
     FatalConnect(E),
 }
 
+/// How one connection attempt failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailedAttempt {
+    /// Loggable description of the route that was tried.
+    pub route: String,
+    /// Loggable description of the error connecting over it.
+    pub error: String,
+}
+
+/// The [`FailedAttempt`]s of a [`connect()`] that gave up, in the order they
+/// finished.
+///
+/// Displays as a list prefixed with `": "`, or as nothing at all when empty,
+/// so that it can directly follow a summary like "all connect attempts
+/// failed".
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttemptFailures(pub Vec<FailedAttempt>);
+
 /// Recorded success and failure information from [`connect()`].
 ///
 /// This should be used to update the internal state of the delay policy after
@@ -343,6 +364,7 @@ where
     let mut connects_started = 0;
     let mut connects_in_progress = FuturesUnordered::new();
     let mut outcomes = Vec::new();
+    let mut failures = Vec::new();
 
     #[derive(Debug)]
     enum Event<C, R> {
@@ -378,7 +400,9 @@ where
         // If there aren't any connection attempts in progress and there
         // also aren't gonna be any more, we've run out of possibilities.
         if poll_or_wait.is_none() && next_connect_in_progress.is_none() {
-            break Err(ConnectError::AllAttemptsFailed);
+            break Err(ConnectError::AllAttemptsFailed(AttemptFailures(
+                std::mem::take(&mut failures),
+            )));
         }
 
         let event = tokio::select! {
@@ -415,6 +439,10 @@ where
                 poll_schedule_for_next = false;
             }
             Event::ConnectionAttemptFinished((route, result, started)) => {
+                let failure = result
+                    .as_ref()
+                    .err()
+                    .and_then(|error| connector.describe_failure(&route, error));
                 let make_outcome = |result| (route, AttemptOutcome { started, result });
                 match result.map_err(&mut on_error) {
                     Ok(connection) => {
@@ -425,6 +453,7 @@ where
                     Err(ControlFlow::Continue(())) => {
                         // Record the non-fatal error outcome and move on.
                         outcomes.push(make_outcome(Err(UnsuccessfulOutcome)));
+                        failures.extend(failure);
                     }
                     Err(ControlFlow::Break(fatal_err)) => {
                         // This isn't a route-level error, it's a
@@ -468,12 +497,34 @@ impl<E: std::fmt::Display> std::fmt::Display for ConnectError<E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ConnectError::NoResolvedRoutes => f.write_str("no resolved routes"),
-            ConnectError::AllAttemptsFailed => f.write_str("all connect attempts failed"),
+            ConnectError::AllAttemptsFailed(failures) => {
+                write!(f, "all connect attempts failed{failures}")
+            }
             ConnectError::FatalConnect(e) => write!(f, "fatal connect error: {e}"),
         }
     }
 }
 
+impl LogSafeDisplay for FailedAttempt {}
+impl std::fmt::Display for FailedAttempt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { route, error } = self;
+        write!(f, "{route}: {error}")
+    }
+}
+
+impl LogSafeDisplay for AttemptFailures {}
+impl std::fmt::Display for AttemptFailures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut separator = ": ";
+        for failure in &self.0 {
+            write!(f, "{separator}{failure}")?;
+            separator = "; ";
+        }
+        Ok(())
+    }
+}
+
 const PER_CONNECTION_WAIT_DURATION: Duration = Duration::from_millis(500);
 
 fn pull_next_route_delay<F>(connects_in_progress: &FuturesUnordered<F>) -> Duration {
@@ -643,6 +694,7 @@ mod test {
                 ws_config: WebSocketConfig::default(),
                 headers: HeaderMap::default(),
                 endpoint: WS_ENDPOINT.clone(),
+                upgrade_timeout: None,
             },
             inner: HttpsProvider {
                 direct_host_header: "http-host".into(),
@@ -655,6 +707,7 @@ mod test {
                         path_prefix: "/front-host-path-prefix".into(),
                         front_name: "front-host",
                         return_routes_with_all_snis: true,
+                        alpn: None,
                     }],
                     http_version: HttpVersion::Http2,
                 },
@@ -678,6 +731,7 @@ mod test {
                     ws_config: WebSocketConfig::default(),
                     headers: HeaderMap::default(),
                     endpoint: WS_ENDPOINT.clone(),
+                    upgrade_timeout: None,
                 },
                 inner: HttpsTlsRoute {
                     fragment: HttpRouteFragment {
@@ -704,6 +758,7 @@ mod test {
                     ws_config: WebSocketConfig::default(),
                     headers: HeaderMap::default(),
                     endpoint: WS_ENDPOINT.clone(),
+                    upgrade_timeout: None,
                 },
                 inner: HttpsTlsRoute {
                     fragment: HttpRouteFragment {
@@ -730,6 +785,7 @@ mod test {
                     ws_config: WebSocketConfig::default(),
                     headers: HeaderMap::default(),
                     endpoint: WS_ENDPOINT.clone(),
+                    upgrade_timeout: None,
                 },
                 inner: HttpsTlsRoute {
                     fragment: HttpRouteFragment {
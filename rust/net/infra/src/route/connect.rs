@@ -11,8 +11,8 @@ use static_assertions::assert_impl_all;
 
 use crate::errors::TransportConnectError;
 use crate::route::{
-    ConnectionProxyRoute, DirectOrProxyRoute, HttpRouteFragment, HttpsTlsRoute, TcpRoute, TlsRoute,
-    TlsRouteFragment, TransportRoute, WebSocketRoute, WebSocketRouteFragment,
+    ConnectionProxyRoute, DirectOrProxyRoute, FailedAttempt, HttpRouteFragment, HttpsTlsRoute,
+    TcpRoute, TlsRoute, TlsRouteFragment, TransportRoute, WebSocketRoute, WebSocketRouteFragment,
     WebSocketServiceRoute,
 };
 use crate::ws::WebSocketConnectError;
@@ -20,6 +20,9 @@ use crate::ws::WebSocketConnectError;
 mod composed;
 pub use composed::*;
 
+mod deadline;
+pub use deadline::*;
+
 mod direct_or_proxy;
 pub use direct_or_proxy::*;
 
@@ -54,6 +57,14 @@ pub trait Connector<R, Inner> {
         route: R,
         log_tag: Arc<str>,
     ) -> impl Future<Output = Result<Self::Connection, Self::Error>> + Send;
+
+    /// Describes a failed attempt to connect over `route`, for
+    /// [`ConnectError::AllAttemptsFailed`](crate::route::ConnectError::AllAttemptsFailed).
+    ///
+    /// Returns `None` by default, since most routes can't be logged as-is.
+    fn describe_failure(&self, _route: &R, _error: &Self::Error) -> Option<FailedAttempt> {
+        None
+    }
 }
 
 pub trait ConnectorExt<R>: Connector<R, ()> {
@@ -216,6 +227,10 @@ impl<C: Connector<R, Inner>, R, Inner> Connector<R, Inner> for &C {
     ) -> impl Future<Output = Result<Self::Connection, Self::Error>> + Send {
         (*self).connect_over(over, route, log_tag)
     }
+
+    fn describe_failure(&self, route: &R, error: &Self::Error) -> Option<FailedAttempt> {
+        (*self).describe_failure(route, error)
+    }
 }
 
 #[cfg(any(test, feature = "test-util"))]
@@ -0,0 +1,248 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use pin_project::pin_project;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+use crate::errors::OperationTimeout;
+use crate::route::connect::Connector;
+use crate::{Connection, TransportInfo};
+
+/// [`Connector`] wrapper that puts a deadline on every read and write over the
+/// stream it connects over.
+///
+/// The wrapped connector is handed a [`DeadlineStream`] in place of the
+/// original stream, so whatever it produces (a websocket, say) fails once the
+/// transport under it stalls. See [`DeadlineStream`] for how the deadline is
+/// applied.
+#[derive(Clone, Debug)]
+pub struct DeadlineConnector<C> {
+    inner: C,
+    operation_timeout: Duration,
+}
+
+impl<C> DeadlineConnector<C> {
+    pub fn new(inner: C, operation_timeout: Duration) -> Self {
+        Self {
+            inner,
+            operation_timeout,
+        }
+    }
+}
+
+impl<C, R, Inner> Connector<R, Inner> for DeadlineConnector<C>
+where
+    C: Connector<R, DeadlineStream<Inner>>,
+{
+    type Connection = C::Connection;
+
+    type Error = C::Error;
+
+    fn connect_over(
+        &self,
+        over: Inner,
+        route: R,
+        log_tag: Arc<str>,
+    ) -> impl Future<Output = Result<Self::Connection, Self::Error>> + Send {
+        let Self {
+            inner,
+            operation_timeout,
+        } = self;
+        inner.connect_over(
+            DeadlineStream::new(over, *operation_timeout),
+            route,
+            log_tag,
+        )
+    }
+}
+
+/// Stream wrapper that fails reads and writes that stall for too long.
+///
+/// Whenever a read (or a write, flush, or shutdown) can't make progress, a
+/// timer is started; if the operation is still blocked when the timer fires,
+/// it fails with an [`std::io::Error`] of kind
+/// [`TimedOut`](std::io::ErrorKind::TimedOut) wrapping [`OperationTimeout`].
+/// Any progress cancels the timer, so a slow but steady transfer is never cut
+/// off. Reads and writes are timed independently, and an idle connection
+/// with no outstanding operation doesn't time out at all; that is left to
+/// keep-alive.
+#[derive(Debug)]
+#[pin_project]
+pub struct DeadlineStream<S> {
+    #[pin]
+    inner: S,
+    operation_timeout: Duration,
+    read_timer: Option<Pin<Box<Sleep>>>,
+    write_timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> DeadlineStream<S> {
+    pub fn new(inner: S, operation_timeout: Duration) -> Self {
+        Self {
+            inner,
+            operation_timeout,
+            read_timer: None,
+            write_timer: None,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+/// Whether `error` is a read or write failing because its [`DeadlineStream`]
+/// deadline passed.
+pub fn is_operation_timeout(error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::TimedOut
+        && error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<OperationTimeout>())
+}
+
+/// Applies the deadline held in `timer` to the result of polling an operation.
+fn with_deadline<T>(
+    timer: &mut Option<Pin<Box<Sleep>>>,
+    operation_timeout: Duration,
+    cx: &mut Context<'_>,
+    poll: Poll<std::io::Result<T>>,
+) -> Poll<std::io::Result<T>> {
+    if poll.is_ready() {
+        *timer = None;
+        return poll;
+    }
+    let sleep = timer.get_or_insert_with(|| Box::pin(tokio::time::sleep(operation_timeout)));
+    match sleep.as_mut().poll(cx) {
+        Poll::Ready(()) => {
+            *timer = None;
+            Poll::Ready(Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                OperationTimeout,
+            )))
+        }
+        Poll::Pending => Poll::Pending,
+    }
+}
+
+impl<S: Connection> Connection for DeadlineStream<S> {
+    fn transport_info(&self) -> TransportInfo {
+        self.inner.transport_info()
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for DeadlineStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.project();
+        let poll = this.inner.poll_read(cx, buf);
+        with_deadline(this.read_timer, *this.operation_timeout, cx, poll)
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for DeadlineStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.project();
+        let poll = this.inner.poll_write(cx, buf);
+        with_deadline(this.write_timer, *this.operation_timeout, cx, poll)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.project();
+        let poll = this.inner.poll_flush(cx);
+        with_deadline(this.write_timer, *this.operation_timeout, cx, poll)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.project();
+        let poll = this.inner.poll_shutdown(cx);
+        with_deadline(this.write_timer, *this.operation_timeout, cx, poll)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    use super::*;
+
+    const OPERATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn assert_operation_timeout(error: std::io::Error) {
+        assert!(is_operation_timeout(&error), "unexpected error: {error:?}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn write_times_out_if_peer_stops_reading() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut client = DeadlineStream::new(client, OPERATION_TIMEOUT);
+
+        // The server reads a little and then stops, leaving the rest of the
+        // write stuck in the full buffer.
+        let server_task = tokio::spawn(async move {
+            let mut buf = [0; 32];
+            server.read_exact(&mut buf).await.expect("can read");
+            server
+        });
+
+        let start = tokio::time::Instant::now();
+        let error = client
+            .write_all(&[0; 1024])
+            .await
+            .expect_err("write should not complete");
+        assert_operation_timeout(error);
+        assert_eq!(start.elapsed(), OPERATION_TIMEOUT);
+
+        let _server = server_task.await.expect("server finished");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn read_times_out_if_peer_sends_nothing() {
+        let (client, _server) = tokio::io::duplex(64);
+        let mut client = DeadlineStream::new(client, OPERATION_TIMEOUT);
+
+        let mut buf = [0; 8];
+        let error = client
+            .read(&mut buf)
+            .await
+            .expect_err("read should not complete");
+        assert_operation_timeout(error);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn progress_renews_deadline() {
+        const CHUNKS: usize = 4;
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut client = DeadlineStream::new(client, OPERATION_TIMEOUT);
+
+        // Each chunk arrives well within the deadline, but the whole transfer
+        // takes longer than a single deadline.
+        tokio::spawn(async move {
+            for _ in 0..CHUNKS {
+                tokio::time::sleep(OPERATION_TIMEOUT / 2).await;
+                server.write_all(&[1]).await.expect("can write");
+            }
+            std::future::pending::<()>().await
+        });
+
+        let start = tokio::time::Instant::now();
+        let mut buf = [0; CHUNKS];
+        client.read_exact(&mut buf).await.expect("not timed out");
+        assert!(start.elapsed() > OPERATION_TIMEOUT);
+    }
+}
@@ -10,7 +10,7 @@ use std::time::Duration;
 use futures_util::TryFutureExt as _;
 
 use super::Connector;
-use crate::route::ResolvedRoute;
+use crate::route::{FailedAttempt, ResolvedRoute};
 use crate::utils::NetworkChangeEvent;
 
 /// A [`Connector`] that listens for network changes and aborts sooner if one happens *and* a
@@ -134,6 +134,13 @@ where
             }
         }
     }
+
+    fn describe_failure(&self, route: &R, error: &Self::Error) -> Option<FailedAttempt> {
+        match error {
+            InterfaceChangedOr::InterfaceChanged => None,
+            InterfaceChangedOr::Other(error) => self.inner.describe_failure(route, error),
+        }
+    }
 }
 
 /// Fetches the local IP address that represents the current preferred network route.
@@ -15,7 +15,7 @@ use crate::dns::dns_utils::log_safe_domain;
 use crate::errors::LogSafeDisplay;
 use crate::host::Host;
 use crate::route::{
-    ConnectionProxyKind, ConnectionProxyRoute, Connector, DirectOrProxyRoute,
+    ConnectionProxyKind, ConnectionProxyRoute, Connector, DirectOrProxyRoute, FailedAttempt,
     HttpProxyRouteFragment, HttpsProxyRoute, HttpsTlsRoute, ProxyTarget, ResolveHostnames,
     ResolvedRoute, SocksRoute, TcpRoute, TlsRoute, TransportRoute, UnresolvedHost,
     UnresolvedTransportRoute, UnresolvedWebsocketServiceRoute, UsesTransport, DEFAULT_HTTPS_PORT,
@@ -55,7 +55,8 @@ impl<R: UsesTransport, D> UsesTransport for WithLoggableDescription<R, D> {
 /// [`Connector`] implementation for [`WithLoggableDescription`].
 ///
 /// Delegates to the wrapped connector, and produces on success its connection
-/// along with the loggable description from the input route.
+/// along with the loggable description from the input route. Failed attempts
+/// are [described](Connector::describe_failure) by that same description.
 pub struct DescribedRouteConnector<C>(pub C);
 
 /// Loggable description for a [`UnresolvedWebsocketServiceRoute`].
@@ -89,8 +90,11 @@ impl<R: ResolvedRoute, D> ResolvedRoute for WithLoggableDescription<R, D> {
     }
 }
 
-impl<R: Clone + Send, Inner, C: Connector<R, Inner>, D: Send>
-    Connector<WithLoggableDescription<R, D>, Inner> for DescribedRouteConnector<C>
+impl<R, Inner, C, D> Connector<WithLoggableDescription<R, D>, Inner> for DescribedRouteConnector<C>
+where
+    R: Clone + Send,
+    C: Connector<R, Inner, Error: LogSafeDisplay>,
+    D: LogSafeDisplay + Send,
 {
     type Connection = (C::Connection, D);
 
@@ -106,6 +110,17 @@ impl<R: Clone + Send, Inner, C: Connector<R, Inner>, D: Send>
             .connect_over(over, route.route, log_tag)
             .map_ok(|c| (c, route.description))
     }
+
+    fn describe_failure(
+        &self,
+        route: &WithLoggableDescription<R, D>,
+        error: &Self::Error,
+    ) -> Option<FailedAttempt> {
+        Some(FailedAttempt {
+            route: route.description.to_string(),
+            error: error.to_string(),
+        })
+    }
 }
 
 impl LogSafeDisplay for UnresolvedRouteDescription {}
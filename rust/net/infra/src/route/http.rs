@@ -67,6 +67,9 @@ pub struct DomainFrontConfig {
     pub front_name: &'static str,
     /// Whether to use all SNIs or just one.
     pub return_routes_with_all_snis: bool,
+    /// If present, the ALPN protocol to offer on this front's routes, instead of the one for the
+    /// provider's [`HttpVersion`].
+    pub alpn: Option<Alpn>,
 }
 
 impl<F, P> HttpsProvider<F, P> {
@@ -116,6 +119,7 @@ impl RouteProvider for DomainFrontRouteProvider {
                       path_prefix,
                       front_name,
                       return_routes_with_all_snis,
+                      alpn,
                   }| {
                 let sni_list = if *return_routes_with_all_snis {
                     &**sni_list
@@ -134,7 +138,7 @@ impl RouteProvider for DomainFrontRouteProvider {
                         fragment: TlsRouteFragment {
                             root_certs: root_certs.clone(),
                             sni: Host::Domain(Arc::clone(sni)),
-                            alpn: Some((*http_version).into()),
+                            alpn: Some(alpn.unwrap_or((*http_version).into())),
                             min_protocol_version: None,
                         },
                     },
@@ -242,6 +246,7 @@ mod test {
                         path_prefix: "/prefix-1".into(),
                         front_name: "front-1",
                         return_routes_with_all_snis: true,
+                        alpn: None,
                     },
                     DomainFrontConfig {
                         http_host: "front-host-2".into(),
@@ -250,6 +255,7 @@ mod test {
                         path_prefix: "/prefix-2".into(),
                         front_name: "front-2",
                         return_routes_with_all_snis: false,
+                        alpn: Some(Alpn::Http2),
                     },
                 ],
                 http_version: HttpVersion::Http1_1,
@@ -337,7 +343,7 @@ mod test {
                         fragment: TlsRouteFragment {
                             root_certs: RootCertificates::Native,
                             sni: Host::Domain("front-sni-2b".into()),
-                            alpn: Some(Alpn::Http1_1),
+                            alpn: Some(Alpn::Http2),
                             min_protocol_version: None,
                         },
                         inner: TcpRoute {
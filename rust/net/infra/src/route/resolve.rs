@@ -66,8 +66,27 @@ pub trait Resolver {
 }
 
 impl Resolver for DnsResolver {
+    /// Looks up `hostname` like [`DnsResolver::lookup_ip_preferring_pinned`],
+    /// keeping only the addresses allowed by the resolver's
+    /// [filters](DnsResolver::add_address_filter).
+    ///
+    /// The lookup is reported to the resolver's
+    /// [metrics](DnsResolver::set_metrics), if it has any.
     fn lookup_ip(&self, hostname: &str) -> impl Future<Output = Result<LookupResult, DnsError>> {
-        DnsResolver::lookup_ip(self, hostname)
+        async move {
+            let started = tokio::time::Instant::now();
+            let result = self.lookup_ip_preferring_pinned(hostname).await;
+            if let Some(metrics) = self.metrics() {
+                metrics.dns_lookup(started.elapsed(), result.is_ok());
+            }
+            let result = result?;
+            let filtered = self.apply_address_filters(result);
+            if filtered.is_empty() {
+                log::warn!("all resolved addresses were removed by the address filters");
+                return Err(DnsError::LookupFailed);
+            }
+            Ok(filtered)
+        }
     }
 }
 
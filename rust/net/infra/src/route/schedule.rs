@@ -20,6 +20,7 @@ use tokio::time::{Duration, Instant};
 use crate::dns::dns_utils::log_safe_domain;
 use crate::dns::DnsError;
 use crate::route::{ResolveHostnames, ResolvedRoute, Resolver, TransportRoute, UsesTransport};
+use crate::tcp_ssl::ipv6_suspicion::Ipv6Suspicion;
 use crate::utils::binary_heap::{MinKeyValueQueue, Queue};
 use crate::utils::future::SomeOrPending;
 use crate::utils::NetworkChangeEvent;
@@ -31,6 +32,9 @@ use crate::utils::NetworkChangeEvent;
 #[derive(Clone)]
 pub struct RouteResolver {
     pub allow_ipv6: bool,
+    /// If set, routes that connect directly to IPv4 addresses are tried first
+    /// while IPv6 is suspect.
+    pub ipv6_suspicion: Option<Ipv6Suspicion>,
 }
 
 /// A policy object that decides how much to delay a route.
@@ -99,7 +103,10 @@ pub struct ConnectionOutcomeParams {
 
 impl Default for RouteResolver {
     fn default() -> Self {
-        Self { allow_ipv6: true }
+        Self {
+            allow_ipv6: true,
+            ipv6_suspicion: None,
+        }
     }
 }
 
@@ -120,7 +127,13 @@ impl RouteResolver {
     where
         R: ResolveHostnames<Resolved: ResolvedRoute> + Clone + 'static,
     {
-        let Self { allow_ipv6 } = self;
+        let Self {
+            allow_ipv6,
+            ipv6_suspicion,
+        } = self;
+        let prefer_ipv4 = ipv6_suspicion
+            .as_ref()
+            .is_some_and(Ipv6Suspicion::is_suspect);
 
         let resolved = eagerly_resolve_each(ordered_routes, resolver).filter_map(
             |(resolution_result, meta)| {
@@ -143,6 +156,11 @@ impl RouteResolver {
                 routes
                     .routes
                     .retain(|route| route.immediate_target().is_ipv4())
+            } else if prefer_ipv4 {
+                // The sort is stable, so each family keeps its own order.
+                routes
+                    .routes
+                    .sort_by_key(|route| route.immediate_target().is_ipv6())
             }
             (routes, meta)
         })
@@ -807,7 +825,7 @@ mod test {
 
     #[tokio::test(start_paused = true)]
     async fn single_resolved_route_e2e() {
-        let resolver = RouteResolver { allow_ipv6: true };
+        let resolver = RouteResolver::default();
         let name_resolver = HashMap::from([(
             "domain-name",
             LookupResult {
@@ -839,9 +857,49 @@ mod test {
         );
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn suspect_ipv6_routes_scheduled_after_ipv4() {
+        let (_network_change_tx, network_change_event) = tokio::sync::watch::channel(());
+        let ipv6_suspicion = Ipv6Suspicion::new(Duration::from_secs(60), &network_change_event);
+        ipv6_suspicion.report_stalled_handshake();
+        let resolver = RouteResolver {
+            ipv6_suspicion: Some(ipv6_suspicion),
+            ..RouteResolver::default()
+        };
+        let name_resolver = HashMap::from([(
+            "domain-name",
+            LookupResult {
+                ipv4: vec![ip_addr!(v4, "192.0.2.1")],
+                ipv6: vec![ip_addr!(v6, "3fff::1234")],
+                source: DnsSource::Static,
+            },
+        )]);
+
+        let unresolved_routes = [FakeRoute(UnresolvedHost("domain-name".into()))];
+
+        let resolve = resolver.resolve(unresolved_routes.into_iter(), &name_resolver);
+        let schedule = Schedule::new(resolve.fuse(), NoDelay, Duration::ZERO);
+
+        let start_at = Instant::now();
+        let schedule = std::pin::pin!(schedule);
+        let schedule: Vec<_> = schedule
+            .as_stream()
+            .map(|r| (r, Instant::now().duration_since(start_at)))
+            .collect()
+            .await;
+
+        assert_eq!(
+            schedule,
+            vec![
+                (FakeRoute(ip_addr!("192.0.2.1")), Duration::ZERO),
+                (FakeRoute(ip_addr!("3fff::1234")), HAPPY_EYEBALLS_DELAY),
+            ]
+        );
+    }
+
     #[tokio::test(start_paused = true)]
     async fn multiple_resolved_routes_e2e() {
-        let resolver = RouteResolver { allow_ipv6: true };
+        let resolver = RouteResolver::default();
 
         let name_resolver = HashMap::from([
             (
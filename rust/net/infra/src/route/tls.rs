@@ -29,6 +29,33 @@ impl std::hash::Hash for TlsRouteFragment {
     }
 }
 
+impl TlsRouteFragment {
+    /// Whether [`Self::min_protocol_version`] rules out everything but TLS 1.3.
+    ///
+    /// This is for TLS libraries that, unlike BoringSSL, support nothing older
+    /// than TLS 1.2, so that any older minimum is the same as none. Returns
+    /// `None` for a minimum newer than TLS 1.3.
+    #[cfg(feature = "rustls-tls")]
+    pub(crate) fn requires_tls1_3(&self) -> Option<bool> {
+        match self.min_protocol_version {
+            None => Some(false),
+            Some(version) if version == SslVersion::TLS1_3 => Some(true),
+            Some(version)
+                if [
+                    SslVersion::SSL3,
+                    SslVersion::TLS1,
+                    SslVersion::TLS1_1,
+                    SslVersion::TLS1_2,
+                ]
+                .contains(&version) =>
+            {
+                Some(false)
+            }
+            Some(_) => None,
+        }
+    }
+}
+
 pub type TlsRoute<T> = SimpleRoute<TlsRouteFragment, T>;
 
 #[derive(Debug)]
@@ -4,6 +4,7 @@
 //
 
 use std::hash::Hash;
+use std::time::Duration;
 
 use http::uri::PathAndQuery;
 use http::HeaderMap;
@@ -19,6 +20,13 @@ pub struct WebSocketRouteFragment {
     pub endpoint: PathAndQuery,
     /// Request headers to include in the HTTP request establishing the connection.
     pub headers: HeaderMap,
+    /// If present, bounds the time spent sending the upgrade request and
+    /// reading the response, separately from connecting the transport.
+    ///
+    /// A peer can accept the connection and then never read the request or
+    /// answer it; this fails such attempts with
+    /// [`WebSocketConnectError::UpgradeTimeout`](crate::ws::WebSocketConnectError::UpgradeTimeout).
+    pub upgrade_timeout: Option<Duration>,
 }
 
 impl AsMut<WebSocketRouteFragment> for WebSocketRouteFragment {
@@ -75,9 +83,11 @@ impl PartialEq for WebSocketRouteFragment {
             ws_config,
             endpoint,
             headers,
+            upgrade_timeout,
         } = self;
         endpoint == &other.endpoint
             && headers == &other.headers
+            && upgrade_timeout == &other.upgrade_timeout
             && ws_config_eq(ws_config, &other.ws_config)
     }
 }
@@ -90,9 +100,11 @@ impl std::hash::Hash for WebSocketRouteFragment {
             ws_config,
             endpoint,
             headers: _,
+            upgrade_timeout,
         } = self;
         ws_config_hash(ws_config, state);
         endpoint.hash(state);
+        upgrade_timeout.hash(state);
     }
 }
 
@@ -0,0 +1,532 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Checking whether a device can reach a service, one step at a time.
+//!
+//! When a connection can't be made, the error only says how the last attempt
+//! failed. [`self_test`] instead goes through each phase of connecting on
+//! every route (DNS, TCP, TLS, and the websocket upgrade) and reports how far
+//! it got and how long each phase took, for onboarding diagnostics or a
+//! support request. The connections it makes are closed as soon as the
+//! upgrade finishes.
+
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use tokio::time::Instant;
+
+use crate::dns::DnsResolver;
+use crate::errors::{LogSafeDisplay, TransportConnectError};
+use crate::route::{
+    Connector as _, DescribeForLog as _, DirectOrProxyRoute, HttpRouteFragment, HttpsTlsRoute,
+    SimpleRoute, TcpRoute, TlsRoute, TlsRouteFragment, UnresolvedHost, UnresolvedRouteDescription,
+    UnresolvedWebsocketServiceRoute, WebSocketRouteFragment,
+};
+use crate::tcp_ssl::{StatelessTcp, StatelessTls};
+use crate::ConnectCancellation;
+
+/// One step of connecting over a route, in the order they happen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Phase {
+    Dns,
+    Tcp,
+    Tls,
+    WebSocketUpgrade,
+}
+
+/// Why a [`Phase`] didn't succeed.
+#[derive(Clone, Debug, PartialEq, Eq, displaydoc::Display)]
+pub enum PhaseError {
+    /// {0}
+    Failed(String),
+    /// the self-test ran out of time
+    DeadlineExceeded,
+    /// the self-test was cancelled
+    Cancelled,
+}
+impl LogSafeDisplay for PhaseError {}
+
+/// How a single [`Phase`] went.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhaseReport {
+    pub phase: Phase,
+    /// The resolved address the phase was run against, for every phase but
+    /// [`Phase::Dns`].
+    pub address: Option<IpAddr>,
+    pub elapsed: Duration,
+    pub result: Result<(), PhaseError>,
+}
+
+/// How far connecting over a route got.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteReport {
+    pub route: UnresolvedRouteDescription,
+    /// The phases that were attempted, in order.
+    ///
+    /// After DNS, the other phases are run against each resolved address in
+    /// turn, until one gets through all of them. Each address's phases stop
+    /// at the first one that fails, and the next address starts over at
+    /// [`Phase::Tcp`].
+    pub phases: Vec<PhaseReport>,
+}
+
+impl RouteReport {
+    /// Whether every phase succeeded, up to and including the websocket
+    /// upgrade.
+    pub fn succeeded(&self) -> bool {
+        self.phases
+            .last()
+            .is_some_and(|last| last.phase == Phase::WebSocketUpgrade && last.result.is_ok())
+    }
+}
+
+/// The result of [`self_test`], with one entry per route tested.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfTestReport {
+    pub routes: Vec<RouteReport>,
+}
+
+impl SelfTestReport {
+    /// Whether the service could be reached over at least one route.
+    pub fn any_route_succeeded(&self) -> bool {
+        self.routes.iter().any(RouteReport::succeeded)
+    }
+}
+
+/// Goes through every phase of connecting on each of `routes`, all at once,
+/// and reports how each went.
+///
+/// Routes through a proxy are left out, since they say more about the proxy
+/// than about the device's own connectivity. Each route's host is resolved,
+/// and its addresses are tried one after another in the order connections
+/// would use them, so a route that only works over IPv4 is still reported as
+/// working. Phases still running at `deadline`, or when `cancellation` is
+/// cancelled, are stopped and reported as such, and no further addresses are
+/// tried; the report is returned either way.
+pub async fn self_test(
+    routes: impl IntoIterator<Item = UnresolvedWebsocketServiceRoute>,
+    dns_resolver: &DnsResolver,
+    deadline: Instant,
+    cancellation: &ConnectCancellation,
+) -> SelfTestReport {
+    let route_tests = routes.into_iter().filter_map(move |route| {
+        let description = route.describe_for_log();
+        let SimpleRoute {
+            fragment: ws_fragment,
+            inner:
+                HttpsTlsRoute {
+                    fragment: http_fragment,
+                    inner:
+                        TlsRoute {
+                            fragment: tls_fragment,
+                            inner: DirectOrProxyRoute::Direct(tcp),
+                        },
+                },
+        } = route
+        else {
+            log::debug!("self-test skipping {description}");
+            return None;
+        };
+        let mut tester = RouteTester {
+            phases: vec![],
+            deadline,
+            cancellation,
+        };
+        Some(async move {
+            tester
+                .run(
+                    dns_resolver,
+                    tcp,
+                    tls_fragment,
+                    (ws_fragment, http_fragment),
+                )
+                .await;
+            RouteReport {
+                route: description,
+                phases: tester.phases,
+            }
+        })
+    });
+    SelfTestReport {
+        routes: join_all(route_tests).await,
+    }
+}
+
+/// Collects the [`PhaseReport`]s for a single route.
+struct RouteTester<'a> {
+    phases: Vec<PhaseReport>,
+    deadline: Instant,
+    cancellation: &'a ConnectCancellation,
+}
+
+impl RouteTester<'_> {
+    /// Resolves the route's host, then runs the other phases against each
+    /// address until they all succeed for one of them.
+    async fn run(
+        &mut self,
+        dns_resolver: &DnsResolver,
+        tcp: TcpRoute<UnresolvedHost>,
+        tls_fragment: TlsRouteFragment,
+        ws_fragments: (WebSocketRouteFragment, HttpRouteFragment),
+    ) -> Option<()> {
+        let TcpRoute {
+            address: UnresolvedHost(hostname),
+            port,
+        } = tcp;
+
+        let addresses = self
+            .phase(Phase::Dns, None, async {
+                let lookup = dns_resolver
+                    .lookup_ip(&hostname)
+                    .await
+                    .map_err(|_| TransportConnectError::DnsError)?;
+                let addresses = Vec::from_iter(lookup);
+                if addresses.is_empty() {
+                    return Err(TransportConnectError::DnsError);
+                }
+                Ok(addresses)
+            })
+            .await?;
+        for address in addresses {
+            let tcp = TcpRoute { address, port };
+            if self
+                .run_over(tcp, tls_fragment.clone(), ws_fragments.clone())
+                .await
+                .is_some()
+            {
+                return Some(());
+            }
+            let stopped = self.phases.last().is_some_and(|last| {
+                matches!(
+                    last.result,
+                    Err(PhaseError::DeadlineExceeded | PhaseError::Cancelled)
+                )
+            });
+            if stopped {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Runs the phases after DNS against a single address, stopping at the
+    /// first one that fails.
+    async fn run_over(
+        &mut self,
+        tcp: TcpRoute<IpAddr>,
+        tls_fragment: TlsRouteFragment,
+        ws_fragments: (WebSocketRouteFragment, HttpRouteFragment),
+    ) -> Option<()> {
+        let address = Some(tcp.address);
+        let log_tag: Arc<str> = "self-test".into();
+
+        let tcp_stream = self
+            .phase(
+                Phase::Tcp,
+                address,
+                StatelessTcp.connect_over((), tcp, log_tag.clone()),
+            )
+            .await?;
+        let tls_stream = self
+            .phase(
+                Phase::Tls,
+                address,
+                StatelessTls::default().connect_over(tcp_stream, tls_fragment, log_tag.clone()),
+            )
+            .await?;
+        // The websocket is closed when it's dropped right away.
+        let _websocket = self
+            .phase(
+                Phase::WebSocketUpgrade,
+                address,
+                crate::ws::Stateless.connect_over(tls_stream, ws_fragments, log_tag),
+            )
+            .await?;
+        Some(())
+    }
+
+    /// Runs a single phase and records how it went, returning what it
+    /// produced if it succeeded.
+    async fn phase<T, E: LogSafeDisplay>(
+        &mut self,
+        phase: Phase,
+        address: Option<IpAddr>,
+        attempt: impl Future<Output = Result<T, E>>,
+    ) -> Option<T> {
+        let started = Instant::now();
+        let result = tokio::select! {
+            biased;
+            () = self.cancellation.cancelled() => Err(PhaseError::Cancelled),
+            () = tokio::time::sleep_until(self.deadline) => Err(PhaseError::DeadlineExceeded),
+            result = attempt => result.map_err(|e| PhaseError::Failed(e.to_string())),
+        };
+        let elapsed = started.elapsed();
+        if let Err(e) = &result {
+            let ip_type = address.map_or("", |address| match address {
+                IpAddr::V4(_) => " over IPv4",
+                IpAddr::V6(_) => " over IPv6",
+            });
+            log::info!("self-test {phase} phase failed{ip_type} after {elapsed:?}: {e}");
+        }
+        let (result, produced) = match result {
+            Ok(produced) => (Ok(()), Some(produced)),
+            Err(e) => (Err(e), None),
+        };
+        self.phases.push(PhaseReport {
+            phase,
+            address,
+            elapsed,
+            result,
+        });
+        produced
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use http::uri::PathAndQuery;
+    use tokio::net::TcpListener;
+    use warp::Filter as _;
+
+    use super::*;
+    use crate::certs::RootCertificates;
+    use crate::dns::lookup_result::LookupResult;
+    use crate::host::Host;
+    use crate::tcp_ssl::testutil::{localhost_http_server, SERVER_CERTIFICATE, SERVER_HOSTNAME};
+    use crate::Alpn;
+
+    const UNKNOWN_HOSTNAME: &str = "unknown.signal.org.local";
+
+    fn route_to(hostname: &str, port: u16, root_cert: &[u8]) -> UnresolvedWebsocketServiceRoute {
+        SimpleRoute {
+            fragment: WebSocketRouteFragment {
+                ws_config: Default::default(),
+                endpoint: PathAndQuery::from_static("/v1/websocket/"),
+                headers: Default::default(),
+                upgrade_timeout: None,
+            },
+            inner: HttpsTlsRoute {
+                fragment: HttpRouteFragment {
+                    host_header: SERVER_HOSTNAME.into(),
+                    path_prefix: "".into(),
+                    front_name: None,
+                },
+                inner: TlsRoute {
+                    fragment: TlsRouteFragment {
+                        root_certs: RootCertificates::FromDer(Cow::Owned(root_cert.to_vec())),
+                        sni: Host::Domain(SERVER_HOSTNAME.into()),
+                        alpn: Some(Alpn::Http1_1),
+                        min_protocol_version: None,
+                    },
+                    inner: DirectOrProxyRoute::Direct(TcpRoute {
+                        address: UnresolvedHost(hostname.into()),
+                        port: port.try_into().expect("not zero"),
+                    }),
+                },
+            },
+        }
+    }
+
+    /// Accepts connections and holds on to them without ever responding.
+    async fn unresponsive_server() -> u16 {
+        let listener = TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let port = listener.local_addr().expect("bound").port();
+        tokio::spawn(async move {
+            let mut connections = vec![];
+            loop {
+                let (stream, _) = listener.accept().await.expect("can accept");
+                connections.push(stream);
+            }
+        });
+        port
+    }
+
+    fn outcomes(report: &RouteReport) -> Vec<(Phase, Option<IpAddr>, Result<(), PhaseError>)> {
+        report
+            .phases
+            .iter()
+            .map(
+                |PhaseReport {
+                     phase,
+                     address,
+                     result,
+                     ..
+                 }| {
+                    // The specific failures are checked separately.
+                    let result = result.clone().map_err(|e| match e {
+                        PhaseError::Failed(_) => PhaseError::Failed("".into()),
+                        other => other,
+                    });
+                    (*phase, *address, result)
+                },
+            )
+            .collect()
+    }
+
+    fn websocket_server_bound_to(address: IpAddr) -> u16 {
+        let websocket = warp::ws().map(|ws: warp::ws::Ws| ws.on_upgrade(|_ws| async {}));
+        let (ws_addr, ws_server) = warp::serve(websocket)
+            .tls()
+            .cert(SERVER_CERTIFICATE.cert.pem())
+            .key(SERVER_CERTIFICATE.key_pair.serialize_pem())
+            .bind_ephemeral((address, 0));
+        tokio::spawn(ws_server);
+        ws_addr.port()
+    }
+
+    #[tokio::test]
+    async fn self_test_reports_how_far_each_route_got() {
+        let ws_port = websocket_server_bound_to(Ipv6Addr::LOCALHOST.into());
+        let ipv4_only_ws_port = websocket_server_bound_to(Ipv4Addr::LOCALHOST.into());
+        let (http_addr, http_server) = localhost_http_server();
+        tokio::spawn(http_server);
+        let closed_port = {
+            let listener = TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+                .await
+                .expect("can bind");
+            listener.local_addr().expect("bound").port()
+        };
+        let unresponsive_port = unresponsive_server().await;
+        let other_cert = rcgen::generate_simple_self_signed([SERVER_HOSTNAME.to_string()])
+            .expect("can generate");
+
+        let trusted = SERVER_CERTIFICATE.cert.der();
+        let routes = [
+            route_to(SERVER_HOSTNAME, ws_port, trusted),
+            route_to(UNKNOWN_HOSTNAME, ws_port, trusted),
+            route_to(SERVER_HOSTNAME, closed_port, trusted),
+            route_to(SERVER_HOSTNAME, ws_port, other_cert.cert.der()),
+            route_to(SERVER_HOSTNAME, http_addr.port(), trusted),
+            route_to(SERVER_HOSTNAME, unresponsive_port, trusted),
+            route_to(SERVER_HOSTNAME, ipv4_only_ws_port, trusted),
+        ];
+        // The localhost lookup puts the IPv6 address first.
+        let v6 = Some(IpAddr::from(Ipv6Addr::LOCALHOST));
+        let v4 = Some(IpAddr::from(Ipv4Addr::LOCALHOST));
+        let dns_resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            SERVER_HOSTNAME,
+            LookupResult::localhost(),
+        )]));
+
+        const DEADLINE: Duration = Duration::from_secs(2);
+        let started = Instant::now();
+        let report = self_test(
+            routes,
+            &dns_resolver,
+            started + DEADLINE,
+            &ConnectCancellation::new(),
+        )
+        .await;
+        assert!(started.elapsed() < DEADLINE * 2);
+        assert!(report.any_route_succeeded());
+        assert!(report.routes[6].succeeded());
+
+        let failed = || Err(PhaseError::Failed("".into()));
+        let expected = [
+            vec![
+                (Phase::Dns, None, Ok(())),
+                (Phase::Tcp, v6, Ok(())),
+                (Phase::Tls, v6, Ok(())),
+                (Phase::WebSocketUpgrade, v6, Ok(())),
+            ],
+            vec![(Phase::Dns, None, failed())],
+            vec![
+                (Phase::Dns, None, Ok(())),
+                (Phase::Tcp, v6, failed()),
+                (Phase::Tcp, v4, failed()),
+            ],
+            vec![
+                (Phase::Dns, None, Ok(())),
+                (Phase::Tcp, v6, Ok(())),
+                (Phase::Tls, v6, failed()),
+                (Phase::Tcp, v4, failed()),
+            ],
+            vec![
+                (Phase::Dns, None, Ok(())),
+                (Phase::Tcp, v6, Ok(())),
+                (Phase::Tls, v6, Ok(())),
+                (Phase::WebSocketUpgrade, v6, failed()),
+                (Phase::Tcp, v4, failed()),
+            ],
+            // Once out of time, no other addresses are tried.
+            vec![
+                (Phase::Dns, None, Ok(())),
+                (Phase::Tcp, v6, Ok(())),
+                (Phase::Tls, v6, Err(PhaseError::DeadlineExceeded)),
+            ],
+            // A route that only works over IPv4 is still found to work.
+            vec![
+                (Phase::Dns, None, Ok(())),
+                (Phase::Tcp, v6, failed()),
+                (Phase::Tcp, v4, Ok(())),
+                (Phase::Tls, v4, Ok(())),
+                (Phase::WebSocketUpgrade, v4, Ok(())),
+            ],
+        ];
+        assert_eq!(
+            report.routes.iter().map(outcomes).collect::<Vec<_>>(),
+            expected
+        );
+
+        assert_eq!(
+            report.routes[1].phases[0].result,
+            Err(PhaseError::Failed(
+                TransportConnectError::DnsError.to_string()
+            ))
+        );
+        let timed_out = report.routes[5].phases.last().expect("has phases");
+        assert!(timed_out.elapsed >= DEADLINE - Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn self_test_stops_when_cancelled() {
+        let unresponsive_port = unresponsive_server().await;
+        let dns_resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            SERVER_HOSTNAME,
+            LookupResult::localhost(),
+        )]));
+        let cancellation = ConnectCancellation::new();
+
+        let (report, ()) = tokio::join!(
+            self_test(
+                [route_to(
+                    SERVER_HOSTNAME,
+                    unresponsive_port,
+                    SERVER_CERTIFICATE.cert.der()
+                )],
+                &dns_resolver,
+                Instant::now() + Duration::from_secs(60),
+                &cancellation,
+            ),
+            async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                cancellation.cancel();
+            }
+        );
+        assert_eq!(
+            outcomes(&report.routes[0]),
+            [
+                (Phase::Dns, None, Ok(())),
+                (Phase::Tcp, Some(Ipv6Addr::LOCALHOST.into()), Ok(())),
+                (
+                    Phase::Tls,
+                    Some(Ipv6Addr::LOCALHOST.into()),
+                    Err(PhaseError::Cancelled)
+                ),
+            ]
+        );
+        assert!(!report.any_route_succeeded());
+    }
+}
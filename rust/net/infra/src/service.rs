@@ -4,8 +4,10 @@
 //
 
 use std::fmt::Debug;
+use std::time::Duration;
 
 use displaydoc::Display;
+use tokio::sync::watch;
 use tokio::time::Instant;
 
 use crate::connection_manager::{ErrorClass, ErrorClassifier};
@@ -31,6 +33,106 @@ pub enum ServiceState<T, CE> {
     ConnectionTimedOut,
 }
 
+impl<T, CE: ErrorClassifier> ServiceState<T, CE> {
+    pub fn status(&self) -> ServiceStatus {
+        match self {
+            ServiceState::Inactive => ServiceStatus::Inactive,
+            ServiceState::Active(_, _) => ServiceStatus::Active,
+            ServiceState::Cooldown(_) | ServiceState::ConnectionTimedOut => {
+                ServiceStatus::Connecting
+            }
+            ServiceState::Error(e) => match e.classify() {
+                ErrorClass::Intermittent | ErrorClass::RetryAt(_) => ServiceStatus::Connecting,
+                ErrorClass::Fatal => ServiceStatus::Failed,
+            },
+        }
+    }
+}
+
+/// A summary of a [`ServiceState`] for callers waiting on the service.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ServiceStatus {
+    /// Service was not explicitly activated.
+    Inactive,
+    /// Service is not connected yet, but will keep trying.
+    Connecting,
+    /// Service is connected and ready to use.
+    Active,
+    /// Service failed in a way that retrying won't fix.
+    Failed,
+}
+
+/// Publishes the [`ServiceStatus`] of a service as its state changes.
+#[derive(Debug)]
+pub struct ServiceStatusNotifier(watch::Sender<ServiceStatus>);
+
+/// Follows the [`ServiceStatus`] published by a [`ServiceStatusNotifier`].
+#[derive(Clone, Debug)]
+pub struct ServiceStatusWatcher(watch::Receiver<ServiceStatus>);
+
+#[derive(Debug, Display, PartialEq, Eq)]
+pub enum WaitForConnectionError {
+    /// Timed out waiting for the service to connect
+    TimedOut,
+    /// Service failed and will not connect
+    Failed,
+}
+
+impl ServiceStatusNotifier {
+    pub fn new() -> Self {
+        Self(watch::Sender::new(ServiceStatus::Inactive))
+    }
+
+    pub fn publish<T, CE: ErrorClassifier>(&self, state: &ServiceState<T, CE>) {
+        self.set_status(state.status())
+    }
+
+    /// Publishes `new_status` directly, for services that don't keep a
+    /// [`ServiceState`].
+    ///
+    /// Watchers are only woken if the status changed.
+    pub fn set_status(&self, new_status: ServiceStatus) {
+        self.0
+            .send_if_modified(|status| std::mem::replace(status, new_status) != new_status);
+    }
+
+    pub fn subscribe(&self) -> ServiceStatusWatcher {
+        ServiceStatusWatcher(self.0.subscribe())
+    }
+}
+
+impl Default for ServiceStatusNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServiceStatusWatcher {
+    pub fn status(&self) -> ServiceStatus {
+        *self.0.borrow()
+    }
+
+    /// Waits until the service is [`ServiceStatus::Active`], for at most
+    /// `timeout`.
+    ///
+    /// Returns immediately if the service is already active. If the service
+    /// fails, or its [`ServiceStatusNotifier`] goes away, this stops waiting
+    /// with [`WaitForConnectionError::Failed`].
+    pub async fn wait_until_connected(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<(), WaitForConnectionError> {
+        let wait = self
+            .0
+            .wait_for(|status| matches!(status, ServiceStatus::Active | ServiceStatus::Failed));
+        match tokio::time::timeout(timeout, wait).await {
+            Err(_elapsed) => Err(WaitForConnectionError::TimedOut),
+            Ok(Ok(status)) if *status == ServiceStatus::Active => Ok(()),
+            Ok(Ok(_) | Err(_)) => Err(WaitForConnectionError::Failed),
+        }
+    }
+}
+
 mod cancel_token;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -76,3 +178,71 @@ pub enum StateError {
     /// Service is unavailable due to the lost connection
     ServiceUnavailable,
 }
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::errors::TransportConnectError;
+
+    type TestState = ServiceState<tokio::net::TcpStream, ConnectError<TransportConnectError>>;
+
+    #[tokio::test]
+    async fn wait_until_connected_resolves_once_the_service_is_active() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("can bind");
+        let addr = listener.local_addr().expect("bound");
+
+        let notifier = ServiceStatusNotifier::new();
+        let mut watcher = notifier.subscribe();
+        notifier.publish(&TestState::Error(ConnectError::AllRoutesFailed {
+            attempts: 1,
+        }));
+        assert_eq!(
+            watcher
+                .wait_until_connected(Duration::from_millis(100))
+                .await,
+            Err(WaitForConnectionError::TimedOut)
+        );
+        assert_eq!(watcher.status(), ServiceStatus::Connecting);
+
+        let waiting = tokio::spawn(async move {
+            watcher
+                .wait_until_connected(Duration::from_secs(10))
+                .await
+                .map(|()| watcher.status())
+        });
+        let stream = tokio::net::TcpStream::connect(addr)
+            .await
+            .expect("can connect");
+        notifier.publish(&TestState::Active(stream, CancellationToken::new()));
+        assert_matches!(waiting.await.expect("no panic"), Ok(ServiceStatus::Active));
+    }
+
+    #[test]
+    fn set_status_only_notifies_on_change() {
+        let notifier = ServiceStatusNotifier::new();
+        let mut watcher = notifier.subscribe();
+        notifier.set_status(ServiceStatus::Inactive);
+        assert!(!watcher.0.has_changed().expect("open"));
+        notifier.set_status(ServiceStatus::Active);
+        assert!(watcher.0.has_changed().expect("open"));
+        assert_eq!(watcher.status(), ServiceStatus::Active);
+    }
+
+    #[tokio::test]
+    async fn wait_until_connected_stops_on_terminal_failure() {
+        let notifier = ServiceStatusNotifier::new();
+        let mut watcher = notifier.subscribe();
+        notifier.publish(&TestState::Error(ConnectError::RejectedByServer(
+            TransportConnectError::CertError,
+        )));
+
+        assert_eq!(
+            watcher.wait_until_connected(Duration::from_secs(10)).await,
+            Err(WaitForConnectionError::Failed)
+        );
+    }
+}
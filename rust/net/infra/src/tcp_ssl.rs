@@ -4,46 +4,236 @@
 //
 
 use std::future::Future;
+use std::io;
 use std::net::IpAddr;
 use std::num::NonZeroU16;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::time::Duration;
 
 use async_trait::async_trait;
 use auto_enums::enum_derive;
-use boring_signal::ssl::{ConnectConfiguration, SslConnector, SslMethod, SslSignatureAlgorithm};
-use futures_util::TryFutureExt;
+use boring_signal::ssl::{
+    ConnectConfiguration, SslConnector, SslMethod, SslRef, SslSignatureAlgorithm,
+};
+use boring_signal::x509::X509;
+use foreign_types::ForeignTypeRef as _;
+use futures_util::stream::FuturesUnordered;
+use futures_util::{StreamExt as _, TryFutureExt};
+use tokio::io::{AsyncRead, ReadBuf};
 use tokio::net::TcpStream;
+use tokio::time::Instant;
 use tokio_boring_signal::SslStream;
 
-use crate::certs::RootCertificates;
+use crate::attempt_budget::AttemptBudget;
+use crate::certs::{check_certificate_name, CertificateHistory, RootCertificates};
 use crate::dns::DnsResolver;
-use crate::errors::TransportConnectError;
+use crate::errors::{LogSafeDisplay, TransportConnectError};
 use crate::host::Host;
+use crate::metrics::ConnectionMetrics;
 use crate::route::{
     ConnectionProxyConfig, Connector, ConnectorExt as _, TcpProxy, TcpRoute, TlsProxy,
     TlsRouteFragment,
 };
+use crate::tcp_ssl::ipv6_suspicion::Ipv6Suspicion;
 use crate::tcp_ssl::proxy::tls::TlsProxyConnector;
+use crate::tcp_ssl::proxy_protocol::ProxyProtocolHeader;
+use crate::tcp_ssl::source_ports::SourcePortRange;
 use crate::timeouts::TCP_CONNECTION_ATTEMPT_DELAY;
 #[cfg(feature = "dev-util")]
 #[allow(unused_imports)]
 use crate::utils::development_only_enable_nss_standard_debug_interop;
 use crate::utils::first_ok;
 use crate::{
-    Alpn, AsyncDuplexStream, Connection, RouteType, ServiceConnectionInfo, StreamAndInfo,
-    TransportConnectionParams, TransportConnector,
+    Alpn, AsyncDuplexStream, Connection, DnsSource, RouteType, ServiceConnectionInfo,
+    StreamAndInfo, TlsSessionInfo, TransportConnectionParams, TransportConnector,
 };
 
+pub mod ipv6_suspicion;
 pub mod proxy;
+pub mod proxy_protocol;
+#[cfg(feature = "rustls-tls")]
+pub mod rustls_tls;
+pub mod source_ports;
 
 pub const LONG_TCP_HANDSHAKE_THRESHOLD: Duration = Duration::from_secs(3);
 pub const LONG_TLS_HANDSHAKE_THRESHOLD: Duration = Duration::from_secs(3);
 
+/// How long an IPv6 handshake has to have been going, when an IPv4 attempt
+/// started after it wins the race, to count as stalled.
+pub const STALLED_IPV6_HANDSHAKE_THRESHOLD: Duration = Duration::from_millis(100);
+
 #[derive(Clone, Debug)]
 pub struct TcpSslConnector {
     dns_resolver: DnsResolver,
     proxy: Result<Option<ConnectionProxyConfig>, InvalidProxyConfig>,
+    proxy_protocol: Option<ProxyProtocolHeader>,
+    race_tls_handshakes: bool,
+    srv_service: Option<Arc<str>>,
+    tls_handshake_retry: TlsHandshakeRetryPolicy,
+    ipv6_suspicion: Option<Ipv6Suspicion>,
+    source_ports: Option<SourcePortRange>,
+    attempt_budget: Option<AttemptBudget>,
+    client_hello: Option<ClientHelloCustomization>,
+    certificate_history: Option<CertificateHistory>,
+    expected_cert_name: Option<Arc<str>>,
+    pre_connect_hook: Option<Arc<dyn PreConnectHook>>,
+    metrics: Option<Arc<dyn ConnectionMetrics>>,
+    tls_implementation: TlsImplementation,
+    renegotiation: RenegotiationPolicy,
+}
+
+/// The TLS library a [`TcpSslConnector`] makes its handshakes with.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TlsImplementation {
+    #[default]
+    BoringSsl,
+    /// rustls, through [`rustls_tls::RustlsTls`].
+    ///
+    /// Only direct connections are supported; connecting through a proxy
+    /// fails with [`TransportConnectError::InvalidConfiguration`]. The
+    /// settings that are specific to BoringSSL handshakes (racing them,
+    /// retrying them, and customizing the ClientHello) are ignored, as is the
+    /// SRV service.
+    #[cfg(feature = "rustls-tls")]
+    Rustls,
+}
+
+/// What to do when a server asks to renegotiate a TLS 1.2 session.
+///
+/// Renegotiating makes the client do another full handshake whenever the
+/// server asks, which servers can use to tie up clients, and which has been
+/// the source of several TLS vulnerabilities. Signal's servers never ask for
+/// it, and TLS 1.3 doesn't have it at all.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RenegotiationPolicy {
+    /// End the connection with a fatal `no_renegotiation` alert.
+    ///
+    /// Reads then fail with an error that [`is_renegotiation_rejection`]
+    /// recognizes. Reads from a [`TcpSslConnectorStream`] carry
+    /// [`TransportConnectError::TlsRenegotiationRejected`] as the inner error.
+    #[default]
+    Reject,
+    /// Make a new handshake each time the server asks for one.
+    Allow,
+}
+
+impl RenegotiationPolicy {
+    fn apply_to(self, ssl: &mut SslRef) {
+        let mode = match self {
+            Self::Reject => boring_sys_signal::ssl_renegotiate_mode_t::ssl_renegotiate_never,
+            Self::Allow => boring_sys_signal::ssl_renegotiate_mode_t::ssl_renegotiate_freely,
+        };
+        // SAFETY: the pointer comes from a live `SslRef`, and the safe
+        // bindings have no way to set the mode.
+        unsafe { boring_sys_signal::SSL_set_renegotiate_mode(ssl.as_ptr(), mode) }
+    }
+}
+
+/// Whether a BoringSSL stream failed with `error` because the server asked to
+/// renegotiate and the [`RenegotiationPolicy`] didn't allow it.
+///
+/// This recognizes both the error BoringSSL reports and the one
+/// [`TcpSslConnectorStream`] replaces it with.
+pub fn is_renegotiation_rejection(error: &io::Error) -> bool {
+    let Some(inner) = error.get_ref() else {
+        return false;
+    };
+    if let Some(TransportConnectError::TlsRenegotiationRejected) = inner.downcast_ref() {
+        return true;
+    }
+    inner
+        .downcast_ref::<boring_signal::ssl::Error>()
+        .and_then(boring_signal::ssl::Error::ssl_error)
+        .is_some_and(|stack| {
+            stack
+                .errors()
+                .iter()
+                .any(|e| e.reason() == Some("NO_RENEGOTIATION"))
+        })
+}
+
+/// When a [`DirectConnector`] should start over after a failed TLS
+/// handshake.
+///
+/// Some transient failures, like a middlebox resetting the first connection,
+/// go away on an immediate retry. Each retry makes a new TCP connection,
+/// since the old one is unusable after a failed handshake.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TlsHandshakeRetryPolicy {
+    /// How many times to retry after the first failed handshake.
+    ///
+    /// The default of 0 never retries.
+    pub max_retries: u8,
+    /// The I/O errors that a handshake can fail with to be retried.
+    pub transient_errors: &'static [std::io::ErrorKind],
+}
+
+impl TlsHandshakeRetryPolicy {
+    /// Errors from the connection being cut off during the handshake.
+    pub const DEFAULT_TRANSIENT_ERRORS: &'static [std::io::ErrorKind] = &[
+        std::io::ErrorKind::ConnectionReset,
+        std::io::ErrorKind::ConnectionAborted,
+        std::io::ErrorKind::BrokenPipe,
+        std::io::ErrorKind::UnexpectedEof,
+    ];
+
+    fn should_retry(&self, error: &TransportConnectError) -> bool {
+        match error {
+            TransportConnectError::SslFailedHandshake(reason) => reason
+                .io_error_kind()
+                .is_some_and(|kind| self.transient_errors.contains(&kind)),
+            _ => false,
+        }
+    }
+}
+
+impl Default for TlsHandshakeRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            transient_errors: Self::DEFAULT_TRANSIENT_ERRORS,
+        }
+    }
+}
+
+/// Adjustments to the TLS ClientHello, to make it look more like that of a
+/// common client.
+///
+/// Censors sometimes pick out traffic by fingerprinting its ClientHello.
+/// These are the parts of it that BoringSSL lets a client change; anything
+/// left at its default is sent as usual.
+#[derive(Clone, Debug, Default)]
+pub struct ClientHelloCustomization {
+    /// Sends GREASE values (RFC 8701) in the cipher suites, extensions, and
+    /// other lists, like browsers do.
+    pub grease: bool,
+    /// Sends the extensions in a different random order each time, like
+    /// Chrome does.
+    pub permute_extensions: bool,
+    /// The signature algorithms to offer, in order of preference.
+    pub signature_algorithms: Option<Vec<SslSignatureAlgorithm>>,
+    /// The cipher suites to offer for TLS 1.2, in OpenSSL's cipher list
+    /// format. The TLS 1.3 suites and their order can't be changed.
+    pub cipher_list: Option<String>,
+}
+
+/// Logic to run before each connection attempt made by a [`TcpSslConnector`].
+///
+/// This is a place for checks that should gate every attempt, including
+/// reconnects, like a kill switch or refreshing credentials that a request
+/// decorator needs.
+#[async_trait]
+pub trait PreConnectHook: std::fmt::Debug + Send + Sync {
+    /// Called with the parameters of the connection about to be attempted.
+    ///
+    /// Returning an error abandons the attempt, which then fails with
+    /// [`TransportConnectError::PreConnectAborted`].
+    async fn before_connect(
+        &self,
+        connection_params: &TransportConnectionParams,
+    ) -> Result<(), Box<dyn LogSafeDisplay + Send + Sync>>;
 }
 
 impl TcpSslConnector {
@@ -51,9 +241,142 @@ impl TcpSslConnector {
         Self {
             dns_resolver,
             proxy: Ok(None),
+            proxy_protocol: None,
+            race_tls_handshakes: false,
+            srv_service: None,
+            tls_handshake_retry: TlsHandshakeRetryPolicy::default(),
+            ipv6_suspicion: None,
+            source_ports: None,
+            attempt_budget: None,
+            client_hello: None,
+            certificate_history: None,
+            expected_cert_name: None,
+            pre_connect_hook: None,
+            metrics: None,
+            tls_implementation: TlsImplementation::default(),
+            renegotiation: RenegotiationPolicy::default(),
         }
     }
 
+    /// Sets what to do when the server of a direct connection asks to
+    /// renegotiate.
+    ///
+    /// See [`RenegotiationPolicy`]. Connections through a proxy, and rustls
+    /// ones, always reject renegotiation.
+    pub fn set_renegotiation_policy(&mut self, renegotiation: RenegotiationPolicy) {
+        self.renegotiation = renegotiation;
+    }
+
+    /// Sets the TLS library to make handshakes with.
+    ///
+    /// See [`TlsImplementation`].
+    pub fn set_tls_implementation(&mut self, tls_implementation: TlsImplementation) {
+        self.tls_implementation = tls_implementation;
+    }
+
+    /// Sets the hook to run before each connection attempt, or `None` to
+    /// stop running one.
+    ///
+    /// See [`PreConnectHook`].
+    pub fn set_pre_connect_hook(&mut self, hook: Option<Arc<dyn PreConnectHook>>) {
+        self.pre_connect_hook = hook;
+    }
+
+    /// Sets where to report connection attempts and their DNS lookups and
+    /// TLS handshakes, or `None` to stop reporting them.
+    ///
+    /// Each call to [`TransportConnector::connect`] counts as one attempt,
+    /// however many addresses it tries.
+    pub fn set_metrics(&mut self, metrics: Option<Arc<dyn ConnectionMetrics>>) {
+        self.metrics = metrics;
+    }
+
+    /// Sets the record used to detect servers whose certificate changed
+    /// between connections, or `None` to stop checking.
+    ///
+    /// See [`CertificateHistory`].
+    pub fn set_certificate_history(&mut self, certificate_history: Option<CertificateHistory>) {
+        self.certificate_history = certificate_history;
+    }
+
+    /// Sets a name that server certificates must also be valid for, or `None`
+    /// to only check the SNI.
+    ///
+    /// The handshake checks the certificate against the SNI; this is an
+    /// additional check made afterwards. When the SNI is a front, this can be
+    /// the real backend's name, to make sure that's what was reached.
+    /// Certificates that aren't valid for it fail with
+    /// [`TransportConnectError::CertNameMismatch`].
+    pub fn set_expected_cert_name(&mut self, expected_cert_name: Option<Arc<str>>) {
+        self.expected_cert_name = expected_cert_name;
+    }
+
+    /// Enables or disables racing TLS handshakes on direct connections.
+    ///
+    /// See [`DirectConnector::race_tls_handshakes`].
+    pub fn set_race_tls_handshakes(&mut self, race_tls_handshakes: bool) {
+        self.race_tls_handshakes = race_tls_handshakes;
+    }
+
+    /// Sets the SRV service name to look up targets for on direct
+    /// connections, or `None` to connect to the configured host.
+    ///
+    /// See [`DirectConnector::srv_service`].
+    pub fn set_srv_service(&mut self, srv_service: Option<Arc<str>>) {
+        self.srv_service = srv_service;
+    }
+
+    /// Sets when to retry failed TLS handshakes on direct connections.
+    ///
+    /// See [`TlsHandshakeRetryPolicy`].
+    pub fn set_tls_handshake_retry(&mut self, policy: TlsHandshakeRetryPolicy) {
+        self.tls_handshake_retry = policy;
+    }
+
+    /// Sets the tracker for IPv6 path MTU black holes on direct connections,
+    /// or `None` to stop tracking them.
+    ///
+    /// See [`DirectConnector::ipv6_suspicion`].
+    pub fn set_ipv6_suspicion(&mut self, ipv6_suspicion: Option<Ipv6Suspicion>) {
+        self.ipv6_suspicion = ipv6_suspicion;
+    }
+
+    /// Sets the local ports that direct connections are made from, or `None`
+    /// to let the OS choose.
+    ///
+    /// See [`DirectConnector::source_ports`].
+    pub fn set_source_ports(&mut self, source_ports: Option<SourcePortRange>) {
+        self.source_ports = source_ports;
+    }
+
+    /// Sets the budget that direct connection attempts are counted against,
+    /// or `None` to not limit them.
+    ///
+    /// See [`DirectConnector::attempt_budget`].
+    pub fn set_attempt_budget(&mut self, attempt_budget: Option<AttemptBudget>) {
+        self.attempt_budget = attempt_budget;
+    }
+
+    /// Sets how to adjust the ClientHello of direct connections, or `None` to
+    /// send the usual one.
+    ///
+    /// See [`ClientHelloCustomization`].
+    pub fn set_client_hello_customization(
+        &mut self,
+        client_hello: Option<ClientHelloCustomization>,
+    ) {
+        self.client_hello = client_hello;
+    }
+
+    /// Sets the PROXY protocol header to send on direct connections, or
+    /// `None` to stop sending one.
+    ///
+    /// This is only needed when connecting through a load balancer that
+    /// expects the header; it is not sent when a proxy is configured.
+    pub fn set_proxy_protocol_header(&mut self, header: Option<ProxyProtocolHeader>) {
+        self.proxy_protocol = header;
+    }
+
     pub fn set_ipv6_enabled(&mut self, ipv6_enabled: bool) {
         self.dns_resolver.set_ipv6_enabled(ipv6_enabled);
     }
@@ -88,20 +411,129 @@ impl TryFrom<&TcpSslConnector> for Option<ConnectionProxyConfig> {
         let TcpSslConnector {
             dns_resolver: _,
             proxy,
+            proxy_protocol: _,
+            race_tls_handshakes: _,
+            srv_service: _,
+            tls_handshake_retry: _,
+            ipv6_suspicion: _,
+            source_ports: _,
+            attempt_budget: _,
+            client_hello: _,
+            certificate_history: _,
+            expected_cert_name: _,
+            pre_connect_hook: _,
+            metrics: _,
+            tls_implementation: _,
+            renegotiation: _,
         } = value;
         proxy.clone()
     }
 }
 
-#[enum_derive(tokio1::AsyncRead, tokio1::AsyncWrite)]
+#[cfg(not(feature = "rustls-tls"))]
+#[enum_derive(tokio1::AsyncWrite)]
+pub enum TcpSslConnectorStream {
+    Direct(<DirectConnector as TransportConnector>::Stream),
+    Proxy(<TlsProxyConnector as TransportConnector>::Stream),
+}
+
+#[cfg(feature = "rustls-tls")]
+#[enum_derive(tokio1::AsyncWrite)]
 pub enum TcpSslConnectorStream {
     Direct(<DirectConnector as TransportConnector>::Stream),
     Proxy(<TlsProxyConnector as TransportConnector>::Stream),
+    Rustls(tokio_rustls::client::TlsStream<TcpStream>),
+}
+
+impl AsyncRead for TcpSslConnectorStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let result = match self.get_mut() {
+            Self::Direct(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Proxy(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "rustls-tls")]
+            Self::Rustls(stream) => return Pin::new(stream).poll_read(cx, buf),
+        };
+        result.map_err(|error| {
+            if is_renegotiation_rejection(&error) {
+                io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    TransportConnectError::TlsRenegotiationRejected,
+                )
+            } else {
+                error
+            }
+        })
+    }
+}
+
+impl TcpSslConnectorStream {
+    /// The leaf certificate the server presented in the handshake.
+    fn peer_certificate(&self) -> Option<X509> {
+        match self {
+            Self::Direct(stream) => stream.ssl().peer_certificate(),
+            Self::Proxy(stream) => stream.ssl().peer_certificate(),
+            #[cfg(feature = "rustls-tls")]
+            Self::Rustls(stream) => {
+                let leaf = stream.get_ref().1.peer_certificates()?.first()?;
+                X509::from_der(leaf).ok()
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct DirectConnector {
     pub dns_resolver: DnsResolver,
+    /// If set, written to the TCP stream before the TLS handshake starts.
+    pub proxy_protocol: Option<ProxyProtocolHeader>,
+    /// If `true`, staggered attempts to each resolved address go all the way
+    /// through the TLS handshake, and the first one to finish wins.
+    ///
+    /// Otherwise only the TCP connections are raced, and the TLS handshake is
+    /// run on whichever one is established first. Racing handshakes hides
+    /// servers that are slow to complete one, at the cost of doing more of
+    /// them.
+    pub race_tls_handshakes: bool,
+    /// If set, the SRV records for this service (like `_chat._tcp.signal.org`)
+    /// decide which hosts and ports to connect to, in order of priority and
+    /// weight; the SNI is left as configured.
+    ///
+    /// The configured host and port are used if there are no SRV records. See
+    /// [`DnsResolver::lookup_srv_candidates`].
+    pub srv_service: Option<Arc<str>>,
+    /// When to start over with a new TCP connection after a failed TLS
+    /// handshake.
+    pub tls_handshake_retry: TlsHandshakeRetryPolicy,
+    /// If set, IPv4 addresses are tried first while IPv6 is suspect.
+    ///
+    /// IPv6 becomes suspect when, with raced handshakes, an IPv6 handshake is
+    /// still going when an IPv4 one succeeds, or when a connection can't be
+    /// made over IPv6 right after one was
+    /// [lost](Ipv6Suspicion::report_connection_lost).
+    ///
+    /// See [`Ipv6Suspicion`].
+    pub ipv6_suspicion: Option<Ipv6Suspicion>,
+    /// If set, TCP connections are made from a local port in this range
+    /// instead of one chosen by the OS.
+    pub source_ports: Option<SourcePortRange>,
+    /// If set, each staggered attempt waits for a permit from this budget
+    /// before connecting, and holds it until the attempt is over.
+    ///
+    /// A raced attempt is over once its TLS handshake finishes; otherwise
+    /// it's over once the TCP connection is made. See [`AttemptBudget`].
+    pub attempt_budget: Option<AttemptBudget>,
+    /// If set, the ClientHello is adjusted as described.
+    ///
+    /// See [`ClientHelloCustomization`].
+    pub client_hello: Option<ClientHelloCustomization>,
+    /// What to do when the server asks to renegotiate.
+    pub renegotiation: RenegotiationPolicy,
+    /// If set, DNS lookups and TLS handshakes are reported here.
+    pub metrics: Option<Arc<dyn ConnectionMetrics>>,
 }
 
 /// Stateless [`Connector`] for [`TcpRoute`]s.
@@ -109,8 +541,19 @@ pub struct DirectConnector {
 pub struct StatelessTcp;
 
 /// Stateless [`Connector`] for [`TlsRouteFragment`]s.
-#[derive(Debug, Default)]
-pub struct StatelessTls;
+#[derive(Clone, Debug, Default)]
+pub struct StatelessTls {
+    metrics: Option<Arc<dyn ConnectionMetrics>>,
+}
+
+impl StatelessTls {
+    /// Reports each handshake, with how long it took, to `metrics`.
+    pub fn with_metrics(metrics: Arc<dyn ConnectionMetrics>) -> Self {
+        Self {
+            metrics: Some(metrics),
+        }
+    }
+}
 
 #[async_trait]
 impl TransportConnector for DirectConnector {
@@ -122,29 +565,241 @@ impl TransportConnector for DirectConnector {
         alpn: Alpn,
     ) -> Result<StreamAndInfo<Self::Stream>, TransportConnectError> {
         let log_tag: Arc<str> = "DirectConnector".into();
-        let StreamAndInfo(tcp_stream, remote_address) = connect_tcp(
-            &self.dns_resolver,
-            RouteType::Direct,
-            connection_params.tcp_host.as_deref(),
-            connection_params.port,
-            log_tag.clone(),
-        )
-        .await?;
-
-        let ssl_stream = connect_tls(tcp_stream, connection_params, alpn, None, log_tag).await?;
+        let (Some(service), Host::Domain(host)) = (&self.srv_service, &connection_params.tcp_host)
+        else {
+            return self
+                .connect_to(connection_params, None, alpn, log_tag)
+                .await;
+        };
 
-        Ok(StreamAndInfo(ssl_stream, remote_address))
+        let candidates = self
+            .resolver_for(connection_params)
+            .lookup_srv_candidates(service, (Arc::clone(host), connection_params.port))
+            .await;
+        let mut last_error = TransportConnectError::DnsError;
+        for (target, port) in candidates {
+            let candidate_params = TransportConnectionParams {
+                tcp_host: Host::Domain(target),
+                port,
+                ..connection_params.clone()
+            };
+            match self
+                .connect_to(&candidate_params, None, alpn, log_tag.clone())
+                .await
+            {
+                Ok(stream_and_info) => return Ok(stream_and_info),
+                Err(e) => {
+                    log::debug!("[{log_tag}] SRV target failed: {e}");
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
     }
 }
 
 impl DirectConnector {
     pub fn new(dns_resolver: DnsResolver) -> Self {
-        Self { dns_resolver }
+        Self {
+            dns_resolver,
+            proxy_protocol: None,
+            race_tls_handshakes: false,
+            srv_service: None,
+            tls_handshake_retry: TlsHandshakeRetryPolicy::default(),
+            ipv6_suspicion: None,
+            source_ports: None,
+            attempt_budget: None,
+            client_hello: None,
+            renegotiation: RenegotiationPolicy::default(),
+            metrics: None,
+        }
     }
 
     pub fn with_proxy(&self, proxy_addr: (Host<Arc<str>>, NonZeroU16)) -> TlsProxyConnector {
-        let Self { dns_resolver } = self;
-        TlsProxyConnector::new(dns_resolver.clone(), proxy_addr)
+        let Self {
+            dns_resolver,
+            proxy_protocol: _,
+            race_tls_handshakes: _,
+            srv_service: _,
+            tls_handshake_retry: _,
+            ipv6_suspicion: _,
+            source_ports: _,
+            attempt_budget: _,
+            client_hello: _,
+            renegotiation: _,
+            metrics,
+        } = self;
+        let mut connector = TlsProxyConnector::new(dns_resolver.clone(), proxy_addr);
+        connector.metrics = metrics.clone();
+        connector
+    }
+
+    /// The resolver for the route's host: its own, if it has one, otherwise the connector's.
+    fn resolver_for<'a>(
+        &'a self,
+        connection_params: &'a TransportConnectionParams,
+    ) -> &'a DnsResolver {
+        connection_params
+            .dns_resolver
+            .as_ref()
+            .unwrap_or(&self.dns_resolver)
+    }
+
+    /// Connects like [`TransportConnector::connect`], but to `addresses`
+    /// instead of whatever `connection_params.tcp_host` resolves to.
+    ///
+    /// This is for when the addresses are already known, say from a pushed
+    /// config. No DNS lookups are made, SRV records included, and the
+    /// resolver's address filters aren't applied; the addresses get the same
+    /// staggered attempts as resolved ones would, in the order given, except
+    /// that IPv4 addresses go first while [IPv6 is
+    /// suspect](Self::ipv6_suspicion).
+    pub async fn connect_with_addresses(
+        &self,
+        connection_params: &TransportConnectionParams,
+        addresses: &[IpAddr],
+        alpn: Alpn,
+    ) -> Result<StreamAndInfo<SslStream<TcpStream>>, TransportConnectError> {
+        self.connect_to(
+            connection_params,
+            Some(addresses),
+            alpn,
+            "DirectConnector".into(),
+        )
+        .await
+    }
+
+    async fn connect_to(
+        &self,
+        connection_params: &TransportConnectionParams,
+        provided_addresses: Option<&[IpAddr]>,
+        alpn: Alpn,
+        log_tag: Arc<str>,
+    ) -> Result<StreamAndInfo<SslStream<TcpStream>>, TransportConnectError> {
+        let mut retries_left = self.tls_handshake_retry.max_retries;
+        loop {
+            match self
+                .connect_once(connection_params, provided_addresses, alpn, log_tag.clone())
+                .await
+            {
+                Err(e) if retries_left > 0 && self.tls_handshake_retry.should_retry(&e) => {
+                    log::info!("[{log_tag}] retrying after transient TLS handshake failure: {e}");
+                    retries_left -= 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn connect_once(
+        &self,
+        connection_params: &TransportConnectionParams,
+        provided_addresses: Option<&[IpAddr]>,
+        alpn: Alpn,
+        log_tag: Arc<str>,
+    ) -> Result<StreamAndInfo<SslStream<TcpStream>>, TransportConnectError> {
+        let (mut addresses, dns_source) = match provided_addresses {
+            Some(addresses) => (addresses.to_vec(), DnsSource::Provided),
+            None => {
+                resolve_addresses(
+                    self.resolver_for(connection_params),
+                    connection_params.tcp_host.as_deref(),
+                    self.metrics.as_deref(),
+                )
+                .await?
+            }
+        };
+
+        let Some(ipv6_suspicion) = &self.ipv6_suspicion else {
+            return self
+                .connect_to_addresses(connection_params, addresses, dns_source, alpn, log_tag)
+                .await;
+        };
+        if ipv6_suspicion.is_suspect() {
+            log::debug!("[{log_tag}] IPv6 is suspect, trying IPv4 addresses first");
+            // The sort is stable, so each family keeps its own order.
+            addresses.sort_by_key(IpAddr::is_ipv6);
+        }
+        let reconnecting_after_ipv6_loss =
+            addresses.iter().any(IpAddr::is_ipv6) && ipv6_suspicion.take_lost_ipv6_connection();
+
+        let result = self
+            .connect_to_addresses(connection_params, addresses, dns_source, alpn, log_tag)
+            .await;
+
+        if reconnecting_after_ipv6_loss {
+            let reconnected_over_ipv6 = matches!(
+                &result,
+                Ok(StreamAndInfo(_, info)) if matches!(info.address, Host::Ip(IpAddr::V6(_)))
+            );
+            if !reconnected_over_ipv6 {
+                ipv6_suspicion.report_failed_ipv6_reconnect();
+            }
+        }
+        result
+    }
+
+    async fn connect_to_addresses(
+        &self,
+        connection_params: &TransportConnectionParams,
+        addresses: Vec<IpAddr>,
+        dns_source: DnsSource,
+        alpn: Alpn,
+        log_tag: Arc<str>,
+    ) -> Result<StreamAndInfo<SslStream<TcpStream>>, TransportConnectError> {
+        if self.race_tls_handshakes {
+            return connect_tcp_and_tls(
+                addresses,
+                dns_source,
+                RouteType::Direct,
+                connection_params,
+                alpn,
+                self.proxy_protocol.as_ref(),
+                self.ipv6_suspicion.as_ref(),
+                self.source_ports.as_ref(),
+                self.attempt_budget.as_ref(),
+                self.client_hello.as_ref(),
+                self.renegotiation,
+                self.metrics.as_deref(),
+                log_tag,
+            )
+            .await;
+        }
+
+        let StreamAndInfo(mut tcp_stream, remote_address) = connect_tcp_to_addresses(
+            addresses,
+            dns_source,
+            RouteType::Direct,
+            connection_params.port,
+            self.source_ports.as_ref(),
+            self.attempt_budget.as_ref(),
+            log_tag,
+        )
+        .await?;
+
+        if let Some(header) = &self.proxy_protocol {
+            header.write_to(&mut tcp_stream).await?;
+        }
+
+        let ssl_stream = connect_tls(
+            tcp_stream,
+            connection_params,
+            alpn,
+            None,
+            self.client_hello.as_ref(),
+            self.renegotiation,
+            self.metrics.as_deref(),
+        )
+        .await?;
+        let tls = TlsSessionInfo::from_stream(&ssl_stream);
+
+        Ok(StreamAndInfo(
+            ssl_stream,
+            ServiceConnectionInfo {
+                tls,
+                ..remote_address
+            },
+        ))
     }
 }
 
@@ -180,27 +835,47 @@ where
         fragment: TlsRouteFragment,
         _log_tag: Arc<str>,
     ) -> impl Future<Output = Result<Self::Connection, Self::Error>> + Send {
-        let TlsRouteFragment {
-            root_certs,
-            sni,
-            alpn,
-            min_protocol_version,
-        } = fragment;
-        let host = sni;
+        let handshake = connect_tls_over(inner, fragment, None, RenegotiationPolicy::default());
+        let metrics = self.metrics.clone();
+        async move { report_tls_handshake(metrics.as_deref(), handshake).await }
+    }
+}
 
-        let ssl_config = ssl_config(&root_certs, host.as_deref(), alpn, min_protocol_version);
+/// Makes the TLS handshake for a [`TlsRouteFragment`], adjusting the
+/// ClientHello as in `client_hello`.
+fn connect_tls_over<Inner: AsyncDuplexStream>(
+    inner: Inner,
+    fragment: TlsRouteFragment,
+    client_hello: Option<&ClientHelloCustomization>,
+    renegotiation: RenegotiationPolicy,
+) -> impl Future<Output = Result<SslStream<Inner>, TransportConnectError>> + Send {
+    let TlsRouteFragment {
+        root_certs,
+        sni,
+        alpn,
+        min_protocol_version,
+    } = fragment;
+    let host = sni;
 
-        async move {
-            let domain = match &host {
-                Host::Ip(ip_addr) => either::Either::Left(ip_addr.to_string()),
-                Host::Domain(domain) => either::Either::Right(&**domain),
-            };
-            let ssl_config = ssl_config?;
+    let ssl_config = ssl_config(
+        &root_certs,
+        host.as_deref(),
+        alpn,
+        min_protocol_version,
+        client_hello,
+        renegotiation,
+    );
+
+    async move {
+        let domain = match &host {
+            Host::Ip(ip_addr) => either::Either::Left(ip_addr.to_string()),
+            Host::Domain(domain) => either::Either::Right(&**domain),
+        };
+        let ssl_config = ssl_config?;
 
-            tokio_boring_signal::connect(ssl_config, &domain, inner)
-                .await
-                .map_err(TransportConnectError::from)
-        }
+        tokio_boring_signal::connect(ssl_config, &domain, inner)
+            .await
+            .map_err(TransportConnectError::from)
     }
 }
 
@@ -210,11 +885,26 @@ impl<S: Connection> Connection for SslStream<S> {
     }
 }
 
+/// This is just the default Boring TLS supported signature scheme list with
+/// ed25519 added at the top of the preference order.
+///
+/// We can't be any more specific because of the fallback proxies.
+const DEFAULT_SIGNATURE_ALGORITHMS: &[SslSignatureAlgorithm] = &[
+    SslSignatureAlgorithm::ED25519,
+    SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+    SslSignatureAlgorithm::RSA_PKCS1_SHA256,
+    SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
+    SslSignatureAlgorithm::RSA_PKCS1_SHA1,
+    SslSignatureAlgorithm::ECDSA_SHA1,
+];
+
 fn ssl_config(
     certs: &RootCertificates,
     host: Host<&str>,
     alpn: Option<Alpn>,
     min_required_tls_version: Option<boring_signal::ssl::SslVersion>,
+    client_hello: Option<&ClientHelloCustomization>,
+    renegotiation: RenegotiationPolicy,
 ) -> Result<ConnectConfiguration, TransportConnectError> {
     let mut ssl = SslConnector::builder(SslMethod::tls_client())?;
     certs.apply_to_connector(&mut ssl, host)?;
@@ -223,17 +913,24 @@ fn ssl_config(
     }
     ssl.set_min_proto_version(min_required_tls_version)?;
 
-    // This is just the default Boring TLS supported signature scheme list
-    //   with ed25519 added at the top of the preference order.
-    // We can't be any more specific because of the fallback proxies.
-    ssl.set_verify_algorithm_prefs(&[
-        SslSignatureAlgorithm::ED25519,
-        SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
-        SslSignatureAlgorithm::RSA_PKCS1_SHA256,
-        SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
-        SslSignatureAlgorithm::RSA_PKCS1_SHA1,
-        SslSignatureAlgorithm::ECDSA_SHA1,
-    ])?;
+    let signature_algorithms = client_hello
+        .and_then(|client_hello| client_hello.signature_algorithms.as_deref())
+        .unwrap_or(DEFAULT_SIGNATURE_ALGORITHMS);
+    ssl.set_verify_algorithm_prefs(signature_algorithms)?;
+
+    if let Some(ClientHelloCustomization {
+        grease,
+        permute_extensions,
+        signature_algorithms: _,
+        cipher_list,
+    }) = client_hello
+    {
+        ssl.set_grease_enabled(*grease);
+        ssl.set_permute_extensions(*permute_extensions);
+        if let Some(cipher_list) = cipher_list {
+            ssl.set_cipher_list(cipher_list)?;
+        }
+    }
 
     // Uncomment and build with the feature "dev-util" to enable NSS-standard
     //   debugging support for e.g. Wireshark.
@@ -243,7 +940,9 @@ fn ssl_config(
     // #[cfg(feature = "dev-util")]
     // development_only_enable_nss_standard_debug_interop(&mut ssl)?;
 
-    Ok(ssl.build().configure()?)
+    let mut config = ssl.build().configure()?;
+    renegotiation.apply_to(&mut config);
+    Ok(config)
 }
 
 async fn connect_tls<S: AsyncDuplexStream>(
@@ -251,25 +950,57 @@ async fn connect_tls<S: AsyncDuplexStream>(
     connection_params: &TransportConnectionParams,
     alpn: Alpn,
     min_protocol_version: Option<boring_signal::ssl::SslVersion>,
-    log_tag: Arc<str>,
+    client_hello: Option<&ClientHelloCustomization>,
+    renegotiation: RenegotiationPolicy,
+    metrics: Option<&dyn ConnectionMetrics>,
 ) -> Result<SslStream<S>, TransportConnectError> {
     let route = TlsRouteFragment {
         root_certs: connection_params.certs.clone(),
         sni: Host::Domain(Arc::clone(&connection_params.sni)),
-        alpn: Some(alpn),
+        alpn: Some(connection_params.alpn_or(alpn)),
         min_protocol_version,
     };
 
-    StatelessTls.connect_over(transport, route, log_tag).await
+    report_tls_handshake(
+        metrics,
+        connect_tls_over(transport, route, client_hello, renegotiation),
+    )
+    .await
 }
 
-async fn connect_tcp(
+/// Runs `handshake`, reporting how long it took and whether it succeeded to
+/// `metrics`, if given.
+async fn report_tls_handshake<T>(
+    metrics: Option<&dyn ConnectionMetrics>,
+    handshake: impl Future<Output = Result<T, TransportConnectError>>,
+) -> Result<T, TransportConnectError> {
+    let Some(metrics) = metrics else {
+        return handshake.await;
+    };
+    let started = Instant::now();
+    let result = handshake.await;
+    metrics.tls_handshake(started.elapsed(), result.is_ok());
+    result
+}
+
+impl TlsSessionInfo {
+    /// Reads the negotiated parameters from a stream that has completed its
+    /// handshake.
+    pub(crate) fn from_stream<S>(stream: &SslStream<S>) -> Option<Self> {
+        let ssl = stream.ssl();
+        Some(Self {
+            protocol_version: ssl.version2()?,
+            cipher_suite: ssl.current_cipher()?.name(),
+        })
+    }
+}
+
+/// Looks up `host`, reporting the lookup to `metrics` if it's a domain.
+async fn resolve_for_connect(
     dns_resolver: &DnsResolver,
-    route_type: RouteType,
     host: Host<&str>,
-    port: NonZeroU16,
-    log_tag: Arc<str>,
-) -> Result<StreamAndInfo<TcpStream>, TransportConnectError> {
+    metrics: Option<&dyn ConnectionMetrics>,
+) -> Result<crate::dns::lookup_result::LookupResult, TransportConnectError> {
     let dns_lookup = match host {
         Host::Ip(ip) => {
             let (ipv4, ipv6) = match ip {
@@ -282,18 +1013,69 @@ async fn connect_tcp(
                 ipv6,
             }
         }
-        Host::Domain(domain) => dns_resolver
-            .lookup_ip(domain)
-            .await
-            .map_err(|_| TransportConnectError::DnsError)?,
+        Host::Domain(domain) => {
+            let started = Instant::now();
+            let result = dns_resolver.lookup_ip_preferring_pinned(domain).await;
+            if let Some(metrics) = metrics {
+                metrics.dns_lookup(started.elapsed(), result.is_ok());
+            }
+            result.map_err(|_| TransportConnectError::DnsError)?
+        }
     };
+    let dns_lookup = dns_resolver.apply_address_filters(dns_lookup);
 
     if dns_lookup.is_empty() {
         return Err(TransportConnectError::DnsError);
     }
+    Ok(dns_lookup)
+}
 
+/// Resolves `host` into the addresses to make staggered attempts to, in order.
+async fn resolve_addresses(
+    dns_resolver: &DnsResolver,
+    host: Host<&str>,
+    metrics: Option<&dyn ConnectionMetrics>,
+) -> Result<(Vec<IpAddr>, DnsSource), TransportConnectError> {
+    let dns_lookup = resolve_for_connect(dns_resolver, host, metrics).await?;
     let dns_source = dns_lookup.source();
+    Ok((dns_lookup.into_iter().collect(), dns_source))
+}
+
+async fn connect_tcp(
+    dns_resolver: &DnsResolver,
+    route_type: RouteType,
+    host: Host<&str>,
+    port: NonZeroU16,
+    source_ports: Option<&SourcePortRange>,
+    metrics: Option<&dyn ConnectionMetrics>,
+    log_tag: Arc<str>,
+) -> Result<StreamAndInfo<TcpStream>, TransportConnectError> {
+    let (addresses, dns_source) = resolve_addresses(dns_resolver, host, metrics).await?;
+    connect_tcp_to_addresses(
+        addresses,
+        dns_source,
+        route_type,
+        port,
+        source_ports,
+        None,
+        log_tag,
+    )
+    .await
+}
 
+/// Like [`connect_tcp`], but to addresses that have already been resolved.
+///
+/// Each attempt holds a permit from `attempt_budget`, if given, while it
+/// connects.
+async fn connect_tcp_to_addresses(
+    addresses: Vec<IpAddr>,
+    dns_source: DnsSource,
+    route_type: RouteType,
+    port: NonZeroU16,
+    source_ports: Option<&SourcePortRange>,
+    attempt_budget: Option<&AttemptBudget>,
+    log_tag: Arc<str>,
+) -> Result<StreamAndInfo<TcpStream>, TransportConnectError> {
     // The idea is to go through the list of candidate IP addresses
     // and to attempt a connection to each of them, giving each one a `CONNECTION_ATTEMPT_DELAY` headstart
     // before moving on to the next candidate.
@@ -303,7 +1085,7 @@ async fn connect_tcp(
     // that incorporates the delay based on its position in the list.
     // This way we can start all futures at once and simply wait for the first one to complete successfully.
     let connector = StatelessTcp;
-    let staggered_futures = dns_lookup.into_iter().enumerate().map(|(idx, ip)| {
+    let staggered_futures = addresses.into_iter().enumerate().map(|(idx, ip)| {
         let delay = TCP_CONNECTION_ATTEMPT_DELAY * idx.try_into().unwrap();
         let connector = &connector;
         let log_tag = log_tag.clone();
@@ -311,9 +1093,12 @@ async fn connect_tcp(
             if !delay.is_zero() {
                 tokio::time::sleep(delay).await;
             }
+            let _permit = match attempt_budget {
+                Some(budget) => Some(budget.acquire().await),
+                None => None,
+            };
             let route = TcpRoute { address: ip, port };
-            connector
-                .connect(route, log_tag)
+            connect_tcp_route(connector, route, source_ports, log_tag)
                 .inspect_err(|e| {
                     log::debug!("failed to connect to IP [{ip}] with an error: {e:?}");
                 })
@@ -326,6 +1111,7 @@ async fn connect_tcp(
                             route_type,
                             dns_source,
                             address: ip.into(),
+                            tls: None,
                         },
                     )
                 })
@@ -337,41 +1123,263 @@ async fn connect_tcp(
         .ok_or(TransportConnectError::TcpConnectionFailed)
 }
 
-#[async_trait]
-impl TransportConnector for TcpSslConnector {
-    type Stream = TcpSslConnectorStream;
+/// Connects to one resolved address, from a port in `source_ports` if given.
+async fn connect_tcp_route(
+    connector: &StatelessTcp,
+    route: TcpRoute<IpAddr>,
+    source_ports: Option<&SourcePortRange>,
+    log_tag: Arc<str>,
+) -> Result<TcpStream, TransportConnectError> {
+    match source_ports {
+        None => connector.connect(route, log_tag).await,
+        Some(source_ports) => source_ports.connect(route).await,
+    }
+}
 
-    async fn connect(
-        &self,
-        connection_params: &TransportConnectionParams,
-        alpn: Alpn,
-    ) -> Result<StreamAndInfo<Self::Stream>, TransportConnectError> {
-        let Self {
-            dns_resolver,
-            proxy,
-        } = self;
-        let proxy = proxy
-            .as_ref()
-            .map_err(|InvalidProxyConfig| TransportConnectError::InvalidConfiguration)?;
+/// Like [`connect_tcp`] followed by [`connect_tls`], except that the TLS
+/// handshake is part of each staggered attempt.
+///
+/// The first attempt to finish its handshake wins, and the rest are dropped,
+/// closing their sockets. If an IPv4 attempt wins while an IPv6 one that
+/// started before it is still handshaking, and has been for at least
+/// [`STALLED_IPV6_HANDSHAKE_THRESHOLD`], it's reported to `ipv6_suspicion`.
+/// Each attempt holds a permit from `attempt_budget`, if given, until its
+/// handshake is done.
+#[allow(clippy::too_many_arguments)]
+async fn connect_tcp_and_tls(
+    addresses: Vec<IpAddr>,
+    dns_source: DnsSource,
+    route_type: RouteType,
+    connection_params: &TransportConnectionParams,
+    alpn: Alpn,
+    proxy_protocol: Option<&ProxyProtocolHeader>,
+    ipv6_suspicion: Option<&Ipv6Suspicion>,
+    source_ports: Option<&SourcePortRange>,
+    attempt_budget: Option<&AttemptBudget>,
+    client_hello: Option<&ClientHelloCustomization>,
+    renegotiation: RenegotiationPolicy,
+    metrics: Option<&dyn ConnectionMetrics>,
+    log_tag: Arc<str>,
+) -> Result<StreamAndInfo<SslStream<TcpStream>>, TransportConnectError> {
+    let port = connection_params.port;
 
-        let stream_and_info = match proxy {
-            None => {
-                let stream_and_info = DirectConnector {
-                    dns_resolver: dns_resolver.clone(),
-                }
-                .connect(connection_params, alpn)
-                .await?;
+    // For each attempt, by index, when its IPv6 handshake started, if one is
+    // in progress. The entry is cleared once the handshake finishes either
+    // way, so attempts dropped in the middle of a handshake keep theirs.
+    let ipv6_handshakes_started = std::sync::Mutex::new(vec![None; addresses.len()]);
 
-                stream_and_info.map_stream(TcpSslConnectorStream::Direct)
+    let connector = StatelessTcp;
+    let staggered_futures = addresses.into_iter().enumerate().map(|(idx, ip)| {
+        let delay = TCP_CONNECTION_ATTEMPT_DELAY * idx.try_into().unwrap();
+        let connector = &connector;
+        let ipv6_handshakes_started = &ipv6_handshakes_started;
+        let log_tag = log_tag.clone();
+        async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            let _permit = match attempt_budget {
+                Some(budget) => Some(budget.acquire().await),
+                None => None,
+            };
+            let route = TcpRoute { address: ip, port };
+            let mut tcp_stream = connect_tcp_route(connector, route, source_ports, log_tag).await?;
+            if let Some(header) = proxy_protocol {
+                header.write_to(&mut tcp_stream).await?;
+            }
+            if !ip.is_ipv6() {
+                return connect_tls(
+                    tcp_stream,
+                    connection_params,
+                    alpn,
+                    None,
+                    client_hello,
+                    renegotiation,
+                    metrics,
+                )
+                .await;
+            }
+            ipv6_handshakes_started.lock().expect("not poisoned")[idx] = Some(Instant::now());
+            let result = connect_tls(
+                tcp_stream,
+                connection_params,
+                alpn,
+                None,
+                client_hello,
+                renegotiation,
+                metrics,
+            )
+            .await;
+            ipv6_handshakes_started.lock().expect("not poisoned")[idx] = None;
+            result
+        }
+        .inspect_err(move |e| {
+            log::debug!("failed to connect to IP [{ip}] with an error: {e:?}");
+        })
+        .map_ok(move |stream| {
+            log::debug!("successfully connected to IP [{ip}]");
+            let tls = TlsSessionInfo::from_stream(&stream);
+            let stream_and_info = StreamAndInfo(
+                stream,
+                ServiceConnectionInfo {
+                    route_type,
+                    dns_source,
+                    address: ip.into(),
+                    tls,
+                },
+            );
+            (idx, stream_and_info)
+        })
+    });
+
+    // Unlike with TCP-only attempts, a failure here might come from a server
+    // that was reachable but rejected the handshake, which is worth reporting
+    // over a generic connection failure.
+    let mut attempts = FuturesUnordered::from_iter(staggered_futures);
+    let mut last_handshake_error = None;
+    let result = loop {
+        match attempts.next().await {
+            Some(Ok(winner)) => break Some(winner),
+            Some(Err(TransportConnectError::TcpConnectionFailed)) => {}
+            Some(Err(e)) => last_handshake_error = Some(e),
+            None => break None,
+        }
+    };
+    drop(attempts);
+
+    if let (Some(ipv6_suspicion), Some((winner_idx, StreamAndInfo(_, info)))) =
+        (ipv6_suspicion, &result)
+    {
+        // Attempts are started in order, so only the ones before the winner
+        // had a head start on it. A later one can still be handshaking just
+        // because it started late, like when IPv4 goes first.
+        let now = Instant::now();
+        let stalled = ipv6_handshakes_started.lock().expect("not poisoned")[..*winner_idx]
+            .iter()
+            .flatten()
+            .any(|started| now - *started >= STALLED_IPV6_HANDSHAKE_THRESHOLD);
+        if matches!(info.address, Host::Ip(IpAddr::V4(_))) && stalled {
+            ipv6_suspicion.report_stalled_handshake();
+        }
+    }
+
+    result
+        .map(|(_idx, stream_and_info)| stream_and_info)
+        .ok_or_else(|| last_handshake_error.unwrap_or(TransportConnectError::TcpConnectionFailed))
+}
+
+#[async_trait]
+impl TransportConnector for TcpSslConnector {
+    type Stream = TcpSslConnectorStream;
+
+    async fn connect(
+        &self,
+        connection_params: &TransportConnectionParams,
+        alpn: Alpn,
+    ) -> Result<StreamAndInfo<Self::Stream>, TransportConnectError> {
+        let Some(metrics) = &self.metrics else {
+            return self.connect_unreported(connection_params, alpn).await;
+        };
+        metrics.attempt_started();
+        let result = self.connect_unreported(connection_params, alpn).await;
+        match &result {
+            Ok(_) => metrics.attempt_succeeded(),
+            Err(e) => metrics.attempt_failed(e.category()),
+        }
+        result
+    }
+}
+
+impl TcpSslConnector {
+    /// Connects like [`TransportConnector::connect`], without reporting the
+    /// attempt itself to the connector's metrics.
+    async fn connect_unreported(
+        &self,
+        connection_params: &TransportConnectionParams,
+        alpn: Alpn,
+    ) -> Result<StreamAndInfo<TcpSslConnectorStream>, TransportConnectError> {
+        let Self {
+            dns_resolver,
+            proxy,
+            proxy_protocol,
+            race_tls_handshakes,
+            srv_service,
+            tls_handshake_retry,
+            ipv6_suspicion,
+            source_ports,
+            attempt_budget,
+            client_hello,
+            certificate_history,
+            expected_cert_name,
+            pre_connect_hook,
+            metrics,
+            tls_implementation,
+            renegotiation,
+        } = self;
+
+        if let Some(hook) = pre_connect_hook {
+            if let Err(reason) = hook.before_connect(connection_params).await {
+                log::info!("pre-connect hook aborted the connection attempt: {reason}");
+                return Err(TransportConnectError::PreConnectAborted);
+            }
+        }
+
+        let proxy = proxy
+            .as_ref()
+            .map_err(|InvalidProxyConfig| TransportConnectError::InvalidConfiguration)?;
+
+        // Without rustls, BoringSSL is the only option.
+        #[cfg(not(feature = "rustls-tls"))]
+        let TlsImplementation::BoringSsl = tls_implementation;
+        #[cfg(feature = "rustls-tls")]
+        if *tls_implementation == TlsImplementation::Rustls && proxy.is_some() {
+            log::warn!("proxies are not supported with rustls");
+            return Err(TransportConnectError::InvalidConfiguration);
+        }
+
+        let stream_and_info = match proxy {
+            #[cfg(feature = "rustls-tls")]
+            None if *tls_implementation == TlsImplementation::Rustls => {
+                let stream_and_info = rustls_tls::connect_direct(
+                    dns_resolver,
+                    connection_params,
+                    alpn,
+                    proxy_protocol.as_ref(),
+                    source_ports.as_ref(),
+                    attempt_budget.as_ref(),
+                    metrics.as_deref(),
+                )
+                .await?;
+                stream_and_info.map_stream(TcpSslConnectorStream::Rustls)
+            }
+            None => {
+                let stream_and_info = DirectConnector {
+                    dns_resolver: dns_resolver.clone(),
+                    proxy_protocol: proxy_protocol.clone(),
+                    race_tls_handshakes: *race_tls_handshakes,
+                    srv_service: srv_service.clone(),
+                    tls_handshake_retry: *tls_handshake_retry,
+                    ipv6_suspicion: ipv6_suspicion.clone(),
+                    source_ports: source_ports.clone(),
+                    attempt_budget: attempt_budget.clone(),
+                    client_hello: client_hello.clone(),
+                    renegotiation: *renegotiation,
+                    metrics: metrics.clone(),
+                }
+                .connect(connection_params, alpn)
+                .await?;
+
+                stream_and_info.map_stream(TcpSslConnectorStream::Direct)
             }
             Some(ConnectionProxyConfig::Tcp(TcpProxy {
                 proxy_host,
                 proxy_port,
             })) => {
-                let connector = TlsProxyConnector::new_tcp(
+                let mut connector = TlsProxyConnector::new_tcp(
                     dns_resolver.clone(),
                     (proxy_host.clone(), *proxy_port),
                 );
+                connector.metrics = metrics.clone();
                 let stream_and_info = connector.connect(connection_params, alpn).await?;
                 stream_and_info.map_stream(TcpSslConnectorStream::Proxy)
             }
@@ -383,6 +1391,7 @@ impl TransportConnector for TcpSslConnector {
                 let mut connector =
                     TlsProxyConnector::new(dns_resolver.clone(), (proxy_host.clone(), *proxy_port));
                 connector.proxy_certs = proxy_certs.clone();
+                connector.metrics = metrics.clone();
                 let stream_and_info = connector.connect(connection_params, alpn).await?;
                 stream_and_info.map_stream(TcpSslConnectorStream::Proxy)
             }
@@ -392,39 +1401,136 @@ impl TransportConnector for TcpSslConnector {
             }
         };
 
+        if certificate_history.is_some() || expected_cert_name.is_some() {
+            let certificate = stream_and_info
+                .0
+                .peer_certificate()
+                .ok_or(TransportConnectError::CertError)?;
+            if let Some(certificate_history) = certificate_history {
+                certificate_history.check(&connection_params.sni, &certificate)?;
+            }
+            if let Some(expected_cert_name) = expected_cert_name {
+                check_certificate_name(expected_cert_name, &certificate)?;
+            }
+        }
+
         Ok(stream_and_info)
     }
 }
 
 #[cfg(test)]
 pub(crate) mod testutil {
+    use std::borrow::Cow;
     use std::future::Future;
     use std::net::{Ipv6Addr, SocketAddr};
     use std::sync::LazyLock;
 
+    use boring_signal::pkey::PKey;
+    use boring_signal::ssl::{SslAcceptor, SslAcceptorBuilder, SslMethod, SslVersion};
+    use boring_signal::x509::X509;
     use rcgen::CertifiedKey;
     use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
     use warp::Filter;
 
+    use crate::certs::RootCertificates;
+    use crate::host::Host;
+    use crate::TransportConnectionParams;
+
     pub(crate) const SERVER_HOSTNAME: &str = "test-server.signal.org.local";
 
     pub(crate) static SERVER_CERTIFICATE: LazyLock<CertifiedKey> = LazyLock::new(|| {
         rcgen::generate_simple_self_signed([SERVER_HOSTNAME.to_string()]).expect("can generate")
     });
 
+    /// Parameters for connecting to [`SERVER_HOSTNAME`] on `port`, trusting
+    /// [`SERVER_CERTIFICATE`].
+    ///
+    /// The hostname has to be resolvable, as with
+    /// [`LookupResult::localhost`](crate::dns::lookup_result::LookupResult::localhost)
+    /// in a static resolver map. Tests that connect by address override
+    /// `tcp_host`.
+    pub(crate) fn localhost_params(port: u16) -> TransportConnectionParams {
+        TransportConnectionParams {
+            sni: SERVER_HOSTNAME.into(),
+            tcp_host: Host::Domain(SERVER_HOSTNAME.into()),
+            port: port.try_into().expect("bound port"),
+            certs: RootCertificates::FromDer(Cow::Borrowed(SERVER_CERTIFICATE.cert.der())),
+            dns_resolver: None,
+            alpn: None,
+        }
+    }
+
+    /// Accepts every connection on `listener` and holds on to it without ever
+    /// answering, like a server whose TLS handshakes stall.
+    ///
+    /// Returns a [`Future`] that runs the listener, and a receiver that gets a
+    /// message for each connection accepted.
+    pub(crate) fn stalling_listener(
+        listener: TcpListener,
+    ) -> (impl Future<Output = ()>, mpsc::UnboundedReceiver<()>) {
+        let (accepted_tx, accepted_rx) = mpsc::unbounded_channel();
+        let server = async move {
+            let mut accepted = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted.push(stream);
+                // The test might not care.
+                let _ = accepted_tx.send(());
+            }
+        };
+        (server, accepted_rx)
+    }
+
+    /// A BoringSSL server presenting [`SERVER_CERTIFICATE`], speaking at most
+    /// `max_version` if given.
+    ///
+    /// Returns the builder so that tests can change other settings before
+    /// building it.
+    pub(crate) fn tls_acceptor(max_version: Option<SslVersion>) -> SslAcceptorBuilder {
+        let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())
+            .expect("can create builder");
+        builder
+            .set_max_proto_version(max_version)
+            .expect("can set version");
+        builder
+            .set_certificate(&X509::from_der(SERVER_CERTIFICATE.cert.der()).expect("valid"))
+            .expect("can set certificate");
+        builder
+            .set_private_key(
+                &PKey::private_key_from_der(SERVER_CERTIFICATE.key_pair.serialized_der())
+                    .expect("valid"),
+            )
+            .expect("can set key");
+        builder
+    }
+
     const FAKE_RESPONSE: &str = "Hello there";
     /// Starts an HTTP server listening on `::1` that responds with 200 and
     /// [`FAKE_RESPONSE`].
     ///
     /// Returns the address of the server and a [`Future`] that runs it.
     pub(crate) fn localhost_http_server() -> (SocketAddr, impl Future<Output = ()>) {
+        http_server_bound_to((Ipv6Addr::LOCALHOST, 0).into())
+    }
+
+    /// Like [`localhost_http_server`], but listens on the given address.
+    pub(crate) fn http_server_bound_to(addr: SocketAddr) -> (SocketAddr, impl Future<Output = ()>) {
+        http_server_with_certificate(addr, &SERVER_CERTIFICATE)
+    }
+
+    /// Like [`http_server_bound_to`], but presents the given certificate.
+    pub(crate) fn http_server_with_certificate(
+        addr: SocketAddr,
+        certificate: &CertifiedKey,
+    ) -> (SocketAddr, impl Future<Output = ()>) {
         let filter = warp::any().map(|| FAKE_RESPONSE);
         let server = warp::serve(filter)
             .tls()
-            .cert(SERVER_CERTIFICATE.cert.pem())
-            .key(SERVER_CERTIFICATE.key_pair.serialize_pem());
+            .cert(certificate.cert.pem())
+            .key(certificate.key_pair.serialize_pem());
 
-        server.bind_ephemeral((Ipv6Addr::LOCALHOST, 0))
+        server.bind_ephemeral(addr)
     }
 
     /// Makes an HTTP request on the provided stream and asserts on the response.
@@ -457,15 +1563,22 @@ pub(crate) mod testutil {
 mod test {
     use std::borrow::Cow;
     use std::collections::HashMap;
-    use std::net::Ipv6Addr;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 
     use assert_matches::assert_matches;
+    use nonzero_ext::nonzero;
     use test_case::test_case;
+    use tokio::io::AsyncReadExt as _;
 
     use super::testutil::*;
     use super::*;
+    use crate::certs::CertProfiles;
     use crate::dns::lookup_result::LookupResult;
+    use crate::errors::UserHint;
     use crate::host::Host;
+    use crate::tcp_ssl::proxy_protocol::testutil::read_proxy_protocol_header;
+    use crate::tcp_ssl::proxy_protocol::ProxyProtocolVersion;
+    use crate::ConnectCancellation;
 
     #[test_case(true; "resolved hostname")]
     #[test_case(false; "by IP")]
@@ -479,13 +1592,11 @@ mod test {
             LookupResult::localhost(),
         )])));
         let connection_params = TransportConnectionParams {
-            sni: SERVER_HOSTNAME.into(),
             tcp_host: match use_hostname {
                 true => Host::Domain(SERVER_HOSTNAME.into()),
                 false => addr.ip().into(),
             },
-            port: addr.port().try_into().expect("bound port"),
-            certs: RootCertificates::FromDer(Cow::Borrowed(SERVER_CERTIFICATE.cert.der())),
+            ..localhost_params(addr.port())
         };
 
         let StreamAndInfo(stream, info) = connector
@@ -499,12 +1610,809 @@ mod test {
                 address: Host::Ip(Ipv6Addr::LOCALHOST.into()),
                 dns_source: crate::DnsSource::Static,
                 route_type: RouteType::Direct,
+                tls: info.tls,
             }
         );
+        assert_eq!(
+            info.tls.map(|tls| tls.protocol_version),
+            Some(boring_signal::ssl::SslVersion::TLS1_3)
+        );
+
+        make_http_request_response_over(stream).await
+    }
+
+    #[test_case(None, &b"http/1.1"[..]; "requested")]
+    #[test_case(Some(Alpn::Http2), &b"h2"[..]; "route's own")]
+    #[tokio::test]
+    async fn connect_offers_the_routes_own_alpn(route_alpn: Option<Alpn>, expected: &[u8]) {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let connector = DirectConnector::new(DnsResolver::new_from_static_map(HashMap::new()));
+        let connection_params = TransportConnectionParams {
+            tcp_host: addr.ip().into(),
+            alpn: route_alpn,
+            ..localhost_params(addr.port())
+        };
+
+        let StreamAndInfo(stream, _info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+
+        assert_eq!(stream.ssl().selected_alpn_protocol(), Some(expected));
+    }
+
+    #[test_case(TlsImplementation::BoringSsl; "BoringSSL")]
+    #[cfg_attr(feature = "rustls-tls", test_case(TlsImplementation::Rustls; "rustls"))]
+    #[tokio::test]
+    async fn tcp_ssl_connector_connects_to_server(tls_implementation: TlsImplementation) {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let mut connector = TcpSslConnector::new_direct(DnsResolver::new_from_static_map(
+            HashMap::from([(SERVER_HOSTNAME, LookupResult::localhost())]),
+        ));
+        connector.set_tls_implementation(tls_implementation);
+        let connection_params = localhost_params(addr.port());
+
+        let StreamAndInfo(stream, info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+        assert_eq!(info.address, Host::Ip(Ipv6Addr::LOCALHOST.into()));
+        assert_eq!(
+            info.tls.map(|tls| tls.protocol_version),
+            Some(boring_signal::ssl::SslVersion::TLS1_3)
+        );
+        make_http_request_response_over(stream).await;
+
+        // Certificates that aren't trusted are rejected either way.
+        let untrusted = rcgen::generate_simple_self_signed([SERVER_HOSTNAME.to_string()])
+            .expect("can generate");
+        let result = connector
+            .connect(
+                &TransportConnectionParams {
+                    certs: RootCertificates::FromDer(Cow::Owned(untrusted.cert.der().to_vec())),
+                    ..connection_params
+                },
+                Alpn::Http1_1,
+            )
+            .await;
+        assert_matches!(
+            result,
+            Err(TransportConnectError::SslFailedHandshake(_) | TransportConnectError::CertError)
+        );
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    #[tokio::test]
+    async fn rustls_does_not_support_proxies() {
+        let mut connector = TcpSslConnector::new_direct(DnsResolver::default());
+        connector.set_tls_implementation(TlsImplementation::Rustls);
+        connector.set_proxy(ConnectionProxyConfig::Tcp(TcpProxy {
+            proxy_host: Host::Domain("proxy.signal.org.local".into()),
+            proxy_port: nonzero!(443u16),
+        }));
+        let connection_params = localhost_params(443);
+
+        assert_matches!(
+            connector.connect(&connection_params, Alpn::Http1_1).await,
+            Err(TransportConnectError::InvalidConfiguration)
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_with_named_cert_profiles() {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let other_certificate = rcgen::generate_simple_self_signed([SERVER_HOSTNAME.to_string()])
+            .expect("can generate");
+        let cert_profiles = CertProfiles::default()
+            .with_profile(
+                "test-server",
+                RootCertificates::FromDer(Cow::Borrowed(SERVER_CERTIFICATE.cert.der())),
+            )
+            .with_profile(
+                "other",
+                RootCertificates::FromDer(Cow::Owned(other_certificate.cert.der().to_vec())),
+            );
+
+        let connector = DirectConnector::new(DnsResolver::new_from_static_map(HashMap::from([(
+            SERVER_HOSTNAME,
+            LookupResult::localhost(),
+        )])));
+        // The base parameters trust neither profile's roots.
+        let connection_params = TransportConnectionParams {
+            certs: RootCertificates::FromStaticDers(&[]),
+            ..localhost_params(addr.port())
+        };
+
+        let StreamAndInfo(stream, _info) = connector
+            .connect_with_cert_profile(
+                &connection_params,
+                &cert_profiles,
+                "test-server",
+                Alpn::Http1_1,
+            )
+            .await
+            .expect("can connect");
+        make_http_request_response_over(stream).await;
+
+        let result = connector
+            .connect_with_cert_profile(&connection_params, &cert_profiles, "other", Alpn::Http1_1)
+            .await;
+        assert_matches!(
+            result,
+            Err(TransportConnectError::SslFailedHandshake(_) | TransportConnectError::CertError)
+        );
+
+        let result = connector
+            .connect_with_cert_profile(&connection_params, &cert_profiles, "missing", Alpn::Http1_1)
+            .await;
+        assert_matches!(result, Err(TransportConnectError::InvalidConfiguration));
+    }
+
+    #[test_case(TlsImplementation::BoringSsl; "BoringSSL")]
+    #[cfg_attr(feature = "rustls-tls", test_case(TlsImplementation::Rustls; "rustls"))]
+    #[tokio::test]
+    async fn certificate_change_is_detected_on_reconnect(tls_implementation: TlsImplementation) {
+        use rcgen::{BasicConstraints, CertificateParams, CertifiedKey, IsCa, KeyPair};
+
+        // Both servers have certificates issued by the same trusted CA, so
+        // either one passes normal validation.
+        let ca_key = KeyPair::generate().expect("can generate");
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).expect("valid");
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).expect("can sign");
+        let issue_leaf = || {
+            let key_pair = KeyPair::generate().expect("can generate");
+            let cert = CertificateParams::new([SERVER_HOSTNAME.to_string()])
+                .expect("valid")
+                .signed_by(&key_pair, &ca, &ca_key)
+                .expect("can sign");
+            CertifiedKey { cert, key_pair }
+        };
+        let (original, rotated) = (issue_leaf(), issue_leaf());
+
+        let (original_addr, original_server) =
+            http_server_with_certificate((Ipv6Addr::LOCALHOST, 0).into(), &original);
+        let _original_handle = tokio::spawn(original_server);
+        let (rotated_addr, rotated_server) =
+            http_server_with_certificate((Ipv6Addr::LOCALHOST, 0).into(), &rotated);
+        let _rotated_handle = tokio::spawn(rotated_server);
+
+        let params_for = |addr: SocketAddr| TransportConnectionParams {
+            certs: RootCertificates::FromDer(Cow::Owned(ca.der().to_vec())),
+            ..localhost_params(addr.port())
+        };
+        let connector_with_history = |history| {
+            let mut connector = TcpSslConnector::new_direct(DnsResolver::new_from_static_map(
+                HashMap::from([(SERVER_HOSTNAME, LookupResult::localhost())]),
+            ));
+            connector.set_certificate_history(Some(history));
+            connector.set_tls_implementation(tls_implementation);
+            connector
+        };
+
+        let connector = connector_with_history(CertificateHistory::default());
+        for _ in 0..2 {
+            let StreamAndInfo(stream, _info) = connector
+                .connect(&params_for(original_addr), Alpn::Http1_1)
+                .await
+                .expect("same certificate is accepted");
+            make_http_request_response_over(stream).await;
+        }
+        assert_matches!(
+            connector
+                .connect(&params_for(rotated_addr), Alpn::Http1_1)
+                .await,
+            Err(TransportConnectError::CertificateChanged)
+        );
+
+        // With the new key on the allowlist, the change is accepted and
+        // becomes the expected certificate.
+        let rotated_hash =
+            CertificateHistory::spki_hash_of_der(rotated.cert.der()).expect("valid certificate");
+        let connector = connector_with_history(CertificateHistory::new([rotated_hash]));
+        connector
+            .connect(&params_for(original_addr), Alpn::Http1_1)
+            .await
+            .expect("can connect");
+        connector
+            .connect(&params_for(rotated_addr), Alpn::Http1_1)
+            .await
+            .expect("rotation is allowed");
+        assert_matches!(
+            connector
+                .connect(&params_for(original_addr), Alpn::Http1_1)
+                .await,
+            Err(TransportConnectError::CertificateChanged)
+        );
+    }
+
+    #[test_case(TlsImplementation::BoringSsl; "BoringSSL")]
+    #[cfg_attr(feature = "rustls-tls", test_case(TlsImplementation::Rustls; "rustls"))]
+    #[tokio::test]
+    async fn expected_cert_name_is_checked_separately_from_sni(
+        tls_implementation: TlsImplementation,
+    ) {
+        const FRONT: &str = "front.signal.org.local";
+        const BACKEND: &str = "backend.signal.org.local";
+
+        let certificate =
+            rcgen::generate_simple_self_signed([FRONT.to_string(), BACKEND.to_string()])
+                .expect("can generate");
+        let (addr, server) =
+            http_server_with_certificate((Ipv6Addr::LOCALHOST, 0).into(), &certificate);
+        let _server_handle = tokio::spawn(server);
+
+        let connection_params = TransportConnectionParams {
+            sni: FRONT.into(),
+            tcp_host: Host::Ip(addr.ip()),
+            certs: RootCertificates::FromDer(Cow::Owned(certificate.cert.der().to_vec())),
+            ..localhost_params(addr.port())
+        };
+        let connector_expecting = |name: &str| {
+            let mut connector = TcpSslConnector::new_direct(DnsResolver::default());
+            connector.set_expected_cert_name(Some(name.into()));
+            connector.set_tls_implementation(tls_implementation);
+            connector
+        };
+
+        let StreamAndInfo(stream, _info) = connector_expecting(BACKEND)
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("certificate is valid for the backend");
+        make_http_request_response_over(stream).await;
+
+        assert_matches!(
+            connector_expecting("other.signal.org.local")
+                .connect(&connection_params, Alpn::Http1_1)
+                .await,
+            Err(TransportConnectError::CertNameMismatch)
+        );
+    }
+
+    #[test_case(true => UserHint::CheckClock; "expired")]
+    #[test_case(false => UserHint::SignInToNetwork; "untrusted")]
+    #[tokio::test]
+    async fn rejected_certificate_hints_at_the_cause(expired: bool) -> UserHint {
+        use rcgen::{CertificateParams, CertifiedKey, KeyPair};
+
+        let key_pair = KeyPair::generate().expect("can generate");
+        let mut params = CertificateParams::new([SERVER_HOSTNAME.to_string()]).expect("valid");
+        if expired {
+            params.not_before = rcgen::date_time_ymd(2000, 1, 1);
+            params.not_after = rcgen::date_time_ymd(2001, 1, 1);
+        }
+        let cert = params.self_signed(&key_pair).expect("can sign");
+        let certificate = CertifiedKey { cert, key_pair };
+        let (addr, server) =
+            http_server_with_certificate((Ipv6Addr::LOCALHOST, 0).into(), &certificate);
+        let _server_handle = tokio::spawn(server);
+
+        let trusted = if expired {
+            &certificate
+        } else {
+            &*SERVER_CERTIFICATE
+        };
+        let connection_params = TransportConnectionParams {
+            tcp_host: Host::Ip(addr.ip()),
+            certs: RootCertificates::FromDer(Cow::Owned(trusted.cert.der().to_vec())),
+            ..localhost_params(addr.port())
+        };
+        let error = TcpSslConnector::new_direct(DnsResolver::default())
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .map(|_| ())
+            .expect_err("certificate is rejected");
+        assert_matches!(error, TransportConnectError::SslFailedHandshake(_));
+        error.user_hint()
+    }
+
+    /// Connects with `client_hello` to a server that hangs up after reading
+    /// the ClientHello, and returns the cipher suites and signature
+    /// algorithms that it offered.
+    async fn offered_in_client_hello(
+        client_hello: Option<ClientHelloCustomization>,
+    ) -> (Vec<u16>, Vec<u16>) {
+        use tls_parser::{TlsExtension, TlsMessage, TlsMessageHandshake};
+
+        let listener = tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let addr = listener.local_addr().expect("bound");
+        let connector = DirectConnector {
+            client_hello,
+            ..DirectConnector::new(DnsResolver::default())
+        };
+        let connection_params = TransportConnectionParams {
+            tcp_host: Host::Ip(addr.ip()),
+            ..localhost_params(addr.port())
+        };
+
+        let server = async {
+            let (mut socket, _) = listener.accept().await.expect("incoming connection");
+            let mut received = vec![];
+            loop {
+                let mut buf = [0; 4096];
+                let read = socket.read(&mut buf).await.expect("can read");
+                assert_ne!(read, 0, "closed before sending a ClientHello");
+                received.extend_from_slice(&buf[..read]);
+                let record = match tls_parser::parse_tls_plaintext(&received) {
+                    Ok((_, record)) => record,
+                    Err(tls_parser::Err::Incomplete(_)) => continue,
+                    Err(e) => panic!("failed to parse TLS: {e}"),
+                };
+                let hello = assert_matches!(
+                    record.msg.first(),
+                    Some(TlsMessage::Handshake(TlsMessageHandshake::ClientHello(hello))) => hello
+                );
+                let ciphers = hello.ciphers.iter().map(|cipher| cipher.0).collect();
+                let (_, extensions) = tls_parser::parse_tls_client_hello_extensions(
+                    hello.ext.expect("has extensions"),
+                )
+                .expect("can parse extensions");
+                let signature_algorithms = extensions
+                    .into_iter()
+                    .find_map(|extension| match extension {
+                        TlsExtension::SignatureAlgorithms(algorithms) => Some(algorithms),
+                        _ => None,
+                    })
+                    .expect("has signature algorithms");
+                break (ciphers, signature_algorithms);
+            }
+        };
+        let (offered, _result) =
+            tokio::join!(server, connector.connect(&connection_params, Alpn::Http1_1));
+        offered
+    }
+
+    #[tokio::test]
+    async fn client_hello_customization_changes_what_is_offered() {
+        let is_grease = |value: &u16| value & 0x0f0f == 0x0a0a;
+
+        let (default_ciphers, default_signature_algorithms) = offered_in_client_hello(None).await;
+        assert!(
+            !default_ciphers.iter().any(is_grease),
+            "{default_ciphers:x?}"
+        );
+        assert_eq!(
+            default_signature_algorithms.first(),
+            Some(&0x0807),
+            "ed25519 is preferred by default"
+        );
+
+        let (ciphers, signature_algorithms) =
+            offered_in_client_hello(Some(ClientHelloCustomization {
+                grease: true,
+                signature_algorithms: Some(vec![
+                    SslSignatureAlgorithm::ECDSA_SECP256R1_SHA256,
+                    SslSignatureAlgorithm::RSA_PSS_RSAE_SHA256,
+                ]),
+                ..Default::default()
+            }))
+            .await;
+        assert!(ciphers.iter().any(is_grease), "{ciphers:x?}");
+        assert_eq!(
+            signature_algorithms
+                .into_iter()
+                .filter(|algorithm| !is_grease(algorithm))
+                .collect::<Vec<_>>(),
+            [0x0403, 0x0804]
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_reports_negotiated_tls_parameters() {
+        use boring_signal::ssl::SslVersion;
+
+        const CIPHER_SUITE: &str = "ECDHE-ECDSA-AES256-GCM-SHA384";
+
+        // Pin the server to a single TLS 1.2 suite so that the outcome of
+        // the negotiation doesn't depend on what the hardware accelerates.
+        let acceptor = {
+            let mut builder = tls_acceptor(Some(SslVersion::TLS1_2));
+            builder
+                .set_cipher_list(CIPHER_SUITE)
+                .expect("can set cipher");
+            builder.build()
+        };
+        let listener = tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let addr = listener.local_addr().expect("bound");
+        let server = tokio::spawn(async move {
+            let (tcp_stream, _) = listener.accept().await.expect("incoming connection");
+            tokio_boring_signal::accept(&acceptor, tcp_stream)
+                .await
+                .expect("handshake successful")
+        });
+
+        let connector = DirectConnector::new(DnsResolver::new_from_static_map(HashMap::from([(
+            SERVER_HOSTNAME,
+            LookupResult::localhost(),
+        )])));
+        let connection_params = localhost_params(addr.port());
+
+        let StreamAndInfo(_stream, info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+        let _server_stream = server.await.expect("server finished");
+
+        assert_eq!(
+            info.tls,
+            Some(TlsSessionInfo {
+                protocol_version: SslVersion::TLS1_2,
+                cipher_suite: CIPHER_SUITE,
+            })
+        );
+    }
+
+    #[test_case(ProxyProtocolVersion::V1)]
+    #[test_case(ProxyProtocolVersion::V2)]
+    #[tokio::test]
+    async fn connect_with_proxy_protocol_header(version: ProxyProtocolVersion) {
+        let (server_addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let header = ProxyProtocolHeader {
+            version,
+            source: "[3fff::1]:56324".parse().unwrap(),
+            destination: "[3fff::2]:443".parse().unwrap(),
+        };
+
+        // Stands in for the load balancer: checks the header, then passes the
+        // rest of the stream on to the server.
+        let lb_listener = tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let lb_addr = lb_listener.local_addr().expect("bound");
+        let expected_header = header.encode();
+        let lb_handle = tokio::spawn(async move {
+            let (mut client, _) = lb_listener.accept().await.expect("incoming connection");
+            let received = read_proxy_protocol_header(&mut client, version).await;
+            assert_eq!(received, expected_header);
+
+            let mut server = TcpStream::connect(server_addr)
+                .await
+                .expect("can reach server");
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+        });
+
+        let mut connector = TcpSslConnector::new_direct(DnsResolver::default());
+        connector.set_proxy_protocol_header(Some(header));
+        let connection_params = TransportConnectionParams {
+            tcp_host: Host::Ip(lb_addr.ip()),
+            ..localhost_params(lb_addr.port())
+        };
+
+        let stream = match connector.connect(&connection_params, Alpn::Http1_1).await {
+            Ok(StreamAndInfo(stream, _)) => stream,
+            Err(e) => panic!("failed to connect: {e}"),
+        };
+
+        make_http_request_response_over(stream).await;
+        lb_handle.await.expect("load balancer stub finished");
+    }
+
+    /// Starts a server like [`http_server_bound_to`] on `127.0.0.1`, and binds
+    /// a listener on `::1` with the same port for the test to handle.
+    ///
+    /// The port is picked when binding the server, and might already be
+    /// taken on `::1`; if so, this tries again with a new one.
+    async fn ipv4_http_server_and_ipv6_listener(
+    ) -> (u16, impl Future<Output = ()>, tokio::net::TcpListener) {
+        loop {
+            let (addr, server) = http_server_bound_to((Ipv4Addr::LOCALHOST, 0).into());
+            match tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, addr.port())).await {
+                Ok(listener) => return (addr.port(), server, listener),
+                Err(e) => log::info!("port {} is taken over IPv6: {e}", addr.port()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn race_tls_handshakes_skips_stalled_handshake() {
+        // The IPv4 address, tried after a delay, is a well-behaved server.
+        let (port, server, ipv6_listener) = ipv4_http_server_and_ipv6_listener().await;
+        let _server_handle = tokio::spawn(server);
 
+        // The IPv6 address is tried first. It accepts the TCP connection
+        // right away but never answers the TLS handshake.
+        let (stalling_server, _stalled_rx) = stalling_listener(ipv6_listener);
+        let _stalling_handle = tokio::spawn(stalling_server);
+
+        let mut connector = DirectConnector::new(DnsResolver::new_from_static_map(HashMap::from(
+            [(SERVER_HOSTNAME, LookupResult::localhost())],
+        )));
+        connector.race_tls_handshakes = true;
+        let connection_params = localhost_params(port);
+
+        let StreamAndInfo(stream, info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+
+        assert_eq!(info.address, Host::Ip(Ipv4Addr::LOCALHOST.into()));
         make_http_request_response_over(stream).await
     }
 
+    #[tokio::test]
+    async fn race_tls_handshakes_reports_failed_handshake() {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let mut connector = DirectConnector::new(DnsResolver::default());
+        connector.race_tls_handshakes = true;
+        let untrusted = rcgen::generate_simple_self_signed([SERVER_HOSTNAME.to_string()])
+            .expect("can generate");
+        let connection_params = TransportConnectionParams {
+            tcp_host: Host::Ip(addr.ip()),
+            certs: RootCertificates::FromDer(Cow::Owned(untrusted.cert.der().to_vec())),
+            ..localhost_params(addr.port())
+        };
+
+        let error = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .map(|_| ())
+            .expect_err("certificate is rejected");
+        assert_matches!(error, TransportConnectError::SslFailedHandshake(_));
+    }
+
+    #[tokio::test]
+    async fn stalled_ipv6_handshake_deprioritizes_ipv6() {
+        // The IPv4 address works.
+        let (port, server, ipv6_listener) = ipv4_http_server_and_ipv6_listener().await;
+        let _server_handle = tokio::spawn(server);
+
+        // The IPv6 address accepts TCP connections but never answers the TLS
+        // handshake, like a path that drops large packets.
+        let (stalling_server, mut stalled_rx) = stalling_listener(ipv6_listener);
+        let _stalling_handle = tokio::spawn(stalling_server);
+
+        let (_network_change_tx, network_change_event) = tokio::sync::watch::channel(());
+        let ipv6_suspicion = Ipv6Suspicion::new(Duration::from_secs(60), &network_change_event);
+        let mut suspicion_updates = ipv6_suspicion.subscribe();
+
+        let mut connector = DirectConnector::new(DnsResolver::new_from_static_map(HashMap::from(
+            [(SERVER_HOSTNAME, LookupResult::localhost())],
+        )));
+        connector.race_tls_handshakes = true;
+        connector.ipv6_suspicion = Some(ipv6_suspicion.clone());
+        let connection_params = localhost_params(port);
+
+        assert!(!ipv6_suspicion.is_suspect());
+        let StreamAndInfo(_stream, info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+        assert_eq!(info.address, Host::Ip(Ipv4Addr::LOCALHOST.into()));
+        assert_eq!(stalled_rx.recv().await, Some(()));
+
+        // The stall was reported...
+        assert!(suspicion_updates.has_changed().expect("not closed"));
+        assert_matches!(*suspicion_updates.borrow_and_update(), Some(_));
+        assert!(ipv6_suspicion.is_suspect());
+
+        // ...so the next connect goes to the IPv4 address first, and is done
+        // before the IPv6 attempt would have started.
+        let StreamAndInfo(stream, info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+        assert_eq!(info.address, Host::Ip(Ipv4Addr::LOCALHOST.into()));
+        make_http_request_response_over(stream).await;
+        assert_matches!(stalled_rx.try_recv(), Err(_));
+    }
+
+    #[tokio::test]
+    async fn late_started_ipv6_handshake_is_not_reported() {
+        let (server_addr, server) = http_server_bound_to((Ipv4Addr::LOCALHOST, 0).into());
+        let _server_handle = tokio::spawn(server);
+
+        // The IPv4 address, tried first, is slow enough to answer that the
+        // IPv6 attempt starts before it's done.
+        let (slow_listener, ipv6_listener) = loop {
+            let slow_listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+                .await
+                .expect("can bind");
+            let port = slow_listener.local_addr().expect("bound").port();
+            match tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, port)).await {
+                Ok(listener) => break (slow_listener, listener),
+                Err(e) => log::info!("port {port} is taken over IPv6: {e}"),
+            }
+        };
+        let port = slow_listener.local_addr().expect("bound").port();
+        let _slow_handle = tokio::spawn(async move {
+            let (mut client, _) = slow_listener.accept().await.expect("can accept");
+            tokio::time::sleep(TCP_CONNECTION_ATTEMPT_DELAY * 2).await;
+            let mut server = TcpStream::connect(server_addr).await.expect("can connect");
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+        });
+
+        // The IPv6 address never answers the handshake.
+        let (stalling_server, mut stalled_rx) = stalling_listener(ipv6_listener);
+        let _stalling_handle = tokio::spawn(stalling_server);
+
+        let (_network_change_tx, network_change_event) = tokio::sync::watch::channel(());
+        let ipv6_suspicion = Ipv6Suspicion::new(Duration::from_secs(60), &network_change_event);
+        let suspicion_updates = ipv6_suspicion.subscribe();
+
+        let mut connector = DirectConnector::new(DnsResolver::default());
+        connector.race_tls_handshakes = true;
+        connector.ipv6_suspicion = Some(ipv6_suspicion.clone());
+        let connection_params = localhost_params(port);
+
+        let StreamAndInfo(stream, info) = connector
+            .connect_with_addresses(
+                &connection_params,
+                &[Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()],
+                Alpn::Http1_1,
+            )
+            .await
+            .expect("can connect");
+        assert_eq!(info.address, Host::Ip(Ipv4Addr::LOCALHOST.into()));
+        // The IPv6 attempt was still handshaking when IPv4 won...
+        assert_eq!(stalled_rx.recv().await, Some(()));
+
+        // ...but it started after the IPv4 one, so it doesn't count.
+        assert!(!suspicion_updates.has_changed().expect("not closed"));
+        assert!(!ipv6_suspicion.is_suspect());
+        make_http_request_response_over(stream).await;
+    }
+
+    #[tokio::test]
+    async fn failed_ipv6_reconnect_after_loss_deprioritizes_ipv6() {
+        // The IPv4 address works.
+        let (port, server, dropping_listener) = ipv4_http_server_and_ipv6_listener().await;
+        let _server_handle = tokio::spawn(server);
+
+        // IPv6 connectivity has gone away: connections to the IPv6 address
+        // are accepted but then cut off before the handshake.
+        let (dropped_tx, mut dropped_rx) = tokio::sync::mpsc::unbounded_channel();
+        let _dropping_handle = tokio::spawn(async move {
+            loop {
+                let (stream, _) = dropping_listener.accept().await.expect("can accept");
+                drop(stream);
+                dropped_tx.send(()).expect("test is waiting");
+            }
+        });
+
+        let (_network_change_tx, network_change_event) = tokio::sync::watch::channel(());
+        let ipv6_suspicion = Ipv6Suspicion::new(Duration::from_secs(60), &network_change_event);
+
+        let mut connector = DirectConnector::new(DnsResolver::new_from_static_map(HashMap::from(
+            [(SERVER_HOSTNAME, LookupResult::localhost())],
+        )));
+        connector.ipv6_suspicion = Some(ipv6_suspicion.clone());
+        let connection_params = localhost_params(port);
+
+        // A single failure over IPv6 isn't enough to give up on it...
+        assert!(connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .is_err());
+        assert_eq!(dropped_rx.recv().await, Some(()));
+        assert!(!ipv6_suspicion.is_suspect());
+
+        // ...but failing to reconnect over IPv6 after losing a connection
+        // over it is.
+        ipv6_suspicion.report_connection_lost(Ipv6Addr::LOCALHOST.into());
+        assert!(connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .is_err());
+        assert_eq!(dropped_rx.recv().await, Some(()));
+        assert!(ipv6_suspicion.is_suspect());
+
+        // The next attempt goes to the IPv4 address first, and is done before
+        // the IPv6 attempt would have started.
+        let StreamAndInfo(stream, info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+        assert_eq!(info.address, Host::Ip(Ipv4Addr::LOCALHOST.into()));
+        make_http_request_response_over(stream).await;
+        assert_matches!(dropped_rx.try_recv(), Err(_));
+    }
+
+    #[tokio::test]
+    async fn tls_handshake_is_retried_after_transient_failure() {
+        let (server_addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        // Stands in for a middlebox that resets the first connection in the
+        // middle of the handshake, then lets later ones through.
+        let listener = tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let addr = listener.local_addr().expect("bound");
+        let (accepted_tx, mut accepted_rx) = tokio::sync::mpsc::unbounded_channel();
+        let _middlebox_handle = tokio::spawn(async move {
+            let (mut first, _) = listener.accept().await.expect("can accept");
+            accepted_tx.send(()).expect("test is waiting");
+            let _client_hello = first.read(&mut [0; 1024]).await.expect("can read");
+            first
+                .set_linger(Some(Duration::ZERO))
+                .expect("can set linger");
+            drop(first);
+
+            loop {
+                let (mut client, _) = listener.accept().await.expect("can accept");
+                accepted_tx.send(()).expect("test is waiting");
+                tokio::spawn(async move {
+                    let mut server = TcpStream::connect(server_addr).await.expect("can connect");
+                    let _ignore_result =
+                        tokio::io::copy_bidirectional(&mut client, &mut server).await;
+                });
+            }
+        });
+
+        let mut connector = DirectConnector::new(DnsResolver::new_from_static_map(HashMap::from(
+            [(SERVER_HOSTNAME, LookupResult::localhost())],
+        )));
+        connector.tls_handshake_retry = TlsHandshakeRetryPolicy {
+            max_retries: 1,
+            ..Default::default()
+        };
+        let connection_params = localhost_params(addr.port());
+
+        let StreamAndInfo(stream, _info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("retry succeeds");
+        make_http_request_response_over(stream).await;
+
+        // The retry went over a new TCP connection.
+        assert_eq!(accepted_rx.recv().await, Some(()));
+        assert_eq!(accepted_rx.recv().await, Some(()));
+        assert_matches!(accepted_rx.try_recv(), Err(_));
+    }
+
+    #[tokio::test]
+    async fn cancelled_connect_stops_promptly_and_closes_sockets() {
+        // Accepts the connection but never answers the ClientHello.
+        let listener = tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let addr = listener.local_addr().expect("bound");
+
+        let connector = DirectConnector::new(DnsResolver::new_from_static_map(HashMap::from([(
+            SERVER_HOSTNAME,
+            LookupResult::localhost(),
+        )])));
+        let connection_params = localhost_params(addr.port());
+
+        let cancellation = ConnectCancellation::new();
+        let connect =
+            connector.connect_cancellable(&connection_params, Alpn::Http1_1, &cancellation);
+        let stall_then_cancel = async {
+            let (mut socket, _) = listener.accept().await.expect("can accept");
+            let _client_hello = socket.read(&mut [0; 1024]).await.expect("can read");
+            cancellation.cancel();
+            socket
+        };
+
+        let (result, mut socket) = tokio::time::timeout(
+            Duration::from_secs(5),
+            futures_util::future::join(connect, stall_then_cancel),
+        )
+        .await
+        .expect("cancelled promptly");
+        assert_matches!(result, Err(TransportConnectError::Cancelled));
+
+        // The client closed its end of the stalled handshake.
+        let _ignore_reset =
+            tokio::time::timeout(Duration::from_secs(5), socket.read_to_end(&mut Vec::new()))
+                .await
+                .expect("socket was closed");
+    }
+
     #[tokio::test]
     async fn connect_through_invalid() {
         let (addr, server) = localhost_http_server();
@@ -516,12 +2424,24 @@ mod test {
                 LookupResult::localhost(),
             )])),
             proxy: Err(InvalidProxyConfig),
+            proxy_protocol: None,
+            race_tls_handshakes: false,
+            srv_service: None,
+            tls_handshake_retry: TlsHandshakeRetryPolicy::default(),
+            ipv6_suspicion: None,
+            source_ports: None,
+            attempt_budget: None,
+            client_hello: None,
+            certificate_history: None,
+            expected_cert_name: None,
+            pre_connect_hook: None,
+            metrics: None,
+            tls_implementation: TlsImplementation::default(),
+            renegotiation: RenegotiationPolicy::default(),
         };
         let connection_params = TransportConnectionParams {
-            sni: SERVER_HOSTNAME.into(),
             tcp_host: Host::Ip(addr.ip()),
-            port: addr.port().try_into().expect("bound port"),
-            certs: RootCertificates::FromDer(Cow::Borrowed(SERVER_CERTIFICATE.cert.der())),
+            ..localhost_params(addr.port())
         };
 
         match connector.connect(&connection_params, Alpn::Http1_1).await {
@@ -534,4 +2454,373 @@ mod test {
             }
         }
     }
+
+    /// Aborts the first attempt and allows every later one.
+    #[derive(Debug, Default)]
+    struct AbortFirstAttempt {
+        attempts: std::sync::atomic::AtomicUsize,
+    }
+
+    #[derive(Debug, displaydoc::Display)]
+    /// first attempt
+    struct FirstAttempt;
+    impl LogSafeDisplay for FirstAttempt {}
+
+    #[async_trait]
+    impl PreConnectHook for AbortFirstAttempt {
+        async fn before_connect(
+            &self,
+            connection_params: &TransportConnectionParams,
+        ) -> Result<(), Box<dyn LogSafeDisplay + Send + Sync>> {
+            assert_eq!(&*connection_params.sni, SERVER_HOSTNAME);
+            match self
+                .attempts
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+            {
+                0 => Err(Box::new(FirstAttempt)),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn pre_connect_hook_can_abort_attempts() {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let hook = Arc::new(AbortFirstAttempt::default());
+        let mut connector = TcpSslConnector::new_direct(DnsResolver::new_from_static_map(
+            HashMap::from([(SERVER_HOSTNAME, LookupResult::localhost())]),
+        ));
+        connector.set_pre_connect_hook(Some(hook.clone()));
+        let connection_params = localhost_params(addr.port());
+
+        assert_matches!(
+            connector.connect(&connection_params, Alpn::Http1_1).await,
+            Err(TransportConnectError::PreConnectAborted)
+        );
+        let StreamAndInfo(stream, _info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("second attempt is allowed");
+        make_http_request_response_over(stream).await;
+
+        assert_eq!(hook.attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn connect_reports_metrics() {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let metrics = Arc::new(crate::metrics::OpenMetricsRecorder::new());
+        let mut connector = TcpSslConnector::new_direct(DnsResolver::new_from_static_map(
+            HashMap::from([(SERVER_HOSTNAME, LookupResult::localhost())]),
+        ));
+        connector.set_metrics(Some(metrics.clone()));
+        let connection_params = localhost_params(addr.port());
+
+        let StreamAndInfo(stream, _info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+        make_http_request_response_over(stream).await;
+
+        let other_certificate = rcgen::generate_simple_self_signed([SERVER_HOSTNAME.to_string()])
+            .expect("can generate");
+        let untrusted = TransportConnectionParams {
+            certs: RootCertificates::FromDer(Cow::Owned(other_certificate.cert.der().to_vec())),
+            ..connection_params
+        };
+        assert_matches!(
+            connector.connect(&untrusted, Alpn::Http1_1).await,
+            Err(TransportConnectError::SslFailedHandshake(_))
+        );
+
+        let rendered = metrics.render();
+        let lines = Vec::from_iter(rendered.lines());
+        for expected in [
+            "libsignal_net_connect_attempts_total 2",
+            "libsignal_net_connect_successes_total 1",
+            "libsignal_net_connect_failures_total{reason=\"tls\"} 1",
+            "libsignal_net_dns_lookups_total{result=\"success\"} 2",
+            "libsignal_net_tls_handshakes_total{result=\"success\"} 1",
+            "libsignal_net_tls_handshakes_total{result=\"failure\"} 1",
+        ] {
+            assert!(
+                lines.contains(&expected),
+                "missing {expected} in\n{rendered}"
+            );
+        }
+    }
+
+    /// Resolves every domain to localhost until told to start failing.
+    #[derive(Debug, Default)]
+    struct FailableLookup(Arc<std::sync::atomic::AtomicBool>);
+
+    #[async_trait::async_trait]
+    impl crate::dns::dns_lookup::DnsLookup for FailableLookup {
+        async fn dns_lookup(
+            &self,
+            _request: crate::dns::dns_lookup::DnsLookupRequest,
+        ) -> crate::dns::Result<LookupResult> {
+            match self.0.load(std::sync::atomic::Ordering::SeqCst) {
+                true => Err(crate::dns::DnsError::LookupFailed),
+                false => Ok(LookupResult::localhost()),
+            }
+        }
+    }
+
+    #[test_case(Duration::from_secs(3600); "fresh pin")]
+    #[test_case(Duration::ZERO; "expired pin")]
+    #[tokio::test]
+    async fn connect_uses_pinned_addresses_when_resolver_fails(pin_lifetime: Duration) {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let lookup = FailableLookup::default();
+        let fail_lookups = Arc::clone(&lookup.0);
+        let dns_resolver =
+            DnsResolver::new_custom(vec![(Box::new(lookup), Duration::from_secs(1))]);
+
+        let connection_params = localhost_params(addr.port());
+        dns_resolver
+            .prefetch_and_pin([&connection_params], pin_lifetime)
+            .await;
+
+        fail_lookups.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert_matches!(
+            dns_resolver.lookup_ip(SERVER_HOSTNAME).await,
+            Err(crate::dns::DnsError::LookupFailed)
+        );
+
+        let connector = DirectConnector::new(dns_resolver);
+        let StreamAndInfo(stream, info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+        assert_eq!(info.address, Host::Ip(Ipv6Addr::LOCALHOST.into()));
+
+        make_http_request_response_over(stream).await
+    }
+
+    /// Fails the test if anything is looked up.
+    #[derive(Debug)]
+    struct NoLookups;
+
+    #[async_trait::async_trait]
+    impl crate::dns::dns_lookup::DnsLookup for NoLookups {
+        async fn dns_lookup(
+            &self,
+            request: crate::dns::dns_lookup::DnsLookupRequest,
+        ) -> crate::dns::Result<LookupResult> {
+            panic!("unexpected lookup: {request:?}")
+        }
+    }
+
+    #[test_case(false; "racing TCP")]
+    #[test_case(true; "racing TLS")]
+    #[tokio::test]
+    async fn connect_with_provided_addresses(race_tls_handshakes: bool) {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let mut connector = DirectConnector::new(DnsResolver::new_custom(vec![(
+            Box::new(NoLookups),
+            Duration::from_secs(1),
+        )]));
+        connector.race_tls_handshakes = race_tls_handshakes;
+        let connection_params = localhost_params(addr.port());
+
+        // The server only listens on the IPv6 address, so the first one is
+        // refused.
+        let StreamAndInfo(stream, info) = connector
+            .connect_with_addresses(
+                &connection_params,
+                &[Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()],
+                Alpn::Http1_1,
+            )
+            .await
+            .expect("can connect");
+        assert_eq!(info.address, Host::Ip(Ipv6Addr::LOCALHOST.into()));
+        assert_eq!(info.dns_source, crate::DnsSource::Provided);
+
+        make_http_request_response_over(stream).await
+    }
+
+    /// Resolves every domain to localhost, and points SRV lookups at one port.
+    #[derive(Debug)]
+    struct SrvLookup(NonZeroU16);
+
+    #[async_trait::async_trait]
+    impl crate::dns::dns_lookup::DnsLookup for SrvLookup {
+        async fn dns_lookup(
+            &self,
+            _request: crate::dns::dns_lookup::DnsLookupRequest,
+        ) -> crate::dns::Result<LookupResult> {
+            Ok(LookupResult::localhost())
+        }
+
+        async fn srv_lookup(
+            &self,
+            _service: Arc<str>,
+        ) -> crate::dns::Result<Vec<crate::dns::dns_lookup::SrvRecord>> {
+            Ok(vec![crate::dns::dns_lookup::SrvRecord {
+                priority: 0,
+                weight: 0,
+                port: self.0,
+                target: SERVER_HOSTNAME.into(),
+            }])
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_uses_srv_targets() {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let server_port = addr.port().try_into().expect("bound port");
+        let mut connector = DirectConnector::new(DnsResolver::new_custom(vec![(
+            Box::new(SrvLookup(server_port)),
+            Duration::from_secs(1),
+        )]));
+        connector.srv_service = Some("_test._tcp.signal.org.local".into());
+
+        // Nothing is listening on the configured port; only the one from the
+        // SRV record works.
+        let connection_params = TransportConnectionParams {
+            tcp_host: Host::Domain("srv.signal.org.local".into()),
+            ..localhost_params(1)
+        };
+        let StreamAndInfo(stream, _info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+
+        make_http_request_response_over(stream).await
+    }
+
+    #[tokio::test]
+    async fn address_filters_exclude_rejected_addresses() {
+        const HOST: &str = "filtered.signal.org.local";
+        let dns_resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            HOST,
+            LookupResult::new(
+                crate::DnsSource::Test,
+                vec![
+                    Ipv4Addr::new(192, 0, 2, 1),
+                    Ipv4Addr::new(198, 51, 100, 1),
+                    Ipv4Addr::new(192, 0, 2, 2),
+                    Ipv4Addr::new(198, 51, 100, 2),
+                ],
+                vec![Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)],
+            ),
+        )]));
+        dns_resolver.add_address_filter(|ip| match ip {
+            IpAddr::V4(v4) => v4.octets()[..3] != [192, 0, 2],
+            IpAddr::V6(_) => true,
+        });
+        dns_resolver.add_address_filter(|ip| *ip != Ipv4Addr::new(198, 51, 100, 2));
+
+        let allowed = resolve_for_connect(&dns_resolver, Host::Domain(HOST), None)
+            .await
+            .expect("some addresses pass");
+        assert_eq!(
+            allowed.iter().collect::<Vec<_>>(),
+            [
+                IpAddr::from(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                Ipv4Addr::new(198, 51, 100, 1).into(),
+            ]
+        );
+
+        dns_resolver.add_address_filter(|ip| ip.is_ipv4());
+        dns_resolver.add_address_filter(|ip| ip.is_ipv6());
+        assert_matches!(
+            resolve_for_connect(&dns_resolver, Host::Domain(HOST), None).await,
+            Err(TransportConnectError::DnsError)
+        );
+    }
+
+    #[tokio::test]
+    async fn each_route_resolves_with_its_own_resolver() {
+        const PRIMARY_HOST: &str = "primary.signal.org.local";
+        const FRONTING_HOST: &str = "fronting.signal.org.local";
+
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let system_resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            PRIMARY_HOST,
+            LookupResult::new(
+                crate::DnsSource::SystemLookup,
+                vec![Ipv4Addr::LOCALHOST],
+                vec![],
+            ),
+        )]));
+        let doh_resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            FRONTING_HOST,
+            LookupResult::new(
+                crate::DnsSource::DnsOverHttpsLookup,
+                vec![Ipv4Addr::LOCALHOST],
+                vec![],
+            ),
+        )]));
+        let route = |host: &'static str, dns_resolver: &DnsResolver| TransportConnectionParams {
+            tcp_host: Host::Domain(host.into()),
+            dns_resolver: Some(dns_resolver.clone()),
+            ..localhost_params(addr.port())
+        };
+
+        // The connector's own resolver knows neither host.
+        let connector = DirectConnector::new(DnsResolver::new_from_static_map(HashMap::new()));
+
+        for (params, expected_source) in [
+            (
+                route(PRIMARY_HOST, &system_resolver),
+                crate::DnsSource::SystemLookup,
+            ),
+            (
+                route(FRONTING_HOST, &doh_resolver),
+                crate::DnsSource::DnsOverHttpsLookup,
+            ),
+        ] {
+            let StreamAndInfo(stream, info) = connector
+                .connect(&params, Alpn::Http1_1)
+                .await
+                .expect("can connect");
+            assert_eq!(info.dns_source, expected_source);
+            make_http_request_response_over(stream).await;
+        }
+
+        assert_matches!(
+            connector
+                .connect(&route(FRONTING_HOST, &system_resolver), Alpn::Http1_1)
+                .await,
+            Err(TransportConnectError::DnsError)
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_from_configured_source_ports() {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let source_ports =
+            SourcePortRange::new(nonzero!(41000u16)..=nonzero!(41999u16)).expect("not empty");
+        let mut connector = DirectConnector::new(DnsResolver::new_from_static_map(HashMap::from(
+            [(SERVER_HOSTNAME, LookupResult::localhost())],
+        )));
+        connector.source_ports = Some(source_ports.clone());
+
+        let connection_params = localhost_params(addr.port());
+        let StreamAndInfo(stream, _info) = connector
+            .connect(&connection_params, Alpn::Http1_1)
+            .await
+            .expect("can connect");
+
+        let local_port = stream.get_ref().local_addr().expect("connected").port();
+        assert!(source_ports.contains(local_port), "{local_port}");
+
+        make_http_request_response_over(stream).await
+    }
 }
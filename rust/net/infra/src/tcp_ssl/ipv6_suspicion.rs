@@ -0,0 +1,130 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Detection of IPv6 path MTU black holes.
+//!
+//! On some networks, packets over IPv6 that are too large for the path are
+//! dropped without the "packet too big" message that would let the sender
+//! adjust. Small packets get through, so the TCP connection is established,
+//! but the TLS handshake, whose records are large, stalls. A
+//! [`Ipv6Suspicion`] notices this happening when handshakes are raced over
+//! both address families, and asks for IPv4 to be tried first for a while.
+//!
+//! IPv6 connectivity can also go away outright, like when a VPN is turned on
+//! or off. That shows up as a connection over IPv6 being lost and then not
+//! coming back over IPv6, which deprioritizes IPv6 in the same way.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::utils::NetworkChangeEvent;
+
+/// Whether IPv6 is suspected of being broken on the current network.
+///
+/// Clones share the same state.
+#[derive(Clone, Debug)]
+pub struct Ipv6Suspicion {
+    deprioritize_for: Duration,
+    suspect_until: Arc<watch::Sender<Option<Instant>>>,
+    network_change_event: Arc<Mutex<NetworkChangeEvent>>,
+    /// Set when a connection over IPv6 is lost, until the next attempt.
+    lost_ipv6_connection: Arc<AtomicBool>,
+}
+
+impl Ipv6Suspicion {
+    /// Creates a tracker that deprioritizes IPv6 for `deprioritize_for` after
+    /// each stalled handshake or failure to reconnect over IPv6.
+    ///
+    /// The suspicion is cleared when the network changes, since the black
+    /// hole is a property of the path.
+    pub fn new(deprioritize_for: Duration, network_change_event: &NetworkChangeEvent) -> Self {
+        let mut network_change_event = network_change_event.clone();
+        network_change_event.mark_unchanged();
+        Self {
+            deprioritize_for,
+            suspect_until: Arc::new(watch::Sender::new(None)),
+            network_change_event: Arc::new(Mutex::new(network_change_event)),
+            lost_ipv6_connection: Default::default(),
+        }
+    }
+
+    /// Observes the suspicion.
+    ///
+    /// The value is updated each time IPv6 becomes suspect, to the time until
+    /// which it will be deprioritized, and reset to `None` when the network
+    /// changes.
+    pub fn subscribe(&self) -> watch::Receiver<Option<Instant>> {
+        self.suspect_until.subscribe()
+    }
+
+    /// Whether IPv6 addresses should currently be tried after IPv4 ones.
+    pub fn is_suspect(&self) -> bool {
+        let mut network_change_event = self.network_change_event.lock().expect("not poisoned");
+        if network_change_event
+            .has_changed()
+            .is_ok_and(|changed| changed)
+        {
+            network_change_event.mark_unchanged();
+            self.suspect_until.send_replace(None);
+        }
+        self.suspect_until
+            .borrow()
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Records that an established connection to `remote_address` was lost.
+    ///
+    /// If it was over IPv6 and the next connection attempt (that has an IPv6
+    /// address to try) doesn't end up over IPv6 too, IPv6 is deprioritized.
+    pub fn report_connection_lost(&self, remote_address: IpAddr) {
+        if remote_address.is_ipv6() {
+            self.lost_ipv6_connection.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Records whether a connection attempt not made by a
+    /// [`DirectConnector`](crate::tcp_ssl::DirectConnector), which checks its
+    /// own, ended up over IPv6.
+    ///
+    /// If a connection over IPv6 was [lost](Self::report_connection_lost)
+    /// before this attempt and this one isn't over IPv6 too, IPv6 is
+    /// deprioritized.
+    pub fn report_connect_outcome(&self, connected_over_ipv6: bool) {
+        if self.take_lost_ipv6_connection() && !connected_over_ipv6 {
+            self.report_failed_ipv6_reconnect();
+        }
+    }
+
+    /// Whether a connection over IPv6 was lost since the last call.
+    pub(crate) fn take_lost_ipv6_connection(&self) -> bool {
+        self.lost_ipv6_connection.swap(false, Ordering::Relaxed)
+    }
+
+    /// Records that a connection couldn't be made over IPv6 right after one
+    /// was lost.
+    pub(crate) fn report_failed_ipv6_reconnect(&self) {
+        log::info!(
+            "couldn't reconnect over IPv6 after losing a connection; preferring IPv4 for {:?}",
+            self.deprioritize_for
+        );
+        self.suspect_until
+            .send_replace(Some(Instant::now() + self.deprioritize_for));
+    }
+
+    /// Records that an IPv6 handshake stalled while an IPv4 one succeeded.
+    pub fn report_stalled_handshake(&self) {
+        log::info!(
+            "IPv6 handshake stalled while IPv4 succeeded; preferring IPv4 for {:?}",
+            self.deprioritize_for
+        );
+        self.suspect_until
+            .send_replace(Some(Instant::now() + self.deprioritize_for));
+    }
+}
@@ -55,7 +55,7 @@ impl Connector<ConnectionProxyRoute<IpAddr>, ()> for StatelessProxied {
                 .connect(inner, log_tag.clone())
                 .await?;
                 LoggingConnector::new(
-                    super::StatelessTls,
+                    super::StatelessTls::default(),
                     LONG_TLS_HANDSHAKE_THRESHOLD,
                     "Proxy-TLS",
                 )
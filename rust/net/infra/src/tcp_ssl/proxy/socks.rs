@@ -25,7 +25,7 @@ use crate::errors::TransportConnectError;
 use crate::host::Host;
 use crate::route::{Connector, ConnectorExt as _, SocksRoute, TcpRoute};
 use crate::{
-    Alpn, Connection, DnsSource, RouteType, ServiceConnectionInfo, StreamAndInfo,
+    Alpn, Connection, DnsSource, RouteType, ServiceConnectionInfo, StreamAndInfo, TlsSessionInfo,
     TransportConnectionParams, TransportConnector,
 };
 
@@ -96,7 +96,9 @@ impl TransportConnector for SocksConnector {
             RouteType::SocksProxy,
             proxy_host.as_deref(),
             *proxy_port,
-            log_tag.clone(),
+            None,
+            None,
+            log_tag,
         )
         .await?;
         let is_ipv6 = tcp_stream
@@ -110,7 +112,10 @@ impl TransportConnector for SocksConnector {
                 DnsSource::Static,
             ),
             Host::Domain(host) if *resolve_hostname_locally => {
-                let LookupResult { source, ipv4, ipv6 } = dns_resolver
+                let LookupResult { source, ipv4, ipv6 } = connection_params
+                    .dns_resolver
+                    .as_ref()
+                    .unwrap_or(dns_resolver)
                     .lookup_ip(host)
                     .await
                     .map_err(|_| TransportConnectError::DnsError)?;
@@ -148,9 +153,17 @@ impl TransportConnector for SocksConnector {
             })?;
 
         log::debug!("connecting TLS through proxy");
-        let stream =
-            crate::tcp_ssl::connect_tls(socks_stream, connection_params, alpn, None, log_tag)
-                .await?;
+        let stream = crate::tcp_ssl::connect_tls(
+            socks_stream,
+            connection_params,
+            alpn,
+            None,
+            None,
+            crate::tcp_ssl::RenegotiationPolicy::default(),
+            None,
+        )
+        .await?;
+        let tls = TlsSessionInfo::from_stream(&stream);
 
         log::info!("connection through SOCKS proxy established successfully");
         Ok(StreamAndInfo(
@@ -159,6 +172,7 @@ impl TransportConnector for SocksConnector {
                 route_type: RouteType::SocksProxy,
                 dns_source,
                 address: remote_address.address,
+                tls,
             },
         ))
     }
@@ -496,6 +510,8 @@ mod test {
             certs: crate::certs::RootCertificates::FromDer(std::borrow::Cow::Borrowed(
                 SERVER_CERTIFICATE.cert.der(),
             )),
+            dns_resolver: None,
+            alpn: None,
         };
         let mut connect = connector.connect(&connection_params, Alpn::Http1_1);
 
@@ -549,7 +565,8 @@ mod test {
             ServiceConnectionInfo {
                 route_type: RouteType::SocksProxy,
                 dns_source: expected_dns_source,
-                address: Host::Ip(tls_server.tcp.listen_addr.ip())
+                address: Host::Ip(tls_server.tcp.listen_addr.ip()),
+                tls: client_info.tls,
             }
         );
 
@@ -619,6 +636,8 @@ mod test {
             certs: crate::certs::RootCertificates::FromDer(std::borrow::Cow::Borrowed(
                 SERVER_CERTIFICATE.cert.der(),
             )),
+            dns_resolver: None,
+            alpn: None,
         };
         let connect = connector.connect(&connection_params, Alpn::Http1_1);
 
@@ -15,10 +15,11 @@ use crate::certs::RootCertificates;
 use crate::dns::DnsResolver;
 use crate::errors::TransportConnectError;
 use crate::host::Host;
-use crate::tcp_ssl::{connect_tcp, connect_tls, ssl_config};
+use crate::metrics::ConnectionMetrics;
+use crate::tcp_ssl::{connect_tcp, connect_tls, ssl_config, RenegotiationPolicy};
 use crate::{
-    Alpn, RouteType, ServiceConnectionInfo, StreamAndInfo, TransportConnectionParams,
-    TransportConnector,
+    Alpn, RouteType, ServiceConnectionInfo, StreamAndInfo, TlsSessionInfo,
+    TransportConnectionParams, TransportConnector,
 };
 
 /// A [`TransportConnector`] that proxies through a TLS server.
@@ -37,6 +38,9 @@ pub struct TlsProxyConnector {
     proxy_port: NonZeroU16,
     pub(crate) proxy_certs: RootCertificates,
     use_tls_for_proxy: ShouldUseTls,
+    /// If set, DNS lookups for the proxy and TLS handshakes with the server
+    /// are reported here.
+    pub metrics: Option<Arc<dyn ConnectionMetrics>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -60,7 +64,9 @@ impl TransportConnector for TlsProxyConnector {
             RouteType::TlsProxy,
             self.proxy_host.as_deref(),
             self.proxy_port,
-            log_tag.clone(),
+            None,
+            self.metrics.as_deref(),
+            log_tag,
         )
         .await?;
 
@@ -73,8 +79,14 @@ impl TransportConnector for TlsProxyConnector {
                 );
                 // This won't always work, but it's enough to connect to proxies
                 // by hostnames.
-                let ssl_config =
-                    ssl_config(&self.proxy_certs, self.proxy_host.as_deref(), None, None)?;
+                let ssl_config = ssl_config(
+                    &self.proxy_certs,
+                    self.proxy_host.as_deref(),
+                    None,
+                    None,
+                    None,
+                    RenegotiationPolicy::default(),
+                )?;
                 Either::Left(
                     tokio_boring_signal::connect(
                         ssl_config,
@@ -94,12 +106,23 @@ impl TransportConnector for TlsProxyConnector {
             }
         };
 
-        let tls_stream = connect_tls(inner_stream, connection_params, alpn, None, log_tag).await?;
+        let tls_stream = connect_tls(
+            inner_stream,
+            connection_params,
+            alpn,
+            None,
+            None,
+            RenegotiationPolicy::default(),
+            self.metrics.as_deref(),
+        )
+        .await?;
+        let tls = TlsSessionInfo::from_stream(&tls_stream);
 
         Ok(StreamAndInfo(
             tls_stream,
             ServiceConnectionInfo {
                 route_type: RouteType::TlsProxy,
+                tls,
                 ..remote_address
             },
         ))
@@ -123,6 +146,7 @@ impl TlsProxyConnector {
             // is also TLS-encrypted.
             proxy_certs: RootCertificates::Native,
             use_tls_for_proxy,
+            metrics: None,
         }
     }
 
@@ -199,6 +223,8 @@ mod test {
             tcp_host: Host::Domain("localhost".into()),
             port: addr.port().try_into().expect("bound port"),
             certs: RootCertificates::FromDer(Cow::Borrowed(SERVER_CERTIFICATE.cert.der())),
+            dns_resolver: None,
+            alpn: None,
         };
 
         let StreamAndInfo(stream, info) = connector
@@ -212,6 +238,7 @@ mod test {
                 address: Host::Ip(Ipv6Addr::LOCALHOST.into()),
                 dns_source: crate::DnsSource::Static,
                 route_type: RouteType::TlsProxy,
+                tls: info.tls,
             }
         );
 
@@ -242,6 +269,8 @@ mod test {
             tcp_host: Host::Domain("localhost".into()),
             port: addr.port().try_into().expect("bound port"),
             certs: RootCertificates::FromDer(Cow::Borrowed(SERVER_CERTIFICATE.cert.der())),
+            dns_resolver: None,
+            alpn: None,
         };
 
         let StreamAndInfo(stream, info) = connector
@@ -254,7 +283,8 @@ mod test {
             ServiceConnectionInfo {
                 address: Host::Ip(Ipv6Addr::LOCALHOST.into()),
                 dns_source: crate::DnsSource::Static,
-                route_type: RouteType::TlsProxy
+                route_type: RouteType::TlsProxy,
+                tls: info.tls,
             }
         );
 
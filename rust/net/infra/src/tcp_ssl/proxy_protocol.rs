@@ -0,0 +1,249 @@
+//
+// Copyright 2024 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Support for the [PROXY protocol] header expected by some L4 load balancers.
+//!
+//! The header is written once, immediately after the TCP connection is
+//! established and before any TLS bytes are sent.
+//!
+//! [PROXY protocol]: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt as _};
+
+use crate::errors::TransportConnectError;
+
+/// Which version of the PROXY protocol header to send.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProxyProtocolVersion {
+    /// The human-readable single-line format.
+    V1,
+    /// The binary format.
+    V2,
+}
+
+/// A PROXY protocol header announcing the client's declared addresses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyProtocolHeader {
+    pub version: ProxyProtocolVersion,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+/// The fixed 12-byte prefix of every v2 header.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// Protocol version 2 in the high nibble.
+const V2_VERSION: u8 = 0x20;
+const V2_COMMAND_LOCAL: u8 = 0x0;
+const V2_COMMAND_PROXY: u8 = 0x1;
+const V2_FAMILY_UNSPEC: u8 = 0x00;
+const V2_FAMILY_TCP_OVER_IPV4: u8 = 0x11;
+const V2_FAMILY_TCP_OVER_IPV6: u8 = 0x21;
+
+impl ProxyProtocolHeader {
+    /// Serializes the header.
+    ///
+    /// The protocol can't describe a connection whose source and destination
+    /// have different address families. In that case the header is still
+    /// sent, but in its "unknown" (v1) or "local" (v2) form, which tells the
+    /// receiver to ignore the addresses.
+    pub fn encode(&self) -> Vec<u8> {
+        match self.version {
+            ProxyProtocolVersion::V1 => self.encode_v1(),
+            ProxyProtocolVersion::V2 => self.encode_v2(),
+        }
+    }
+
+    /// Sends the header over `stream`.
+    pub async fn write_to(
+        &self,
+        stream: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<(), TransportConnectError> {
+        stream
+            .write_all(&self.encode())
+            .await
+            .map_err(|_| TransportConnectError::TcpConnectionFailed)
+    }
+
+    fn encode_v1(&self) -> Vec<u8> {
+        let Self {
+            version: _,
+            source,
+            destination,
+        } = self;
+        let line = match (source, destination) {
+            (SocketAddr::V4(src), SocketAddr::V4(dst)) => format!(
+                "PROXY TCP4 {} {} {} {}\r\n",
+                src.ip(),
+                dst.ip(),
+                src.port(),
+                dst.port()
+            ),
+            (SocketAddr::V6(src), SocketAddr::V6(dst)) => format!(
+                "PROXY TCP6 {} {} {} {}\r\n",
+                src.ip(),
+                dst.ip(),
+                src.port(),
+                dst.port()
+            ),
+            _ => "PROXY UNKNOWN\r\n".to_owned(),
+        };
+        line.into_bytes()
+    }
+
+    fn encode_v2(&self) -> Vec<u8> {
+        let Self {
+            version: _,
+            source,
+            destination,
+        } = self;
+        let (command, family, addresses) = match (source, destination) {
+            (SocketAddr::V4(src), SocketAddr::V4(dst)) => (
+                V2_COMMAND_PROXY,
+                V2_FAMILY_TCP_OVER_IPV4,
+                [
+                    &src.ip().octets()[..],
+                    &dst.ip().octets()[..],
+                    &src.port().to_be_bytes()[..],
+                    &dst.port().to_be_bytes()[..],
+                ]
+                .concat(),
+            ),
+            (SocketAddr::V6(src), SocketAddr::V6(dst)) => (
+                V2_COMMAND_PROXY,
+                V2_FAMILY_TCP_OVER_IPV6,
+                [
+                    &src.ip().octets()[..],
+                    &dst.ip().octets()[..],
+                    &src.port().to_be_bytes()[..],
+                    &dst.port().to_be_bytes()[..],
+                ]
+                .concat(),
+            ),
+            _ => (V2_COMMAND_LOCAL, V2_FAMILY_UNSPEC, vec![]),
+        };
+        let length = u16::try_from(addresses.len()).expect("addresses are short");
+
+        let mut header = Vec::with_capacity(V2_SIGNATURE.len() + 4 + addresses.len());
+        header.extend_from_slice(&V2_SIGNATURE);
+        header.push(V2_VERSION | command);
+        header.push(family);
+        header.extend_from_slice(&length.to_be_bytes());
+        header.extend_from_slice(&addresses);
+        header
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testutil {
+    use tokio::io::{AsyncRead, AsyncReadExt as _};
+
+    use super::*;
+
+    /// Reads a PROXY protocol header of the given version from `stream`,
+    /// consuming exactly the header bytes, and returns it in encoded form.
+    pub(crate) async fn read_proxy_protocol_header(
+        stream: &mut (impl AsyncRead + Unpin),
+        version: ProxyProtocolVersion,
+    ) -> Vec<u8> {
+        let mut header = vec![];
+        match version {
+            ProxyProtocolVersion::V1 => {
+                while !header.ends_with(b"\r\n") {
+                    header.push(stream.read_u8().await.expect("can read header"));
+                }
+            }
+            ProxyProtocolVersion::V2 => {
+                header.resize(V2_SIGNATURE.len() + 4, 0);
+                stream
+                    .read_exact(&mut header)
+                    .await
+                    .expect("can read header");
+                let length = u16::from_be_bytes([header[14], header[15]]);
+                let mut addresses = vec![0; length.into()];
+                stream
+                    .read_exact(&mut addresses)
+                    .await
+                    .expect("can read addresses");
+                header.extend_from_slice(&addresses);
+            }
+        }
+        header
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use const_str::ip_addr;
+    use test_case::test_case;
+
+    use super::*;
+
+    const SOURCE_V4: SocketAddr = SocketAddr::new(ip_addr!("192.0.2.1"), 56324);
+    const DESTINATION_V4: SocketAddr = SocketAddr::new(ip_addr!("192.0.2.2"), 443);
+    const SOURCE_V6: SocketAddr = SocketAddr::new(ip_addr!("3fff::1"), 56324);
+    const DESTINATION_V6: SocketAddr = SocketAddr::new(ip_addr!("3fff::2"), 443);
+
+    #[test_case(
+        SOURCE_V4,
+        DESTINATION_V4,
+        "PROXY TCP4 192.0.2.1 192.0.2.2 56324 443\r\n"
+    )]
+    #[test_case(SOURCE_V6, DESTINATION_V6, "PROXY TCP6 3fff::1 3fff::2 56324 443\r\n")]
+    #[test_case(SOURCE_V4, DESTINATION_V6, "PROXY UNKNOWN\r\n")]
+    fn v1_encoding(source: SocketAddr, destination: SocketAddr, expected: &str) {
+        let header = ProxyProtocolHeader {
+            version: ProxyProtocolVersion::V1,
+            source,
+            destination,
+        };
+        assert_eq!(String::from_utf8(header.encode()).unwrap(), expected);
+    }
+
+    #[test]
+    fn v2_encoding_ipv4() {
+        let header = ProxyProtocolHeader {
+            version: ProxyProtocolVersion::V2,
+            source: SOURCE_V4,
+            destination: DESTINATION_V4,
+        };
+        let expected = [
+            &V2_SIGNATURE[..],
+            &[0x21, 0x11, 0x00, 12],
+            &[192, 0, 2, 1],
+            &[192, 0, 2, 2],
+            &56324u16.to_be_bytes(),
+            &443u16.to_be_bytes(),
+        ]
+        .concat();
+        assert_eq!(header.encode(), expected);
+    }
+
+    #[test]
+    fn v2_encoding_ipv6() {
+        let encoded = ProxyProtocolHeader {
+            version: ProxyProtocolVersion::V2,
+            source: SOURCE_V6,
+            destination: DESTINATION_V6,
+        }
+        .encode();
+        assert_eq!(encoded[..12], V2_SIGNATURE);
+        assert_eq!(encoded[12..16], [0x21, 0x21, 0x00, 36]);
+        assert_eq!(encoded.len(), 16 + 36);
+    }
+
+    #[test]
+    fn v2_encoding_mismatched_families() {
+        let encoded = ProxyProtocolHeader {
+            version: ProxyProtocolVersion::V2,
+            source: SOURCE_V6,
+            destination: DESTINATION_V4,
+        }
+        .encode();
+        assert_eq!(encoded[..12], V2_SIGNATURE);
+        assert_eq!(encoded[12..], [0x20, 0x00, 0x00, 0x00]);
+    }
+}
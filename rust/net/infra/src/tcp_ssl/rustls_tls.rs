@@ -0,0 +1,374 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! TLS using [`rustls`] instead of BoringSSL.
+//!
+//! [`RustlsTls`] is a drop-in replacement for [`StatelessTls`] wherever a
+//! [`Connector`] for [`TlsRouteFragment`]s is used, for targets where linking
+//! BoringSSL is impractical. It takes the same route configuration and aims to
+//! behave the same way:
+//!
+//! - the [`RootCertificates`] are trusted in the same way, with
+//!   [`RootCertificates::Native`] using the platform verifier;
+//! - the SNI is sent for domain names (rustls never sends it for IP
+//!   addresses), and the server certificate must be valid for it;
+//! - the requested ALPN protocol, if any, is offered;
+//! - the route's minimum protocol version is respected, with one difference:
+//!   rustls supports nothing older than TLS 1.2, so any older minimum (or none
+//!   at all) allows TLS 1.2 and 1.3.
+//!
+//! [`TcpSslConnector`] uses it for direct connections when set to
+//! [`TlsImplementation::Rustls`].
+//!
+//! [`StatelessTls`]: super::StatelessTls
+//! [`TcpSslConnector`]: super::TcpSslConnector
+//! [`TlsImplementation::Rustls`]: super::TlsImplementation::Rustls
+
+use std::future::Future;
+use std::sync::Arc;
+
+use rustls::client::danger::ServerCertVerifier;
+use rustls::pki_types::{CertificateDer, ServerName};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+
+use crate::attempt_budget::AttemptBudget;
+use crate::certs::RootCertificates;
+use crate::dns::DnsResolver;
+use crate::errors::{FailedHandshakeReason, TransportConnectError};
+use crate::host::Host;
+use crate::metrics::ConnectionMetrics;
+use crate::route::{Connector, TlsRouteFragment};
+use crate::tcp_ssl::proxy_protocol::ProxyProtocolHeader;
+use crate::tcp_ssl::source_ports::SourcePortRange;
+use crate::{
+    Alpn, AsyncDuplexStream, Connection, RouteType, ServiceConnectionInfo, StreamAndInfo,
+    TlsSessionInfo, TransportConnectionParams,
+};
+
+/// Stateless [`Connector`] for [`TlsRouteFragment`]s that uses rustls.
+///
+/// See the [module-level documentation](self).
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RustlsTls;
+
+impl<Inner> Connector<TlsRouteFragment, Inner> for RustlsTls
+where
+    Inner: AsyncDuplexStream,
+{
+    type Connection = TlsStream<Inner>;
+
+    type Error = TransportConnectError;
+
+    fn connect_over(
+        &self,
+        inner: Inner,
+        fragment: TlsRouteFragment,
+        _log_tag: Arc<str>,
+    ) -> impl Future<Output = Result<Self::Connection, Self::Error>> + Send {
+        let requires_tls1_3 = fragment.requires_tls1_3();
+        let TlsRouteFragment {
+            root_certs,
+            sni,
+            alpn,
+            min_protocol_version: _,
+        } = fragment;
+
+        let config = requires_tls1_3
+            .ok_or(TransportConnectError::InvalidConfiguration)
+            .and_then(|requires_tls1_3| client_config(&root_certs, alpn, requires_tls1_3));
+
+        async move {
+            let server_name = match sni {
+                Host::Ip(ip) => ServerName::IpAddress(ip.into()),
+                Host::Domain(domain) => ServerName::try_from(domain.to_string())
+                    .map_err(|_| TransportConnectError::InvalidConfiguration)?,
+            };
+            let connector = tokio_rustls::TlsConnector::from(Arc::new(config?));
+
+            connector.connect(server_name, inner).await.map_err(|e| {
+                log::debug!("handshake error: {e}");
+                TransportConnectError::SslFailedHandshake(FailedHandshakeReason::io(e.kind()))
+            })
+        }
+    }
+}
+
+impl<S: Connection> Connection for TlsStream<S> {
+    fn transport_info(&self) -> crate::TransportInfo {
+        self.get_ref().0.transport_info()
+    }
+}
+
+impl TlsSessionInfo {
+    /// Like [`TlsSessionInfo::from_stream`], but for a rustls stream.
+    ///
+    /// Cipher suites are reported with their IANA names (like
+    /// `TLS13_AES_128_GCM_SHA256`), which differ from BoringSSL's.
+    pub fn from_rustls_stream<S>(stream: &TlsStream<S>) -> Option<Self> {
+        let connection = stream.get_ref().1;
+        Self::from_rustls_session(
+            connection.protocol_version()?,
+            connection.negotiated_cipher_suite()?.suite().as_str()?,
+        )
+    }
+}
+
+/// Connects directly to the host in `connection_params`, like
+/// [`DirectConnector`] does, but with a rustls handshake.
+///
+/// The addresses are tried with the same staggered TCP attempts, and the TLS
+/// handshake is made on the first connection to be established.
+///
+/// [`DirectConnector`]: super::DirectConnector
+pub(super) async fn connect_direct(
+    dns_resolver: &DnsResolver,
+    connection_params: &TransportConnectionParams,
+    alpn: Alpn,
+    proxy_protocol: Option<&ProxyProtocolHeader>,
+    source_ports: Option<&SourcePortRange>,
+    attempt_budget: Option<&AttemptBudget>,
+    metrics: Option<&dyn ConnectionMetrics>,
+) -> Result<StreamAndInfo<TlsStream<TcpStream>>, TransportConnectError> {
+    let log_tag: Arc<str> = "RustlsTls".into();
+    let dns_resolver = connection_params
+        .dns_resolver
+        .as_ref()
+        .unwrap_or(dns_resolver);
+    let (addresses, dns_source) =
+        super::resolve_addresses(dns_resolver, connection_params.tcp_host.as_deref(), metrics)
+            .await?;
+
+    let StreamAndInfo(mut tcp_stream, remote_address) = super::connect_tcp_to_addresses(
+        addresses,
+        dns_source,
+        RouteType::Direct,
+        connection_params.port,
+        source_ports,
+        attempt_budget,
+        log_tag.clone(),
+    )
+    .await?;
+
+    if let Some(header) = proxy_protocol {
+        header.write_to(&mut tcp_stream).await?;
+    }
+
+    let fragment = TlsRouteFragment {
+        root_certs: connection_params.certs.clone(),
+        sni: Host::Domain(Arc::clone(&connection_params.sni)),
+        alpn: Some(connection_params.alpn_or(alpn)),
+        min_protocol_version: None,
+    };
+    let stream = super::report_tls_handshake(
+        metrics,
+        RustlsTls.connect_over(tcp_stream, fragment, log_tag),
+    )
+    .await?;
+    let tls = TlsSessionInfo::from_rustls_stream(&stream);
+
+    Ok(StreamAndInfo(
+        stream,
+        ServiceConnectionInfo {
+            tls,
+            ..remote_address
+        },
+    ))
+}
+
+fn client_config(
+    root_certs: &RootCertificates,
+    alpn: Option<Alpn>,
+    requires_tls1_3: bool,
+) -> Result<rustls::ClientConfig, TransportConnectError> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let versions: &[&rustls::SupportedProtocolVersion] = match requires_tls1_3 {
+        true => &[&rustls::version::TLS13],
+        false => rustls::DEFAULT_VERSIONS,
+    };
+
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_protocol_versions(versions)
+        .map_err(|_| TransportConnectError::InvalidConfiguration)?;
+
+    let ders: &[&[u8]] = match root_certs {
+        RootCertificates::Native => {
+            let mut verifier = rustls_platform_verifier::Verifier::new();
+            verifier.set_provider(provider);
+            let verifier: Arc<dyn ServerCertVerifier> = Arc::new(verifier);
+            let mut config = builder
+                .dangerous()
+                .with_custom_certificate_verifier(verifier)
+                .with_no_client_auth();
+            config.alpn_protocols = alpn_protocols(alpn);
+            return Ok(config);
+        }
+        RootCertificates::FromStaticDers(ders) => ders,
+        RootCertificates::FromDer(der) => &[der],
+    };
+    let mut root_store = rustls::RootCertStore::empty();
+    for der in ders {
+        root_store
+            .add(CertificateDer::from(der.to_vec()))
+            .map_err(|_| TransportConnectError::CertError)?;
+    }
+
+    let mut config = builder
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    config.alpn_protocols = alpn_protocols(alpn);
+    Ok(config)
+}
+
+fn alpn_protocols(alpn: Option<Alpn>) -> Vec<Vec<u8>> {
+    alpn.into_iter()
+        .map(|alpn| {
+            // The BoringSSL representation is a wire-format list of names,
+            // each preceded by its length; rustls wants just the name.
+            let wire_format = alpn.as_ref();
+            wire_format[1..].to_vec()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+    use std::net::Ipv6Addr;
+
+    use assert_matches::assert_matches;
+    use boring_signal::ssl::SslVersion;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::tcp_ssl::testutil::*;
+
+    fn fragment(sni: &str, alpn: Option<Alpn>) -> TlsRouteFragment {
+        TlsRouteFragment {
+            root_certs: RootCertificates::FromDer(Cow::Borrowed(SERVER_CERTIFICATE.cert.der())),
+            sni: Host::Domain(sni.into()),
+            alpn,
+            min_protocol_version: None,
+        }
+    }
+
+    #[test_case(Alpn::Http1_1, b"http/1.1")]
+    #[test_case(Alpn::Http2, b"h2")]
+    #[tokio::test]
+    async fn connects_and_negotiates_alpn(alpn: Alpn, expected: &[u8]) {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let tcp = TcpStream::connect(addr).await.expect("can connect");
+        let stream = RustlsTls
+            .connect_over(tcp, fragment(SERVER_HOSTNAME, Some(alpn)), "test".into())
+            .await
+            .expect("handshake succeeds");
+
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(expected));
+        assert_matches!(
+            TlsSessionInfo::from_rustls_stream(&stream),
+            Some(TlsSessionInfo {
+                protocol_version,
+                ..
+            }) if protocol_version == SslVersion::TLS1_3
+        );
+        if alpn == Alpn::Http1_1 {
+            make_http_request_response_over(stream).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_certificate_for_other_host() {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let tcp = TcpStream::connect(addr).await.expect("can connect");
+        let result = RustlsTls
+            .connect_over(
+                tcp,
+                fragment("not-the-server.signal.org.local", None),
+                "test".into(),
+            )
+            .await;
+        assert_matches!(result, Err(TransportConnectError::SslFailedHandshake(_)));
+    }
+
+    #[tokio::test]
+    async fn rejects_untrusted_certificate() {
+        let (addr, server) = localhost_http_server();
+        let _server_handle = tokio::spawn(server);
+
+        let other_root = rcgen::generate_simple_self_signed([SERVER_HOSTNAME.to_string()])
+            .expect("can generate");
+        let tcp = TcpStream::connect(addr).await.expect("can connect");
+        let result = RustlsTls
+            .connect_over(
+                tcp,
+                TlsRouteFragment {
+                    root_certs: RootCertificates::FromDer(Cow::Owned(
+                        other_root.cert.der().to_vec(),
+                    )),
+                    ..fragment(SERVER_HOSTNAME, None)
+                },
+                "test".into(),
+            )
+            .await;
+        assert_matches!(result, Err(TransportConnectError::SslFailedHandshake(_)));
+    }
+
+    #[tokio::test]
+    async fn enforces_minimum_protocol_version() {
+        // A server that only speaks TLS 1.2.
+        let acceptor = Arc::new(tls_acceptor(Some(SslVersion::TLS1_2)).build());
+        let listener = tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let addr = listener.local_addr().expect("bound");
+        let _server_handle = tokio::spawn(async move {
+            loop {
+                let (tcp_stream, _) = listener.accept().await.expect("incoming connection");
+                let acceptor = Arc::clone(&acceptor);
+                tokio::spawn(async move {
+                    let _ignore_result = tokio_boring_signal::accept(&acceptor, tcp_stream).await;
+                });
+            }
+        });
+
+        let tcp = TcpStream::connect(addr).await.expect("can connect");
+        let stream = RustlsTls
+            .connect_over(
+                tcp,
+                TlsRouteFragment {
+                    min_protocol_version: Some(SslVersion::TLS1_2),
+                    ..fragment(SERVER_HOSTNAME, None)
+                },
+                "test".into(),
+            )
+            .await
+            .expect("TLS 1.2 is allowed");
+        assert_matches!(
+            TlsSessionInfo::from_rustls_stream(&stream),
+            Some(TlsSessionInfo {
+                protocol_version,
+                ..
+            }) if protocol_version == SslVersion::TLS1_2
+        );
+
+        let tcp = TcpStream::connect(addr).await.expect("can connect");
+        let result = RustlsTls
+            .connect_over(
+                tcp,
+                TlsRouteFragment {
+                    min_protocol_version: Some(SslVersion::TLS1_3),
+                    ..fragment(SERVER_HOSTNAME, None)
+                },
+                "test".into(),
+            )
+            .await;
+        assert_matches!(result, Err(TransportConnectError::SslFailedHandshake(_)));
+    }
+}
@@ -0,0 +1,184 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Choosing the local port of outgoing TCP connections.
+//!
+//! Some locked-down networks only let connections out from particular source
+//! ports. Normally the OS picks an ephemeral port; with a [`SourcePortRange`]
+//! the socket is instead bound to a port from the range before connecting.
+
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::num::NonZeroU16;
+use std::ops::RangeInclusive;
+
+use tokio::net::{TcpSocket, TcpStream};
+
+use crate::errors::TransportConnectError;
+use crate::route::TcpRoute;
+use crate::utils::rng::RngProvider;
+
+/// The local ports that outgoing TCP connections may be made from.
+#[derive(Clone, Debug)]
+pub struct SourcePortRange {
+    first: NonZeroU16,
+    last: NonZeroU16,
+    rng: RngProvider,
+}
+
+impl PartialEq for SourcePortRange {
+    fn eq(&self, other: &Self) -> bool {
+        let Self {
+            first,
+            last,
+            rng: _,
+        } = self;
+        (first, last) == (&other.first, &other.last)
+    }
+}
+
+impl Eq for SourcePortRange {}
+
+impl SourcePortRange {
+    /// Allows the ports in `ports`, or returns `None` if it's empty.
+    pub fn new(ports: RangeInclusive<NonZeroU16>) -> Option<Self> {
+        let (first, last) = ports.into_inner();
+        (first <= last).then(|| Self {
+            first,
+            last,
+            rng: RngProvider::default(),
+        })
+    }
+
+    /// Picks the port to start from with `rng` instead of the OS's generator.
+    ///
+    /// Passing a [seeded](RngProvider::seeded) `rng` makes the choices reproducible.
+    pub fn with_rng(self, rng: RngProvider) -> Self {
+        Self { rng, ..self }
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        (self.first.get()..=self.last.get()).contains(&port)
+    }
+
+    /// Connects to `route` from one of the ports in the range.
+    ///
+    /// Ports are tried starting from a random one, so that concurrent
+    /// connections don't all contend for the first port, moving on to the
+    /// next whenever a port is already in use. The connection fails once
+    /// every port has been tried.
+    pub(crate) async fn connect(
+        &self,
+        route: TcpRoute<IpAddr>,
+    ) -> Result<TcpStream, TransportConnectError> {
+        let TcpRoute { address, port } = route;
+        let remote = SocketAddr::new(address, port.get());
+        let unspecified: IpAddr = match address {
+            IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+
+        let [first, last] = [self.first, self.last].map(NonZeroU16::get);
+        let start = self.random_port();
+        for local_port in (start..=last).chain(first..start) {
+            let socket = match address {
+                IpAddr::V4(_) => TcpSocket::new_v4(),
+                IpAddr::V6(_) => TcpSocket::new_v6(),
+            }
+            .map_err(|_| TransportConnectError::TcpConnectionFailed)?;
+
+            match socket.bind(SocketAddr::new(unspecified, local_port)) {
+                Ok(()) => {}
+                Err(e) if is_port_conflict(e.kind()) => continue,
+                Err(_) => return Err(TransportConnectError::TcpConnectionFailed),
+            }
+            match socket.connect(remote).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) if is_port_conflict(e.kind()) => continue,
+                Err(_) => return Err(TransportConnectError::TcpConnectionFailed),
+            }
+        }
+
+        log::warn!("no source port in {first}..={last} was available");
+        Err(TransportConnectError::TcpConnectionFailed)
+    }
+
+    fn random_port(&self) -> u16 {
+        let [first, last] = [self.first, self.last].map(|port| u64::from(port.get()));
+        self.rng
+            .random_range(first..last + 1)
+            .try_into()
+            .expect("in range")
+    }
+}
+
+fn is_port_conflict(kind: ErrorKind) -> bool {
+    matches!(kind, ErrorKind::AddrInUse | ErrorKind::AddrNotAvailable)
+}
+
+#[cfg(test)]
+mod test {
+    use nonzero_ext::nonzero;
+
+    use super::*;
+    use crate::tcp_ssl::testutil::stalling_listener;
+
+    #[test]
+    fn empty_range_is_rejected() {
+        assert_eq!(
+            SourcePortRange::new(nonzero!(2000u16)..=nonzero!(1999u16)),
+            None
+        );
+    }
+
+    #[test]
+    fn seeded_rng_picks_reproducible_ports() {
+        let range = SourcePortRange::new(nonzero!(2000u16)..=nonzero!(2999u16)).expect("not empty");
+        let picks = |seed| {
+            let range = range.clone().with_rng(RngProvider::seeded(seed));
+            Vec::from_iter(std::iter::repeat_with(|| range.random_port()).take(10))
+        };
+        let first_picks = picks(1);
+        assert_eq!(first_picks, picks(1));
+        assert_ne!(first_picks, picks(2));
+        assert!(first_picks.iter().all(|port| range.contains(*port)));
+    }
+
+    #[tokio::test]
+    async fn skips_ports_that_are_in_use() {
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let server_addr = listener.local_addr().expect("bound");
+        let (server, _accepted_rx) = stalling_listener(listener);
+        let _server_handle = tokio::spawn(server);
+
+        // Hold on to one port of a two-port range; every connection has to
+        // use the other.
+        let held = std::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).expect("can bind");
+        let held_port = held.local_addr().expect("bound").port();
+        let (first, last) = match held_port.checked_add(1) {
+            Some(next) => (held_port, next),
+            None => (held_port - 1, held_port),
+        };
+        let free_port = if first == held_port { last } else { first };
+        let range = SourcePortRange::new(
+            NonZeroU16::new(first).expect("nonzero")..=NonZeroU16::new(last).expect("nonzero"),
+        )
+        .expect("not empty");
+
+        let route = TcpRoute {
+            address: server_addr.ip(),
+            port: server_addr.port().try_into().expect("bound port"),
+        };
+        let stream = match range.connect(route).await {
+            Ok(stream) => stream,
+            // Something else on the machine might be using the other port.
+            Err(TransportConnectError::TcpConnectionFailed) => return,
+            Err(e) => panic!("unexpected error: {e}"),
+        };
+        assert_eq!(stream.local_addr().expect("connected").port(), free_port);
+    }
+}
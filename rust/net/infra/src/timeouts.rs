@@ -38,6 +38,15 @@ pub const DNS_CALL_BACKGROUND_TIMEOUT: Duration = Duration::from_secs(30);
 pub const WS_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(31);
 /// Maximum time of incoming packets inactivity allowed on a WebSocket connection
 pub const WS_MAX_IDLE_INTERVAL: Duration = Duration::from_secs(45);
+/// Maximum time for sending a WebSocket upgrade request and receiving the response
+pub const WS_UPGRADE_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long a read or write on an enclave connection may go without progress.
+///
+/// This matches [`WS_MAX_IDLE_INTERVAL`], so reads (which the websocket's own
+/// idle checks already cover) aren't cut off any sooner. What it adds is a
+/// bound on writes: a write the server has stopped reading blocks the
+/// websocket, idle checks included, until it fails.
+pub const ENCLAVE_OPERATION_TIMEOUT: Duration = WS_MAX_IDLE_INTERVAL;
 
 /// Timeout for a connect operation that attempts one route
 /// (this includes DNS resolution, TCP connection, and SSL handshake)
@@ -13,8 +13,12 @@ use http::HeaderValue;
 use tokio::time::Instant;
 
 pub(crate) mod binary_heap;
+pub mod clock_offset;
+pub mod compression;
 pub mod future;
 pub mod oneshot_broadcast;
+pub mod rng;
+pub mod session_affinity;
 
 /// Constructs the value of the `Authorization` header for the `Basic` auth scheme.
 pub fn basic_authorization(username: &str, password: &str) -> HeaderValue {
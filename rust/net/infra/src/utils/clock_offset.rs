@@ -0,0 +1,111 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Estimating how far the local clock is from the server's.
+//!
+//! A device whose clock is far off fails certificate validation and other
+//! time-based checks in confusing ways. The `Date` header on responses lets
+//! an app notice this and warn about it, or compensate.
+
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use http::HeaderMap;
+
+/// A smoothed estimate of how far the server's clock is ahead of the local
+/// one, based on the `Date` headers of responses.
+///
+/// Nothing is recorded unless responses are explicitly fed in, as with
+/// [`AggregatingHttp2Client::with_clock_offset_tracking`](crate::http_client::AggregatingHttp2Client::with_clock_offset_tracking).
+/// Clones share the same estimate.
+#[derive(Clone, Debug, Default)]
+pub struct ServerClockOffset(Arc<Mutex<Option<f64>>>);
+
+impl ServerClockOffset {
+    /// How far each new observation moves the estimate, between 0 and 1.
+    const SMOOTHING_FACTOR: f64 = 0.25;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the estimate from the `Date` header in `headers`, for a
+    /// response that just arrived.
+    ///
+    /// Responses without a valid `Date` header are ignored.
+    pub fn observe_response_headers(&self, headers: &HeaderMap) {
+        self.observe_response_headers_at(headers, SystemTime::now())
+    }
+
+    fn observe_response_headers_at(&self, headers: &HeaderMap, received_at: SystemTime) {
+        let Some(server_time) = headers
+            .get(http::header::DATE)
+            .and_then(|value| httpdate::parse_http_date(value.to_str().ok()?).ok())
+        else {
+            return;
+        };
+        let offset = match server_time.duration_since(received_at) {
+            Ok(ahead) => ahead.as_secs_f64(),
+            Err(behind) => -behind.duration().as_secs_f64(),
+        };
+
+        let mut estimate = self.0.lock().expect("not poisoned");
+        *estimate = Some(match *estimate {
+            None => offset,
+            Some(previous) => previous + Self::SMOOTHING_FACTOR * (offset - previous),
+        });
+    }
+
+    /// How many seconds the server's clock is ahead of the local one
+    /// (negative if it's behind), or `None` if no response has had a usable
+    /// `Date` header yet.
+    ///
+    /// `Date` headers have a resolution of one second, so this is only
+    /// accurate to about that much.
+    pub fn offset_secs(&self) -> Option<f64> {
+        *self.0.lock().expect("not poisoned")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn offset_is_smoothed() {
+        let clock_offset = ServerClockOffset::new();
+        assert_eq!(clock_offset.offset_secs(), None);
+
+        let headers = HeaderMap::from_iter([(
+            http::header::DATE,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+        )]);
+        let server_time = UNIX_EPOCH + Duration::from_secs(784111777);
+
+        // The local clock is 100 seconds behind.
+        clock_offset.observe_response_headers_at(&headers, server_time - Duration::from_secs(100));
+        assert_eq!(clock_offset.offset_secs(), Some(100.0));
+
+        // A single differing observation only moves the estimate part of the way.
+        clock_offset.observe_response_headers_at(&headers, server_time + Duration::from_secs(100));
+        assert_eq!(clock_offset.offset_secs(), Some(50.0));
+
+        // Responses without a Date header don't change anything.
+        clock_offset.observe_response_headers_at(&HeaderMap::new(), server_time);
+        assert_eq!(clock_offset.offset_secs(), Some(50.0));
+
+        // Neither do ones with a Date header that can't be parsed.
+        let bad_headers = HeaderMap::from_iter([(
+            http::header::DATE,
+            HeaderValue::from_static("Sun, 06 Foo 1994 08:49:37 GMT"),
+        )]);
+        clock_offset.observe_response_headers_at(&bad_headers, server_time);
+        assert_eq!(clock_offset.offset_secs(), Some(50.0));
+    }
+}
@@ -0,0 +1,204 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Deciding which payloads are worth compressing.
+//!
+//! Compressing data that is already compressed, like most media, costs CPU
+//! (and so battery) without making anything smaller. An
+//! [`AggregatingHttp2Client`] set up with
+//! [request compression](crate::http_client::AggregatingHttp2Client::with_request_compression)
+//! consults a [`CompressionPolicy`] for each request body, and gzips the ones
+//! it approves of. Websocket compression isn't negotiated.
+//!
+//! [`AggregatingHttp2Client`]: crate::http_client::AggregatingHttp2Client
+
+use std::borrow::Cow;
+use std::io::Write as _;
+
+use bytes::Bytes;
+use http::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use http::{HeaderMap, HeaderValue};
+
+/// Decides whether an outgoing payload should be compressed.
+///
+/// A payload is sent uncompressed if any of these hold:
+/// - it is smaller than the [minimum size](Self::with_min_size), since the
+///   savings wouldn't cover the framing overhead;
+/// - its content type is one of the [skipped types](Self::with_skipped_content_types);
+/// - its first bytes look random enough that it's probably already
+///   compressed or encrypted.
+#[derive(Clone, Debug)]
+pub struct CompressionPolicy {
+    skipped_content_types: Vec<Cow<'static, str>>,
+    min_size: usize,
+}
+
+/// How many bytes at the start of a payload are checked for randomness.
+const ENTROPY_SAMPLE_LEN: usize = 512;
+
+/// The entropy, in bits per byte, above which a sample is taken to be
+/// incompressible.
+///
+/// Text and JSON stay well under 6; compressed and encrypted data are close
+/// to the maximum of 8.
+const INCOMPRESSIBLE_ENTROPY: f64 = 7.0;
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        Self {
+            skipped_content_types: [
+                "image/",
+                "audio/",
+                "video/",
+                "application/gzip",
+                "application/zip",
+                "application/zstd",
+            ]
+            .map(Cow::Borrowed)
+            .into(),
+            min_size: 256,
+        }
+    }
+}
+
+impl CompressionPolicy {
+    /// Replaces the content types that are never compressed.
+    ///
+    /// An entry ending in `/`, like `image/`, matches every subtype. Matching
+    /// ignores case and any parameters on the content type.
+    pub fn with_skipped_content_types(
+        self,
+        content_types: impl IntoIterator<Item = impl Into<Cow<'static, str>>>,
+    ) -> Self {
+        Self {
+            skipped_content_types: content_types.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Sets the smallest payload, in bytes, that is compressed.
+    pub fn with_min_size(self, min_size: usize) -> Self {
+        Self { min_size, ..self }
+    }
+
+    pub fn should_compress(&self, content_type: Option<&str>, payload: &[u8]) -> bool {
+        if payload.len() < self.min_size {
+            return false;
+        }
+        if content_type.is_some_and(|content_type| self.is_skipped(content_type)) {
+            return false;
+        }
+        let sample = &payload[..payload.len().min(ENTROPY_SAMPLE_LEN)];
+        entropy_bits_per_byte(sample) < INCOMPRESSIBLE_ENTROPY
+    }
+
+    /// Gzips `body` if this policy says it's worth compressing, adding a
+    /// `Content-Encoding` header to `headers` to say so.
+    ///
+    /// The content type is taken from `headers`. Bodies that already have a
+    /// `Content-Encoding` are left as they are.
+    pub(crate) fn encode_request_body(&self, headers: &mut HeaderMap, body: Bytes) -> Bytes {
+        if headers.contains_key(CONTENT_ENCODING) {
+            return body;
+        }
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        if !self.should_compress(content_type, &body) {
+            return body;
+        }
+        let mut encoder = flate2::write::GzEncoder::new(
+            Vec::with_capacity(body.len() / 2),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(&body).expect("can write to a Vec");
+        let compressed = encoder.finish().expect("can write to a Vec");
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        compressed.into()
+    }
+
+    fn is_skipped(&self, content_type: &str) -> bool {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        self.skipped_content_types.iter().any(|skipped| {
+            if skipped.ends_with('/') {
+                essence
+                    .get(..skipped.len())
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case(skipped))
+            } else {
+                essence.eq_ignore_ascii_case(skipped)
+            }
+        })
+    }
+}
+
+/// The Shannon entropy of the byte values in `sample`.
+fn entropy_bits_per_byte(sample: &[u8]) -> f64 {
+    if sample.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for &byte in sample {
+        counts[usize::from(byte)] += 1;
+    }
+    let len = sample.len() as f64;
+    counts
+        .into_iter()
+        .filter(|&count| count != 0)
+        .map(|count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{RngCore as _, SeedableRng as _};
+    use test_case::test_case;
+
+    use super::*;
+
+    fn json_message() -> Vec<u8> {
+        (0..20)
+            .map(|i| format!(r#"{{"id":{i},"name":"contact {i}","verified":false}}"#))
+            .collect::<Vec<_>>()
+            .join(",")
+            .into_bytes()
+    }
+
+    fn precompressed_blob() -> Vec<u8> {
+        let mut blob = vec![0; 4096];
+        rand::rngs::StdRng::seed_from_u64(0).fill_bytes(&mut blob);
+        blob
+    }
+
+    #[test]
+    fn compresses_json_but_not_precompressed_data() {
+        let policy = CompressionPolicy::default();
+        assert!(policy.should_compress(Some("application/json"), &json_message()));
+        assert!(!policy.should_compress(Some("application/octet-stream"), &precompressed_blob()));
+    }
+
+    #[test_case(Some("image/jpeg"), false; "skipped type")]
+    #[test_case(Some("IMAGE/PNG; charset=binary"), false; "skipped type with parameters")]
+    #[test_case(Some("application/gzip"), false; "exact type")]
+    #[test_case(Some("application/gzipped"), true; "only exact types match")]
+    #[test_case(Some("text/plain"), true; "other type")]
+    #[test_case(None, true; "no type")]
+    fn content_type_skipping(content_type: Option<&str>, expected: bool) {
+        let policy = CompressionPolicy::default();
+        assert_eq!(
+            policy.should_compress(content_type, &json_message()),
+            expected
+        );
+    }
+
+    #[test]
+    fn small_payloads_are_not_compressed() {
+        let message = json_message();
+        let policy = CompressionPolicy::default().with_min_size(message.len() + 1);
+        assert!(!policy.should_compress(Some("application/json"), &message));
+    }
+}
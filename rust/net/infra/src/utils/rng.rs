@@ -0,0 +1,104 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! A single, replaceable source of randomness.
+//!
+//! Everything that makes random choices while connecting, like ordering
+//! routes or picking an SNI, should go through an [`RngProvider`] instead of
+//! reaching for a global generator, so that tests can make those choices
+//! reproducible.
+
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use rand::rngs::{OsRng, StdRng};
+use rand::seq::SliceRandom as _;
+use rand::{Rng as _, RngCore, SeedableRng as _, TryRngCore as _};
+
+use crate::route::RouteProviderContext;
+
+/// The random number generator used for randomized connection behavior.
+///
+/// The default draws from the operating system's secure generator.
+/// [`RngProvider::seeded`] instead uses a deterministic generator, for tests.
+/// Clones share the same generator.
+#[derive(Clone, Debug, Default)]
+pub struct RngProvider(Source);
+
+#[derive(Clone, Debug, Default)]
+enum Source {
+    #[default]
+    Os,
+    Seeded(Arc<Mutex<StdRng>>),
+}
+
+impl RngProvider {
+    /// A provider whose output is entirely determined by `seed`.
+    pub fn seeded(seed: u64) -> Self {
+        Self(Source::Seeded(Arc::new(Mutex::new(StdRng::seed_from_u64(
+            seed,
+        )))))
+    }
+
+    /// Runs `f` with exclusive access to the generator.
+    pub fn with_rng<T>(&self, f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+        match &self.0 {
+            Source::Os => f(&mut OsRng.unwrap_err()),
+            Source::Seeded(rng) => f(&mut *rng.lock().expect("not poisoned")),
+        }
+    }
+
+    /// Returns a uniformly random value in `range`, which must not be empty.
+    pub fn random_range(&self, range: Range<u64>) -> u64 {
+        self.with_rng(|rng| rng.random_range(range))
+    }
+
+    /// Shuffles `items` into a uniformly random order.
+    pub fn shuffle<T>(&self, items: &mut [T]) {
+        self.with_rng(|rng| items.shuffle(rng))
+    }
+}
+
+impl RouteProviderContext for RngProvider {
+    fn random_usize(&self) -> usize {
+        self.with_rng(|rng| rng.random())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample(rng: &RngProvider) -> (Vec<u64>, Vec<usize>, [u8; 8]) {
+        let delays = (0..5).map(|_| rng.random_range(0..1000)).collect();
+        let ids = (0..5).map(|_| rng.random_usize()).collect();
+        let mut order = [0, 1, 2, 3, 4, 5, 6, 7];
+        rng.shuffle(&mut order);
+        (delays, ids, order)
+    }
+
+    #[test]
+    fn seeded_provider_is_reproducible() {
+        assert_eq!(
+            sample(&RngProvider::seeded(0x5eed)),
+            sample(&RngProvider::seeded(0x5eed))
+        );
+        assert_ne!(
+            sample(&RngProvider::seeded(0x5eed)),
+            sample(&RngProvider::seeded(0x5eed + 1))
+        );
+    }
+
+    #[test]
+    fn clones_share_the_generator() {
+        let rng = RngProvider::seeded(0x5eed);
+        let first = sample(&rng.clone());
+        let second = sample(&rng);
+        assert_ne!(first, second);
+
+        let replay = RngProvider::seeded(0x5eed);
+        assert_eq!((sample(&replay), sample(&replay)), (first, second));
+    }
+}
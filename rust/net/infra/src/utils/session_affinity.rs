@@ -0,0 +1,192 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Sticking to the same backend across requests and reconnects.
+//!
+//! A load balancer can hand out a token, in a header or a cookie, that names
+//! the backend a client was routed to. Sending it back on later requests gets
+//! the client to the same backend, which keeps its caches and session state
+//! warm.
+
+use std::sync::{Arc, Mutex};
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::HttpRequestDecorator;
+
+/// Where the server puts its session affinity token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AffinityTokenLocation {
+    /// The token is the value of a response header, and is sent back in a
+    /// request header with the same name.
+    Header(HeaderName),
+    /// The token is a cookie with this name, set with `Set-Cookie` and sent
+    /// back with `Cookie`.
+    Cookie(String),
+}
+
+/// The most recent session affinity token from the server, to be replayed on
+/// subsequent requests and connections.
+///
+/// Nothing is recorded unless responses are explicitly fed in, as with
+/// [`AggregatingHttp2Client::with_session_affinity`](crate::http_client::AggregatingHttp2Client::with_session_affinity).
+/// Clones share the same token.
+#[derive(Clone, Debug)]
+pub struct SessionAffinity {
+    location: Arc<AffinityTokenLocation>,
+    token: Arc<Mutex<Option<HeaderValue>>>,
+}
+
+impl SessionAffinity {
+    pub fn new(location: AffinityTokenLocation) -> Self {
+        Self {
+            location: Arc::new(location),
+            token: Default::default(),
+        }
+    }
+
+    /// Records the token in `headers`, for a response that just arrived.
+    ///
+    /// Responses without the token leave the previous one in place. For a
+    /// cookie, an empty value (as used to delete it) forgets the token.
+    pub fn observe_response_headers(&self, headers: &HeaderMap) {
+        let observed = match &*self.location {
+            AffinityTokenLocation::Header(name) => headers.get(name).cloned().map(Some),
+            AffinityTokenLocation::Cookie(name) => headers
+                .get_all(http::header::SET_COOKIE)
+                .iter()
+                .filter_map(|value| parse_set_cookie(value.to_str().ok()?, name))
+                .last()
+                .map(|value| {
+                    (!value.is_empty()).then(|| {
+                        HeaderValue::try_from(format!("{name}={value}"))
+                            .expect("was part of a valid header")
+                    })
+                }),
+        };
+        if let Some(token) = observed {
+            *self.token.lock().expect("not poisoned") = token;
+        }
+    }
+
+    /// The header that carries the current token back to the server, if one
+    /// has been seen.
+    pub fn request_header(&self) -> Option<(HeaderName, HeaderValue)> {
+        let token = self.token.lock().expect("not poisoned").clone()?;
+        let name = match &*self.location {
+            AffinityTokenLocation::Header(name) => name.clone(),
+            AffinityTokenLocation::Cookie(_) => http::header::COOKIE,
+        };
+        Some((name, token))
+    }
+
+    /// Adds the current token, if any, to the headers of an outgoing request.
+    ///
+    /// A header already set by the caller takes precedence; a cookie is sent
+    /// alongside any others.
+    pub fn add_to_request(&self, headers: &mut HeaderMap) {
+        let Some((name, value)) = self.request_header() else {
+            return;
+        };
+        match &*self.location {
+            AffinityTokenLocation::Header(_) => {
+                headers.entry(name).or_insert(value);
+            }
+            AffinityTokenLocation::Cookie(_) => {
+                headers.append(name, value);
+            }
+        }
+    }
+
+    /// A decorator for the next connection, like a websocket upgrade, that
+    /// sends the current token.
+    ///
+    /// This is a snapshot: a token observed later won't be picked up by it.
+    pub fn decorator(&self) -> Option<HttpRequestDecorator> {
+        self.request_header()
+            .map(|(name, value)| HttpRequestDecorator::header(name, value))
+    }
+
+    pub fn clear(&self) {
+        *self.token.lock().expect("not poisoned") = None;
+    }
+}
+
+/// Returns the value of the cookie `name` if `set_cookie` sets it.
+fn parse_set_cookie<'a>(set_cookie: &'a str, name: &str) -> Option<&'a str> {
+    let (cookie_name, value) = set_cookie.split(';').next()?.split_once('=')?;
+    (cookie_name.trim() == name).then(|| value.trim().trim_matches('"'))
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("backend=b12; Path=/; Secure" => Some("b12"))]
+    #[test_case("backend=\"b12\"" => Some("b12"); "quoted")]
+    #[test_case("backend=; Max-Age=0" => Some(""); "deleted")]
+    #[test_case("other=b12; backend=b13" => None; "only the first pair is the cookie")]
+    #[test_case("backends=b12" => None)]
+    fn set_cookie_parsing(set_cookie: &str) -> Option<&str> {
+        parse_set_cookie(set_cookie, "backend")
+    }
+
+    #[test]
+    fn header_token_is_replayed_until_replaced() {
+        let name = HeaderName::from_static("x-backend");
+        let affinity = SessionAffinity::new(AffinityTokenLocation::Header(name.clone()));
+        assert_eq!(affinity.request_header(), None);
+
+        affinity.observe_response_headers(&HeaderMap::from_iter([(
+            name.clone(),
+            HeaderValue::from_static("b12"),
+        )]));
+        affinity.observe_response_headers(&HeaderMap::new());
+        assert_eq!(
+            affinity.request_header(),
+            Some((name.clone(), HeaderValue::from_static("b12")))
+        );
+
+        // A header the caller set isn't overridden.
+        let mut headers = HeaderMap::from_iter([(name.clone(), HeaderValue::from_static("mine"))]);
+        affinity.add_to_request(&mut headers);
+        assert_eq!(headers.get_all(&name).iter().collect::<Vec<_>>(), ["mine"]);
+
+        affinity.clear();
+        assert_eq!(affinity.request_header(), None);
+    }
+
+    #[test]
+    fn cookie_token_is_sent_as_a_cookie() {
+        let affinity = SessionAffinity::new(AffinityTokenLocation::Cookie("backend".into()));
+        let mut response_headers = HeaderMap::new();
+        for set_cookie in ["session=abc", "backend=b12; Path=/"] {
+            response_headers.append(
+                http::header::SET_COOKIE,
+                HeaderValue::from_static(set_cookie),
+            );
+        }
+        affinity.observe_response_headers(&response_headers);
+
+        let mut headers =
+            HeaderMap::from_iter([(http::header::COOKIE, HeaderValue::from_static("a=b"))]);
+        affinity.add_to_request(&mut headers);
+        assert_eq!(
+            headers
+                .get_all(http::header::COOKIE)
+                .iter()
+                .collect::<Vec<_>>(),
+            ["a=b", "backend=b12"]
+        );
+
+        affinity.observe_response_headers(&HeaderMap::from_iter([(
+            http::header::SET_COOKIE,
+            HeaderValue::from_static("backend=; Max-Age=0"),
+        )]));
+        assert_eq!(affinity.request_header(), None);
+    }
+}
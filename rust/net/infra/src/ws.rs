@@ -19,15 +19,19 @@ use tokio_tungstenite::WebSocketStream;
 use tungstenite::protocol::CloseFrame;
 use tungstenite::{http, Message};
 
-use crate::errors::LogSafeDisplay;
-use crate::route::{Connector, HttpRouteFragment, WebSocketRouteFragment};
+use crate::errors::{LogSafeDisplay, UserHint};
+use crate::route::{is_operation_timeout, Connector, HttpRouteFragment, WebSocketRouteFragment};
 use crate::service::{CancellationReason, CancellationToken};
+use crate::tcp_ssl::is_renegotiation_rejection;
 use crate::ws::error::{HttpFormatError, ProtocolError, SpaceError};
 use crate::{AsyncDuplexStream, Connection};
 
 pub mod error;
 pub use error::{LogSafeTungsteniteError, WebSocketConnectError};
 
+mod long_poll;
+pub use long_poll::{LongPollFallback, LongPollStream, WithLongPollFallback};
+
 mod noise;
 pub use noise::WebSocketTransport;
 
@@ -54,6 +58,11 @@ pub struct WebSocketConfig {
     /// How long to allow the connection to be idle before the server is assumed
     /// to have become unavailable.
     pub max_idle_time: Duration,
+    /// How long to allow for sending the upgrade request and receiving the
+    /// response, once the transport is connected.
+    ///
+    /// See [`WebSocketRouteFragment::upgrade_timeout`].
+    pub upgrade_timeout: Duration,
 }
 
 /// A type that can be used like a [`tokio_tungstenite::WebSocketStream`].
@@ -80,6 +89,69 @@ impl WebSocketConfig {
             remote_idle_disconnect_timeout: self.max_idle_time,
         }
     }
+
+    /// The [`WebSocketRouteFragment`] for connecting with this configuration.
+    pub fn route_fragment(&self, headers: http::HeaderMap) -> WebSocketRouteFragment {
+        WebSocketRouteFragment {
+            ws_config: self.ws_config,
+            endpoint: self.endpoint.clone(),
+            headers,
+            upgrade_timeout: Some(self.upgrade_timeout),
+        }
+    }
+
+    /// Limits the memory the connection can use for buffered frames.
+    ///
+    /// See [`WebSocketMemoryBudget`].
+    pub fn with_memory_budget(mut self, budget: WebSocketMemoryBudget) -> Self {
+        budget.apply_to(&mut self.ws_config);
+        self
+    }
+}
+
+/// A cap on the memory a single websocket connection uses to buffer frames.
+///
+/// The budget is split evenly between the two directions:
+/// - Incoming data is limited by the size of the message being reassembled.
+///   A message that doesn't fit ends the connection with
+///   [`SpaceError::Capacity`].
+/// - Outgoing data is limited by the size of the write buffer. A message that
+///   doesn't fit is rejected with [`SpaceError::SendQueueFull`] without being
+///   sent, and can be retried once the peer has caught up.
+///
+/// Compression isn't negotiated, so there are no other per-connection buffers
+/// to account for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WebSocketMemoryBudget {
+    total_bytes: std::num::NonZeroUsize,
+}
+
+impl WebSocketMemoryBudget {
+    pub const fn new(total_bytes: std::num::NonZeroUsize) -> Self {
+        Self { total_bytes }
+    }
+
+    /// The most memory the reassembly of incoming messages can use.
+    pub fn inbound_bytes(&self) -> usize {
+        self.total_bytes.get() / 2
+    }
+
+    /// The most memory buffered outgoing frames can use.
+    pub fn outbound_bytes(&self) -> usize {
+        self.total_bytes.get() - self.inbound_bytes()
+    }
+
+    /// Overwrites the buffer limits in `config` to stay within the budget.
+    pub fn apply_to(&self, config: &mut tungstenite::protocol::WebSocketConfig) {
+        let inbound = self.inbound_bytes();
+        let outbound = self.outbound_bytes();
+        config.max_message_size = Some(inbound);
+        config.max_frame_size = Some(inbound);
+        // tungstenite requires the maximum to be strictly larger than the
+        // size at which it starts flushing.
+        config.write_buffer_size = outbound / 2;
+        config.max_write_buffer_size = outbound;
+    }
 }
 
 /// A simplified version of [`tungstenite::Error`] that supports [`LogSafeDisplay`].
@@ -93,6 +165,12 @@ pub enum WebSocketServiceError {
     Http(http::Response<Option<Vec<u8>>>),
     HttpFormat(http::Error),
     Url(tungstenite::error::UrlError),
+    /// The server asked to renegotiate TLS, which
+    /// [`RenegotiationPolicy`](crate::tcp_ssl::RenegotiationPolicy) didn't allow.
+    TlsRenegotiationRejected,
+    /// A read or write on the stream underneath made no progress before its
+    /// [deadline](crate::route::DeadlineStream).
+    OperationTimeout,
     Other(&'static str),
 }
 
@@ -145,6 +223,7 @@ where
                 ws_config,
                 endpoint,
                 headers,
+                upgrade_timeout,
             },
             HttpRouteFragment {
                 host_header,
@@ -185,9 +264,16 @@ where
                 .body(())
                 .map_err(tungstenite::Error::from)?;
 
-            let (stream, response) =
-                tokio_tungstenite::client_async_with_config(request, inner, Some(ws_config))
-                    .await?;
+            let upgrade =
+                tokio_tungstenite::client_async_with_config(request, inner, Some(ws_config));
+            let (stream, response) = match upgrade_timeout {
+                None => upgrade.await?,
+                Some(upgrade_timeout) => tokio::time::timeout(upgrade_timeout, upgrade)
+                    .await
+                    .map_err(|_: tokio::time::error::Elapsed| {
+                        WebSocketConnectError::UpgradeTimeout
+                    })??,
+            };
 
             Ok(StreamWithResponseHeaders {
                 stream,
@@ -240,17 +326,51 @@ impl Display for WebSocketServiceError {
                 write!(f, "HTTP format error: {}", HttpFormatError::from(e))
             }
             WebSocketServiceError::Url(_) => write!(f, "URL error"),
+            WebSocketServiceError::TlsRenegotiationRejected => {
+                write!(f, "server asked to renegotiate TLS")
+            }
+            WebSocketServiceError::OperationTimeout => write!(f, "operation timed out"),
             WebSocketServiceError::Other(message) => write!(f, "other web socket error: {message}"),
         }
     }
 }
 
+impl WebSocketServiceError {
+    pub fn user_hint(&self) -> UserHint {
+        match self {
+            Self::ChannelClosed
+            | Self::ChannelIdleTooLong
+            | Self::Io(_)
+            | Self::OperationTimeout => UserHint::CheckConnection,
+            Self::Http(response) => UserHint::for_http_status(response.status()),
+            Self::Protocol(_)
+            | Self::Capacity(_)
+            | Self::HttpFormat(_)
+            | Self::Url(_)
+            | Self::TlsRenegotiationRejected
+            | Self::Other(_) => UserHint::NoAction,
+        }
+    }
+}
+
+impl From<std::io::Error> for WebSocketServiceError {
+    fn from(value: std::io::Error) -> Self {
+        if is_renegotiation_rejection(&value) {
+            Self::TlsRenegotiationRejected
+        } else if is_operation_timeout(&value) {
+            Self::OperationTimeout
+        } else {
+            Self::Io(value)
+        }
+    }
+}
+
 impl From<tungstenite::Error> for WebSocketServiceError {
     fn from(value: tungstenite::Error) -> Self {
         match value {
             tungstenite::Error::ConnectionClosed => Self::ChannelClosed,
             tungstenite::Error::AlreadyClosed => Self::ChannelClosed,
-            tungstenite::Error::Io(e) => Self::Io(e),
+            tungstenite::Error::Io(e) => e.into(),
             tungstenite::Error::Protocol(e) => Self::Protocol(e.into()),
             tungstenite::Error::Capacity(e) => Self::Capacity(e.into()),
             tungstenite::Error::WriteBufferFull(_) => Self::Capacity(SpaceError::SendQueueFull),
@@ -276,6 +396,8 @@ impl<S: AsyncDuplexStream, E> WebSocketClientWriter<S, E>
 where
     WebSocketServiceError: Into<E>,
 {
+    /// Sends a message, writing it out right away along with any deferred
+    /// messages before it.
     pub async fn send(&self, message: impl Into<Message>) -> Result<(), E> {
         run_and_update_status(&self.service_cancellation, || {
             async {
@@ -288,6 +410,30 @@ where
         })
         .await
     }
+
+    /// Queues a message to be written out with the next [`Self::send`] or
+    /// [`Self::flush`].
+    ///
+    /// This lets a burst of messages go out together, in as few writes as
+    /// possible, at the cost of latency for the ones queued first. Messages
+    /// are still sent in order. If enough messages are queued to fill the
+    /// write buffer, some of them are written out without waiting.
+    pub async fn send_deferred(&self, message: impl Into<Message>) -> Result<(), E> {
+        run_and_update_status(&self.service_cancellation, || {
+            async { self.ws_sink.lock().await.feed(message.into()).await }
+                .map_err(|e: tungstenite::Error| WebSocketServiceError::from(e).into())
+        })
+        .await
+    }
+
+    /// Writes out any messages queued by [`Self::send_deferred`].
+    pub async fn flush(&self) -> Result<(), E> {
+        run_and_update_status(&self.service_cancellation, || {
+            async { self.ws_sink.lock().await.flush().await }
+                .map_err(|e: tungstenite::Error| WebSocketServiceError::from(e).into())
+        })
+        .await
+    }
 }
 
 #[derive(Debug)]
@@ -453,14 +599,23 @@ pub mod testutil {
 
     pub async fn fake_websocket() -> (WebSocketStream<DuplexStream>, WebSocketStream<DuplexStream>)
     {
+        fake_websocket_with_client_config(WebSocketConfig::default()).await
+    }
+
+    /// Like [`fake_websocket`], but with the given configuration for the
+    /// client end.
+    pub async fn fake_websocket_with_client_config(
+        ws_config: WebSocketConfig,
+    ) -> (WebSocketStream<DuplexStream>, WebSocketStream<DuplexStream>) {
         let (client, server) = tokio::io::duplex(1024);
         let client_future = Stateless.connect_over(
             client,
             (
                 WebSocketRouteFragment {
-                    ws_config: WebSocketConfig::default(),
+                    ws_config,
                     endpoint: PathAndQuery::from_static("/"),
                     headers: Default::default(),
+                    upgrade_timeout: None,
                 },
                 HttpRouteFragment {
                     host_header: "localhost".into(),
@@ -483,10 +638,43 @@ pub mod testutil {
 
 #[cfg(test)]
 mod test {
+    use assert_matches::assert_matches;
+    use nonzero_ext::nonzero;
 
     use super::testutil::*;
     use super::*;
 
+    #[tokio::test(start_paused = true)]
+    async fn upgrade_times_out_if_server_never_responds() {
+        const UPGRADE_TIMEOUT: Duration = Duration::from_secs(5);
+
+        // The server end is kept open but never read from or written to.
+        let (client, _server) = tokio::io::duplex(1024);
+        let start = Instant::now();
+        let result = Stateless
+            .connect_over(
+                client,
+                (
+                    WebSocketRouteFragment {
+                        ws_config: Default::default(),
+                        endpoint: PathAndQuery::from_static("/"),
+                        headers: Default::default(),
+                        upgrade_timeout: Some(UPGRADE_TIMEOUT),
+                    },
+                    HttpRouteFragment {
+                        host_header: "localhost".into(),
+                        path_prefix: "".into(),
+                        front_name: None,
+                    },
+                ),
+                "test".into(),
+            )
+            .await;
+
+        assert_matches!(result, Err(WebSocketConnectError::UpgradeTimeout));
+        assert_eq!(start.elapsed(), UPGRADE_TIMEOUT);
+    }
+
     #[tokio::test]
     async fn websocket_client_sends_pong_on_server_ping() {
         let (mut server, mut client) = fake_websocket().await;
@@ -501,4 +689,135 @@ mod test {
             .expect("ok result");
         assert_eq!(response, Message::Pong(vec![]));
     }
+
+    #[tokio::test]
+    async fn deferred_messages_wait_for_a_flush() {
+        use futures_util::FutureExt as _;
+
+        let (mut server, client) = fake_websocket().await;
+        let (sink, _stream) = client.split();
+        let writer = WebSocketClientWriter::<_, WebSocketServiceError> {
+            ws_sink: Arc::new(Mutex::new(sink)),
+            service_cancellation: CancellationToken::new(),
+            error_type: PhantomData,
+        };
+
+        writer.send_deferred("first").await.expect("can queue");
+        writer.send_deferred("second").await.expect("can queue");
+        assert_matches!(server.next().now_or_never(), None);
+
+        // An immediate send writes out everything queued before it.
+        writer.send("third").await.expect("can send");
+        for expected in ["first", "second", "third"] {
+            let message = server.next().await.expect("open").expect("ok");
+            assert_eq!(message, Message::Text(expected.into()));
+        }
+
+        writer.send_deferred("fourth").await.expect("can queue");
+        assert_matches!(server.next().now_or_never(), None);
+        writer.flush().await.expect("can flush");
+        let message = server.next().await.expect("open").expect("ok");
+        assert_eq!(message, Message::Text("fourth".into()));
+    }
+
+    const MEMORY_BUDGET: WebSocketMemoryBudget = WebSocketMemoryBudget::new(nonzero!(4096usize));
+
+    fn budgeted_ws_config() -> tungstenite::protocol::WebSocketConfig {
+        let mut ws_config = tungstenite::protocol::WebSocketConfig::default();
+        MEMORY_BUDGET.apply_to(&mut ws_config);
+        ws_config
+    }
+
+    #[tokio::test]
+    async fn memory_budget_disconnects_on_oversized_incoming_message() {
+        let (mut server, mut client) =
+            fake_websocket_with_client_config(budgeted_ws_config()).await;
+
+        let _server = tokio::spawn(async move {
+            let oversized = vec![0; MEMORY_BUDGET.inbound_bytes() + 1];
+            let _ignore_error = server.send(Message::Binary(oversized)).await;
+            // Keep the server end open so the client can't just see EOF.
+            while let Some(Ok(_)) = server.next().await {}
+        });
+
+        let error = client
+            .next()
+            .await
+            .expect("some result")
+            .expect_err("message exceeds the budget");
+        assert_matches!(
+            WebSocketServiceError::from(error),
+            WebSocketServiceError::Capacity(SpaceError::Capacity(_))
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_budget_rejects_oversized_outgoing_message() {
+        let (_server, mut client) = fake_websocket_with_client_config(budgeted_ws_config()).await;
+
+        let oversized = vec![0; MEMORY_BUDGET.outbound_bytes() + 1];
+        let error = client
+            .send(Message::Binary(oversized))
+            .await
+            .expect_err("message exceeds the budget");
+        assert_matches!(
+            WebSocketServiceError::from(error),
+            WebSocketServiceError::Capacity(SpaceError::SendQueueFull)
+        );
+
+        // Messages that fit are still accepted afterwards.
+        client
+            .send(Message::Binary(vec![0; 16]))
+            .await
+            .expect("fits in the budget");
+    }
+
+    #[tokio::test]
+    async fn upgrade_fails_distinctly_when_server_asks_to_renegotiate() {
+        use warp::Filter as _;
+
+        use crate::errors::TransportConnectError;
+        use crate::tcp_ssl::testutil::{localhost_params, SERVER_CERTIFICATE};
+        use crate::testutil::InMemoryTlsConnector;
+        use crate::{Alpn, StreamAndInfo, TransportConnector as _};
+
+        let connector = InMemoryTlsConnector::new_requesting_renegotiation(
+            warp::any().map(|| "hello"),
+            SERVER_CERTIFICATE.cert.der(),
+            SERVER_CERTIFICATE.key_pair.serialized_der(),
+            &[Alpn::Http1_1],
+        );
+        let StreamAndInfo(stream, _info) = connector
+            .connect(&localhost_params(443), Alpn::Http1_1)
+            .await
+            .expect("handshake succeeds");
+
+        let result = Stateless
+            .connect_over(
+                stream,
+                (
+                    WebSocketRouteFragment {
+                        ws_config: Default::default(),
+                        endpoint: PathAndQuery::from_static("/"),
+                        headers: Default::default(),
+                        upgrade_timeout: None,
+                    },
+                    HttpRouteFragment {
+                        host_header: "localhost".into(),
+                        path_prefix: "".into(),
+                        front_name: None,
+                    },
+                ),
+                "test".into(),
+            )
+            .await;
+
+        let error = assert_matches!(result, Err(error) => error);
+        assert_matches!(
+            error,
+            WebSocketConnectError::Transport(TransportConnectError::TlsRenegotiationRejected),
+            "{error}"
+        );
+        assert_eq!(error.user_hint(), UserHint::NoAction);
+    }
 }
@@ -11,14 +11,16 @@ use std::borrow::{Borrow, Cow};
 
 use tungstenite::protocol::CloseFrame;
 
-use crate::errors::{LogSafeDisplay, TransportConnectError};
+use crate::errors::{ConnectErrorCategory, LogSafeDisplay, TransportConnectError, UserHint};
+use crate::tcp_ssl::is_renegotiation_rejection;
 
 /// Errors that can occur when connecting a websocket.
 #[derive(Debug, thiserror::Error)]
 pub enum WebSocketConnectError {
     Transport(#[from] TransportConnectError),
     Timeout,
-    WebSocketError(#[from] tungstenite::Error),
+    UpgradeTimeout,
+    WebSocketError(tungstenite::Error),
 }
 
 impl std::fmt::Display for WebSocketConnectError {
@@ -26,6 +28,9 @@ impl std::fmt::Display for WebSocketConnectError {
         match self {
             WebSocketConnectError::Transport(e) => write!(f, "transport: {e}"),
             WebSocketConnectError::Timeout => write!(f, "timed out while connecting"),
+            WebSocketConnectError::UpgradeTimeout => {
+                write!(f, "timed out during the websocket upgrade")
+            }
             WebSocketConnectError::WebSocketError(e) => {
                 write!(f, "websocket error: {}", LogSafeTungsteniteError::from(e))
             }
@@ -35,9 +40,50 @@ impl std::fmt::Display for WebSocketConnectError {
 
 impl LogSafeDisplay for WebSocketConnectError {}
 
+impl WebSocketConnectError {
+    pub fn user_hint(&self) -> UserHint {
+        match self {
+            Self::Transport(e) => e.user_hint(),
+            Self::Timeout | Self::UpgradeTimeout => UserHint::CheckConnection,
+            Self::WebSocketError(e) => LogSafeTungsteniteError::from(e).user_hint(),
+        }
+    }
+
+    pub fn category(&self) -> ConnectErrorCategory {
+        match self {
+            Self::Transport(e) => e.category(),
+            Self::Timeout | Self::UpgradeTimeout => ConnectErrorCategory::Network,
+            Self::WebSocketError(tungstenite::Error::Http(_)) => ConnectErrorCategory::Server,
+            Self::WebSocketError(_) => ConnectErrorCategory::Network,
+        }
+    }
+
+    /// See [`TransportConnectError::is_retryable`].
+    ///
+    /// Timeouts and failed upgrades are retryable, since the server or the
+    /// network in between may just have been having trouble.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Transport(e) => e.is_retryable(),
+            Self::Timeout | Self::UpgradeTimeout | Self::WebSocketError(_) => true,
+        }
+    }
+}
+
+impl From<tungstenite::Error> for WebSocketConnectError {
+    fn from(value: tungstenite::Error) -> Self {
+        match value {
+            tungstenite::Error::Io(e) if is_renegotiation_rejection(&e) => {
+                Self::Transport(TransportConnectError::TlsRenegotiationRejected)
+            }
+            value => Self::WebSocketError(value),
+        }
+    }
+}
+
 impl From<std::io::Error> for WebSocketConnectError {
     fn from(value: std::io::Error) -> Self {
-        Self::WebSocketError(value.into())
+        tungstenite::Error::from(value).into()
     }
 }
 
@@ -104,6 +150,21 @@ pub enum LogSafeTungsteniteError {
 
 impl LogSafeDisplay for LogSafeTungsteniteError {}
 
+impl LogSafeTungsteniteError {
+    pub fn user_hint(&self) -> UserHint {
+        match self {
+            Self::Closed | Self::Io => UserHint::CheckConnection,
+            Self::Http(status) => UserHint::for_http_status(*status),
+            Self::Space(_)
+            | Self::Protocol(_)
+            | Self::Url
+            | Self::BadUtf8
+            | Self::HttpFormat(_)
+            | Self::UnexpectedTlsError => UserHint::NoAction,
+        }
+    }
+}
+
 /// Mirror of [`tungstenite::error::CapacityError`] and [`tungstenite::Error::WriteBufferFull`].
 ///
 /// Provides a user-data-free [`std::fmt::Display`] implementation.
@@ -0,0 +1,610 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::future::Either;
+use futures_util::{Sink, Stream};
+use http::uri::PathAndQuery;
+use http::{HeaderMap, StatusCode};
+use http_body_util::{BodyExt as _, Full, Limited};
+use hyper::client::conn::http1;
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_util::sync::PollSender;
+use tungstenite::Message;
+
+use crate::route::{Connector, HttpRouteFragment, WebSocketRouteFragment};
+use crate::ws::WebSocketConnectError;
+use crate::{AsyncDuplexStream, Connection, TransportInfo};
+
+/// How many messages can be waiting in each direction of a [`LongPollStream`].
+const LONG_POLL_CHANNEL_SIZE: usize = 16;
+
+/// [`Connector`] for websocket routes that switches to HTTP long-polling while
+/// websocket upgrades keep failing.
+///
+/// Without a [`LongPollFallback`], every connection is made with the wrapped
+/// websocket connector. With one, the same routes are used to make
+/// [`LongPollStream`]s instead whenever the fallback says so.
+#[derive(Debug)]
+pub struct WithLongPollFallback<W> {
+    websocket: W,
+    fallback: Option<LongPollFallback>,
+}
+
+/// Decides when a [`WithLongPollFallback`] should use long-polling.
+///
+/// Long-polling is used once `fallback_after` websocket upgrades in a row have
+/// failed. After `retry_websocket_after` has passed since the latest failure,
+/// the next connection tries a websocket again: if the upgrade works, the
+/// failures are forgotten and websockets are used from then on; if not,
+/// long-polling is used for another `retry_websocket_after`. Failures to
+/// connect the transport underneath don't count, since long-polling wouldn't
+/// help with those.
+///
+/// Clones share the same record of failures, so that every connection to a
+/// service learns from the others.
+#[derive(Clone, Debug)]
+pub struct LongPollFallback {
+    fallback_after: usize,
+    retry_websocket_after: Duration,
+    upgrades: Arc<Mutex<UpgradeHistory>>,
+}
+
+#[derive(Debug, Default)]
+struct UpgradeHistory {
+    failures_in_a_row: usize,
+    last_failure: Option<Instant>,
+}
+
+/// A websocket-like message stream carried over plain HTTP/1.1 requests.
+///
+/// Incoming messages are received by repeatedly sending a `GET` to the
+/// websocket endpoint, which the server holds until it has a message to send
+/// (responding with `200 OK` and the message as the body) or until it gives
+/// up waiting (responding with `204 No Content`). Outgoing messages are each
+/// sent as the body of a `POST` to the same endpoint. Since HTTP/1.1 allows
+/// only one request at a time, outgoing messages are sent between polls, so
+/// the server should not hold polls for long.
+///
+/// Pings are answered locally, since there's no websocket peer to answer
+/// them; a server that stops responding shows up as a failed poll instead.
+/// Incoming messages are always [`Message::Binary`].
+#[derive(Debug)]
+pub struct LongPollStream<S> {
+    incoming: mpsc::Receiver<Result<Message, tungstenite::Error>>,
+    outgoing: PollSender<Message>,
+    transport: SharedTransport<S>,
+}
+
+/// Lets a [`LongPollStream`] get at the transport its HTTP connection owns.
+#[derive(Debug)]
+struct SharedTransport<S>(Arc<Mutex<S>>);
+
+impl<W> WithLongPollFallback<W> {
+    pub fn new(websocket: W, fallback: Option<LongPollFallback>) -> Self {
+        Self {
+            websocket,
+            fallback,
+        }
+    }
+}
+
+impl LongPollFallback {
+    pub fn new(fallback_after: usize, retry_websocket_after: Duration) -> Self {
+        Self {
+            fallback_after,
+            retry_websocket_after,
+            upgrades: Default::default(),
+        }
+    }
+
+    /// Whether connection attempts are currently made with long-polling.
+    pub fn is_falling_back(&self) -> bool {
+        let upgrades = self.upgrades.lock().expect("not poisoned");
+        upgrades.failures_in_a_row >= self.fallback_after
+            && upgrades
+                .last_failure
+                .is_some_and(|at| at.elapsed() < self.retry_websocket_after)
+    }
+
+    fn record_upgrade<T>(&self, result: &Result<T, WebSocketConnectError>) {
+        let mut upgrades = self.upgrades.lock().expect("not poisoned");
+        match result {
+            Ok(_) => *upgrades = UpgradeHistory::default(),
+            Err(
+                WebSocketConnectError::WebSocketError(_) | WebSocketConnectError::UpgradeTimeout,
+            ) => {
+                upgrades.failures_in_a_row += 1;
+                upgrades.last_failure = Some(Instant::now());
+            }
+            Err(WebSocketConnectError::Transport(_) | WebSocketConnectError::Timeout) => {}
+        }
+    }
+}
+
+impl<W, Inner> Connector<(WebSocketRouteFragment, HttpRouteFragment), Inner>
+    for WithLongPollFallback<W>
+where
+    W: Connector<(WebSocketRouteFragment, HttpRouteFragment), Inner, Error = WebSocketConnectError>
+        + Sync,
+    Inner: AsyncDuplexStream + 'static,
+{
+    type Connection = Either<W::Connection, LongPollStream<Inner>>;
+
+    type Error = WebSocketConnectError;
+
+    async fn connect_over(
+        &self,
+        over: Inner,
+        route: (WebSocketRouteFragment, HttpRouteFragment),
+        log_tag: Arc<str>,
+    ) -> Result<Self::Connection, Self::Error> {
+        let Some(fallback) = &self.fallback else {
+            return self
+                .websocket
+                .connect_over(over, route, log_tag)
+                .await
+                .map(Either::Left);
+        };
+
+        if fallback.is_falling_back() {
+            log::info!("[{log_tag}] websocket upgrades keep failing, using long-polling");
+            return LongPollStream::connect_over(over, route, log_tag)
+                .await
+                .map(Either::Right);
+        }
+
+        let result = self.websocket.connect_over(over, route, log_tag).await;
+        fallback.record_upgrade(&result);
+        result.map(Either::Left)
+    }
+}
+
+impl<S: AsyncDuplexStream + 'static> LongPollStream<S> {
+    /// Starts long-polling the websocket endpoint of `route` over `over`.
+    pub async fn connect_over(
+        over: S,
+        route: (WebSocketRouteFragment, HttpRouteFragment),
+        log_tag: Arc<str>,
+    ) -> Result<Self, WebSocketConnectError> {
+        let (
+            WebSocketRouteFragment {
+                ws_config,
+                endpoint,
+                headers,
+                upgrade_timeout: _,
+            },
+            HttpRouteFragment {
+                host_header,
+                path_prefix,
+                front_name: _,
+            },
+        ) = route;
+
+        let path = if path_prefix.is_empty() {
+            endpoint
+        } else {
+            PathAndQuery::from_maybe_shared(format!("{path_prefix}{endpoint}"))
+                .map_err(http::Error::from)
+                .map_err(tungstenite::Error::from)?
+        };
+
+        let transport = Arc::new(Mutex::new(over));
+        let (sender, connection) =
+            http1::handshake::<_, Full<Bytes>>(TokioIo::new(SharedTransport(transport.clone())))
+                .await
+                .map_err(std::io::Error::other)?;
+        let connection_log_tag = log_tag.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                log::warn!("[{connection_log_tag}] long-poll connection failed: {e}");
+            }
+        });
+
+        let target = PollTarget {
+            path,
+            host_header,
+            headers,
+            max_message_size: ws_config.max_message_size.unwrap_or(usize::MAX),
+        };
+        let (incoming_tx, incoming_rx) = mpsc::channel(LONG_POLL_CHANNEL_SIZE);
+        let (outgoing_tx, outgoing_rx) = mpsc::channel(LONG_POLL_CHANNEL_SIZE);
+        tokio::spawn(async move { target.run(sender, incoming_tx, outgoing_rx, log_tag).await });
+
+        Ok(Self {
+            incoming: incoming_rx,
+            outgoing: PollSender::new(outgoing_tx),
+            transport: SharedTransport(transport),
+        })
+    }
+}
+
+impl<S: Connection> Connection for LongPollStream<S> {
+    fn transport_info(&self) -> TransportInfo {
+        self.transport
+            .0
+            .lock()
+            .expect("not poisoned")
+            .transport_info()
+    }
+}
+
+impl<L: Connection, R: Connection> Connection for Either<L, R> {
+    fn transport_info(&self) -> TransportInfo {
+        match self {
+            Self::Left(l) => l.transport_info(),
+            Self::Right(r) => r.transport_info(),
+        }
+    }
+}
+
+impl<S> Stream for LongPollStream<S> {
+    type Item = Result<Message, tungstenite::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().incoming.poll_recv(cx)
+    }
+}
+
+impl<S> Sink<Message> for LongPollStream<S> {
+    type Error = tungstenite::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let result = ready!(self.get_mut().outgoing.poll_reserve(cx));
+        Poll::Ready(result.map_err(|_| tungstenite::Error::AlreadyClosed))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        self.get_mut()
+            .outgoing
+            .send_item(item)
+            .map_err(|_| tungstenite::Error::AlreadyClosed)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().outgoing.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for SharedTransport<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.0.lock().expect("not poisoned")).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for SharedTransport<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut *self.0.lock().expect("not poisoned")).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.0.lock().expect("not poisoned")).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.0.lock().expect("not poisoned")).poll_shutdown(cx)
+    }
+}
+
+/// Where and how a [`LongPollStream`] sends its requests.
+struct PollTarget {
+    path: PathAndQuery,
+    host_header: Arc<str>,
+    headers: HeaderMap,
+    max_message_size: usize,
+}
+
+impl PollTarget {
+    async fn run(
+        self,
+        mut sender: http1::SendRequest<Full<Bytes>>,
+        incoming: mpsc::Sender<Result<Message, tungstenite::Error>>,
+        mut outgoing: mpsc::Receiver<Message>,
+        log_tag: Arc<str>,
+    ) {
+        let mut queued = VecDeque::new();
+        loop {
+            while let Some(body) = queued.pop_front() {
+                if let Err(e) = self.exchange(&mut sender, http::Method::POST, body).await {
+                    log::info!("[{log_tag}] long-poll send failed: {e}");
+                    let _ = incoming.send(Err(e)).await;
+                    return;
+                }
+            }
+
+            let poll = self.exchange(&mut sender, http::Method::GET, Bytes::new());
+            let mut poll = std::pin::pin!(poll);
+            let polled = loop {
+                tokio::select! {
+                    result = &mut poll => break result,
+                    message = outgoing.recv() => match message {
+                        None | Some(Message::Close(_)) => return,
+                        Some(Message::Ping(data)) => {
+                            if incoming.send(Ok(Message::Pong(data))).await.is_err() {
+                                return;
+                            }
+                        }
+                        Some(Message::Pong(_) | Message::Frame(_)) => {}
+                        Some(Message::Binary(data)) => queued.push_back(data.into()),
+                        Some(Message::Text(text)) => queued.push_back(text.into()),
+                    },
+                }
+            };
+
+            let message = match polled {
+                Ok((StatusCode::NO_CONTENT, _)) => continue,
+                Ok((_, body)) => Ok(Message::Binary(body.into())),
+                Err(e) => {
+                    log::info!("[{log_tag}] long-poll receive failed: {e}");
+                    Err(e)
+                }
+            };
+            let failed = message.is_err();
+            if incoming.send(message).await.is_err() || failed {
+                return;
+            }
+        }
+    }
+
+    /// Sends a single request, returning the status and body of a successful
+    /// response.
+    async fn exchange(
+        &self,
+        sender: &mut http1::SendRequest<Full<Bytes>>,
+        method: http::Method,
+        body: Bytes,
+    ) -> Result<(StatusCode, Bytes), tungstenite::Error> {
+        let mut builder = http::Request::builder();
+        *builder.headers_mut().expect("no headers, so not invalid") = self.headers.clone();
+        let request = builder
+            .method(method)
+            .uri(self.path.clone())
+            .header(http::header::HOST, &*self.host_header)
+            .body(Full::new(body))?;
+
+        sender.ready().await.map_err(std::io::Error::other)?;
+        let response = sender
+            .send_request(request)
+            .await
+            .map_err(std::io::Error::other)?;
+        let (parts, body) = response.into_parts();
+        if !parts.status.is_success() {
+            return Err(tungstenite::Error::Http(http::Response::from_parts(
+                parts, None,
+            )));
+        }
+        let body = Limited::new(body, self.max_message_size)
+            .collect()
+            .await
+            .map_err(std::io::Error::other)?
+            .to_bytes();
+        Ok((parts.status, body))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+
+    use assert_matches::assert_matches;
+    use futures_util::{SinkExt as _, StreamExt as _};
+    use hyper::body::Incoming;
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::ws::WithoutResponseHeaders;
+
+    const SERVER_GREETING: &[u8] = b"hello";
+    const POLL_HOLD_TIME: Duration = Duration::from_secs(10);
+    const RETRY_WEBSOCKET_AFTER: Duration = Duration::from_secs(300);
+
+    fn route() -> (WebSocketRouteFragment, HttpRouteFragment) {
+        (
+            WebSocketRouteFragment {
+                ws_config: Default::default(),
+                endpoint: PathAndQuery::from_static("/chat"),
+                headers: Default::default(),
+                upgrade_timeout: None,
+            },
+            HttpRouteFragment {
+                host_header: "localhost".into(),
+                path_prefix: "".into(),
+                front_name: None,
+            },
+        )
+    }
+
+    /// A server that refuses websocket upgrades but supports long-polling,
+    /// echoing back each message it receives.
+    #[derive(Clone, Default)]
+    struct LongPollServer {
+        to_send: Arc<Mutex<VecDeque<Bytes>>>,
+        ready: Arc<Notify>,
+    }
+
+    impl LongPollServer {
+        fn serve(&self, stream: tokio::io::DuplexStream) {
+            let server = self.clone();
+            tokio::spawn(async move {
+                let service = hyper::service::service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.respond(request).await) }
+                });
+                let _ignore_errors = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+
+        async fn respond(&self, request: http::Request<Incoming>) -> http::Response<Full<Bytes>> {
+            let response = |status, body: Bytes| {
+                let mut response = http::Response::new(Full::new(body));
+                *response.status_mut() = status;
+                response
+            };
+
+            assert_eq!(request.uri().path(), "/chat");
+            if request.headers().contains_key(http::header::UPGRADE) {
+                return response(StatusCode::BAD_REQUEST, Bytes::new());
+            }
+            match *request.method() {
+                http::Method::POST => {
+                    let body = request.into_body().collect().await.unwrap().to_bytes();
+                    self.push([b"echo: ", &body[..]].concat().into());
+                    response(StatusCode::OK, Bytes::new())
+                }
+                http::Method::GET => {
+                    let wait = async {
+                        loop {
+                            let ready = self.ready.notified();
+                            if let Some(message) = self.to_send.lock().unwrap().pop_front() {
+                                return message;
+                            }
+                            ready.await;
+                        }
+                    };
+                    match tokio::time::timeout(POLL_HOLD_TIME, wait).await {
+                        Ok(message) => response(StatusCode::OK, message),
+                        Err(_) => response(StatusCode::NO_CONTENT, Bytes::new()),
+                    }
+                }
+                _ => response(StatusCode::METHOD_NOT_ALLOWED, Bytes::new()),
+            }
+        }
+
+        fn push(&self, message: Bytes) {
+            self.to_send.lock().unwrap().push_back(message);
+            self.ready.notify_waiters();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn messages_flow_over_long_polling_when_upgrades_fail() {
+        let server = LongPollServer::default();
+        server.push(SERVER_GREETING.into());
+        let fallback = LongPollFallback::new(2, RETRY_WEBSOCKET_AFTER);
+        let connector =
+            WithLongPollFallback::new(WithoutResponseHeaders::new(), Some(fallback.clone()));
+
+        let connect = || {
+            let (client, server_stream) = tokio::io::duplex(1024);
+            server.serve(server_stream);
+            connector.connect_over(client, route(), "test".into())
+        };
+        for _ in 0..2 {
+            assert_matches!(
+                connect().await,
+                Err(WebSocketConnectError::WebSocketError(tungstenite::Error::Http(response)))
+                    if response.status() == StatusCode::BAD_REQUEST
+            );
+        }
+        assert!(fallback.is_falling_back());
+        let mut stream = assert_matches!(connect().await, Ok(Either::Right(stream)) => stream);
+
+        assert_matches!(
+            stream.next().await,
+            Some(Ok(Message::Binary(message))) if message == SERVER_GREETING
+        );
+
+        // Nothing is waiting to be sent, so this poll gets held until the
+        // server gives up on it.
+        stream
+            .send(Message::Binary(b"are you there?".to_vec()))
+            .await
+            .expect("can send");
+        assert_matches!(
+            stream.next().await,
+            Some(Ok(Message::Binary(message))) if message == b"echo: are you there?"
+        );
+
+        stream
+            .send(Message::Ping(b"ping".to_vec()))
+            .await
+            .expect("can send");
+        assert_matches!(
+            stream.next().await,
+            Some(Ok(Message::Pong(data))) if data == b"ping"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn switches_back_to_websockets_once_upgrades_work_again() {
+        let server = LongPollServer::default();
+        let fallback = LongPollFallback::new(1, RETRY_WEBSOCKET_AFTER);
+        let connector =
+            WithLongPollFallback::new(WithoutResponseHeaders::new(), Some(fallback.clone()));
+
+        let connect_to_long_poll_server = || {
+            let (client, server_stream) = tokio::io::duplex(1024);
+            server.serve(server_stream);
+            connector.connect_over(client, route(), "test".into())
+        };
+        assert_matches!(
+            connect_to_long_poll_server().await,
+            Err(WebSocketConnectError::WebSocketError(_))
+        );
+        assert_matches!(connect_to_long_poll_server().await, Ok(Either::Right(_)));
+
+        // Once the retry interval is up, websockets get another chance; when
+        // that upgrade fails too, long-polling is used for another interval.
+        tokio::time::advance(RETRY_WEBSOCKET_AFTER).await;
+        assert!(!fallback.is_falling_back());
+        assert_matches!(
+            connect_to_long_poll_server().await,
+            Err(WebSocketConnectError::WebSocketError(_))
+        );
+        assert!(fallback.is_falling_back());
+        assert_matches!(connect_to_long_poll_server().await, Ok(Either::Right(_)));
+
+        // This time the server accepts the upgrade, so websockets are used
+        // from then on.
+        tokio::time::advance(RETRY_WEBSOCKET_AFTER).await;
+        let (client, server_stream) = tokio::io::duplex(1024);
+        let accept = tokio::spawn(tokio_tungstenite::accept_async(server_stream));
+        assert_matches!(
+            connector.connect_over(client, route(), "test".into()).await,
+            Ok(Either::Left(_))
+        );
+        let _server_websocket = accept.await.expect("no panic").expect("upgraded");
+        assert!(!fallback.is_falling_back());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn websockets_are_always_used_without_a_fallback() {
+        let server = LongPollServer::default();
+        let connector = WithLongPollFallback::new(WithoutResponseHeaders::new(), None);
+
+        for _ in 0..3 {
+            let (client, server_stream) = tokio::io::duplex(1024);
+            server.serve(server_stream);
+            assert_matches!(
+                connector.connect_over(client, route(), "test".into()).await,
+                Err(WebSocketConnectError::WebSocketError(_))
+            );
+        }
+    }
+}
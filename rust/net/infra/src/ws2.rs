@@ -8,9 +8,10 @@ use std::io::Error as IoError;
 use std::pin::Pin;
 use std::sync::Arc;
 
-use futures_util::{SinkExt as _, Stream, StreamExt as _};
+use futures_util::{FutureExt as _, SinkExt as _, Stream, StreamExt as _};
 use pin_project::pin_project;
 use tokio::select;
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::time::{Duration, Instant};
 use tungstenite::protocol::frame::coding::CloseCode;
 use tungstenite::protocol::CloseFrame;
@@ -19,6 +20,7 @@ use tungstenite::Message;
 use crate::errors::LogSafeDisplay;
 use crate::ws::{TextOrBinary, WebSocketServiceError, WebSocketStreamLike};
 
+pub mod ack;
 pub mod attested;
 
 /// Configuration values for managing the connected websocket.
@@ -99,6 +101,16 @@ pub struct Connection<S, R> {
     /// The last time that a message was received from the server.
     last_heard_from_server: Option<Instant>,
 
+    /// Controls whether the websocket is read from.
+    ///
+    /// See [`Connection::with_read_pausing`].
+    read_pausing: Option<ReadPausing>,
+
+    /// Requests to check that the server is still responsive.
+    ///
+    /// See [`Connection::with_liveness_probes`].
+    liveness_probes: Option<LivenessProbes>,
+
     /// Configuration for this websocket client's behavior.
     config: Config,
 
@@ -106,6 +118,46 @@ pub struct Connection<S, R> {
     log_tag: Arc<str>,
 }
 
+/// State for [`Connection::with_read_pausing`].
+#[derive(Debug)]
+struct ReadPausing {
+    reads_paused: watch::Receiver<bool>,
+    /// The value of `reads_paused` as of the last check.
+    paused: bool,
+}
+
+/// A request for a [`Connection`] to check that the server is responsive.
+///
+/// See [`Connection::with_liveness_probes`].
+#[derive(Debug)]
+pub struct LivenessProbe {
+    /// How long the server has to answer.
+    pub timeout: Duration,
+    /// Notified when the server is heard from. Dropped without being notified
+    /// if the server doesn't answer in time.
+    pub on_alive: oneshot::Sender<()>,
+}
+
+/// State for [`Connection::with_liveness_probes`].
+#[derive(Debug)]
+struct LivenessProbes {
+    requests: mpsc::UnboundedReceiver<LivenessProbe>,
+    /// Probes waiting to hear from the server.
+    waiting: Vec<oneshot::Sender<()>>,
+    /// If there are waiting probes, when the earliest of them times out, and
+    /// its timeout.
+    deadline: Option<(Instant, Duration)>,
+}
+
+impl LivenessProbes {
+    fn heard_from_server(&mut self) {
+        for on_alive in self.waiting.drain(..) {
+            let _ignore_dropped_probe = on_alive.send(());
+        }
+        self.deadline = None;
+    }
+}
+
 /// Fatal error that causes a connection to be closed.
 #[derive(Debug, displaydoc::Display, thiserror::Error)]
 pub enum NextEventError {
@@ -215,10 +267,57 @@ where
             last_heard_from_server: None,
             last_sent_to_server: None,
             last_sent_ping_to_server: None,
+            read_pausing: None,
+            liveness_probes: None,
             log_tag,
         }
     }
 
+    /// Stops reading from the websocket whenever `reads_paused` holds `true`.
+    ///
+    /// While reads are paused, incoming frames are left unread, so once the
+    /// transport's buffers fill up the server's writes block. Pings are
+    /// still sent when the connection is locally idle, but the server isn't
+    /// expected to answer them (since the answers wouldn't be read), so the
+    /// connection isn't timed out for being remotely idle. When reads resume,
+    /// the server gets a fresh [`Config::remote_idle_disconnect_timeout`].
+    ///
+    /// Note that pings from the server aren't answered while paused either,
+    /// so pausing for longer than the server's own idle timeout will lead to
+    /// a disconnect.
+    pub fn with_read_pausing(self, reads_paused: watch::Receiver<bool>) -> Self {
+        Self {
+            read_pausing: Some(ReadPausing {
+                reads_paused,
+                paused: false,
+            }),
+            ..self
+        }
+    }
+
+    /// Lets the owner check on demand whether the server is still responsive.
+    ///
+    /// This catches half-open connections much sooner than waiting out the
+    /// [`Config::remote_idle_disconnect_timeout`]. For each [`LivenessProbe`]
+    /// received on `probes`, a ping is sent right away. If anything is heard
+    /// from the server within the probe's timeout, the probe is notified;
+    /// otherwise the connection is declared dead, finishing with
+    /// [`NextEventError::ServerIdleTimeout`].
+    ///
+    /// While a probe is waiting, the websocket is read from even if reads are
+    /// [paused](Self::with_read_pausing), since otherwise the answer couldn't
+    /// be seen.
+    pub fn with_liveness_probes(self, probes: mpsc::UnboundedReceiver<LivenessProbe>) -> Self {
+        Self {
+            liveness_probes: Some(LivenessProbes {
+                requests: probes,
+                waiting: vec![],
+                deadline: None,
+            }),
+            ..self
+        }
+    }
+
     /// Wait for the first available event, returning the outcome.
     ///
     /// The events that can be handled include
@@ -228,6 +327,9 @@ where
     /// - the client hangs up on the outgoing queue
     /// - the websocket is quiet for too long and a Ping is sent
     ///
+    /// A ping that's due is sent before any more queued outgoing messages, no
+    /// matter how many are waiting.
+    ///
     /// Events that should terminate the connection are returned as a
     /// [`Outcome::Finished`] value; others are returned as
     /// [`Outcome::Continue`]. Once this function returns
@@ -258,6 +360,8 @@ where
             last_sent_to_server,
             last_sent_ping_to_server,
             last_heard_from_server,
+            read_pausing,
+            liveness_probes,
             log_tag,
         } = self.project();
 
@@ -277,48 +381,122 @@ where
             Received(Result<Message, tungstenite::Error>),
             ConnectionIdle,
             RemoteDisconnectedTimeout,
+            ProbeRequested(LivenessProbe),
+            ProbeTimedOut(Duration),
         }
 
-        let (earliest_timeout, inactivity_event) = {
-            // If we haven't sent anything to the server in a while, send a ping to
-            // make sure that it knows we're still around.
-            let local_connection_idle_timeout = (
-                *last_sent_to_server + *local_idle_timeout,
-                Event::ConnectionIdle,
-            );
-
-            // If we haven't heard anything from the server in a while, send it a
-            // ping to make sure it's still around. Don't be too eager, though: if
-            // we sent a ping recently, don't keep spamming the server.
-            let remote_connection_idle = (
-                Instant::max(*last_sent_ping_to_server, *last_heard_from_server)
-                    + *remote_idle_ping_timeout,
-                Event::ConnectionIdle,
-            );
-
-            // If we haven't heard from the server for long enough, declare the
-            // connection dead.
-            let remote_connection_disconnected = (
-                *last_heard_from_server + *remote_idle_disconnect_timeout,
-                Event::RemoteDisconnectedTimeout,
-            );
-
-            [
-                local_connection_idle_timeout,
-                remote_connection_idle,
-                remote_connection_disconnected,
-            ]
-            .into_iter()
-            .min_by_key(|(time, _)| *time)
-            .expect("non-empty array")
-        };
-
-        inactivity_sleep.as_mut().reset(earliest_timeout);
-
-        let event = select! {
-            to_send = outgoing_rx.next() => to_send.map_or(Event::ClientDisconnect, Event::ToSend),
-            recv = stream.next() => recv.map_or(Event::ServerDisconnect, Event::Received),
-            () = inactivity_sleep.as_mut() => inactivity_event,
+        let event = loop {
+            let paused = match read_pausing {
+                Some(ReadPausing {
+                    reads_paused,
+                    paused,
+                }) => {
+                    let was_paused = std::mem::replace(paused, *reads_paused.borrow_and_update());
+                    if was_paused && !*paused {
+                        // The server couldn't be heard from until now.
+                        *last_heard_from_server = Instant::now();
+                    }
+                    *paused
+                }
+                None => false,
+            };
+            let probe_deadline = liveness_probes
+                .as_ref()
+                .and_then(|probes| probes.deadline)
+                .map(|(deadline, timeout)| (deadline, Event::ProbeTimedOut(timeout)));
+            let reading = !paused || probe_deadline.is_some();
+
+            let (earliest_timeout, inactivity_event) = {
+                // If we haven't sent anything to the server in a while, send a ping to
+                // make sure that it knows we're still around.
+                let local_connection_idle_timeout = (
+                    *last_sent_to_server + *local_idle_timeout,
+                    Event::ConnectionIdle,
+                );
+
+                // If we haven't heard anything from the server in a while, send it a
+                // ping to make sure it's still around. Don't be too eager, though: if
+                // we sent a ping recently, don't keep spamming the server.
+                let remote_connection_idle = (
+                    Instant::max(*last_sent_ping_to_server, *last_heard_from_server)
+                        + *remote_idle_ping_timeout,
+                    Event::ConnectionIdle,
+                );
+
+                // If we haven't heard from the server for long enough, declare the
+                // connection dead.
+                let remote_connection_disconnected = (
+                    *last_heard_from_server + *remote_idle_disconnect_timeout,
+                    Event::RemoteDisconnectedTimeout,
+                );
+
+                // While reads are paused, we can't hear from the server, so only
+                // the local timeout applies.
+                [
+                    Some(local_connection_idle_timeout),
+                    (!paused).then_some(remote_connection_idle),
+                    (!paused).then_some(remote_connection_disconnected),
+                    probe_deadline,
+                ]
+                .into_iter()
+                .flatten()
+                .min_by_key(|(time, _)| *time)
+                .expect("local timeout is always present")
+            };
+
+            inactivity_sleep.as_mut().reset(earliest_timeout);
+
+            let pause_changed = async {
+                match read_pausing {
+                    Some(ReadPausing { reads_paused, .. }) => {
+                        if reads_paused.changed().await.is_err() {
+                            // Nobody can change the setting anymore.
+                            std::future::pending().await
+                        }
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+
+            let next_probe = async {
+                match liveness_probes {
+                    Some(LivenessProbes { requests, .. }) => match requests.recv().await {
+                        Some(probe) => probe,
+                        // Nobody can send probes anymore.
+                        None => std::future::pending().await,
+                    },
+                    None => std::future::pending().await,
+                }
+            };
+
+            // Incoming and outgoing messages are picked between fairly, so
+            // that neither direction starves the other.
+            let next_message = async {
+                select! {
+                    to_send = outgoing_rx.next() => to_send.map_or(Event::ClientDisconnect, Event::ToSend),
+                    recv = stream.next(), if reading => recv.map_or(Event::ServerDisconnect, Event::Received),
+                }
+            };
+
+            break select! {
+                // Pings (and timeouts) take priority over messages. Otherwise a
+                // steady supply of outgoing messages could hold up a keep-alive
+                // ping past the point where the server gives up on us, or where
+                // we'd give up on the server.
+                biased;
+                () = inactivity_sleep.as_mut() => {
+                    // A frame can have arrived by the time the timer is due,
+                    // like after the task wasn't scheduled for a while. Take
+                    // it before deciding the connection is idle.
+                    match reading.then(|| stream.next().now_or_never()).flatten() {
+                        Some(recv) => recv.map_or(Event::ServerDisconnect, Event::Received),
+                        None => inactivity_event,
+                    }
+                }
+                probe = next_probe => Event::ProbeRequested(probe),
+                () = pause_changed => continue,
+                event = next_message => event,
+            };
         };
 
         match event {
@@ -330,7 +508,28 @@ where
                     *remote_idle_disconnect_timeout,
                 )))
             }
-            Event::ConnectionIdle => {
+            Event::ProbeTimedOut(timeout) => {
+                log::warn!(
+                    "[{log_tag}] server didn't answer a liveness probe within {timeout:.3?}"
+                );
+                if let Some(probes) = liveness_probes {
+                    // Let the waiting probes know right away.
+                    probes.waiting.clear();
+                }
+                Outcome::Finished(Err(NextEventError::ServerIdleTimeout(timeout)))
+            }
+            event @ (Event::ConnectionIdle | Event::ProbeRequested(_)) => {
+                if let Event::ProbeRequested(LivenessProbe { timeout, on_alive }) = event {
+                    let probes = liveness_probes
+                        .as_mut()
+                        .expect("probes can only be requested if enabled");
+                    let deadline = Instant::now() + timeout;
+                    probes.waiting.push(on_alive);
+                    probes.deadline = Some(match probes.deadline {
+                        Some(existing) if existing.0 <= deadline => existing,
+                        _ => (deadline, timeout),
+                    });
+                }
                 if last_sent_to_server > last_heard_from_server {
                     // Differentiate between local-idle and remote-idle pings by checking if we have a
                     // ping or request sent since our last response.
@@ -386,6 +585,9 @@ where
             }
             Event::Received(Ok(message)) => {
                 *last_heard_from_server = Instant::now();
+                if let Some(probes) = liveness_probes {
+                    probes.heard_from_server();
+                }
                 match message {
                     Message::Text(text) => {
                         Outcome::Continue(MessageEvent::ReceivedMessage(TextOrBinary::Text(text)))
@@ -571,7 +773,7 @@ impl From<TungsteniteError> for WebSocketServiceError {
             TungsteniteError::AlreadyClosed | TungsteniteError::ConnectionClosed => {
                 Self::ChannelClosed
             }
-            TungsteniteError::Io(error) => Self::Io(error),
+            TungsteniteError::Io(error) => error.into(),
             TungsteniteError::CapacityErrorMessageTooLarge { size, max_size } => {
                 Self::Capacity(crate::ws::error::SpaceError::Capacity(
                     tungstenite::error::CapacityError::MessageTooLong { size, max_size },
@@ -894,6 +1096,127 @@ mod test {
             if err.kind() == IoErrorKind::ConnectionReset);
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn paused_reads_apply_backpressure_but_keep_alive() {
+        const LOCAL_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+        const REMOTE_IDLE_DISCONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+        let (ws_server, ws_client) = TestStream::new_pair(1);
+        let (mut server_tx, mut server_rx) = ws_server.split();
+        let (reads_paused_tx, reads_paused) = watch::channel(true);
+        let outgoing_rx = futures_util::stream::pending::<(_, ())>();
+        let connection = Connection::new(
+            ws_client,
+            outgoing_rx,
+            Config {
+                local_idle_timeout: LOCAL_IDLE_TIMEOUT,
+                remote_idle_ping_timeout: LOCAL_IDLE_TIMEOUT,
+                remote_idle_disconnect_timeout: REMOTE_IDLE_DISCONNECT_TIMEOUT,
+            },
+            "test".into(),
+        )
+        .with_read_pausing(reads_paused);
+        pin_mut!(connection);
+
+        // The transport holds one message. The second can't be written until
+        // the client reads the first.
+        server_tx
+            .send(Message::Text("first".to_string()))
+            .await
+            .expect("can buffer");
+        let second_send = server_tx.send(Message::Text("second".to_string()));
+        pin_mut!(second_send);
+
+        // Keep-alive pings still go out, and the connection isn't timed out
+        // even though nothing is heard from the server for longer than the
+        // disconnect timeout.
+        let start = Instant::now();
+        while Instant::now() - start <= REMOTE_IDLE_DISCONNECT_TIMEOUT {
+            select! {
+                result = connection.as_mut().handle_next_event() => {
+                    assert_matches!(result, Outcome::Continue(MessageEvent::SentPing));
+                }
+                _ = &mut second_send => panic!("server write should block while reads are paused"),
+            }
+            assert_matches!(server_rx.next().await, Some(Ok(Message::Ping(_))));
+        }
+
+        // Once reading resumes, the blocked write goes through.
+        reads_paused_tx.send_replace(false);
+        let (result, send_result) =
+            tokio::join!(connection.as_mut().handle_next_event(), &mut second_send);
+        send_result.expect("can send once the client reads");
+        assert_matches!(
+            result,
+            Outcome::Continue(MessageEvent::ReceivedMessage(TextOrBinary::Text(text))) if text == "first"
+        );
+        assert_matches!(
+            connection.as_mut().handle_next_event().await,
+            Outcome::Continue(MessageEvent::ReceivedMessage(TextOrBinary::Text(text))) if text == "second"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn liveness_probe_detects_unresponsive_server() {
+        const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+        let (mut ws_server, ws_client) = TestStream::new_pair(10);
+        let (probe_tx, probe_rx) = mpsc::unbounded_channel();
+        let outgoing_rx = futures_util::stream::pending::<(_, ())>();
+        let connection = Connection::new(
+            ws_client,
+            outgoing_rx,
+            Config {
+                local_idle_timeout: FOREVER,
+                remote_idle_ping_timeout: FOREVER,
+                remote_idle_disconnect_timeout: FOREVER,
+            },
+            "test".into(),
+        )
+        .with_liveness_probes(probe_rx);
+        pin_mut!(connection);
+
+        let probe = || {
+            let (on_alive, alive) = oneshot::channel();
+            probe_tx
+                .send(LivenessProbe {
+                    timeout: PROBE_TIMEOUT,
+                    on_alive,
+                })
+                .expect("connection is alive");
+            alive
+        };
+
+        // The server answers the first probe's ping.
+        let mut alive = probe();
+        assert_matches!(
+            connection.as_mut().handle_next_event().await,
+            Outcome::Continue(MessageEvent::SentPing)
+        );
+        let ping = assert_matches!(ws_server.next().await, Some(Ok(Message::Ping(ping))) => ping);
+        ws_server.send(Message::Pong(ping)).await.expect("can send");
+        assert_matches!(
+            connection.as_mut().handle_next_event().await,
+            Outcome::Continue(MessageEvent::ReceivedPingPong)
+        );
+        assert_matches!(alive.try_recv(), Ok(()));
+
+        // Then it stops responding, which is noticed after the probe timeout
+        // instead of the (much longer) idle timeout.
+        let alive = probe();
+        let start = Instant::now();
+        assert_matches!(
+            connection.as_mut().handle_next_event().await,
+            Outcome::Continue(MessageEvent::SentPing)
+        );
+        assert_matches!(
+            connection.as_mut().handle_next_event().await,
+            Outcome::Finished(Err(NextEventError::ServerIdleTimeout(PROBE_TIMEOUT)))
+        );
+        assert_eq!(Instant::now() - start, PROBE_TIMEOUT);
+        assert_matches!(alive.await, Err(_));
+    }
+
     #[tokio::test(start_paused = true)]
     async fn sends_ping_after_local_inactivity() {
         const LOCAL_IDLE_TIMEOUT: Duration = Duration::from_secs(123);
@@ -935,6 +1258,72 @@ mod test {
         assert_ne!(first_ping, second_ping);
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn keep_alive_pings_are_not_held_up_by_queued_messages() {
+        const PING_INTERVAL: Duration = Duration::from_secs(1);
+        // How long the server takes to read each frame. With room for only
+        // one frame in the transport, this is also how long each send takes.
+        const READ_INTERVAL: Duration = Duration::from_millis(100);
+        const PING_COUNT: usize = 5;
+
+        let (mut ws_server, ws_client) = TestStream::new_pair(1);
+        let (outgoing_tx, outgoing_rx) = mpsc::channel(10);
+        let connection = Connection::new(
+            ws_client,
+            ReceiverStream::new(outgoing_rx),
+            Config {
+                local_idle_timeout: FOREVER,
+                remote_idle_ping_timeout: PING_INTERVAL,
+                remote_idle_disconnect_timeout: FOREVER,
+            },
+            "test".into(),
+        );
+        pin_mut!(connection);
+
+        // Keep the outgoing queue full for the whole test.
+        tokio::spawn(async move {
+            while outgoing_tx
+                .send((TextOrBinary::Binary(vec![0; 1024]), ()))
+                .await
+                .is_ok()
+            {}
+        });
+        tokio::spawn(async move {
+            while ws_server.next().await.is_some() {
+                tokio::time::sleep(READ_INTERVAL).await;
+            }
+        });
+
+        let mut next_ping_due = Instant::now() + PING_INTERVAL;
+        let mut pings = 0;
+        let mut messages = 0;
+        while pings < PING_COUNT {
+            match connection.as_mut().handle_next_event().await {
+                Outcome::Continue(MessageEvent::SentMessage(())) => messages += 1,
+                Outcome::Continue(MessageEvent::SentPing) => {
+                    // At worst, the ping waits for a message that was already
+                    // being sent when it came due, then for its own turn.
+                    let now = Instant::now();
+                    assert!(
+                        now <= next_ping_due + 2 * READ_INTERVAL,
+                        "ping {pings} was late by {:?}",
+                        now - next_ping_due
+                    );
+                    next_ping_due = now + PING_INTERVAL;
+                    pings += 1;
+                }
+                other => panic!("unexpected outcome {other:?}"),
+            }
+        }
+
+        // The connection really was saturated the whole time: about ten frames
+        // can be sent per ping interval, and all but the ping were messages.
+        assert!(
+            messages >= PING_COUNT * 8,
+            "only {messages} messages were sent"
+        );
+    }
+
     #[tokio::test(start_paused = true)]
     async fn sends_ping_after_remote_inactivity_then_time_out() {
         // A single ping will be sent locally before the server times out.
@@ -972,6 +1361,40 @@ mod test {
         assert_eq!(Instant::now() - start, REMOTE_DISCONNECT_TIMEOUT);
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn frame_waiting_when_server_timeout_is_due_is_read() {
+        const REMOTE_DISCONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+        let (mut ws_server, ws_client) = TestStream::new_pair(10);
+        let outgoing_rx = futures_util::stream::pending::<(_, ())>();
+        let connection = Connection::new(
+            ws_client,
+            outgoing_rx,
+            Config {
+                local_idle_timeout: FOREVER,
+                remote_idle_ping_timeout: FOREVER,
+                remote_idle_disconnect_timeout: REMOTE_DISCONNECT_TIMEOUT,
+            },
+            "test".into(),
+        );
+        pin_mut!(connection);
+
+        // The server sends a frame in time, but the client doesn't get around
+        // to reading it until after the timeout, as if it had stalled.
+        ws_server
+            .send(Message::Text("from server".to_string()))
+            .await
+            .expect("can send from server");
+        tokio::time::advance(REMOTE_DISCONNECT_TIMEOUT * 2).await;
+
+        let result = connection
+            .as_mut()
+            .handle_next_event()
+            .now_or_never()
+            .expect("frame is waiting");
+        assert_matches!(result, Outcome::Continue(MessageEvent::ReceivedMessage(_)));
+    }
+
     #[tokio::test(start_paused = true)]
     async fn incoming_message_resets_server_timeout() {
         const REMOTE_IDLE_TIMEOUT: Duration = Duration::from_secs(20);
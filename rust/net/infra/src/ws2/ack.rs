@@ -0,0 +1,298 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Tracking whether the server accepted the messages sent to it.
+//!
+//! A websocket only says that a frame was written, not that the server did
+//! anything with it. Protocols that care have the server answer each message
+//! with an acknowledgment that names it; [`AckTracker`] hands out those names
+//! and matches the answers back up with the messages they're for.
+
+use std::collections::HashMap;
+
+use tokio::sync::oneshot;
+use tokio::time::{Duration, Instant};
+
+use crate::ws::TextOrBinary;
+
+/// Identifies an outgoing message to the server.
+///
+/// IDs are never reused by an [`AckTracker`], even across reconnects, so a
+/// late acknowledgment can't be mistaken for one for a newer message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MessageId(pub u64);
+
+impl std::fmt::Display for MessageId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The server's answer to a message, as read from an acknowledgment.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Ack {
+    Accepted,
+    Rejected,
+}
+
+/// What became of a message tracked by an [`AckTracker`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum AckOutcome {
+    /// The server acknowledged the message.
+    Accepted,
+    /// The server answered that it didn't accept the message.
+    Rejected,
+    /// No acknowledgment arrived within the tracker's timeout.
+    TimedOut,
+    /// The connection was lost before the message was acknowledged.
+    ///
+    /// The server might or might not have acted on the message.
+    Uncertain,
+}
+
+/// Assigns IDs to outgoing messages and correlates the server's
+/// acknowledgments with them.
+///
+/// This doesn't read or write the websocket itself. The owner of the
+/// connection (e.g. the code calling [`Connection::handle_next_event`])
+/// builds outgoing messages with [`AckTracker::prepare`], passes every
+/// incoming message to [`AckTracker::handle_received`], and calls
+/// [`AckTracker::connection_lost`] when the connection finishes, before
+/// sending anything over the next one.
+///
+/// [`Connection::handle_next_event`]: crate::ws2::Connection::handle_next_event
+#[derive(Debug)]
+pub struct AckTracker<F> {
+    extract_ack: F,
+    ack_timeout: Duration,
+    next_id: u64,
+    pending: HashMap<MessageId, oneshot::Sender<AckOutcome>>,
+}
+
+/// Resolves once the server has answered a message, or once it's clear that
+/// it won't.
+#[derive(Debug)]
+pub struct PendingAck {
+    id: MessageId,
+    deadline: Instant,
+    outcome: oneshot::Receiver<AckOutcome>,
+}
+
+impl<F> AckTracker<F>
+where
+    F: Fn(&TextOrBinary) -> Option<(MessageId, Ack)>,
+{
+    /// Creates a tracker that waits up to `ack_timeout` for each
+    /// acknowledgment.
+    ///
+    /// `extract_ack` recognizes acknowledgments among the incoming messages,
+    /// returning the ID of the message being answered and the answer, or
+    /// `None` for any other message.
+    pub fn new(ack_timeout: Duration, extract_ack: F) -> Self {
+        Self {
+            extract_ack,
+            ack_timeout,
+            next_id: 0,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Builds the next outgoing message and starts waiting for its
+    /// acknowledgment.
+    ///
+    /// `message_with_id` must embed the ID in the message so that the server
+    /// can refer to it. The timeout starts now, not when the message is
+    /// actually written to the websocket.
+    pub fn prepare(
+        &mut self,
+        message_with_id: impl FnOnce(MessageId) -> TextOrBinary,
+    ) -> (TextOrBinary, PendingAck) {
+        // Forget messages that already timed out or whose outcome nobody is
+        // waiting for anymore.
+        self.pending.retain(|_, tx| !tx.is_closed());
+
+        let id = MessageId(self.next_id);
+        self.next_id += 1;
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.insert(id, tx);
+        (
+            message_with_id(id),
+            PendingAck {
+                id,
+                deadline: Instant::now() + self.ack_timeout,
+                outcome: rx,
+            },
+        )
+    }
+
+    /// Checks whether `message` is an acknowledgment, resolving the message it
+    /// answers if so.
+    ///
+    /// Returns `true` if `message` was an acknowledgment (even one for a
+    /// message that is no longer being waited on), in which case it shouldn't
+    /// be processed any further.
+    pub fn handle_received(&mut self, message: &TextOrBinary) -> bool {
+        let Some((id, ack)) = (self.extract_ack)(message) else {
+            return false;
+        };
+        match self.pending.remove(&id) {
+            Some(tx) => {
+                let _ignore_no_longer_waiting = tx.send(match ack {
+                    Ack::Accepted => AckOutcome::Accepted,
+                    Ack::Rejected => AckOutcome::Rejected,
+                });
+            }
+            None => log::debug!("received acknowledgment for untracked message {id}"),
+        }
+        true
+    }
+
+    /// Resolves every message still waiting on an acknowledgment as
+    /// [`AckOutcome::Uncertain`].
+    ///
+    /// Acknowledgments can't outlive the connection they were sent on, so
+    /// this should be called whenever the connection finishes, including
+    /// before reconnecting.
+    pub fn connection_lost(&mut self) {
+        for (_id, tx) in self.pending.drain() {
+            let _ignore_no_longer_waiting = tx.send(AckOutcome::Uncertain);
+        }
+    }
+
+    /// The number of messages that are waiting on an acknowledgment.
+    pub fn pending_count(&self) -> usize {
+        self.pending.values().filter(|tx| !tx.is_closed()).count()
+    }
+}
+
+impl PendingAck {
+    pub fn id(&self) -> MessageId {
+        self.id
+    }
+
+    /// Waits for the message to be acknowledged, time out, or be lost.
+    ///
+    /// If the tracker is dropped first, the outcome is
+    /// [`AckOutcome::Uncertain`].
+    pub async fn outcome(self) -> AckOutcome {
+        match tokio::time::timeout_at(self.deadline, self.outcome).await {
+            Ok(Ok(outcome)) => outcome,
+            Ok(Err(_tracker_dropped)) => AckOutcome::Uncertain,
+            Err(_elapsed) => AckOutcome::TimedOut,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use futures_util::{pin_mut, SinkExt as _, StreamExt as _};
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::ReceiverStream;
+    use tungstenite::Message;
+
+    use super::*;
+    use crate::testutil::TestStream;
+    use crate::ws2::{Config, Connection, MessageEvent, Outcome};
+
+    const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+    const FOREVER: Duration = Duration::from_secs(10000000000);
+
+    /// Messages are `"<id> <body>"`; acknowledgments are `"ack <id>"` or
+    /// `"nack <id>"`.
+    fn extract_ack(message: &TextOrBinary) -> Option<(MessageId, Ack)> {
+        let TextOrBinary::Text(text) = message else {
+            return None;
+        };
+        let (ack, id) = text.split_once(' ')?;
+        let ack = match ack {
+            "ack" => Ack::Accepted,
+            "nack" => Ack::Rejected,
+            _ => return None,
+        };
+        Some((MessageId(id.parse().ok()?), ack))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acknowledged_message_resolves_as_accepted() {
+        let (mut ws_server, ws_client) = TestStream::new_pair(2);
+        let (outgoing_tx, outgoing_rx) = mpsc::channel(1);
+        let connection = Connection::new(
+            ws_client,
+            ReceiverStream::new(outgoing_rx),
+            Config {
+                local_idle_timeout: FOREVER,
+                remote_idle_ping_timeout: FOREVER,
+                remote_idle_disconnect_timeout: FOREVER,
+            },
+            "test".into(),
+        );
+        pin_mut!(connection);
+        let mut tracker = AckTracker::new(ACK_TIMEOUT, extract_ack);
+
+        let (message, pending) = tracker.prepare(|id| format!("{id} hello").into());
+        outgoing_tx.send((message, ())).await.expect("not closed");
+        assert_matches!(
+            connection.as_mut().handle_next_event().await,
+            Outcome::Continue(MessageEvent::SentMessage(()))
+        );
+
+        let Some(Ok(Message::Text(sent))) = ws_server.next().await else {
+            panic!("server should receive the message");
+        };
+        let (id, body) = sent.split_once(' ').expect("has an ID");
+        assert_eq!(body, "hello");
+        ws_server
+            .send(Message::Text(format!("ack {id}")))
+            .await
+            .expect("can send");
+
+        let Outcome::Continue(MessageEvent::ReceivedMessage(received)) =
+            connection.as_mut().handle_next_event().await
+        else {
+            panic!("client should receive the acknowledgment");
+        };
+        assert!(tracker.handle_received(&received));
+        assert_eq!(pending.outcome().await, AckOutcome::Accepted);
+        assert_eq!(tracker.pending_count(), 0);
+
+        assert!(!tracker.handle_received(&"unrelated message".into()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unacknowledged_message_times_out() {
+        let mut tracker = AckTracker::new(ACK_TIMEOUT, extract_ack);
+        let (_message, pending) = tracker.prepare(|id| format!("{id} hello").into());
+        let id = pending.id();
+
+        let start = Instant::now();
+        assert_eq!(pending.outcome().await, AckOutcome::TimedOut);
+        assert_eq!(start.elapsed(), ACK_TIMEOUT);
+        assert_eq!(tracker.pending_count(), 0);
+
+        // A late acknowledgment is still recognized, but there's nothing left
+        // to resolve.
+        assert!(tracker.handle_received(&format!("ack {id}").into()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn messages_in_flight_across_a_reconnect_are_uncertain() {
+        let mut tracker = AckTracker::new(ACK_TIMEOUT, extract_ack);
+        let (_message, lost) = tracker.prepare(|id| format!("{id} first").into());
+        let (_message, rejected) = tracker.prepare(|id| format!("{id} second").into());
+
+        assert!(tracker.handle_received(&format!("nack {}", rejected.id()).into()));
+        tracker.connection_lost();
+
+        let (_message, after_reconnect) = tracker.prepare(|id| format!("{id} third").into());
+        assert_ne!(after_reconnect.id(), lost.id());
+        assert_eq!(tracker.pending_count(), 1);
+
+        assert_eq!(lost.outcome().await, AckOutcome::Uncertain);
+        assert_eq!(rejected.outcome().await, AckOutcome::Rejected);
+    }
+}
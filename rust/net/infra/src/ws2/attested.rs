@@ -469,9 +469,7 @@ impl From<SendError> for AttestedConnectionError {
             SendError::WebSocketProtocol(protocol_error) => {
                 AttestedConnectionError::WebSocket(WebSocketServiceError::Protocol(protocol_error))
             }
-            SendError::Io(error) => {
-                AttestedConnectionError::WebSocket(WebSocketServiceError::Io(error))
-            }
+            SendError::Io(error) => AttestedConnectionError::WebSocket(error.into()),
             SendError::MessageTooLarge { size, max_size } => AttestedConnectionError::WebSocket(
                 WebSocketServiceError::Capacity(SpaceError::Capacity(
                     tungstenite::error::CapacityError::MessageTooLong { size, max_size },
@@ -10,9 +10,12 @@ use libsignal_core::{Aci, Pni, E164};
 use libsignal_net_infra::errors::{LogSafeDisplay, RetryLater, TransportConnectError};
 use libsignal_net_infra::extract_retry_later;
 use libsignal_net_infra::route::{
-    RouteProvider, ThrottlingConnector, UnresolvedWebsocketServiceRoute,
+    DeadlineConnector, RouteProvider, ThrottlingConnector, UnresolvedWebsocketServiceRoute,
+};
+use libsignal_net_infra::timeouts::ENCLAVE_OPERATION_TIMEOUT;
+use libsignal_net_infra::ws::{
+    NextOrClose, WebSocketConnectError, WebSocketServiceError, WithoutResponseHeaders,
 };
-use libsignal_net_infra::ws::{NextOrClose, WebSocketConnectError, WebSocketServiceError};
 use libsignal_net_infra::ws2::attested::{
     AttestedConnection, AttestedConnectionError, AttestedProtocolError,
 };
@@ -293,7 +296,9 @@ impl From<crate::enclave::Error> for LookupError {
                     Self::WebSocket(WebSocketServiceError::Http(response))
                 }
                 WebSocketServiceConnectError::Connect(e, _) => match e {
-                    WebSocketConnectError::Timeout => Self::ConnectionTimedOut,
+                    WebSocketConnectError::Timeout | WebSocketConnectError::UpgradeTimeout => {
+                        Self::ConnectionTimedOut
+                    }
                     WebSocketConnectError::Transport(e) => Self::ConnectTransport(e),
                     WebSocketConnectError::WebSocketError(e) => Self::WebSocket(e.into()),
                 },
@@ -321,6 +326,24 @@ struct RateLimitExceededResponse {
 #[cfg_attr(test, derive(Debug))]
 pub struct ClientResponseCollector(CdsiConnection);
 
+/// The [`Connector`](libsignal_net_infra::route::Connector) for the websocket
+/// under a [`CdsiConnection`].
+///
+/// Each read and write over the transport gets [`ENCLAVE_OPERATION_TIMEOUT`]
+/// to make progress.
+fn ws_connector() -> ThrottlingConnector<DeadlineConnector<WithoutResponseHeaders>> {
+    // We don't want to race multiple websocket handshakes because when
+    // we take the first one, the others will be uncermoniously closed.
+    // That looks like unexpected behavior at the server end, and the
+    // wasted handshakes consume resources unnecessarily.  Instead,
+    // allow parallelism at the transport level but throttle the number
+    // of websocket handshakes that can complete.
+    ThrottlingConnector::new(
+        DeadlineConnector::new(WithoutResponseHeaders::new(), ENCLAVE_OPERATION_TIMEOUT),
+        1,
+    )
+}
+
 impl CdsiConnection {
     pub async fn connect_with(
         connection_resources: ConnectionResources<'_, impl WebSocketTransportConnectorFactory>,
@@ -333,16 +356,7 @@ impl CdsiConnection {
             .connect_attested_ws(
                 route_provider,
                 auth,
-                (
-                    ws_config,
-                    // We don't want to race multiple websocket handshakes because when
-                    // we take the first one, the others will be uncermoniously closed.
-                    // That looks like unexpected behavior at the server end, and the
-                    // wasted handshakes consume resources unnecessarily.  Instead,
-                    // allow parallelism at the transport level but throttle the number
-                    // of websocket handshakes that can complete.
-                    ThrottlingConnector::new(crate::infra::ws::WithoutResponseHeaders::new(), 1),
-                ),
+                (ws_config, ws_connector()),
                 "cdsi".into(),
                 params,
             )
@@ -513,10 +527,14 @@ mod test {
 
     use assert_matches::assert_matches;
     use const_str::hex;
+    use futures_util::SinkExt as _;
+    use http::uri::PathAndQuery;
     use itertools::Itertools as _;
     use libsignal_net_infra::dns::DnsResolver;
     use libsignal_net_infra::route::testutils::ConnectFn;
-    use libsignal_net_infra::route::DirectOrProxyProvider;
+    use libsignal_net_infra::route::{
+        Connector as _, DirectOrProxyProvider, HttpRouteFragment, WebSocketRouteFragment,
+    };
     use libsignal_net_infra::testutil::no_network_change_events;
     use libsignal_net_infra::ws::testutil::fake_websocket;
     use libsignal_net_infra::ws2::attested::testutil::{
@@ -1016,6 +1034,7 @@ mod test {
                 dns_resolver: &dns_resolver,
                 network_change_event: &network_change_event,
                 confirmation_header_name: None,
+                cancellation: None,
             },
             DirectOrProxyProvider::maybe_proxied(
                 env.cdsi.route_provider(EnableDomainFronting::No),
@@ -1035,6 +1054,60 @@ mod test {
         )
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn handshake_times_out_when_server_stops_reading() {
+        // Too small to hold the client's handshake message, so writing it
+        // stalls once the server stops reading.
+        let (client, server) = tokio::io::duplex(32);
+
+        let _server = tokio::spawn(async move {
+            let mut websocket = tokio_tungstenite::accept_async(server)
+                .await
+                .expect("can accept");
+            websocket
+                .send(Vec::from(FAKE_ATTESTATION).into())
+                .await
+                .expect("can send");
+            // Keep the connection open without reading from it.
+            std::future::pending::<()>().await;
+            drop(websocket);
+        });
+
+        let websocket = ws_connector()
+            .connect_over(
+                client,
+                (
+                    WebSocketRouteFragment {
+                        ws_config: Default::default(),
+                        endpoint: PathAndQuery::from_static("/"),
+                        headers: Default::default(),
+                        upgrade_timeout: None,
+                    },
+                    HttpRouteFragment {
+                        host_header: "localhost".into(),
+                        path_prefix: "".into(),
+                        front_name: None,
+                    },
+                ),
+                "test".into(),
+            )
+            .await
+            .expect("upgraded");
+
+        let start = tokio::time::Instant::now();
+        let error = AttestedConnection::connect(websocket, FAKE_WS_CONFIG, "test".into(), |_| {
+            attest::sgx_session::testutil::handshake_from_tests_data()
+        })
+        .await
+        .expect_err("server isn't reading");
+
+        assert_matches!(
+            LookupError::from(error),
+            LookupError::WebSocket(WebSocketServiceError::OperationTimeout)
+        );
+        assert_eq!(start.elapsed(), ENCLAVE_OPERATION_TIMEOUT);
+    }
+
     #[tokio::test]
     async fn websocket_invalid_token_close() {
         let (server, client) = fake_websocket().await;
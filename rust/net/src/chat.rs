@@ -4,22 +4,34 @@
 //
 
 use std::fmt::{Debug, Display};
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
 
 use ::http::uri::PathAndQuery;
 use ::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use futures_util::future::Either;
+use futures_util::SinkExt as _;
 use libsignal_net_infra::connection_manager::MultiRouteConnectionManager;
+use libsignal_net_infra::metrics::ConnectionMetrics;
 use libsignal_net_infra::route::{
     Connector, HttpsTlsRoute, RouteProvider, RouteProviderExt, ThrottlingConnector, TransportRoute,
     UnresolvedHttpsServiceRoute, UnresolvedWebsocketServiceRoute, UsePreconnect, WebSocketRoute,
     WebSocketRouteFragment,
 };
+use libsignal_net_infra::service::{ServiceStatus, ServiceStatusNotifier};
+use libsignal_net_infra::tcp_ssl::ipv6_suspicion::Ipv6Suspicion;
 use libsignal_net_infra::timeouts::ONE_ROUTE_CONNECTION_TIMEOUT;
+use libsignal_net_infra::utils::clock_offset::ServerClockOffset;
+use libsignal_net_infra::utils::rng::RngProvider;
+use libsignal_net_infra::utils::session_affinity::SessionAffinity;
 use libsignal_net_infra::utils::NetworkChangeEvent;
-use libsignal_net_infra::ws::StreamWithResponseHeaders;
+use libsignal_net_infra::ws::{
+    LongPollFallback, LongPollStream, StreamWithResponseHeaders, WithLongPollFallback,
+};
 use libsignal_net_infra::{
-    make_ws_config, AsHttpHeader, Connection, EndpointConnection, IpType, TransportInfo,
+    make_ws_config, AsHttpHeader, ConnectFailure, ConnectResult, Connection, EndpointConnection,
+    IpType, TransportInfo,
 };
 use tokio_tungstenite::WebSocketStream;
 
@@ -27,6 +39,7 @@ use crate::auth::Auth;
 use crate::connect_state::{
     ConnectionResources, DefaultTransportConnector, RouteInfo, WebSocketTransportConnectorFactory,
 };
+use crate::connection_quality::ConnectionQuality;
 use crate::env::{add_user_agent_header, ConnectionConfig, UserAgent};
 use crate::proto;
 
@@ -141,6 +154,7 @@ pub fn endpoint_connection(
     include_fallback: bool,
     enforce_minimum_tls: &EnforceMinimumTls,
     network_change_event: &NetworkChangeEvent,
+    rng: &RngProvider,
 ) -> EndpointConnection<MultiRouteConnectionManager> {
     let connection_config = match enforce_minimum_tls {
         EnforceMinimumTls::No => &connection_config.config_with_permissive_min_tls_version(),
@@ -148,7 +162,7 @@ pub fn endpoint_connection(
     };
     let chat_endpoint = PathAndQuery::from_static(crate::env::constants::WEB_SOCKET_PATH);
     let chat_connection_params = if include_fallback {
-        connection_config.connection_params_with_fallback()
+        connection_config.connection_params_with_fallback(rng)
     } else {
         vec![connection_config.direct_connection_params()]
     };
@@ -162,6 +176,16 @@ pub fn endpoint_connection(
     )
 }
 
+/// The [`WebSocketRouteFragment`] for connecting to the chat server, sending
+/// `headers` with the upgrade request.
+fn ws_route_fragment(headers: HeaderMap) -> WebSocketRouteFragment {
+    make_ws_config(
+        PathAndQuery::from_static(crate::env::constants::WEB_SOCKET_PATH),
+        ONE_ROUTE_CONNECTION_TIMEOUT,
+    )
+    .route_fragment(headers)
+}
+
 /// Information about an established connection.
 #[derive(Clone, Debug)]
 pub struct ConnectionInfo {
@@ -172,6 +196,7 @@ pub struct ConnectionInfo {
 pub struct ChatConnection {
     inner: self::ws2::Chat,
     connection_info: ConnectionInfo,
+    metrics: Option<Arc<dyn ConnectionMetrics>>,
 }
 
 type ChatTransportConnection =
@@ -182,11 +207,17 @@ type ChatTransportConnection =
 /// Parameterized over the type of the transport-level connection for testing.
 #[derive(Debug)]
 pub struct PendingChatConnection<T = ChatTransportConnection> {
-    connection: WebSocketStream<T>,
+    connection: Either<WebSocketStream<T>, LongPollStream<T>>,
     connect_response_headers: http::HeaderMap,
     ws_config: ws2::Config,
     route_info: RouteInfo,
     log_tag: Arc<str>,
+    /// How long it took to establish the connection.
+    connect_duration: Duration,
+    quality: Option<ConnectionQuality>,
+    ipv6_suspicion: Option<Ipv6Suspicion>,
+    metrics: Option<Arc<dyn ConnectionMetrics>>,
+    status: Option<Arc<ServiceStatusNotifier>>,
 }
 
 #[cfg_attr(test, derive(Clone))]
@@ -198,12 +229,17 @@ pub struct AuthenticatedChatHeaders {
 pub type ChatServiceRoute = UnresolvedWebsocketServiceRoute;
 
 impl ChatConnection {
+    /// Connects to the chat service over the first route that works.
+    ///
+    /// With a `long_poll_fallback`, the connection is made with HTTP
+    /// long-polling instead of a websocket whenever the fallback says so.
     pub async fn start_connect_with<TC>(
         connection_resources: ConnectionResources<'_, TC>,
         http_route_provider: impl RouteProvider<Route = UnresolvedHttpsServiceRoute>,
         user_agent: &UserAgent,
         ws_config: self::ws2::Config,
         auth: Option<AuthenticatedChatHeaders>,
+        long_poll_fallback: Option<&LongPollFallback>,
         log_tag: &str,
     ) -> Result<PendingChatConnection, ConnectError>
     where
@@ -218,11 +254,41 @@ impl ChatConnection {
             user_agent,
             ws_config,
             auth,
+            long_poll_fallback,
             log_tag,
         )
         .await
     }
 
+    /// Like [`Self::start_connect_with`], but also reporting the route that was used and how long
+    /// the attempt took, with a failure broken down into a [`ConnectFailure`].
+    pub async fn start_connect_with_result<TC>(
+        connection_resources: ConnectionResources<'_, TC>,
+        http_route_provider: impl RouteProvider<Route = UnresolvedHttpsServiceRoute>,
+        user_agent: &UserAgent,
+        ws_config: self::ws2::Config,
+        auth: Option<AuthenticatedChatHeaders>,
+        long_poll_fallback: Option<&LongPollFallback>,
+        log_tag: &str,
+    ) -> ConnectResult<PendingChatConnection, ConnectError>
+    where
+        TC: WebSocketTransportConnectorFactory<
+            UsePreconnect<TransportRoute>,
+            Connection = ChatTransportConnection,
+        >,
+    {
+        with_connect_result(Self::start_connect_with_transport(
+            connection_resources,
+            http_route_provider,
+            user_agent,
+            ws_config,
+            auth,
+            long_poll_fallback,
+            log_tag,
+        ))
+        .await
+    }
+
     #[cfg_attr(feature = "test-util", visibility::make(pub))]
     async fn start_connect_with_transport<TC>(
         connection_resources: ConnectionResources<'_, TC>,
@@ -230,6 +296,7 @@ impl ChatConnection {
         user_agent: &UserAgent,
         ws_config: self::ws2::Config,
         auth: Option<AuthenticatedChatHeaders>,
+        long_poll_fallback: Option<&LongPollFallback>,
         log_tag: &str,
     ) -> Result<PendingChatConnection<TC::Connection>, ConnectError>
     where
@@ -245,11 +312,7 @@ impl ChatConnection {
                  }| [auth.as_header(), receive_stories.as_header()],
             )
             .chain([user_agent.as_header()]);
-        let ws_fragment = WebSocketRouteFragment {
-            ws_config: Default::default(),
-            endpoint: PathAndQuery::from_static(crate::env::constants::WEB_SOCKET_PATH),
-            headers: HeaderMap::from_iter(headers),
-        };
+        let ws_fragment = ws_route_fragment(HeaderMap::from_iter(headers));
 
         let ws_routes = http_route_provider.map_routes(move |http| WebSocketRoute {
             inner: HttpsTlsRoute {
@@ -263,6 +326,7 @@ impl ChatConnection {
         });
 
         let log_tag: Arc<str> = log_tag.into();
+        let connect_start = tokio::time::Instant::now();
         let (connection, route_info) = connection_resources
             .connect_ws(
                 ws_routes,
@@ -272,24 +336,39 @@ impl ChatConnection {
                 // lets us get connection parallelism at the transport level (which
                 // is useful) while limiting us to one fully established connection
                 // at a time.
-                ThrottlingConnector::new(crate::infra::ws::Stateless, 1),
+                ThrottlingConnector::new(
+                    WithLongPollFallback::new(
+                        crate::infra::ws::Stateless,
+                        long_poll_fallback.cloned(),
+                    ),
+                    1,
+                ),
                 log_tag.clone(),
             )
             .await?;
 
         // It's okay to discard the ThrottlingConnection layer here, because no other routes are
         // still connecting.
-        let StreamWithResponseHeaders {
-            stream,
-            response_headers,
-        } = connection.into_inner();
+        let (connection, connect_response_headers) = match connection.into_inner() {
+            Either::Left(StreamWithResponseHeaders {
+                stream,
+                response_headers,
+            }) => (Either::Left(stream), response_headers),
+            // There's no upgrade response to take headers from.
+            Either::Right(stream) => (Either::Right(stream), HeaderMap::new()),
+        };
 
         Ok(PendingChatConnection {
-            connection: stream,
-            connect_response_headers: response_headers,
+            connection,
+            connect_response_headers,
             route_info,
             ws_config,
             log_tag,
+            connect_duration: connect_start.elapsed(),
+            quality: None,
+            ipv6_suspicion: None,
+            metrics: None,
+            status: None,
         })
     }
 
@@ -304,12 +383,47 @@ impl ChatConnection {
             ws_config,
             route_info,
             log_tag,
+            connect_duration,
+            quality,
+            ipv6_suspicion,
+            metrics,
+            status,
         } = pending;
+        if let Some(quality) = &quality {
+            quality.record_handshake(connect_duration);
+        }
+        let listener: ws2::EventListener = match ipv6_suspicion {
+            None => listener,
+            Some(ipv6_suspicion) => {
+                let remote_address = route_info.immediate_target();
+                let mut listener = listener;
+                Box::new(move |event| {
+                    if matches!(event, ws2::ListenerEvent::Finished(Err(_))) {
+                        ipv6_suspicion.report_connection_lost(remote_address);
+                    }
+                    listener(event)
+                })
+            }
+        };
+        let listener: ws2::EventListener = match status {
+            None => listener,
+            Some(status) => {
+                status.set_status(ServiceStatus::Active);
+                let mut listener = listener;
+                Box::new(move |event| {
+                    if matches!(event, ws2::ListenerEvent::Finished(_)) {
+                        status.set_status(ServiceStatus::Inactive);
+                    }
+                    listener(event)
+                })
+            }
+        };
         Self {
             connection_info: ConnectionInfo {
                 route_info,
                 transport_info: connection.transport_info(),
             },
+            metrics,
             inner: ws2::Chat::new(
                 tokio_runtime,
                 connection,
@@ -317,27 +431,112 @@ impl ChatConnection {
                 ws_config,
                 log_tag,
                 listener,
+                quality,
             ),
         }
     }
 
     pub async fn send(&self, msg: Request, timeout: Duration) -> Result<Response, SendError> {
+        let sent_at = tokio::time::Instant::now();
         let send_result = tokio::time::timeout(timeout, self.inner.send(msg))
             .await
             .map_err(|_elapsed| SendError::RequestTimedOut)?;
-        Ok(send_result?)
+        let response = send_result?;
+        if let Some(metrics) = &self.metrics {
+            metrics.round_trip(sent_at.elapsed());
+        }
+        Ok(response)
     }
 
     pub async fn disconnect(&self) {
         self.inner.disconnect().await
     }
 
+    /// See [`ws2::Chat::pause_reads`].
+    pub fn pause_reads(&self) {
+        self.inner.pause_reads()
+    }
+
+    /// See [`ws2::Chat::resume_reads`].
+    pub fn resume_reads(&self) {
+        self.inner.resume_reads()
+    }
+
+    /// See [`ws2::Chat::drain`].
+    pub async fn drain(&self, deadline: tokio::time::Instant) {
+        self.inner.drain(deadline).await
+    }
+
+    /// See [`ws2::Chat::check_alive`].
+    pub async fn check_alive(&self, timeout: Duration) -> bool {
+        self.inner.check_alive(timeout).await
+    }
+
     pub fn connection_info(&self) -> &ConnectionInfo {
         &self.connection_info
     }
 }
 
 impl PendingChatConnection {
+    /// Reports the time taken to connect, and once the connection is
+    /// finished, its keep-alive round-trip times and disconnects, to
+    /// `quality`.
+    pub fn with_quality_tracking(self, quality: ConnectionQuality) -> Self {
+        Self {
+            quality: Some(quality),
+            ..self
+        }
+    }
+
+    /// Reports the connection to `ipv6_suspicion` if it's lost, rather than
+    /// closed by either end.
+    ///
+    /// This should be the same suspicion the [`ConnectState`] used for
+    /// connecting has, so that the next connection attempt can tell whether
+    /// IPv6 came back.
+    ///
+    /// [`ConnectState`]: crate::connect_state::ConnectState
+    pub fn with_ipv6_suspicion(self, ipv6_suspicion: Ipv6Suspicion) -> Self {
+        Self {
+            ipv6_suspicion: Some(ipv6_suspicion),
+            ..self
+        }
+    }
+
+    /// Publishes the connection to `status` as [`ServiceStatus::Active`] once
+    /// it's finished, and as [`ServiceStatus::Inactive`] once it's
+    /// disconnected, by either end or by being lost.
+    pub fn with_status_notifier(self, status: Arc<ServiceStatusNotifier>) -> Self {
+        Self {
+            status: Some(status),
+            ..self
+        }
+    }
+
+    /// Reports the round-trip time of each request sent once the connection
+    /// is finished to `metrics`.
+    pub fn with_metrics(self, metrics: Arc<dyn ConnectionMetrics>) -> Self {
+        Self {
+            metrics: Some(metrics),
+            ..self
+        }
+    }
+
+    /// Feeds the `Date` header from the server's response to the websocket
+    /// upgrade to `clock_offset`.
+    pub fn observe_server_time(&self, clock_offset: &ServerClockOffset) {
+        clock_offset.observe_response_headers(&self.connect_response_headers);
+    }
+
+    /// Records the session affinity token, if any, from the server's response
+    /// to the websocket upgrade.
+    ///
+    /// Pass [`SessionAffinity::decorator`] to the next connection attempt to
+    /// get back to the same backend.
+    pub fn observe_session_affinity(&self, session_affinity: &SessionAffinity) {
+        session_affinity.observe_response_headers(&self.connect_response_headers);
+    }
+
     pub fn connection_info(&self) -> ConnectionInfo {
         ConnectionInfo {
             route_info: self.route_info.clone(),
@@ -346,7 +545,11 @@ impl PendingChatConnection {
     }
 
     pub async fn disconnect(&mut self) {
-        if let Err(error) = self.connection.close(None).await {
+        let closed = match &mut self.connection {
+            Either::Left(websocket) => websocket.close(None).await,
+            Either::Right(long_poll) => long_poll.close().await,
+        };
+        if let Err(error) = closed {
             log::error!(
                 "[{}] pending chat connection disconnect failed with {error}",
                 &self.log_tag
@@ -355,6 +558,22 @@ impl PendingChatConnection {
     }
 }
 
+/// Times `connect`, and describes how it went.
+async fn with_connect_result<T>(
+    connect: impl Future<Output = Result<PendingChatConnection<T>, ConnectError>>,
+) -> ConnectResult<PendingChatConnection<T>, ConnectError> {
+    let start = tokio::time::Instant::now();
+    let outcome = connect.await;
+    ConnectResult {
+        route: outcome
+            .as_ref()
+            .ok()
+            .map(|pending| pending.route_info.to_string()),
+        elapsed: start.elapsed(),
+        outcome: outcome.map_err(ConnectFailure::from),
+    }
+}
+
 impl Display for ConnectionInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let Self {
@@ -406,7 +625,7 @@ pub mod test_support {
 
         let connect = ConnectState::new_with_transport_connector(
             SUGGESTED_CONNECT_CONFIG,
-            PreconnectingFactory::new(DefaultConnectorFactory, Duration::ZERO),
+            PreconnectingFactory::new(DefaultConnectorFactory::default(), Duration::ZERO),
         );
         let user_agent = UserAgent::with_libsignal_version("test_simple_chat_connection");
 
@@ -425,6 +644,7 @@ pub mod test_support {
                 .connect
                 .confirmation_header_name
                 .map(HeaderName::from_static),
+            cancellation: None,
         };
 
         let pending = ChatConnection::start_connect_with(
@@ -433,6 +653,7 @@ pub mod test_support {
             &user_agent,
             ws_config,
             None,
+            None,
             "test",
         )
         .await?;
@@ -458,7 +679,7 @@ pub(crate) mod test {
     use libsignal_net_infra::certs::RootCertificates;
     use libsignal_net_infra::dns::lookup_result::LookupResult;
     use libsignal_net_infra::dns::DnsResolver;
-    use libsignal_net_infra::errors::{RetryLater, TransportConnectError};
+    use libsignal_net_infra::errors::{ConnectErrorCategory, RetryLater, TransportConnectError};
     use libsignal_net_infra::host::Host;
     use libsignal_net_infra::route::testutils::ConnectFn;
     use libsignal_net_infra::route::{
@@ -474,6 +695,18 @@ pub(crate) mod test {
     use super::*;
     use crate::connect_state::{ConnectState, SUGGESTED_CONNECT_CONFIG};
 
+    #[test]
+    fn ws_route_fragment_has_upgrade_timeout() {
+        let headers = HeaderMap::from_iter([UserAgent::with_libsignal_version("test").as_header()]);
+        let fragment = ws_route_fragment(headers.clone());
+        assert_eq!(
+            fragment.upgrade_timeout,
+            Some(libsignal_net_infra::timeouts::WS_UPGRADE_TIMEOUT)
+        );
+        assert_eq!(fragment.endpoint, crate::env::constants::WEB_SOCKET_PATH);
+        assert_eq!(fragment.headers, headers);
+    }
+
     #[test]
     fn proto_into_response_works_with_valid_data() {
         let expected_body = b"content";
@@ -618,17 +851,17 @@ pub(crate) mod test {
     // It's easier to use this with test_case in string form.
     const CONFIRMATION_HEADER: &str = "x-really-signal";
 
-    #[test_case(403, &[] => matches ConnectError::AllAttemptsFailed)]
-    #[test_case(403, &[(CONFIRMATION_HEADER, "1")] => matches ConnectError::DeviceDeregistered)]
-    #[test_case(499, &[(CONFIRMATION_HEADER, "1")] => matches ConnectError::AppExpired)]
-    #[test_case(429, &[(CONFIRMATION_HEADER, "1"), ("retry-after", "20")] => matches ConnectError::RetryLater(RetryLater { retry_after_seconds: 20 }))]
-    #[test_case(500, &[(CONFIRMATION_HEADER, "1"), ("retry-after", "20")] => matches ConnectError::RetryLater(RetryLater { retry_after_seconds: 20 }))]
-    #[test_case(429, &[("retry-after", "20")] => matches ConnectError::AllAttemptsFailed)]
+    #[test_case(403, &[] => matches (ConnectError::AllAttemptsFailed(_), ConnectErrorCategory::Network, true))]
+    #[test_case(403, &[(CONFIRMATION_HEADER, "1")] => matches (ConnectError::DeviceDeregistered, ConnectErrorCategory::Server, false))]
+    #[test_case(499, &[(CONFIRMATION_HEADER, "1")] => matches (ConnectError::AppExpired, ConnectErrorCategory::Server, false))]
+    #[test_case(429, &[(CONFIRMATION_HEADER, "1"), ("retry-after", "20")] => matches (ConnectError::RetryLater(RetryLater { retry_after_seconds: 20 }), ConnectErrorCategory::Server, true))]
+    #[test_case(500, &[(CONFIRMATION_HEADER, "1"), ("retry-after", "20")] => matches (ConnectError::RetryLater(RetryLater { retry_after_seconds: 20 }), ConnectErrorCategory::Server, true))]
+    #[test_case(429, &[("retry-after", "20")] => matches (ConnectError::AllAttemptsFailed(_), ConnectErrorCategory::Network, true))]
     #[test_log::test(tokio::test(start_paused = true))]
     async fn html_status_tests(
         status: u16,
         headers: &'static [(&'static str, &'static str)],
-    ) -> ConnectError {
+    ) -> (ConnectError, ConnectErrorCategory, bool) {
         let (client, mut server) = tokio::io::duplex(1024);
 
         let server_task = tokio::spawn(async move {
@@ -671,9 +904,10 @@ pub(crate) mod test {
             )])),
             network_change_event: &no_network_change_events(),
             confirmation_header_name: Some(HeaderName::from_static(CONFIRMATION_HEADER)),
+            cancellation: None,
         };
 
-        let err = ChatConnection::start_connect_with_transport(
+        let result = with_connect_result(ChatConnection::start_connect_with_transport(
             connection_resources,
             vec![HttpsTlsRoute {
                 fragment: HttpRouteFragment {
@@ -702,14 +936,21 @@ pub(crate) mod test {
                 initial_request_id: 0,
             },
             None,
+            None,
             "fake chat",
-        )
-        .await
-        .expect_err("should fail to connect");
+        ))
+        .await;
 
         server_task.await.expect("clean exit");
 
-        err
+        // No route was settled on.
+        assert_eq!(result.route, None);
+        let ConnectFailure {
+            category,
+            retryable,
+            error,
+        } = result.outcome.expect_err("should fail to connect");
+        (error, category, retryable)
     }
 
     #[test_log::test(tokio::test(start_paused = true))]
@@ -763,6 +1004,7 @@ pub(crate) mod test {
             dns_resolver: &dns_resolver,
             network_change_event: &network_change_event,
             confirmation_header_name: Some(HeaderName::from_static(CONFIRMATION_HEADER)),
+            cancellation: None,
         };
 
         make_connection_resources()
@@ -799,12 +1041,13 @@ pub(crate) mod test {
                 initial_request_id: 0,
             },
             Some(auth_headers.clone()),
+            None,
             "fake chat",
         )
         .await
         .expect_err("should fail to connect");
 
-        assert_matches!(err, ConnectError::AllAttemptsFailed);
+        assert_matches!(err, ConnectError::AllAttemptsFailed(_));
         // 1 preconnect that subsequently fails, 1 IPv4 follow-up connection that also fails.
         assert_eq!(number_of_times_called.load(atomic::Ordering::SeqCst), 2);
 
@@ -819,12 +1062,13 @@ pub(crate) mod test {
                 initial_request_id: 0,
             },
             Some(auth_headers),
+            None,
             "fake chat",
         )
         .await
         .expect_err("should fail to connect");
 
-        assert_matches!(err, ConnectError::AllAttemptsFailed);
+        assert_matches!(err, ConnectError::AllAttemptsFailed(_));
         assert_eq!(number_of_times_called.load(atomic::Ordering::SeqCst), 4);
     }
 }
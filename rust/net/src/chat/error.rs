@@ -3,11 +3,13 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
-use libsignal_net_infra::errors::{LogSafeDisplay, RetryLater, TransportConnectError};
-use libsignal_net_infra::extract_retry_later;
-use libsignal_net_infra::route::ConnectError as RouteConnectError;
+use libsignal_net_infra::errors::{
+    ConnectErrorCategory, LogSafeDisplay, RetryLater, TransportConnectError, UserHint,
+};
+use libsignal_net_infra::route::{AttemptFailures, ConnectError as RouteConnectError};
 use libsignal_net_infra::timeouts::TimeoutOr;
 use libsignal_net_infra::ws::{WebSocketConnectError, WebSocketServiceError};
+use libsignal_net_infra::{extract_retry_later, ConnectFailure};
 
 use crate::ws::WebSocketServiceConnectError;
 
@@ -18,6 +20,8 @@ pub enum SendError {
     RequestTimedOut,
     /// connection is already closed
     Disconnected,
+    /// connection was lost while the request was in flight, so it may or may not have been processed
+    RequestOutcomeUnknown,
     /// the server explicitly disconnected us because we connected elsewhere with the same credentials
     ConnectedElsewhere,
     /// the server explicitly disconnected us for some reason other than that we connected elsewhere
@@ -28,16 +32,34 @@ pub enum SendError {
     IncomingDataInvalid,
     /// request object must contain only ASCII text as header names and values.
     RequestHasInvalidHeader,
+    /// connection is draining and not accepting new requests
+    Draining,
 }
 impl LogSafeDisplay for SendError where WebSocketServiceError: LogSafeDisplay {}
 
+impl SendError {
+    pub fn user_hint(&self) -> UserHint {
+        match self {
+            Self::RequestTimedOut | Self::Disconnected | Self::RequestOutcomeUnknown => {
+                UserHint::CheckConnection
+            }
+            Self::WebSocket(e) => e.user_hint(),
+            // The app handles these itself, by reconnecting or not.
+            Self::ConnectedElsewhere | Self::ConnectionInvalidated | Self::Draining => {
+                UserHint::NoAction
+            }
+            Self::IncomingDataInvalid | Self::RequestHasInvalidHeader => UserHint::NoAction,
+        }
+    }
+}
+
 /// Error that can occur when connecting to the Chat service.
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum ConnectError {
     /// timed out while establishing a connection
     Timeout,
-    /// all connect attempts failed
-    AllAttemptsFailed,
+    /// all connect attempts failed{0}
+    AllAttemptsFailed(AttemptFailures),
     /// the connection information was invalid
     InvalidConnectionConfiguration,
     /// websocket error: {0}
@@ -48,17 +70,69 @@ pub enum ConnectError {
     AppExpired,
     /// device was deregistered
     DeviceDeregistered,
+    /// the connection attempt was cancelled
+    Cancelled,
 }
 impl LogSafeDisplay for ConnectError {}
 
+impl ConnectError {
+    pub fn user_hint(&self) -> UserHint {
+        match self {
+            Self::Timeout | Self::AllAttemptsFailed(_) => UserHint::CheckConnection,
+            Self::InvalidConnectionConfiguration | Self::Cancelled => UserHint::NoAction,
+            Self::WebSocket(e) => e.user_hint(),
+            Self::RetryLater(_) => UserHint::ServerUnavailable,
+            Self::AppExpired => UserHint::UpdateApp,
+            Self::DeviceDeregistered => UserHint::Reregister,
+        }
+    }
+
+    pub fn category(&self) -> ConnectErrorCategory {
+        match self {
+            Self::Timeout | Self::AllAttemptsFailed(_) => ConnectErrorCategory::Network,
+            Self::InvalidConnectionConfiguration => ConnectErrorCategory::Configuration,
+            Self::WebSocket(e) => e.category(),
+            Self::RetryLater(_) | Self::AppExpired | Self::DeviceDeregistered => {
+                ConnectErrorCategory::Server
+            }
+            Self::Cancelled => ConnectErrorCategory::Aborted,
+        }
+    }
+
+    /// Whether connecting again later might succeed.
+    ///
+    /// A rate limit is retryable once it's over, but an expired app or a
+    /// deregistered device isn't.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout | Self::AllAttemptsFailed(_) | Self::RetryLater(_) => true,
+            Self::WebSocket(e) => e.is_retryable(),
+            Self::InvalidConnectionConfiguration
+            | Self::AppExpired
+            | Self::DeviceDeregistered
+            | Self::Cancelled => false,
+        }
+    }
+}
+
+impl From<ConnectError> for ConnectFailure<ConnectError> {
+    fn from(error: ConnectError) -> Self {
+        Self {
+            category: error.category(),
+            retryable: error.is_retryable(),
+            error,
+        }
+    }
+}
+
 impl<T: Into<ConnectError>> From<TimeoutOr<RouteConnectError<T>>> for ConnectError {
     fn from(e: TimeoutOr<RouteConnectError<T>>) -> Self {
         match e {
             TimeoutOr::Other(RouteConnectError::NoResolvedRoutes) => {
                 ConnectError::InvalidConnectionConfiguration
             }
-            TimeoutOr::Other(RouteConnectError::AllAttemptsFailed) => {
-                ConnectError::AllAttemptsFailed
+            TimeoutOr::Other(RouteConnectError::AllAttemptsFailed(failures)) => {
+                ConnectError::AllAttemptsFailed(failures)
             }
             TimeoutOr::Other(RouteConnectError::FatalConnect(err)) => err.into(),
             TimeoutOr::Timeout {
@@ -71,6 +145,10 @@ impl<T: Into<ConnectError>> From<TimeoutOr<RouteConnectError<T>>> for ConnectErr
 impl From<WebSocketServiceConnectError> for ConnectError {
     fn from(e: WebSocketServiceConnectError) -> Self {
         match e {
+            WebSocketServiceConnectError::Connect(
+                WebSocketConnectError::Transport(TransportConnectError::Cancelled),
+                _,
+            ) => Self::Cancelled,
             WebSocketServiceConnectError::Connect(e, _) => Self::WebSocket(e),
             WebSocketServiceConnectError::RejectedByServer {
                 response,
@@ -104,6 +182,104 @@ impl From<WebSocketServiceConnectError> for ConnectError {
 /// particular HTTP responses.
 impl From<TransportConnectError> for ConnectError {
     fn from(e: TransportConnectError) -> Self {
-        Self::WebSocket(WebSocketConnectError::Transport(e))
+        match e {
+            TransportConnectError::Cancelled => Self::Cancelled,
+            e => Self::WebSocket(WebSocketConnectError::Transport(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use libsignal_net_infra::route::FailedAttempt;
+    use test_case::test_case;
+
+    use super::*;
+
+    fn rejected_with_status(status: u16) -> ConnectError {
+        let response = http::Response::builder()
+            .status(status)
+            .body(None)
+            .expect("valid response");
+        ConnectError::WebSocket(WebSocketConnectError::WebSocketError(
+            tungstenite::Error::Http(response),
+        ))
+    }
+
+    #[test_case(TransportConnectError::DnsError.into() => UserHint::CheckConnection)]
+    #[test_case(ConnectError::Timeout => UserHint::CheckConnection; "timeout")]
+    #[test_case(
+        TransportConnectError::SslFailedHandshake(
+            libsignal_net_infra::errors::FailedHandshakeReason::TIMED_OUT
+        ).into() => UserHint::CheckConnection;
+        "TLS handshake timeout"
+    )]
+    #[test_case(rejected_with_status(511) => UserHint::SignInToNetwork; "network authentication required")]
+    #[test_case(rejected_with_status(302) => UserHint::SignInToNetwork; "redirect")]
+    #[test_case(TransportConnectError::CertificateChanged.into() => UserHint::SignInToNetwork)]
+    #[test_case(rejected_with_status(503) => UserHint::ServerUnavailable)]
+    #[test_case(ConnectError::RetryLater(RetryLater { retry_after_seconds: 5 }) => UserHint::ServerUnavailable)]
+    #[test_case(ConnectError::AppExpired => UserHint::UpdateApp)]
+    #[test_case(ConnectError::DeviceDeregistered => UserHint::Reregister)]
+    #[test_case(TransportConnectError::Cancelled.into() => UserHint::NoAction)]
+    #[test_case(TransportConnectError::TooManyConnections.into() => UserHint::NoAction)]
+    #[test_case(ConnectError::Cancelled => UserHint::NoAction; "cancelled")]
+    #[test_case(ConnectError::InvalidConnectionConfiguration => UserHint::NoAction)]
+    fn connect_error_user_hint(error: ConnectError) -> UserHint {
+        error.user_hint()
+    }
+
+    #[test_case(TransportConnectError::DnsError.into() => (ConnectErrorCategory::Dns, true))]
+    #[test_case(TransportConnectError::CertError.into() => (ConnectErrorCategory::Certificate, false))]
+    #[test_case(TransportConnectError::TlsRenegotiationRejected.into() => (ConnectErrorCategory::Tls, false))]
+    #[test_case(ConnectError::AllAttemptsFailed(AttemptFailures::default()) => (ConnectErrorCategory::Network, true))]
+    #[test_case(rejected_with_status(503) => (ConnectErrorCategory::Server, true))]
+    #[test_case(ConnectError::RetryLater(RetryLater { retry_after_seconds: 5 }) => (ConnectErrorCategory::Server, true))]
+    #[test_case(ConnectError::AppExpired => (ConnectErrorCategory::Server, false))]
+    #[test_case(ConnectError::Cancelled => (ConnectErrorCategory::Aborted, false))]
+    #[test_case(ConnectError::InvalidConnectionConfiguration => (ConnectErrorCategory::Configuration, false))]
+    fn connect_error_category_and_retryability(
+        error: ConnectError,
+    ) -> (ConnectErrorCategory, bool) {
+        let ConnectFailure {
+            category,
+            retryable,
+            error: _,
+        } = ConnectFailure::<ConnectError>::from(error);
+        (category, retryable)
+    }
+
+    #[test]
+    fn all_attempts_failed_lists_each_attempt() {
+        let error = ConnectError::from(TimeoutOr::Other(
+            RouteConnectError::<ConnectError>::AllAttemptsFailed(AttemptFailures(vec![
+                FailedAttempt {
+                    route: "chat.signal.org:443 (direct)".to_owned(),
+                    error: "TCP connection failed".to_owned(),
+                },
+                FailedAttempt {
+                    route: "chat.signal.org:443 fronted by proxyf".to_owned(),
+                    error: "TLS handshake failed".to_owned(),
+                },
+            ])),
+        ));
+        assert_eq!(
+            error.to_string(),
+            "all connect attempts failed: chat.signal.org:443 (direct): TCP connection failed; \
+             chat.signal.org:443 fronted by proxyf: TLS handshake failed"
+        );
+
+        assert_eq!(
+            ConnectError::AllAttemptsFailed(AttemptFailures::default()).to_string(),
+            "all connect attempts failed"
+        );
+    }
+
+    #[test_case(SendError::RequestTimedOut => UserHint::CheckConnection)]
+    #[test_case(SendError::WebSocket(WebSocketServiceError::ChannelIdleTooLong) => UserHint::CheckConnection)]
+    #[test_case(SendError::Draining => UserHint::NoAction)]
+    #[test_case(SendError::ConnectedElsewhere => UserHint::NoAction)]
+    fn send_error_user_hint(error: SendError) -> UserHint {
+        error.user_hint()
     }
 }
@@ -87,8 +87,10 @@ impl ChatConnection {
                 config,
                 log_tag,
                 listener,
+                None,
             ),
             connection_info,
+            metrics: None,
         };
         (chat, remote)
     }
@@ -4,10 +4,14 @@
 //
 
 use libsignal_net_infra::ws::WebSocketServiceError;
+use libsignal_net_infra::ws2::NextEventError;
 use libsignal_protocol::Timestamp;
+use tungstenite::protocol::frame::coding::CloseCode;
 
 use crate::chat::{ws2, RequestProto, SendError};
-use crate::env::TIMESTAMP_HEADER_NAME;
+use crate::env::{
+    CONNECTED_ELSEWHERE_CLOSE_CODE, CONNECTION_INVALIDATED_CLOSE_CODE, TIMESTAMP_HEADER_NAME,
+};
 
 pub type ResponseEnvelopeSender =
     Box<dyn FnOnce(http::StatusCode) -> Result<(), SendError> + Send + Sync>;
@@ -27,9 +31,57 @@ pub enum ServerEvent {
 #[derive(Debug, derive_more::From)]
 pub enum DisconnectCause {
     LocalDisconnect,
+    /// The server closed the connection with a close frame.
+    ///
+    /// Close codes that have a dedicated [`SendError`] (like
+    /// [`SendError::ConnectedElsewhere`]) are reported as [`Self::Error`]
+    /// instead.
+    RemoteClose {
+        code: u16,
+        reason: String,
+    },
     Error(#[from] SendError),
 }
 
+impl DisconnectCause {
+    /// Whether it's worth connecting again after this disconnect.
+    ///
+    /// This is `false` when the client chose to disconnect, and when the
+    /// server indicated that reconnecting won't help, either with one of our
+    /// own close codes or with a standard code that points at a problem with
+    /// what the client sent.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::LocalDisconnect => false,
+            Self::RemoteClose { code, reason: _ } => match CloseCode::from(*code) {
+                CloseCode::Protocol
+                | CloseCode::Unsupported
+                | CloseCode::Invalid
+                | CloseCode::Policy
+                | CloseCode::Size
+                | CloseCode::Extension
+                | CloseCode::Library(
+                    CONNECTED_ELSEWHERE_CLOSE_CODE | CONNECTION_INVALIDATED_CLOSE_CODE,
+                ) => false,
+                _ => true,
+            },
+            Self::Error(SendError::ConnectedElsewhere | SendError::ConnectionInvalidated) => false,
+            Self::Error(_) => true,
+        }
+    }
+
+    /// Converts to the error reported to the app, if there is one.
+    pub fn into_error(self) -> Option<SendError> {
+        match self {
+            Self::LocalDisconnect => None,
+            Self::RemoteClose { .. } => {
+                Some(SendError::WebSocket(WebSocketServiceError::ChannelClosed))
+            }
+            Self::Error(error) => Some(error),
+        }
+    }
+}
+
 impl std::fmt::Debug for ServerEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -85,6 +137,20 @@ impl TryFrom<ws2::ListenerEvent> for ServerEvent {
                 Err(ws2::FinishError::Unknown) => DisconnectCause::Error(SendError::WebSocket(
                     WebSocketServiceError::Other("unexpected exit"),
                 )),
+                Err(ws2::FinishError::Error(ws2::TaskExitError::WebsocketError(
+                    NextEventError::AbnormalServerClose { code, reason },
+                ))) if !matches!(
+                    code,
+                    CloseCode::Library(
+                        CONNECTED_ELSEWHERE_CLOSE_CODE | CONNECTION_INVALIDATED_CLOSE_CODE
+                    )
+                ) =>
+                {
+                    DisconnectCause::RemoteClose {
+                        code: code.into(),
+                        reason,
+                    }
+                }
                 Err(ws2::FinishError::Error(e)) => DisconnectCause::Error(e.into()),
             })),
         }
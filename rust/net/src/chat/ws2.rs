@@ -9,6 +9,7 @@ use std::future::Future;
 use std::io::ErrorKind as IoErrorKind;
 use std::panic::AssertUnwindSafe;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use futures_util::{pin_mut, Stream, StreamExt as _};
@@ -20,18 +21,19 @@ pub use libsignal_net_infra::ws2::FinishReason;
 use libsignal_net_infra::ws2::Outcome;
 use pin_project::pin_project;
 use prost::Message as _;
-use tokio::sync::{mpsc, oneshot, Mutex as TokioMutex};
+use tokio::sync::{mpsc, oneshot, watch, Mutex as TokioMutex};
 use tokio::task::JoinHandle;
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
 use tungstenite::protocol::frame::coding::CloseCode;
 
 use crate::chat::{ChatMessageType, MessageProto, Request, RequestProto, Response, ResponseProto};
+use crate::connection_quality::ConnectionQuality;
 use crate::env::{
     ALERT_HEADER_NAME, CONNECTED_ELSEWHERE_CLOSE_CODE, CONNECTION_INVALIDATED_CLOSE_CODE,
 };
 use crate::infra::ws::TextOrBinary;
-use crate::infra::ws2::{MessageEvent, NextEventError, TungsteniteSendError};
+use crate::infra::ws2::{LivenessProbe, MessageEvent, NextEventError, TungsteniteSendError};
 
 /// Chat service avilable via a connected websocket.
 ///
@@ -49,6 +51,18 @@ pub struct Chat {
     /// points. If it were a regular [`Mutex`] the futures produced by methods
     /// on `Chat` would not be `Send`.
     state: TokioMutex<TaskState>,
+
+    /// Whether the backing task should stop reading from the websocket.
+    reads_paused: watch::Sender<bool>,
+
+    /// Requests that the backing task check whether the server is responsive.
+    liveness_probes: mpsc::UnboundedSender<LivenessProbe>,
+
+    /// Set by [`Chat::drain`] to turn away new requests.
+    draining: AtomicBool,
+
+    /// The number of [`Chat::send`] calls that haven't finished yet.
+    sends_in_flight: watch::Sender<usize>,
 }
 
 /// Instantiation-time configuration for a [`Chat`] instance.
@@ -100,6 +114,9 @@ pub enum ListenerEvent {
 pub enum SendError {
     /// the chat service is no longer connected
     Disconnected(DisconnectedReason),
+    /// the connection ended after the request was sent but before a response
+    /// arrived, so the server may or may not have processed it
+    Ambiguous(DisconnectedReason),
     /// an OS-level I/O error occurred
     Io(IoErrorKind),
     /// the message is larger than the configured limit
@@ -110,6 +127,8 @@ pub enum SendError {
     InvalidResponse,
     /// the request was invalid
     InvalidRequest(InvalidRequestError),
+    /// the connection is draining and not accepting new requests
+    Draining,
 }
 
 #[derive(Debug)]
@@ -172,6 +191,7 @@ impl Chat {
         config: Config,
         log_tag: Arc<str>,
         mut listener: EventListener,
+        quality: Option<ConnectionQuality>,
     ) -> Self
     where
         T: WebSocketStreamLike + Send + 'static,
@@ -198,6 +218,7 @@ impl Chat {
             initial_request_id,
             log_tag,
             listener,
+            quality,
             tokio_runtime,
         )
     }
@@ -222,7 +243,23 @@ impl Chat {
     /// If the request can't be sent or the response isn't received, this
     /// returns an error.
     pub async fn send(&self, request: Request) -> Result<Response, SendError> {
-        let Self { state } = self;
+        let Self {
+            state,
+            reads_paused: _,
+            liveness_probes: _,
+            draining,
+            sends_in_flight,
+        } = self;
+
+        // Count the send before checking for draining so that `drain` can't
+        // miss it.
+        sends_in_flight.send_modify(|count| *count += 1);
+        let _in_flight = scopeguard::guard((), |()| {
+            sends_in_flight.send_modify(|count| *count -= 1);
+        });
+        if draining.load(Ordering::SeqCst) {
+            return Err(SendError::Draining);
+        }
 
         let Request {
             method,
@@ -277,6 +314,74 @@ impl Chat {
         *guard = new_state
     }
 
+    /// Stops accepting new requests, then disconnects once the ones in flight
+    /// have finished.
+    ///
+    /// New calls to [`Self::send`] fail with [`SendError::Draining`] as soon
+    /// as this is called. Requests already sent are given until `deadline` to
+    /// get their responses; any still waiting after that fail as if the
+    /// connection had been lost. This returns once the disconnect has been
+    /// requested.
+    pub async fn drain(&self, deadline: Instant) {
+        self.draining.store(true, Ordering::SeqCst);
+
+        let mut sends_in_flight = self.sends_in_flight.subscribe();
+        if tokio::time::timeout_at(deadline, sends_in_flight.wait_for(|count| *count == 0))
+            .await
+            .is_err()
+        {
+            log::info!(
+                "disconnecting with {} requests still in flight after draining",
+                *sends_in_flight.borrow()
+            );
+        }
+
+        self.disconnect().await
+    }
+
+    /// Stops reading incoming frames from the server until
+    /// [`Self::resume_reads`] is called.
+    ///
+    /// This lets the server's writes back up (eventually blocking them)
+    /// instead of buffering incoming messages here. Keep-alive pings are
+    /// still sent while paused, and the connection isn't timed out for
+    /// failing to hear from the server. Responses to requests sent while
+    /// paused won't arrive until reads resume.
+    pub fn pause_reads(&self) {
+        self.reads_paused.send_replace(true);
+    }
+
+    /// Resumes reading from the server after [`Self::pause_reads`].
+    pub fn resume_reads(&self) {
+        self.reads_paused.send_replace(false);
+    }
+
+    /// Checks whether the server is still responsive.
+    ///
+    /// This sends a ping right away and waits up to `timeout` to hear back.
+    /// It's meant for when a half-open connection is suspected, like when a
+    /// request hasn't been answered for a while, since a dead connection is
+    /// otherwise only noticed after the remote idle timeout. If the server
+    /// doesn't answer in time, the connection is closed, and the listener
+    /// gets a [`ListenerEvent::Finished`] error so that it can reconnect.
+    ///
+    /// Returns `false` if the connection was already closed.
+    pub async fn check_alive(&self, timeout: Duration) -> bool {
+        if !self.is_connected().await {
+            return false;
+        }
+        let (on_alive, alive) = oneshot::channel();
+        if self
+            .liveness_probes
+            .send(LivenessProbe { timeout, on_alive })
+            .is_err()
+        {
+            // The task exited since the check above.
+            return false;
+        }
+        alive.await.is_ok()
+    }
+
     /// Returns `true` if the websocket is known to be connected.
     ///
     /// If this returns `false`, the websocket is either disconnected or in the
@@ -315,6 +420,7 @@ impl Chat {
         initial_request_id: u64,
         log_tag: Arc<str>,
         listener: EventListener,
+        quality: Option<ConnectionQuality>,
         tokio_runtime: tokio::runtime::Handle,
     ) -> Self {
         let (request_tx, request_rx) = mpsc::channel(1);
@@ -346,14 +452,22 @@ impl Chat {
             (message, OutgoingMeta::ResponseToIncoming)
         });
 
+        let (reads_paused, reads_paused_rx) = watch::channel(false);
+        let (liveness_probes, liveness_probes_rx) = mpsc::unbounded_channel();
         let inner_connection = into_inner_connection.into_inner_connection(
             tokio_stream::StreamExt::merge(request_rx, response_rx),
+            reads_paused_rx,
+            liveness_probes_rx,
             log_tag.clone(),
         );
 
         let connection = ConnectionImpl {
             inner: inner_connection,
             requests_in_flight,
+            quality: quality.map(|quality| QualityTracking {
+                quality,
+                ping_sent_at: None,
+            }),
         };
 
         let task = tokio_runtime.spawn(spawned_task_body(
@@ -370,6 +484,10 @@ impl Chat {
 
         Self {
             state: TokioMutex::new(state),
+            reads_paused,
+            liveness_probes,
+            draining: AtomicBool::new(false),
+            sends_in_flight: watch::channel(0).0,
         }
     }
 }
@@ -436,6 +554,8 @@ enum TaskSendError {
     StreamSendFailed(TungsteniteSendError),
     /// received an invalid response to request
     InvalidResponse,
+    /// the connection ended while the request was in flight
+    InFlightAtDisconnect,
 }
 
 #[derive(Debug)]
@@ -544,6 +664,40 @@ struct ConnectionImpl<I> {
     #[pin]
     inner: I,
     requests_in_flight: InFlightRequests,
+    quality: Option<QualityTracking>,
+}
+
+/// Feeds keep-alive round-trip times and disconnects to a
+/// [`ConnectionQuality`].
+struct QualityTracking {
+    quality: ConnectionQuality,
+    /// When the ping currently awaiting an answer was sent.
+    ping_sent_at: Option<tokio::time::Instant>,
+}
+
+impl QualityTracking {
+    fn observe<C, F>(&mut self, event: &Outcome<MessageEvent<C>, Result<FinishReason, F>>) {
+        let now = tokio::time::Instant::now();
+        match event {
+            Outcome::Continue(MessageEvent::SentPing) => {
+                self.ping_sent_at.get_or_insert(now);
+            }
+            // Any frame from the server shows that the ping got through, not
+            // just the matching pong.
+            Outcome::Continue(
+                MessageEvent::ReceivedPingPong | MessageEvent::ReceivedMessage(_),
+            ) => {
+                if let Some(sent_at) = self.ping_sent_at.take() {
+                    self.quality.record_rtt(now - sent_at);
+                }
+            }
+            Outcome::Continue(MessageEvent::SentMessage(_) | MessageEvent::SendFailed(..)) => {}
+            Outcome::Finished(Ok(FinishReason::LocalDisconnect)) => {}
+            Outcome::Finished(Ok(FinishReason::RemoteDisconnect) | Err(_)) => {
+                self.quality.record_disconnect(now)
+            }
+        }
+    }
 }
 
 /// The metadata for an outgoing message.
@@ -743,6 +897,24 @@ async fn send_request(
     {
         // The request was sent, now wait for the response to be sent back.
         match receiver.await {
+            Ok(Err(TaskSendError::InFlightAtDisconnect)) => {
+                // The server got the request, but we'll never know what it did
+                // with it. Report that rather than a plain disconnect, so the
+                // caller doesn't assume it's safe to send the request again.
+                Err(
+                    match get_task_finish_error(state, "connection ended with request in flight")
+                        .await
+                    {
+                        // The server's own reasons for closing the connection
+                        // are more useful to the caller, and they mean the
+                        // request won't be acted on anyway.
+                        SendError::Disconnected(
+                            reason @ DisconnectedReason::SocketClosed { .. },
+                        ) => SendError::Ambiguous(reason),
+                        other => other,
+                    },
+                )
+            }
             Ok(response) => response.map_err(SendError::from),
             Err(_) => {
                 // The sender was dropped without sending a response.
@@ -837,6 +1009,17 @@ impl InFlightRequests {
     }
 }
 
+impl Drop for InFlightRequests {
+    fn drop(&mut self) {
+        // Requests that were written to the connection might have been acted
+        // on by the server, so tell their senders explicitly instead of just
+        // hanging up on them.
+        for (_id, sender) in self.outstanding_reqs.drain() {
+            let _ignore_send_error = sender.send(Err(TaskSendError::InFlightAtDisconnect));
+        }
+    }
+}
+
 /// Effectively a [`FnOnce`] that produces an [`InnerConnection`] impl.
 ///
 /// This isn't just a [`FnOnce`] because the output type is generic over the
@@ -849,6 +1032,8 @@ trait IntoInnerConnection {
     fn into_inner_connection<R>(
         self,
         outgoing_stream: R,
+        reads_paused: watch::Receiver<bool>,
+        liveness_probes: mpsc::UnboundedReceiver<LivenessProbe>,
         log_tag: Arc<str>,
     ) -> impl InnerConnection + Send + 'static
     where
@@ -862,6 +1047,8 @@ where
     fn into_inner_connection<R>(
         self,
         outgoing_stream: R,
+        reads_paused: watch::Receiver<bool>,
+        liveness_probes: mpsc::UnboundedReceiver<LivenessProbe>,
         log_tag: Arc<str>,
     ) -> impl InnerConnection + Send + 'static
     where
@@ -869,6 +1056,8 @@ where
     {
         let (stream, config) = self;
         crate::infra::ws2::Connection::new(stream, outgoing_stream, config, log_tag)
+            .with_read_pausing(reads_paused)
+            .with_liveness_probes(liveness_probes)
     }
 }
 
@@ -905,9 +1094,13 @@ impl<I: InnerConnection> ConnectionImpl<I> {
         let ConnectionImplProj {
             mut inner,
             requests_in_flight,
+            quality,
         } = self.project();
 
         let inner_event = inner.as_mut().handle_next_event().await;
+        if let Some(quality) = quality {
+            quality.observe(&inner_event);
+        }
 
         Self::handle_inner_response(requests_in_flight, inner_event)
     }
@@ -1134,6 +1327,12 @@ impl From<TaskSendError> for SendError {
         match value {
             TaskSendError::StreamSendFailed(send_error) => send_error.into(),
             TaskSendError::InvalidResponse => SendError::InvalidResponse,
+            TaskSendError::InFlightAtDisconnect => {
+                SendError::Ambiguous(DisconnectedReason::SocketClosed {
+                    #[cfg(test)]
+                    reason: "connection ended with request in flight",
+                })
+            }
         }
     }
 }
@@ -1231,6 +1430,15 @@ impl From<SendError> for super::SendError {
             SendError::Disconnected(DisconnectedReason::ConnectionInvalidated) => {
                 Self::ConnectionInvalidated
             }
+            SendError::Ambiguous(DisconnectedReason::SocketClosed { .. }) => {
+                Self::RequestOutcomeUnknown
+            }
+            SendError::Ambiguous(DisconnectedReason::ConnectedElsewhere) => {
+                Self::ConnectedElsewhere
+            }
+            SendError::Ambiguous(DisconnectedReason::ConnectionInvalidated) => {
+                Self::ConnectionInvalidated
+            }
             SendError::Io(error_kind) => {
                 Self::WebSocket(WebSocketServiceError::Io(error_kind.into()))
             }
@@ -1248,6 +1456,7 @@ impl From<SendError> for super::SendError {
             SendError::InvalidRequest(InvalidRequestError::InvalidHeader) => {
                 Self::RequestHasInvalidHeader
             }
+            SendError::Draining => Self::Draining,
         }
     }
 }
@@ -1258,12 +1467,15 @@ mod test {
 
     use assert_matches::assert_matches;
     use futures_util::stream::FuturesUnordered;
+    use futures_util::SinkExt as _;
     use http::HeaderMap;
+    use libsignal_net_infra::testutil::TestStream;
     use test_case::test_case;
     use tokio::select;
     use tokio::sync::mpsc::error::TryRecvError;
 
     use super::*;
+    use crate::chat::server_requests::{DisconnectCause, ServerEvent};
 
     mod fake {
         use futures_util::future::Either;
@@ -1297,12 +1509,14 @@ mod test {
 
         pub(super) struct FakeConfig {
             pub initial_request_id: u64,
+            pub quality: Option<ConnectionQuality>,
         }
 
         pub(super) fn new_chat(listener: EventListener) -> (Chat, FakeTxRxChannels) {
             new_chat_with_config(
                 FakeConfig {
                     initial_request_id: INITIAL_REQUEST_ID,
+                    quality: None,
                 },
                 listener,
             )
@@ -1311,7 +1525,10 @@ mod test {
             config: FakeConfig,
             listener: EventListener,
         ) -> (Chat, FakeTxRxChannels) {
-            let FakeConfig { initial_request_id } = config;
+            let FakeConfig {
+                initial_request_id,
+                quality,
+            } = config;
             let (outgoing_events_tx, outgoing_events_rx) = mpsc::unbounded_channel();
             let (incoming_events_tx, incoming_events_rx) = mpsc::unbounded_channel();
             let chat = Chat::new_inner(
@@ -1322,6 +1539,7 @@ mod test {
                 initial_request_id,
                 "test".into(),
                 listener,
+                quality,
                 tokio::runtime::Handle::current(),
             );
 
@@ -1406,6 +1624,8 @@ mod test {
             fn into_inner_connection<R>(
                 self,
                 outgoing_stream: R,
+                _reads_paused: watch::Receiver<bool>,
+                _liveness_probes: mpsc::UnboundedReceiver<LivenessProbe>,
                 _log_tag: Arc<str>,
             ) -> impl InnerConnection + Send + 'static
             where
@@ -1921,6 +2141,118 @@ mod test {
         assert!(!chat.is_connected().await);
     }
 
+    #[test_log::test(tokio::test(start_paused = true))]
+    async fn check_alive_detects_unresponsive_server() {
+        const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+        let (mut server, client) =
+            TestStream::<tungstenite::Message, tungstenite::Error>::new_pair(10);
+        let (listener_tx, mut listener_rx) = mpsc::unbounded_channel();
+        let chat = Chat::new(
+            tokio::runtime::Handle::current(),
+            client,
+            HeaderMap::new(),
+            Config {
+                initial_request_id: fake::INITIAL_REQUEST_ID,
+                local_idle_timeout: Duration::from_secs(60),
+                remote_idle_timeout: Duration::from_secs(120),
+            },
+            "test".into(),
+            listener_tx.into_event_listener(),
+            None,
+        );
+        assert_matches!(
+            listener_rx.recv().await,
+            Some(ListenerEvent::ReceivedAlerts(_))
+        );
+
+        // A responsive server answers the probe's ping.
+        let (alive, ()) = tokio::join!(chat.check_alive(PROBE_TIMEOUT), async {
+            let ping = assert_matches!(
+                server.next().await,
+                Some(Ok(tungstenite::Message::Ping(ping))) => ping
+            );
+            server
+                .send(tungstenite::Message::Pong(ping))
+                .await
+                .expect("can send");
+        });
+        assert!(alive);
+
+        // Once it stops responding, that's noticed after the probe timeout
+        // rather than the much longer idle timeout...
+        let start = tokio::time::Instant::now();
+        assert!(!chat.check_alive(PROBE_TIMEOUT).await);
+        assert_eq!(start.elapsed(), PROBE_TIMEOUT);
+
+        // ...and the listener is told, so that it can reconnect.
+        assert_matches!(
+            listener_rx.recv().await,
+            Some(ListenerEvent::Finished(Err(_)))
+        );
+        assert!(!chat.check_alive(PROBE_TIMEOUT).await);
+    }
+
+    #[test_log::test(tokio::test(start_paused = true))]
+    async fn drain_finishes_in_flight_requests_and_rejects_new_ones() {
+        use futures_util::FutureExt as _;
+
+        let (chat, (mut chat_events, inner_responses)) = fake::new_chat(Box::new(|_| ()));
+        let request = || Request {
+            method: Method::GET,
+            body: None,
+            headers: Default::default(),
+            path: PathAndQuery::from_static("/"),
+        };
+
+        let in_flight = chat.send(request());
+        pin_mut!(in_flight);
+        let receive_outbound_request = async {
+            let fake::OutgoingMessage(_message, meta) =
+                chat_events.recv().await.expect("not ended");
+            let request_id = assert_matches!(&meta, OutgoingMeta::SentRequest(id, _) => *id);
+            inner_responses
+                .send(Outcome::Continue(MessageEvent::SentMessage(meta)).into())
+                .expect("not closed");
+            request_id
+        };
+        let sent_request_id = select! {
+            biased;
+            response = &mut in_flight => unreachable!("send finished before a response was sent: {response:?}"),
+            req = receive_outbound_request => req,
+        };
+
+        let drain = chat.drain(Instant::now() + Duration::from_secs(10));
+        pin_mut!(drain);
+        assert_matches!(drain.as_mut().now_or_never(), None);
+
+        // New requests are turned away...
+        assert_eq!(chat.send(request()).await, Err(SendError::Draining));
+
+        // ...but the one in flight still gets its response.
+        inner_responses
+            .send(
+                Outcome::Continue(MessageEvent::ReceivedMessage(TextOrBinary::Binary(
+                    MessageProto::from(ChatMessageProto::Response(ResponseProto {
+                        id: Some(sent_request_id.0),
+                        status: Some(200),
+                        message: None,
+                        headers: vec![],
+                        body: None,
+                    }))
+                    .encode_to_vec(),
+                )))
+                .into(),
+            )
+            .expect("can send response");
+        let _response = in_flight.await.expect("request succeeded");
+
+        // Then the connection is closed.
+        drain.await;
+        assert!(!chat.is_connected().await);
+        assert_matches!(chat_events.recv().await, None);
+    }
+
     #[test_case(
         CloseCode::from(CONNECTION_INVALIDATED_CLOSE_CODE) => matches crate::chat::SendError::ConnectionInvalidated;
         "CONNECTION_INVALIDATED_CLOSE_CODE results in ConnectionInvalidated"
@@ -1980,6 +2312,43 @@ mod test {
         actual_error
     }
 
+    #[test_case(CloseCode::from(4499_u16) => true; "custom code is retryable")]
+    #[test_case(CloseCode::Restart => true; "server restart is retryable")]
+    #[test_case(CloseCode::Policy => false; "policy violation is terminal")]
+    #[test_log::test(tokio::test(start_paused = true))]
+    async fn remote_close_is_reported_with_code_and_reason(close_code: CloseCode) -> bool {
+        let (received_events_tx, mut received_events_rx) = mpsc::unbounded_channel();
+        let (_chat, (_inner_events, inner_responses)) =
+            fake::new_chat(received_events_tx.into_event_listener());
+
+        inner_responses
+            .send(
+                Outcome::Finished(Err(NextEventError::AbnormalServerClose {
+                    code: close_code,
+                    reason: "go away".to_owned(),
+                }))
+                .into(),
+            )
+            .expect("can disconnect");
+
+        let listener_event = received_events_rx
+            .recv()
+            .await
+            .expect("should receive an event");
+
+        let cause = match ServerEvent::try_from(listener_event) {
+            Ok(ServerEvent::Stopped(cause)) => cause,
+            other => panic!("Unexpected server event: {other:?}"),
+        };
+        let retryable = cause.is_retryable();
+        assert_matches!(
+            cause,
+            DisconnectCause::RemoteClose { code, reason }
+                if code == u16::from(close_code) && reason == "go away"
+        );
+        retryable
+    }
+
     impl From<MessageProto> for TextOrBinary {
         fn from(proto: MessageProto) -> Self {
             TextOrBinary::Binary(proto.encode_to_vec())
@@ -2036,6 +2405,7 @@ mod test {
         let (chat, (mut inner_events, inner_responses)) = fake::new_chat_with_config(
             fake::FakeConfig {
                 initial_request_id: u64::MAX,
+                quality: None,
             },
             Box::new(|_| ()),
         );
@@ -2243,8 +2613,8 @@ mod test {
     )]
     #[test_case(
         CloseCode::Normal,
-        SendError::Disconnected(DisconnectedReason::SocketClosed { #[cfg(test)] reason: "server closed abnormally" });
-        "Normal close results in Disconnected"
+        SendError::Ambiguous(DisconnectedReason::SocketClosed { #[cfg(test)] reason: "server closed abnormally" });
+        "Normal close results in Ambiguous"
     )]
     #[test_log::test(tokio::test(start_paused = true))]
     async fn connection_close_with_in_flight_request(
@@ -2309,4 +2679,81 @@ mod test {
 
         assert_eq!(send_result, Err(expected_error));
     }
+
+    #[test_log::test(tokio::test(start_paused = true))]
+    async fn local_disconnect_with_in_flight_request_is_ambiguous() {
+        let (chat, (mut chat_events, inner_responses)) = fake::new_chat(Box::new(|_| ()));
+
+        let send_request = chat.send(Request {
+            method: Method::POST,
+            path: PathAndQuery::from_static("/not-idempotent"),
+            headers: HeaderMap::default(),
+            body: None,
+        });
+        pin_mut!(send_request);
+
+        let meta = select! {
+            biased;
+            response = &mut send_request => unreachable!("send finished before the request went out: {response:?}"),
+            message = chat_events.recv() => {
+                let fake::OutgoingMessage(_message, meta) = message.expect("not ended");
+                meta
+            }
+        };
+        inner_responses
+            .send(Outcome::Continue(MessageEvent::SentMessage(meta)).into())
+            .expect("not closed");
+
+        chat.disconnect().await;
+        inner_responses
+            .send(Outcome::Finished(Ok(FinishReason::LocalDisconnect)).into())
+            .expect("not closed");
+
+        assert_eq!(
+            send_request.await,
+            Err(SendError::Ambiguous(DisconnectedReason::SocketClosed {
+                reason: "connection ended with request in flight"
+            }))
+        );
+        assert_matches!(
+            crate::chat::SendError::from(SendError::Ambiguous(DisconnectedReason::SocketClosed {
+                reason: "connection ended with request in flight"
+            })),
+            crate::chat::SendError::RequestOutcomeUnknown
+        );
+    }
+
+    #[test_log::test(tokio::test(start_paused = true))]
+    async fn keep_alive_round_trips_and_disconnects_feed_connection_quality() {
+        let quality = ConnectionQuality::default();
+        let (_chat, (_chat_events, inner_responses)) = fake::new_chat_with_config(
+            fake::FakeConfig {
+                initial_request_id: fake::INITIAL_REQUEST_ID,
+                quality: Some(quality.clone()),
+            },
+            Box::new(|_| ()),
+        );
+        let mut score = quality.subscribe();
+
+        inner_responses
+            .send(Outcome::Continue(MessageEvent::SentPing).into())
+            .expect("not closed");
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        inner_responses
+            .send(Outcome::Continue(MessageEvent::ReceivedPingPong).into())
+            .expect("not closed");
+        score.changed().await.expect("quality is alive");
+        let after_slow_pong = *score.borrow_and_update();
+        assert!(after_slow_pong < 1.0, "{after_slow_pong}");
+
+        inner_responses
+            .send(Outcome::Finished(Err(NextEventError::UnexpectedConnectionClose)).into())
+            .expect("not closed");
+        score.changed().await.expect("quality is alive");
+        let after_disconnect = *score.borrow();
+        assert!(
+            after_disconnect < after_slow_pong,
+            "{after_disconnect} < {after_slow_pong}"
+        );
+    }
 }
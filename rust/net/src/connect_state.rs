@@ -6,6 +6,7 @@
 use std::default::Default;
 use std::fmt::Debug;
 use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr};
 use std::ops::ControlFlow;
 use std::sync::Arc;
 use std::time::Duration;
@@ -15,29 +16,31 @@ use http::HeaderName;
 use itertools::Itertools as _;
 use libsignal_net_infra::connection_manager::{ErrorClass, ErrorClassifier as _};
 use libsignal_net_infra::dns::DnsResolver;
-use libsignal_net_infra::errors::{LogSafeDisplay, TransportConnectError};
+use libsignal_net_infra::errors::{ConnectErrorCategory, LogSafeDisplay, TransportConnectError};
+use libsignal_net_infra::metrics::ConnectionMetrics;
 use libsignal_net_infra::route::{
     ComposedConnector, ConnectError, ConnectionOutcomeParams, ConnectionOutcomes, Connector,
     ConnectorFactory, DelayBasedOnTransport, DescribeForLog, DescribedRouteConnector,
     DirectOrProxy, HttpRouteFragment, InterfaceChangedOr, InterfaceMonitor, LoggingConnector,
     ResettingConnectionOutcomes, ResolveHostnames, ResolveWithSavedDescription, ResolvedRoute,
-    RouteProvider, RouteProviderContext, RouteProviderExt as _, RouteResolver, ThrottlingConnector,
-    TransportRoute, UnresolvedRouteDescription, UnresolvedTransportRoute,
-    UnresolvedWebsocketServiceRoute, UsePreconnect, UsesTransport, VariableTlsTimeoutConnector,
-    WebSocketRouteFragment, WebSocketServiceRoute,
+    RouteProvider, RouteProviderExt as _, RouteResolver, ThrottlingConnector, TransportRoute,
+    UnresolvedRouteDescription, UnresolvedTransportRoute, UnresolvedWebsocketServiceRoute,
+    UsePreconnect, UsesTransport, VariableTlsTimeoutConnector, WebSocketRouteFragment,
+    WebSocketServiceRoute,
+};
+use libsignal_net_infra::tcp_ssl::{
+    StatelessTls, LONG_TCP_HANDSHAKE_THRESHOLD, LONG_TLS_HANDSHAKE_THRESHOLD,
+    STALLED_IPV6_HANDSHAKE_THRESHOLD,
 };
-use libsignal_net_infra::tcp_ssl::{LONG_TCP_HANDSHAKE_THRESHOLD, LONG_TLS_HANDSHAKE_THRESHOLD};
 use libsignal_net_infra::timeouts::{
     TimeoutOr, MIN_TLS_HANDSHAKE_TIMEOUT, NETWORK_INTERFACE_POLL_INTERVAL,
     ONE_ROUTE_CONNECTION_TIMEOUT, POST_ROUTE_CHANGE_CONNECTION_TIMEOUT,
 };
+use libsignal_net_infra::utils::rng::RngProvider;
 use libsignal_net_infra::utils::NetworkChangeEvent;
 use libsignal_net_infra::ws::{WebSocketConnectError, WebSocketStreamLike};
 use libsignal_net_infra::ws2::attested::AttestedConnection;
-use libsignal_net_infra::{AsHttpHeader as _, AsyncDuplexStream};
-use rand::distr::uniform::{UniformSampler, UniformUsize};
-use rand_core::{OsRng, UnwrapErr};
-use static_assertions::assert_eq_size_val;
+use libsignal_net_infra::{AsHttpHeader as _, AsyncDuplexStream, ConnectCancellation};
 use tokio::time::Instant;
 
 use crate::auth::Auth;
@@ -103,8 +106,14 @@ pub struct ConnectState<ConnectorFactory = DefaultConnectorFactory> {
     make_transport_connector: ConnectorFactory,
     /// Record of connection outcomes.
     attempts_record: ConnectionOutcomes<TransportRoute>,
-    /// [`RouteProviderContext`] passed to route providers.
-    route_provider_context: RouteProviderContextImpl,
+    /// Randomness for route providers, passed as their
+    /// [`RouteProviderContext`](libsignal_net_infra::route::RouteProviderContext).
+    ///
+    /// Replace this with a [seeded](RngProvider::seeded) provider to make
+    /// route selection reproducible.
+    pub rng: RngProvider,
+    /// If present, told about each connection attempt and how it ended.
+    pub metrics: Option<Arc<dyn ConnectionMetrics>>,
 }
 
 pub type DefaultTransportConnector = VariableTlsTimeoutConnector<
@@ -130,9 +139,27 @@ pub struct ConnectionResources<'a, TC> {
     pub dns_resolver: &'a DnsResolver,
     pub network_change_event: &'a NetworkChangeEvent,
     pub confirmation_header_name: Option<HeaderName>,
+    /// If present, lets the caller abandon the connection attempt.
+    ///
+    /// Once cancelled, the attempt stops right away, dropping any DNS lookups, TCP connections
+    /// and handshakes in progress, and fails with [`TransportConnectError::Cancelled`].
+    pub cancellation: Option<&'a ConnectCancellation>,
+}
+
+#[derive(Debug, Default)]
+pub struct DefaultConnectorFactory {
+    metrics: Option<Arc<dyn ConnectionMetrics>>,
+}
+
+impl DefaultConnectorFactory {
+    /// Makes connectors that report each TLS handshake to `metrics`.
+    pub fn with_metrics(metrics: Arc<dyn ConnectionMetrics>) -> Self {
+        Self {
+            metrics: Some(metrics),
+        }
+    }
 }
 
-pub struct DefaultConnectorFactory;
 impl<R> ConnectorFactory<R> for DefaultConnectorFactory
 where
     DefaultTransportConnector: Connector<R, ()>,
@@ -141,8 +168,13 @@ where
     type Connection = <DefaultTransportConnector as Connector<R, ()>>::Connection;
 
     fn make(&self) -> Self::Connector {
+        let tls_connector = self
+            .metrics
+            .clone()
+            .map(StatelessTls::with_metrics)
+            .unwrap_or_default();
         let throttle_tls_connections = ThrottlingConnector::new(
-            LoggingConnector::new(Default::default(), LONG_TLS_HANDSHAKE_THRESHOLD, "TLS"),
+            LoggingConnector::new(tls_connector, LONG_TLS_HANDSHAKE_THRESHOLD, "TLS"),
             1,
         );
         let proxy_or_direct_connector = DirectOrProxy::new(
@@ -160,7 +192,7 @@ where
 
 impl ConnectState {
     pub fn new(config: Config) -> std::sync::Mutex<Self> {
-        Self::new_with_transport_connector(config, DefaultConnectorFactory)
+        Self::new_with_transport_connector(config, DefaultConnectorFactory::default())
     }
 }
 
@@ -182,7 +214,8 @@ impl<ConnectorFactory> ConnectState<ConnectorFactory> {
             post_route_change_connect_timeout,
             make_transport_connector,
             attempts_record: ConnectionOutcomes::new(connect_params),
-            route_provider_context: RouteProviderContextImpl::default(),
+            rng: RngProvider::default(),
+            metrics: None,
         }
         .into()
     }
@@ -195,12 +228,18 @@ impl<ConnectorFactory> ConnectState<ConnectorFactory> {
 #[derive(Clone, Debug, PartialEq)]
 pub struct RouteInfo {
     unresolved: UnresolvedRouteDescription,
+    /// The address the connection was made to; see
+    /// [`ResolvedRoute::immediate_target`].
+    immediate_target: IpAddr,
 }
 
 impl LogSafeDisplay for RouteInfo {}
 impl std::fmt::Display for RouteInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let Self { unresolved } = self;
+        let Self {
+            unresolved,
+            immediate_target: _,
+        } = self;
         (unresolved as &dyn LogSafeDisplay).fmt(f)
     }
 }
@@ -209,6 +248,145 @@ impl RouteInfo {
     pub fn fake() -> Self {
         Self {
             unresolved: UnresolvedRouteDescription::fake(),
+            immediate_target: Ipv4Addr::UNSPECIFIED.into(),
+        }
+    }
+
+    /// The address of the host the connection was made to directly.
+    ///
+    /// For a connection through a proxy, this is the address of the proxy.
+    pub fn immediate_target(&self) -> IpAddr {
+        self.immediate_target
+    }
+}
+
+/// Why [`with_timeout_and_cancellation`] gave up on a connection attempt.
+enum Abandoned {
+    Timeout { attempt_duration: Duration },
+    Cancelled,
+}
+
+impl Abandoned {
+    fn map_cancelled<E>(self, cancelled: impl FnOnce() -> E) -> TimeoutOr<ConnectError<E>> {
+        match self {
+            Self::Timeout { attempt_duration } => TimeoutOr::Timeout { attempt_duration },
+            Self::Cancelled => TimeoutOr::Other(ConnectError::FatalConnect(cancelled())),
+        }
+    }
+}
+
+/// Runs `connect` for up to `connect_timeout`, or until `cancellation` is cancelled.
+async fn with_timeout_and_cancellation<T>(
+    connect: impl Future<Output = T>,
+    connect_timeout: Duration,
+    cancellation: Option<&ConnectCancellation>,
+    log_tag: &str,
+) -> Result<T, Abandoned> {
+    let cancelled = async {
+        match cancellation {
+            Some(cancellation) => cancellation.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        biased;
+        () = cancelled => {
+            log::info!("[{log_tag}] connection attempt cancelled");
+            Err(Abandoned::Cancelled)
+        }
+        result = tokio::time::timeout(connect_timeout, connect) => {
+            result.map_err(|_: tokio::time::error::Elapsed| Abandoned::Timeout {
+                attempt_duration: connect_timeout,
+            })
+        }
+    }
+}
+
+/// What the transport attempts of one connection did over IPv6, for noticing
+/// IPv6 handshakes that stall while IPv4 ones get through.
+///
+/// This is the route-based counterpart of what
+/// [`DirectConnector`](libsignal_net_infra::tcp_ssl::DirectConnector) checks
+/// for the addresses it races itself. A transport attempt ends with the TLS
+/// handshake, so an attempt that is still going is one whose handshake
+/// hasn't finished.
+#[derive(Debug, Default)]
+struct Ipv6AttemptWatch {
+    /// When each IPv6 attempt started, in the order they started, while it's
+    /// in progress. Attempts dropped before they finish keep their entry.
+    ipv6_started: std::sync::Mutex<Vec<Option<Instant>>>,
+    /// Set when an IPv4 attempt succeeds while an IPv6 attempt that started
+    /// before it has been going for at least
+    /// [`STALLED_IPV6_HANDSHAKE_THRESHOLD`].
+    ipv6_stalled: std::sync::atomic::AtomicBool,
+}
+
+impl Ipv6AttemptWatch {
+    fn ipv6_attempt_started(&self, started: Instant) -> usize {
+        let mut ipv6_started = self.ipv6_started.lock().expect("not poisoned");
+        ipv6_started.push(Some(started));
+        ipv6_started.len() - 1
+    }
+
+    fn ipv6_attempt_finished(&self, index: usize) {
+        self.ipv6_started.lock().expect("not poisoned")[index] = None;
+    }
+
+    fn ipv4_attempt_succeeded(&self, started: Instant) {
+        let now = Instant::now();
+        let stalled = self
+            .ipv6_started
+            .lock()
+            .expect("not poisoned")
+            .iter()
+            .flatten()
+            .any(|ipv6_started| {
+                *ipv6_started <= started && now - *ipv6_started >= STALLED_IPV6_HANDSHAKE_THRESHOLD
+            });
+        if stalled {
+            self.ipv6_stalled
+                .store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+}
+
+/// Connects like the wrapped connector, keeping an [`Ipv6AttemptWatch`] up to
+/// date.
+struct WatchIpv6Attempts<'a, C> {
+    inner: C,
+    watch: &'a Ipv6AttemptWatch,
+}
+
+impl<R, Inner, C> Connector<R, Inner> for WatchIpv6Attempts<'_, C>
+where
+    C: Connector<R, Inner> + Sync,
+    R: ResolvedRoute + Send,
+    Inner: Send,
+{
+    type Connection = C::Connection;
+
+    type Error = C::Error;
+
+    fn connect_over(
+        &self,
+        over: Inner,
+        route: R,
+        log_tag: Arc<str>,
+    ) -> impl Future<Output = Result<Self::Connection, Self::Error>> + Send {
+        let started = Instant::now();
+        let ipv6_index = route
+            .immediate_target()
+            .is_ipv6()
+            .then(|| self.watch.ipv6_attempt_started(started));
+        let connect = self.inner.connect_over(over, route, log_tag);
+        async move {
+            let result = connect.await;
+            match ipv6_index {
+                Some(index) => self.watch.ipv6_attempt_finished(index),
+                None if result.is_ok() => self.watch.ipv4_attempt_succeeded(started),
+                None => {}
+            }
+            result
         }
     }
 }
@@ -223,7 +401,8 @@ struct ConnectStateSnapshot<C> {
     post_route_change_connect_timeout: Duration,
     transport_connector: C,
     attempts_record: ConnectionOutcomes<TransportRoute>,
-    route_provider_context: RouteProviderContextImpl,
+    route_provider_context: RngProvider,
+    metrics: Option<Arc<dyn ConnectionMetrics>>,
 }
 
 impl<TC> ConnectState<TC> {
@@ -238,7 +417,8 @@ impl<TC> ConnectState<TC> {
             post_route_change_connect_timeout,
             make_transport_connector,
             attempts_record,
-            route_provider_context,
+            rng,
+            metrics,
         } = self;
 
         ConnectStateSnapshot {
@@ -248,11 +428,37 @@ impl<TC> ConnectState<TC> {
             post_route_change_connect_timeout: *post_route_change_connect_timeout,
             transport_connector: make_transport_connector.make(),
             attempts_record: attempts_record.clone(),
-            route_provider_context: route_provider_context.clone(),
+            route_provider_context: rng.clone(),
+            metrics: metrics.clone(),
         }
     }
 }
 
+/// The category to report to [`ConnectionMetrics`] for a connection attempt
+/// that failed with `error`.
+fn failure_category<E>(
+    error: &TimeoutOr<ConnectError<E>>,
+    fatal_category: impl FnOnce(&E) -> ConnectErrorCategory,
+) -> ConnectErrorCategory {
+    match error {
+        TimeoutOr::Timeout {
+            attempt_duration: _,
+        } => ConnectErrorCategory::Network,
+        TimeoutOr::Other(error) => route_failure_category(error, fatal_category),
+    }
+}
+
+fn route_failure_category<E>(
+    error: &ConnectError<E>,
+    fatal_category: impl FnOnce(&E) -> ConnectErrorCategory,
+) -> ConnectErrorCategory {
+    match error {
+        ConnectError::NoResolvedRoutes => ConnectErrorCategory::Configuration,
+        ConnectError::AllAttemptsFailed(_) => ConnectErrorCategory::Network,
+        ConnectError::FatalConnect(e) => fatal_category(e),
+    }
+}
+
 impl<TC> ConnectionResources<'_, TC> {
     pub async fn connect_ws<WC, UR, Transport>(
         self,
@@ -286,6 +492,7 @@ impl<TC> ConnectionResources<'_, TC> {
             dns_resolver,
             network_change_event,
             confirmation_header_name,
+            cancellation,
         } = self;
 
         let ConnectStateSnapshot {
@@ -296,6 +503,7 @@ impl<TC> ConnectionResources<'_, TC> {
             transport_connector,
             attempts_record,
             route_provider_context,
+            metrics,
         } = connect_state.lock().expect("not poisoned").snapshot();
 
         let routes = routes.routes(&route_provider_context).collect_vec();
@@ -304,12 +512,19 @@ impl<TC> ConnectionResources<'_, TC> {
             "[{log_tag}] starting connection attempt with {} routes",
             routes.len()
         );
+        if let Some(metrics) = &metrics {
+            metrics.attempt_started();
+        }
 
         let route_provider = routes.into_iter().map(ResolveWithSavedDescription);
+        let ipv6_attempts = Ipv6AttemptWatch::default();
         let connector = InterfaceMonitor::new(
             DescribedRouteConnector(ComposedConnector::new(
                 LoggingConnector::new(ws_connector, Duration::from_secs(3), "websocket"),
-                &transport_connector,
+                WatchIpv6Attempts {
+                    inner: &transport_connector,
+                    watch: &ipv6_attempts,
+                },
             )),
             network_change_event.clone(),
             network_interface_poll_interval,
@@ -346,11 +561,26 @@ impl<TC> ConnectionResources<'_, TC> {
             },
         );
 
-        let (result, updates) = tokio::time::timeout(connect_timeout, connect)
-            .await
-            .map_err(|_: tokio::time::error::Elapsed| TimeoutOr::Timeout {
-                attempt_duration: connect_timeout,
-            })?;
+        let (result, updates) =
+            with_timeout_and_cancellation(connect, connect_timeout, cancellation, &log_tag)
+                .await
+                .map_err(|e| {
+                    e.map_cancelled(|| {
+                        WebSocketServiceConnectError::from_websocket_error(
+                            WebSocketConnectError::Transport(TransportConnectError::Cancelled),
+                            None,
+                            Instant::now(),
+                        )
+                    })
+                })
+                .inspect_err(|e| {
+                    if let Some(metrics) = &metrics {
+                        metrics.attempt_failed(failure_category(
+                            e,
+                            WebSocketServiceConnectError::category,
+                        ));
+                    }
+                })?;
 
         match &result {
             Ok((_connection, route)) => log::info!(
@@ -359,6 +589,32 @@ impl<TC> ConnectionResources<'_, TC> {
             ),
             Err(e) => log::info!("[{log_tag}] connection failed with {e}"),
         }
+        if let Some(metrics) = &metrics {
+            match &result {
+                Ok(_) => metrics.attempt_succeeded(),
+                Err(e) => metrics.attempt_failed(route_failure_category(
+                    e,
+                    WebSocketServiceConnectError::category,
+                )),
+            }
+        }
+
+        // The successful attempt, if any, is always the last one recorded.
+        let connected_to = updates
+            .outcomes
+            .last()
+            .filter(|(_route, outcome)| outcome.result.is_ok())
+            .map(|(route, _outcome)| *route.immediate_target());
+        if let Some(ipv6_suspicion) = &route_resolver.ipv6_suspicion {
+            ipv6_suspicion.report_connect_outcome(connected_to.is_some_and(|ip| ip.is_ipv6()));
+            if connected_to.is_some_and(|ip| ip.is_ipv4())
+                && ipv6_attempts
+                    .ipv6_stalled
+                    .load(std::sync::atomic::Ordering::Relaxed)
+            {
+                ipv6_suspicion.report_stalled_handshake();
+            }
+        }
 
         connect_state
             .lock()
@@ -377,6 +633,7 @@ impl<TC> ConnectionResources<'_, TC> {
             connection,
             RouteInfo {
                 unresolved: description,
+                immediate_target: connected_to.expect("successful attempt was recorded"),
             },
         ))
     }
@@ -410,7 +667,7 @@ impl<TC> ConnectionResources<'_, TC> {
             .await
             .map_err(|e| match e {
                 TimeoutOr::Other(
-                    ConnectError::NoResolvedRoutes | ConnectError::AllAttemptsFailed,
+                    ConnectError::NoResolvedRoutes | ConnectError::AllAttemptsFailed(_),
                 )
                 | TimeoutOr::Timeout {
                     attempt_duration: _,
@@ -445,6 +702,7 @@ where
             dns_resolver,
             network_change_event,
             confirmation_header_name: _,
+            cancellation,
         } = self;
 
         let ConnectStateSnapshot {
@@ -455,6 +713,7 @@ where
             transport_connector,
             attempts_record,
             route_provider_context,
+            metrics,
         } = connect_state
             .lock()
             .expect("not poisoned")
@@ -472,6 +731,9 @@ where
             "[{log_tag}] starting connection attempt with {} routes",
             routes.len()
         );
+        if let Some(metrics) = &metrics {
+            metrics.attempt_started();
+        }
 
         struct ConnectWithSavedRoute<C>(C);
 
@@ -528,11 +790,16 @@ where
             },
         );
 
-        let (result, updates) = tokio::time::timeout(connect_timeout, connect)
-            .await
-            .map_err(|_: tokio::time::error::Elapsed| TimeoutOr::Timeout {
-                attempt_duration: connect_timeout,
-            })?;
+        let (result, updates) =
+            with_timeout_and_cancellation(connect, connect_timeout, cancellation, &log_tag)
+                .await
+                .map_err(|e| e.map_cancelled(|| TransportConnectError::Cancelled))
+                .inspect_err(|e| {
+                    if let Some(metrics) = &metrics {
+                        metrics
+                            .attempt_failed(failure_category(e, TransportConnectError::category));
+                    }
+                })?;
 
         match &result {
             Ok(_) => {
@@ -545,6 +812,13 @@ where
             }
             Err(e) => log::info!("[{log_tag}] connection failed with {e}"),
         }
+        if let Some(metrics) = &metrics {
+            match &result {
+                Ok(_) => metrics.attempt_succeeded(),
+                Err(e) => metrics
+                    .attempt_failed(route_failure_category(e, TransportConnectError::category)),
+            }
+        }
 
         // Don't exit yet, we have to save the results!
         {
@@ -577,18 +851,6 @@ where
     }
 }
 
-#[derive(Debug, Default, Clone)]
-struct RouteProviderContextImpl(UnwrapErr<OsRng>);
-
-impl RouteProviderContext for RouteProviderContextImpl {
-    fn random_usize(&self) -> usize {
-        // OsRng is zero-sized, so we're not losing random values by copying it.
-        let mut owned_rng: UnwrapErr<OsRng> = self.0;
-        assert_eq_size_val!(owned_rng, ());
-        UniformUsize::sample_single_inclusive(0, usize::MAX, &mut owned_rng).expect("valid range")
-    }
-}
-
 /// Convenience alias for using `PreconnectingConnector`s with [`ConnectState`].
 pub type PreconnectingFactory<Inner = DefaultConnectorFactory> =
     libsignal_net_infra::route::PreconnectingFactory<TransportRoute, Inner>;
@@ -596,6 +858,7 @@ pub type PreconnectingFactory<Inner = DefaultConnectorFactory> =
 #[cfg(test)]
 mod test {
     use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
     use std::sync::{Arc, LazyLock, Mutex};
     use std::time::Duration;
 
@@ -604,16 +867,20 @@ mod test {
     use http::uri::PathAndQuery;
     use http::HeaderMap;
     use libsignal_net_infra::certs::RootCertificates;
+    use libsignal_net_infra::dns::dns_lookup::{DnsLookup, DnsLookupRequest};
     use libsignal_net_infra::dns::lookup_result::LookupResult;
+    use libsignal_net_infra::dns::DnsError;
     use libsignal_net_infra::host::Host;
+    use libsignal_net_infra::metrics::OpenMetricsRecorder;
     use libsignal_net_infra::route::testutils::ConnectFn;
     use libsignal_net_infra::route::{
-        AttemptOutcome, DirectOrProxyRoute, HttpsTlsRoute, TcpRoute, TlsRoute, TlsRouteFragment,
-        UnresolvedHost, UnresolvedTransportRoute, UnsuccessfulOutcome, WebSocketRoute,
-        HAPPY_EYEBALLS_DELAY,
+        AttemptFailures, AttemptOutcome, DirectOrProxyRoute, FailedAttempt, HttpsTlsRoute,
+        TcpRoute, TlsRoute, TlsRouteFragment, UnresolvedHost, UnresolvedTransportRoute,
+        UnsuccessfulOutcome, WebSocketRoute, HAPPY_EYEBALLS_DELAY,
     };
+    use libsignal_net_infra::tcp_ssl::ipv6_suspicion::Ipv6Suspicion;
     use libsignal_net_infra::testutil::no_network_change_events;
-    use libsignal_net_infra::{Alpn, DnsSource, RouteType};
+    use libsignal_net_infra::{Alpn, DnsSource, RouteType, TransportConnectionParams};
     use nonzero_ext::nonzero;
 
     use super::*;
@@ -640,6 +907,7 @@ mod test {
                         ws_config: Default::default(),
                         endpoint: PathAndQuery::from_static("/first"),
                         headers: HeaderMap::new(),
+                        upgrade_timeout: None,
                     },
                     inner: HttpsTlsRoute {
                         fragment: HttpRouteFragment {
@@ -655,6 +923,7 @@ mod test {
                         ws_config: Default::default(),
                         endpoint: PathAndQuery::from_static("/second"),
                         headers: HeaderMap::new(),
+                        upgrade_timeout: None,
                     },
                     inner: HttpsTlsRoute {
                         fragment: HttpRouteFragment {
@@ -700,7 +969,8 @@ mod test {
             route_resolver: RouteResolver::default(),
             attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
             make_transport_connector: fake_transport_connector,
-            route_provider_context: Default::default(),
+            rng: Default::default(),
+            metrics: None,
         }
         .into();
 
@@ -709,6 +979,7 @@ mod test {
             dns_resolver: &resolver,
             network_change_event: &no_network_change_events(),
             confirmation_header_name: None,
+            cancellation: None,
         };
 
         let result = connection_resources
@@ -725,9 +996,399 @@ mod test {
             connection,
             (succeeding_route.fragment, succeeding_route.inner.fragment)
         );
-        let RouteInfo { unresolved } = info;
+        let RouteInfo {
+            unresolved,
+            immediate_target,
+        } = info;
 
         assert_eq!(unresolved.to_string(), "REDACTED:1234 fronted by proxyf");
+        assert_eq!(immediate_target, ip_addr!("192.0.2.1"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connect_ws_reports_how_each_attempt_failed() {
+        let ws_connector = ConnectFn(|(), _route, _log_tag| {
+            std::future::ready(Err::<(), WebSocketConnectError>(
+                tungstenite::Error::ConnectionClosed.into(),
+            ))
+        });
+        let resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            FAKE_HOST_NAME,
+            LookupResult::new(DnsSource::Static, vec![ip_addr!(v4, "192.0.2.1")], vec![]),
+        )]));
+
+        let fake_transport_connector =
+            ConnectFn(move |(), _, _| std::future::ready(Ok::<_, WebSocketConnectError>(())));
+
+        let state = ConnectState {
+            connect_timeout: Duration::MAX,
+            network_interface_poll_interval: Duration::MAX,
+            post_route_change_connect_timeout: Duration::MAX,
+            route_resolver: RouteResolver::default(),
+            attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
+            make_transport_connector: fake_transport_connector,
+            rng: Default::default(),
+            metrics: None,
+        }
+        .into();
+
+        let result = ConnectionResources {
+            connect_state: &state,
+            dns_resolver: &resolver,
+            network_change_event: &no_network_change_events(),
+            confirmation_header_name: None,
+            cancellation: None,
+        }
+        .connect_ws(
+            (*FAKE_WEBSOCKET_ROUTES).to_vec(),
+            ws_connector,
+            "test".into(),
+        )
+        .await;
+
+        let failures = assert_matches!(
+            result,
+            Err(TimeoutOr::Other(ConnectError::AllAttemptsFailed(AttemptFailures(failures)))) => failures
+        );
+        let error = WebSocketConnectError::from(tungstenite::Error::ConnectionClosed).to_string();
+        assert_eq!(
+            failures,
+            [
+                FailedAttempt {
+                    route: "REDACTED:1234 (direct)".to_owned(),
+                    error: error.clone(),
+                },
+                FailedAttempt {
+                    route: "REDACTED:1234 fronted by proxyf".to_owned(),
+                    error,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connect_ws_applies_address_filters() {
+        const FILTERED_OUT: Ipv4Addr = ip_addr!(v4, "192.0.2.1");
+        const ALLOWED: Ipv4Addr = ip_addr!(v4, "192.0.2.2");
+
+        let resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            FAKE_HOST_NAME,
+            LookupResult::new(DnsSource::Static, vec![FILTERED_OUT, ALLOWED], vec![]),
+        )]));
+        resolver.add_address_filter(|ip| *ip != IpAddr::V4(FILTERED_OUT));
+
+        let connected_addresses = Mutex::new(Vec::new());
+        let transport_connector = ConnectFn(|(), route: TransportRoute, _| {
+            let DirectOrProxyRoute::Direct(TcpRoute { address, .. }) = route.inner else {
+                unreachable!("no proxy is configured");
+            };
+            connected_addresses
+                .lock()
+                .expect("not poisoned")
+                .push(address);
+            std::future::ready(Ok::<_, WebSocketConnectError>(()))
+        });
+        let ws_connector = ConnectFn(|(), route, _log_tag| {
+            std::future::ready(Ok::<_, WebSocketConnectError>(route))
+        });
+
+        let state = ConnectState {
+            connect_timeout: Duration::MAX,
+            network_interface_poll_interval: Duration::MAX,
+            post_route_change_connect_timeout: Duration::MAX,
+            route_resolver: RouteResolver::default(),
+            attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
+            make_transport_connector: transport_connector,
+            rng: Default::default(),
+            metrics: None,
+        }
+        .into();
+
+        let connection_resources = ConnectionResources {
+            connect_state: &state,
+            dns_resolver: &resolver,
+            network_change_event: &no_network_change_events(),
+            confirmation_header_name: None,
+            cancellation: None,
+        };
+
+        let [route, _] = (*FAKE_WEBSOCKET_ROUTES).clone();
+        let _connection = connection_resources
+            .connect_ws(vec![route], ws_connector, "test".into())
+            .await
+            .expect("succeeded");
+
+        assert_eq!(
+            *connected_addresses.lock().expect("not poisoned"),
+            [IpAddr::V4(ALLOWED)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connect_ws_prefers_pinned_addresses() {
+        const PINNED: Ipv4Addr = ip_addr!(v4, "192.0.2.1");
+        const LATER: Ipv4Addr = ip_addr!(v4, "192.0.2.2");
+
+        /// Answers each lookup with the next of its addresses.
+        #[derive(Debug)]
+        struct SequentialLookup(Mutex<std::vec::IntoIter<Ipv4Addr>>);
+
+        #[async_trait::async_trait]
+        impl DnsLookup for SequentialLookup {
+            async fn dns_lookup(
+                &self,
+                _request: DnsLookupRequest,
+            ) -> Result<LookupResult, DnsError> {
+                let ip = self.0.lock().expect("not poisoned").next();
+                let ip = ip.ok_or(DnsError::LookupFailed)?;
+                Ok(LookupResult::new(DnsSource::Test, vec![ip], vec![]))
+            }
+        }
+
+        let resolver = DnsResolver::new_custom(vec![(
+            Box::new(SequentialLookup(Mutex::new(
+                vec![PINNED, LATER].into_iter(),
+            ))),
+            Duration::from_secs(1),
+        )]);
+        let pin_params = TransportConnectionParams {
+            sni: FAKE_HOST_NAME.into(),
+            tcp_host: Host::Domain(FAKE_HOST_NAME.into()),
+            port: nonzero!(1234u16),
+            certs: RootCertificates::Native,
+            dns_resolver: None,
+            alpn: None,
+        };
+        resolver
+            .prefetch_and_pin([&pin_params], Duration::from_secs(60))
+            .await;
+
+        let connected_addresses = Mutex::new(Vec::new());
+        let transport_connector = ConnectFn(|(), route: TransportRoute, _| {
+            let DirectOrProxyRoute::Direct(TcpRoute { address, .. }) = route.inner else {
+                unreachable!("no proxy is configured");
+            };
+            connected_addresses
+                .lock()
+                .expect("not poisoned")
+                .push(address);
+            std::future::ready(Ok::<_, WebSocketConnectError>(()))
+        });
+        let ws_connector = ConnectFn(|(), route, _log_tag| {
+            std::future::ready(Ok::<_, WebSocketConnectError>(route))
+        });
+
+        let state = ConnectState {
+            connect_timeout: Duration::MAX,
+            network_interface_poll_interval: Duration::MAX,
+            post_route_change_connect_timeout: Duration::MAX,
+            route_resolver: RouteResolver::default(),
+            attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
+            make_transport_connector: transport_connector,
+            rng: Default::default(),
+            metrics: None,
+        }
+        .into();
+
+        let connection_resources = ConnectionResources {
+            connect_state: &state,
+            dns_resolver: &resolver,
+            network_change_event: &no_network_change_events(),
+            confirmation_header_name: None,
+            cancellation: None,
+        };
+
+        let [route, _] = (*FAKE_WEBSOCKET_ROUTES).clone();
+        let _connection = connection_resources
+            .connect_ws(vec![route], ws_connector, "test".into())
+            .await
+            .expect("succeeded");
+
+        // The connection used the pinned answer instead of looking the host up
+        // again.
+        assert_eq!(
+            *connected_addresses.lock().expect("not poisoned"),
+            [IpAddr::V4(PINNED)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connect_ws_reports_failed_ipv6_reconnect() {
+        const V4: Ipv4Addr = ip_addr!(v4, "192.0.2.1");
+        const V6: Ipv6Addr = ip_addr!(v6, "3fff::1");
+
+        let resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            FAKE_HOST_NAME,
+            LookupResult::new(DnsSource::Static, vec![V4], vec![V6]),
+        )]));
+
+        // IPv6 connectivity has gone away.
+        let transport_connector = ConnectFn(|(), route: TransportRoute, _| {
+            std::future::ready(if route.immediate_target().is_ipv6() {
+                Err(WebSocketConnectError::Transport(
+                    TransportConnectError::TcpConnectionFailed,
+                ))
+            } else {
+                Ok(())
+            })
+        });
+        let ws_connector = ConnectFn(|(), route, _log_tag| {
+            std::future::ready(Ok::<_, WebSocketConnectError>(route))
+        });
+
+        let (_network_change_tx, network_change_event) = tokio::sync::watch::channel(());
+        let ipv6_suspicion = Ipv6Suspicion::new(Duration::from_secs(60), &network_change_event);
+        let state = ConnectState {
+            connect_timeout: Duration::MAX,
+            network_interface_poll_interval: Duration::MAX,
+            post_route_change_connect_timeout: Duration::MAX,
+            route_resolver: RouteResolver {
+                ipv6_suspicion: Some(ipv6_suspicion.clone()),
+                ..RouteResolver::default()
+            },
+            attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
+            make_transport_connector: transport_connector,
+            rng: Default::default(),
+            metrics: None,
+        }
+        .into();
+
+        let no_network_changes = no_network_change_events();
+        let connect = || {
+            let [route, _] = (*FAKE_WEBSOCKET_ROUTES).clone();
+            ConnectionResources {
+                connect_state: &state,
+                dns_resolver: &resolver,
+                network_change_event: &no_network_changes,
+                confirmation_header_name: None,
+                cancellation: None,
+            }
+            .connect_ws(vec![route], &ws_connector, "test".into())
+        };
+
+        // Connecting over IPv4 isn't suspicious on its own...
+        let (_connection, info) = connect().await.expect("succeeded");
+        assert_eq!(info.immediate_target(), IpAddr::V4(V4));
+        assert!(!ipv6_suspicion.is_suspect());
+
+        // ...but it is right after losing a connection over IPv6.
+        ipv6_suspicion.report_connection_lost(V6.into());
+        let (_connection, info) = connect().await.expect("succeeded");
+        assert_eq!(info.immediate_target(), IpAddr::V4(V4));
+        assert!(ipv6_suspicion.is_suspect());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connect_ws_reports_stalled_ipv6_handshake() {
+        const V4: Ipv4Addr = ip_addr!(v4, "192.0.2.1");
+        const V6: Ipv6Addr = ip_addr!(v6, "3fff::1");
+
+        let resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            FAKE_HOST_NAME,
+            LookupResult::new(DnsSource::Static, vec![V4], vec![V6]),
+        )]));
+
+        // Handshakes over IPv6 never finish, like on a path that drops large
+        // packets; IPv4 ones succeed right away.
+        let transport_connector = ConnectFn(|(), route: TransportRoute, _| {
+            let over_ipv6 = route.immediate_target().is_ipv6();
+            async move {
+                if over_ipv6 {
+                    std::future::pending::<()>().await;
+                }
+                Ok::<_, WebSocketConnectError>(())
+            }
+        });
+        let ws_connector = ConnectFn(|(), route, _log_tag| {
+            std::future::ready(Ok::<_, WebSocketConnectError>(route))
+        });
+
+        let (_network_change_tx, network_change_event) = tokio::sync::watch::channel(());
+        let ipv6_suspicion = Ipv6Suspicion::new(Duration::from_secs(60), &network_change_event);
+        let state = ConnectState {
+            connect_timeout: Duration::MAX,
+            network_interface_poll_interval: Duration::MAX,
+            post_route_change_connect_timeout: Duration::MAX,
+            route_resolver: RouteResolver {
+                ipv6_suspicion: Some(ipv6_suspicion.clone()),
+                ..RouteResolver::default()
+            },
+            attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
+            make_transport_connector: transport_connector,
+            rng: Default::default(),
+            metrics: None,
+        }
+        .into();
+
+        let [route, _] = (*FAKE_WEBSOCKET_ROUTES).clone();
+        let (_connection, info) = ConnectionResources {
+            connect_state: &state,
+            dns_resolver: &resolver,
+            network_change_event: &no_network_change_events(),
+            confirmation_header_name: None,
+            cancellation: None,
+        }
+        .connect_ws(vec![route], ws_connector, "test".into())
+        .await
+        .expect("succeeded");
+
+        // IPv6 went first, and was still handshaking after the happy eyeballs
+        // delay, when IPv4 got through.
+        assert!(HAPPY_EYEBALLS_DELAY >= STALLED_IPV6_HANDSHAKE_THRESHOLD);
+        assert_eq!(info.immediate_target(), IpAddr::V4(V4));
+        assert!(ipv6_suspicion.is_suspect());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connect_ws_reports_metrics() {
+        let metrics = Arc::new(OpenMetricsRecorder::new());
+        let resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            FAKE_HOST_NAME,
+            LookupResult::new(DnsSource::Static, vec![ip_addr!(v4, "192.0.2.1")], vec![]),
+        )]));
+        resolver.set_metrics(Some(metrics.clone()));
+
+        let transport_connector =
+            ConnectFn(|(), _route, _| std::future::ready(Ok::<_, WebSocketConnectError>(())));
+        let ws_connector = ConnectFn(|(), route, _log_tag| {
+            std::future::ready(Ok::<_, WebSocketConnectError>(route))
+        });
+
+        let state = ConnectState {
+            connect_timeout: Duration::MAX,
+            network_interface_poll_interval: Duration::MAX,
+            post_route_change_connect_timeout: Duration::MAX,
+            route_resolver: RouteResolver::default(),
+            attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
+            make_transport_connector: transport_connector,
+            rng: Default::default(),
+            metrics: Some(metrics.clone()),
+        }
+        .into();
+
+        let [route, _] = (*FAKE_WEBSOCKET_ROUTES).clone();
+        let _connection = ConnectionResources {
+            connect_state: &state,
+            dns_resolver: &resolver,
+            network_change_event: &no_network_change_events(),
+            confirmation_header_name: None,
+            cancellation: None,
+        }
+        .connect_ws(vec![route], ws_connector, "test".into())
+        .await
+        .expect("succeeded");
+
+        let rendered = metrics.render();
+        for line in [
+            "libsignal_net_connect_attempts_total 1",
+            "libsignal_net_connect_successes_total 1",
+            "libsignal_net_dns_lookups_total{result=\"success\"} 1",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "{line} missing from\n{rendered}"
+            );
+        }
     }
 
     #[tokio::test(start_paused = true)]
@@ -751,7 +1412,8 @@ mod test {
             route_resolver: RouteResolver::default(),
             attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
             make_transport_connector: always_hangs_connector,
-            route_provider_context: Default::default(),
+            rng: Default::default(),
+            metrics: None,
         }
         .into();
 
@@ -762,6 +1424,7 @@ mod test {
             dns_resolver: &resolver,
             network_change_event: &no_network_change_events(),
             confirmation_header_name: None,
+            cancellation: None,
         };
 
         let connect = connection_resources.connect_ws(
@@ -782,6 +1445,83 @@ mod test {
         assert_eq!(start.elapsed(), CONNECT_TIMEOUT);
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn connect_ws_cancelled() {
+        let ws_connector = crate::infra::ws::Stateless;
+        let resolver = DnsResolver::new_from_static_map(HashMap::from([(
+            FAKE_HOST_NAME,
+            LookupResult::new(DnsSource::Static, vec![ip_addr!(v4, "192.0.2.1")], vec![]),
+        )]));
+
+        // Each attempt holds a clone of this until it's dropped.
+        let in_flight = Arc::new(());
+        let always_hangs_connector = ConnectFn({
+            let in_flight = Arc::clone(&in_flight);
+            move |(), _, _| {
+                let attempt = Arc::clone(&in_flight);
+                async move {
+                    let _attempt = attempt;
+                    std::future::pending::<Result<tokio::io::DuplexStream, WebSocketConnectError>>()
+                        .await
+                }
+            }
+        });
+
+        let state = ConnectState {
+            connect_timeout: Duration::from_secs(31),
+            network_interface_poll_interval: Duration::MAX,
+            post_route_change_connect_timeout: Duration::MAX,
+            route_resolver: RouteResolver::default(),
+            attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
+            make_transport_connector: always_hangs_connector,
+            rng: Default::default(),
+            metrics: None,
+        }
+        .into();
+
+        let [failing_route, succeeding_route] = (*FAKE_WEBSOCKET_ROUTES).clone();
+
+        let cancellation = ConnectCancellation::new();
+        let connection_resources = ConnectionResources {
+            connect_state: &state,
+            dns_resolver: &resolver,
+            network_change_event: &no_network_change_events(),
+            confirmation_header_name: None,
+            cancellation: Some(&cancellation),
+        };
+
+        const CANCEL_AFTER: Duration = Duration::from_secs(3);
+        tokio::spawn({
+            let cancellation = cancellation.clone();
+            async move {
+                tokio::time::sleep(CANCEL_AFTER).await;
+                cancellation.cancel();
+            }
+        });
+
+        let start = Instant::now();
+        let result: Result<_, TimeoutOr<ConnectError<_>>> = connection_resources
+            .connect_ws(
+                vec![failing_route, succeeding_route],
+                ws_connector,
+                "test".into(),
+            )
+            .await;
+
+        assert_matches!(
+            result,
+            Err(TimeoutOr::Other(ConnectError::FatalConnect(
+                WebSocketServiceConnectError::Connect(
+                    WebSocketConnectError::Transport(TransportConnectError::Cancelled),
+                    NotRejectedByServer { .. }
+                )
+            )))
+        );
+        assert_eq!(start.elapsed(), CANCEL_AFTER);
+        // Only the test's and the connector's own references are left.
+        assert_eq!(Arc::strong_count(&in_flight), 2, "attempts are dropped");
+    }
+
     #[tokio::test(start_paused = true)]
     async fn client_abort_transport_error_is_fatal() {
         // We can't directly test the ClientAbort produced for a network change without *more*
@@ -810,7 +1550,8 @@ mod test {
             route_resolver: RouteResolver::default(),
             attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
             make_transport_connector: client_abort_connector,
-            route_provider_context: Default::default(),
+            rng: Default::default(),
+            metrics: None,
         }
         .into();
 
@@ -821,6 +1562,7 @@ mod test {
             dns_resolver: &resolver,
             network_change_event: &no_network_change_events(),
             confirmation_header_name: None,
+            cancellation: None,
         };
 
         let connect = connection_resources.connect_ws(
@@ -873,7 +1615,8 @@ mod test {
             route_resolver: RouteResolver::default(),
             attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
             make_transport_connector: fake_transport_connector,
-            route_provider_context: Default::default(),
+            rng: Default::default(),
+            metrics: None,
         };
 
         let past_failure = AttemptOutcome {
@@ -901,6 +1644,7 @@ mod test {
             dns_resolver: &resolver,
             network_change_event: &network_change_rx,
             confirmation_header_name: None,
+            cancellation: None,
         };
 
         let mut connect = std::pin::pin!(connection_resources.connect_ws(
@@ -958,7 +1702,8 @@ mod test {
             route_resolver: RouteResolver::default(),
             attempts_record: ConnectionOutcomes::new(SUGGESTED_CONNECT_PARAMS),
             make_transport_connector,
-            route_provider_context: Default::default(),
+            rng: Default::default(),
+            metrics: None,
         }
         .into();
 
@@ -971,6 +1716,7 @@ mod test {
             dns_resolver: &resolver,
             network_change_event: &no_network_change_events(),
             confirmation_header_name: None,
+            cancellation: None,
         };
 
         connection_resources
@@ -994,6 +1740,7 @@ mod test {
             dns_resolver: &resolver,
             network_change_event: &no_network_change_events(),
             confirmation_header_name: None,
+            cancellation: None,
         };
 
         _ = connection_resources
@@ -1005,6 +1752,7 @@ mod test {
                             ws_config: Default::default(),
                             endpoint: PathAndQuery::from_static("/"),
                             headers: HeaderMap::new(),
+                            upgrade_timeout: None,
                         },
                         inner: HttpsTlsRoute {
                             fragment: HttpRouteFragment {
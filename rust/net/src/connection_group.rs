@@ -0,0 +1,627 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Keeping several connections alive together.
+//!
+//! A [`ConnectionGroup`] owns the state that is normally shared between
+//! connections (the [`ConnectState`] with its record of route outcomes, and
+//! the [`DnsResolver`]) and keeps each of its members connected using it. A
+//! network change is handled once for the whole group: the shared state is
+//! reset, and then every member is reconnected.
+//!
+//! While the app is in the background, the group can instead be
+//! [suspended](ConnectionGroup::suspend_until): members are disconnected and
+//! make no attempts at all until a scheduled wake time, or until
+//! [`ConnectionGroup::wake_now`] is called.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use http::HeaderName;
+use libsignal_net_infra::dns::DnsResolver;
+use libsignal_net_infra::errors::LogSafeDisplay;
+use libsignal_net_infra::utils::NetworkChangeEvent;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::chat::server_requests::DisconnectCause;
+use crate::connect_state::{ConnectState, ConnectionResources, DefaultConnectorFactory};
+
+/// How long a member waits to try again after failing to connect, unless a
+/// network change prompts it sooner.
+const RETRY_AFTER_FAILURE_DELAY: Duration = Duration::from_secs(5);
+
+/// A service whose connection is managed by a [`ConnectionGroup`].
+///
+/// Members hold on to their own connection; the group only tells them when to
+/// establish it and when to drop it.
+#[async_trait]
+pub trait GroupMember<TC>: Send + Sync {
+    /// Connects using the group's shared resources, replacing any existing
+    /// connection.
+    async fn connect(
+        &self,
+        resources: ConnectionResources<'_, TC>,
+    ) -> Result<(), Box<dyn LogSafeDisplay + Send + Sync>>;
+
+    /// Closes the current connection, if there is one.
+    async fn disconnect(&self);
+
+    /// Resolves when the connection made by the last successful
+    /// [`connect`](Self::connect) is lost.
+    ///
+    /// If the cause [is retryable](DisconnectCause::is_retryable), the group
+    /// connects the member again right away; otherwise the member is left
+    /// [disconnected](MemberState::Disconnected).
+    async fn connection_lost(&self) -> DisconnectCause;
+}
+
+/// The state of a single [`ConnectionGroup`] member.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemberState {
+    Connecting,
+    Connected,
+    /// The last attempt failed; another one will be made later.
+    Failed,
+    /// Disconnected until the group wakes up, see
+    /// [`ConnectionGroup::suspend_until`].
+    Suspended,
+    /// The connection was lost in a way that reconnecting won't fix, like
+    /// being connected elsewhere; no more attempts will be made.
+    Disconnected,
+    /// The group was shut down.
+    Shutdown,
+}
+
+/// Identifies a member within its [`ConnectionGroup`].
+///
+/// This is the member's index in [`ConnectionGroup::states`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MemberId(pub usize);
+
+struct Shared<TC> {
+    connect_state: std::sync::Mutex<ConnectState<TC>>,
+    dns_resolver: DnsResolver,
+    network_change_event: NetworkChangeEvent,
+    confirmation_header_name: Option<HeaderName>,
+}
+
+impl<TC> Shared<TC> {
+    fn resources(&self) -> ConnectionResources<'_, TC> {
+        let Self {
+            connect_state,
+            dns_resolver,
+            network_change_event,
+            confirmation_header_name,
+        } = self;
+        ConnectionResources {
+            connect_state,
+            dns_resolver,
+            network_change_event,
+            confirmation_header_name: confirmation_header_name.clone(),
+            cancellation: None,
+        }
+    }
+}
+
+/// Several services kept connected together over shared connection state.
+///
+/// See the [module-level documentation](self). Dropping the group stops
+/// managing its members without disconnecting them first; use
+/// [`ConnectionGroup::shutdown`] for that.
+pub struct ConnectionGroup<TC = DefaultConnectorFactory> {
+    shared: Arc<Shared<TC>>,
+    states: watch::Sender<Vec<MemberState>>,
+    /// Bumped once per network change, after the shared state has been reset.
+    reconnect: watch::Sender<u64>,
+    /// When the group is scheduled to wake up, if it is suspended.
+    suspension: watch::Sender<Option<Instant>>,
+    shutdown: watch::Sender<bool>,
+    network_change_task: JoinHandle<()>,
+    member_tasks: Vec<JoinHandle<()>>,
+}
+
+impl<TC: Send + 'static> ConnectionGroup<TC> {
+    pub fn new(
+        connect_state: std::sync::Mutex<ConnectState<TC>>,
+        dns_resolver: DnsResolver,
+        network_change_event: NetworkChangeEvent,
+        confirmation_header_name: Option<HeaderName>,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            connect_state,
+            dns_resolver,
+            network_change_event,
+            confirmation_header_name,
+        });
+        let (reconnect, _) = watch::channel(0);
+        let (suspension, _) = watch::channel(None);
+        let (shutdown, _) = watch::channel(false);
+
+        let network_change_task = tokio::spawn(Self::handle_network_changes(
+            Arc::clone(&shared),
+            reconnect.clone(),
+        ));
+
+        Self {
+            shared,
+            states: watch::channel(vec![]).0,
+            reconnect,
+            suspension,
+            shutdown,
+            network_change_task,
+            member_tasks: vec![],
+        }
+    }
+
+    /// Adds a member and starts connecting it.
+    pub fn add_member(
+        &mut self,
+        name: &str,
+        member: Arc<dyn GroupMember<TC> + 'static>,
+    ) -> MemberId {
+        let mut id = MemberId(0);
+        self.states.send_modify(|states| {
+            id = MemberId(states.len());
+            states.push(MemberState::Connecting);
+        });
+
+        let task = MemberTask {
+            id,
+            log_tag: format!("group member {name}").into(),
+            member,
+            shared: Arc::clone(&self.shared),
+            states: self.states.clone(),
+            reconnect: self.reconnect.subscribe(),
+            suspension: self.suspension.clone(),
+            shutdown: self.shutdown.subscribe(),
+        };
+        self.member_tasks.push(tokio::spawn(task.run()));
+        id
+    }
+
+    /// The current state of each member, indexed by [`MemberId`].
+    pub fn states(&self) -> Vec<MemberState> {
+        self.states.borrow().clone()
+    }
+
+    /// Returns a receiver that is notified whenever any member changes state.
+    pub fn observe(&self) -> watch::Receiver<Vec<MemberState>> {
+        self.states.subscribe()
+    }
+
+    /// Disconnects every member and holds off reconnecting them until
+    /// `wake_at`, when each makes a single attempt.
+    ///
+    /// This is meant for when the app is in the background, to save the
+    /// battery: while suspended, members don't retry on their own or after
+    /// network changes. Once the wake attempt has been made, members are
+    /// managed as usual again, so a failed attempt is retried. Suspending an
+    /// already-suspended group replaces the wake time.
+    pub fn suspend_until(&self, wake_at: Instant) {
+        log::info!("suspending connection group members");
+        self.suspension.send_replace(Some(wake_at));
+    }
+
+    /// Ends a suspension early, reconnecting members right away.
+    ///
+    /// Does nothing if the group isn't suspended.
+    pub fn wake_now(&self) {
+        self.suspension.send_if_modified(|suspension| {
+            let was_suspended = suspension.take().is_some();
+            if was_suspended {
+                log::info!("waking connection group members");
+            }
+            was_suspended
+        });
+    }
+
+    /// Disconnects every member and stops managing them.
+    pub async fn shutdown(mut self) {
+        self.network_change_task.abort();
+        self.shutdown.send_replace(true);
+        let member_tasks = std::mem::take(&mut self.member_tasks);
+        for result in join_all(member_tasks).await {
+            if let Err(e) = result {
+                log::warn!("connection group member task failed: {e}");
+            }
+        }
+    }
+
+    async fn handle_network_changes(shared: Arc<Shared<TC>>, reconnect: watch::Sender<u64>) {
+        let mut network_change_event = shared.network_change_event.clone();
+        while network_change_event.changed().await.is_ok() {
+            let now = Instant::now();
+            log::info!("network change; reconnecting all connection group members");
+            shared
+                .connect_state
+                .lock()
+                .expect("not poisoned")
+                .network_changed(now);
+            shared.dns_resolver.on_network_change(now);
+            reconnect.send_modify(|generation| *generation += 1);
+        }
+    }
+}
+
+impl<TC> Drop for ConnectionGroup<TC> {
+    fn drop(&mut self) {
+        self.network_change_task.abort();
+        for task in &self.member_tasks {
+            task.abort();
+        }
+    }
+}
+
+struct MemberTask<TC> {
+    id: MemberId,
+    log_tag: Arc<str>,
+    member: Arc<dyn GroupMember<TC>>,
+    shared: Arc<Shared<TC>>,
+    states: watch::Sender<Vec<MemberState>>,
+    reconnect: watch::Receiver<u64>,
+    suspension: watch::Sender<Option<Instant>>,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl<TC> MemberTask<TC> {
+    async fn run(self) {
+        let Self {
+            id,
+            log_tag,
+            member,
+            shared,
+            states,
+            mut reconnect,
+            suspension: suspension_tx,
+            mut shutdown,
+        } = self;
+        let mut suspension = suspension_tx.subscribe();
+        let set_state = |state| states.send_modify(|states| states[id.0] = state);
+
+        'member: loop {
+            let wake_at = *suspension.borrow_and_update();
+            if let Some(wake_at) = wake_at {
+                set_state(MemberState::Suspended);
+                tokio::select! {
+                    () = tokio::time::sleep_until(wake_at) => {
+                        // The first member to wake up ends the suspension for
+                        // the rest.
+                        suspension_tx.send_if_modified(|suspension| {
+                            let current = *suspension == Some(wake_at);
+                            if current {
+                                *suspension = None;
+                            }
+                            current
+                        });
+                        // Clearing the suspension marks it as changed for our
+                        // own receiver too; check it again here so that the
+                        // change isn't seen later as a new suspension (or
+                        // wake) once connected.
+                        continue;
+                    }
+                    // Woken early, or given a new wake time.
+                    _ = suspension.changed() => continue,
+                    _ = shutdown.wait_for(|shut_down| *shut_down) => break 'member,
+                }
+            }
+
+            set_state(MemberState::Connecting);
+            // Any network change from before this attempt has already been
+            // accounted for.
+            reconnect.borrow_and_update();
+
+            let attempt = tokio::select! {
+                result = member.connect(shared.resources()) => Some(result),
+                _ = shutdown.wait_for(|shut_down| *shut_down) => None,
+            };
+            let connected = match attempt {
+                None => break 'member,
+                Some(Ok(())) => {
+                    log::info!("[{log_tag}] connected");
+                    set_state(MemberState::Connected);
+                    true
+                }
+                Some(Err(e)) => {
+                    log::info!("[{log_tag}] failed to connect: {e}");
+                    set_state(MemberState::Failed);
+                    false
+                }
+            };
+
+            let retry_after_failure = async {
+                match connected {
+                    true => std::future::pending().await,
+                    false => tokio::time::sleep(RETRY_AFTER_FAILURE_DELAY).await,
+                }
+            };
+            let lost_connection = async {
+                match connected {
+                    true => member.connection_lost().await,
+                    false => std::future::pending().await,
+                }
+            };
+            tokio::pin!(retry_after_failure, lost_connection);
+            loop {
+                tokio::select! {
+                    result = reconnect.changed() => {
+                        if result.is_err() {
+                            // The group is being torn down.
+                            break 'member;
+                        }
+                        log::info!("[{log_tag}] reconnecting after network change");
+                        member.disconnect().await;
+                        break;
+                    }
+                    () = &mut retry_after_failure => break,
+                    cause = &mut lost_connection => {
+                        if cause.is_retryable() {
+                            log::info!("[{log_tag}] connection lost; reconnecting");
+                            break;
+                        }
+                        log::info!("[{log_tag}] connection lost in a way that can't be retried");
+                        set_state(MemberState::Disconnected);
+                        let _ = shutdown.wait_for(|shut_down| *shut_down).await;
+                        break 'member;
+                    }
+                    _ = suspension.changed() => {
+                        if suspension.borrow().is_some() {
+                            log::info!("[{log_tag}] suspending");
+                            member.disconnect().await;
+                            break;
+                        }
+                        // Woken up by another member; there's nothing to
+                        // reconnect.
+                    }
+                    _ = shutdown.wait_for(|shut_down| *shut_down) => break 'member,
+                }
+            }
+        }
+
+        member.disconnect().await;
+        set_state(MemberState::Shutdown);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::chat::SendError;
+    use crate::connect_state::SUGGESTED_CONNECT_CONFIG;
+
+    struct FakeMember {
+        connects: watch::Sender<usize>,
+        disconnects: AtomicUsize,
+        lose_connection: mpsc::UnboundedSender<DisconnectCause>,
+        losses: tokio::sync::Mutex<mpsc::UnboundedReceiver<DisconnectCause>>,
+    }
+
+    impl Default for FakeMember {
+        fn default() -> Self {
+            let (lose_connection, losses) = mpsc::unbounded_channel();
+            Self {
+                connects: Default::default(),
+                disconnects: Default::default(),
+                lose_connection,
+                losses: losses.into(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl<TC: Send + 'static> GroupMember<TC> for FakeMember {
+        async fn connect(
+            &self,
+            _resources: ConnectionResources<'_, TC>,
+        ) -> Result<(), Box<dyn LogSafeDisplay + Send + Sync>> {
+            self.connects.send_modify(|count| *count += 1);
+            Ok(())
+        }
+
+        async fn disconnect(&self) {
+            self.disconnects.fetch_add(1, Ordering::SeqCst);
+        }
+
+        async fn connection_lost(&self) -> DisconnectCause {
+            self.losses
+                .lock()
+                .await
+                .recv()
+                .await
+                .expect("sender is owned by self")
+        }
+    }
+
+    /// Starts a group with a [`FakeMember`] for each of `names`, and waits for
+    /// all of them to connect.
+    ///
+    /// Also returns the sender for the group's network change events, and the
+    /// members in the order they were added.
+    async fn group_with_members(
+        names: &[&str],
+    ) -> (ConnectionGroup, watch::Sender<()>, Vec<Arc<FakeMember>>) {
+        let (network_change_tx, network_change_event) = watch::channel(());
+        let mut group = ConnectionGroup::new(
+            ConnectState::new(SUGGESTED_CONNECT_CONFIG),
+            DnsResolver::new_from_static_map(HashMap::new()),
+            network_change_event,
+            None,
+        );
+        let members = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let member = Arc::new(FakeMember::default());
+                assert_eq!(group.add_member(name, member.clone()), MemberId(index));
+                member
+            })
+            .collect();
+
+        group
+            .observe()
+            .wait_for(|states| states.iter().all(|s| *s == MemberState::Connected))
+            .await
+            .expect("group is alive");
+        (group, network_change_tx, members)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn network_change_reconnects_all_members() {
+        let (group, network_change_tx, members) = group_with_members(&["chat", "signaling"]).await;
+
+        network_change_tx.send_replace(());
+        for member in &members {
+            member
+                .connects
+                .subscribe()
+                .wait_for(|count| *count == 2)
+                .await
+                .expect("member is alive");
+            assert_eq!(member.disconnects.load(Ordering::SeqCst), 1);
+        }
+        group
+            .observe()
+            .wait_for(|states| states.iter().all(|s| *s == MemberState::Connected))
+            .await
+            .expect("group is alive");
+
+        group.shutdown().await;
+        for member in &members {
+            assert_eq!(*member.connects.borrow(), 2);
+            assert_eq!(member.disconnects.load(Ordering::SeqCst), 2);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn suspended_members_make_one_attempt_at_the_scheduled_time() {
+        let (group, network_change_tx, members) = group_with_members(&["chat", "signaling"]).await;
+        let mut states = group.observe();
+
+        let wake_at = Instant::now() + Duration::from_secs(15 * 60);
+        group.suspend_until(wake_at);
+        states
+            .wait_for(|states| *states == [MemberState::Suspended; 2])
+            .await
+            .expect("group is alive");
+        for member in &members {
+            assert_eq!(member.disconnects.load(Ordering::SeqCst), 1);
+        }
+
+        // Neither time passing nor a network change brings a suspended member
+        // back before its wake time.
+        network_change_tx.send_replace(());
+        tokio::time::sleep_until(wake_at - Duration::from_secs(1)).await;
+        for member in &members {
+            assert_eq!(*member.connects.borrow(), 1);
+        }
+        assert_eq!(group.states(), [MemberState::Suspended; 2]);
+
+        tokio::time::sleep_until(wake_at).await;
+        states
+            .wait_for(|states| *states == [MemberState::Connected; 2])
+            .await
+            .expect("group is alive");
+        // Waking up doesn't make any member connect more than once, whether it
+        // ended the suspension itself or saw another member end it.
+        tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+        for member in &members {
+            assert_eq!(*member.connects.borrow(), 2);
+            assert_eq!(member.disconnects.load(Ordering::SeqCst), 1);
+        }
+
+        group.shutdown().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wake_now_ends_a_suspension_early() {
+        let (group, _network_change_tx, members) = group_with_members(&["chat"]).await;
+        let mut states = group.observe();
+
+        let suspended_at = Instant::now();
+        group.suspend_until(suspended_at + Duration::from_secs(15 * 60));
+        states
+            .wait_for(|states| *states == [MemberState::Suspended])
+            .await
+            .expect("group is alive");
+
+        group.wake_now();
+        states
+            .wait_for(|states| *states == [MemberState::Connected])
+            .await
+            .expect("group is alive");
+        assert_eq!(*members[0].connects.borrow(), 2);
+        assert_eq!(Instant::now(), suspended_at);
+
+        group.shutdown().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lost_connection_is_reconnected_if_retryable() {
+        let (group, _network_change_tx, members) = group_with_members(&["chat"]).await;
+        let chat = &members[0];
+
+        chat.lose_connection
+            .send(DisconnectCause::RemoteClose {
+                code: 1001,
+                reason: "going away".to_owned(),
+            })
+            .expect("member is alive");
+        chat.connects
+            .subscribe()
+            .wait_for(|count| *count == 2)
+            .await
+            .expect("member is alive");
+        group
+            .observe()
+            .wait_for(|states| *states == [MemberState::Connected])
+            .await
+            .expect("group is alive");
+
+        group.shutdown().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lost_connection_is_not_reconnected_if_not_retryable() {
+        let (group, network_change_tx, members) = group_with_members(&["chat"]).await;
+        let chat = &members[0];
+
+        chat.lose_connection
+            .send(SendError::ConnectedElsewhere.into())
+            .expect("member is alive");
+        group
+            .observe()
+            .wait_for(|states| *states == [MemberState::Disconnected])
+            .await
+            .expect("group is alive");
+
+        // Neither a network change nor time passing brings the member back.
+        network_change_tx.send_replace(());
+        tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+        assert_eq!(*chat.connects.borrow(), 1);
+        assert_eq!(group.states(), [MemberState::Disconnected]);
+
+        group.shutdown().await;
+        assert_eq!(chat.disconnects.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropping_the_group_stops_its_tasks() {
+        let (group, network_change_tx, members) = group_with_members(&["chat"]).await;
+
+        drop(group);
+        // Give the runtime a chance to drop the aborted tasks.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(Arc::strong_count(&members[0]), 1);
+        assert_eq!(network_change_tx.receiver_count(), 0);
+    }
+}
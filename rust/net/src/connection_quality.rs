@@ -0,0 +1,271 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Summarizing how well connections are working as a single number.
+//!
+//! A [`ConnectionQuality`] is fed observations about one or more connections
+//! (keep-alive round-trip times, how long connecting took, and when
+//! connections were lost) and turns them into a score between `0.0` (unusable)
+//! and `1.0` (perfect), suitable for driving a signal-strength-style
+//! indicator.
+//!
+//! Each kind of observation is first turned into a factor between `0.0` and
+//! `1.0`, and the score is the weighted average of those factors, using the
+//! [`QualityWeights`]:
+//!
+//! - round-trip time and connect time are smoothed, and then scored as
+//!   `reference / (reference + smoothed)`, so that a factor is `1.0` for an
+//!   instantaneous response and `0.5` when the smoothed value equals the
+//!   reference;
+//! - disconnects are counted over a trailing window, and scored as
+//!   `1 / (1 + count)`.
+//!
+//! Until something has been observed, a factor is `1.0`.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// How much each observation counts towards a [`ConnectionQuality`] score.
+///
+/// See the [module-level documentation](self) for how these are used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QualityWeights {
+    /// Weight of the keep-alive round-trip time factor.
+    pub rtt: f64,
+    /// Weight of the recent-disconnects factor.
+    pub disconnects: f64,
+    /// Weight of the connect-time factor.
+    pub handshake: f64,
+    /// The round-trip time at which the round-trip factor is `0.5`.
+    pub rtt_reference: Duration,
+    /// The connect time at which the connect-time factor is `0.5`.
+    pub handshake_reference: Duration,
+    /// How long a disconnect keeps counting against the score.
+    pub disconnect_window: Duration,
+    /// How much a new round-trip or connect time moves the smoothed value,
+    /// between `0.0` (not at all) and `1.0` (replacing it).
+    pub smoothing: f64,
+}
+
+impl Default for QualityWeights {
+    fn default() -> Self {
+        Self {
+            rtt: 0.5,
+            disconnects: 0.3,
+            handshake: 0.2,
+            rtt_reference: Duration::from_millis(300),
+            handshake_reference: Duration::from_secs(1),
+            disconnect_window: Duration::from_secs(10 * 60),
+            smoothing: 0.25,
+        }
+    }
+}
+
+/// A rolling connection-quality score.
+///
+/// Clones share the same observations and score. See the [module-level
+/// documentation](self).
+#[derive(Clone, Debug)]
+pub struct ConnectionQuality {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    weights: QualityWeights,
+    observations: Mutex<Observations>,
+    score: watch::Sender<f64>,
+}
+
+#[derive(Debug, Default)]
+struct Observations {
+    smoothed_rtt: Option<Duration>,
+    smoothed_handshake: Option<Duration>,
+    disconnects: VecDeque<Instant>,
+}
+
+impl Default for ConnectionQuality {
+    fn default() -> Self {
+        Self::new(QualityWeights::default())
+    }
+}
+
+impl ConnectionQuality {
+    pub fn new(weights: QualityWeights) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                weights,
+                observations: Default::default(),
+                score: watch::channel(1.0).0,
+            }),
+        }
+    }
+
+    /// Records the time between sending a keep-alive ping and hearing back.
+    pub fn record_rtt(&self, rtt: Duration) {
+        self.update(|observations, weights| {
+            smooth(&mut observations.smoothed_rtt, rtt, weights.smoothing)
+        })
+    }
+
+    /// Records how long it took to establish a connection.
+    pub fn record_handshake(&self, duration: Duration) {
+        self.update(|observations, weights| {
+            smooth(
+                &mut observations.smoothed_handshake,
+                duration,
+                weights.smoothing,
+            )
+        })
+    }
+
+    /// Records that a connection was lost at `when`.
+    pub fn record_disconnect(&self, when: Instant) {
+        self.update(|observations, _| observations.disconnects.push_back(when))
+    }
+
+    /// The current score, between `0.0` and `1.0`.
+    pub fn score(&self) -> f64 {
+        self.update(|_, _| ());
+        *self.inner.score.borrow()
+    }
+
+    /// Returns a receiver that is notified whenever the score changes.
+    ///
+    /// The score is only recomputed when something is recorded (or when
+    /// [`Self::score`] is called), so old disconnects stop counting against
+    /// it at the next update after they leave the window.
+    pub fn subscribe(&self) -> watch::Receiver<f64> {
+        self.inner.score.subscribe()
+    }
+
+    fn update(&self, record: impl FnOnce(&mut Observations, &QualityWeights)) {
+        let Inner {
+            weights,
+            observations,
+            score,
+        } = &*self.inner;
+        let mut observations = observations.lock().expect("not poisoned");
+        record(&mut observations, weights);
+
+        let now = Instant::now();
+        while observations
+            .disconnects
+            .front()
+            .is_some_and(|when| now.saturating_duration_since(*when) > weights.disconnect_window)
+        {
+            observations.disconnects.pop_front();
+        }
+
+        let new_score = observations.score(weights);
+        score.send_if_modified(|score| {
+            let changed = *score != new_score;
+            *score = new_score;
+            changed
+        });
+    }
+}
+
+impl Observations {
+    fn score(&self, weights: &QualityWeights) -> f64 {
+        let latency_factor = |smoothed: Option<Duration>, reference: Duration| {
+            smoothed.map_or(1.0, |smoothed| {
+                let reference = reference.as_secs_f64();
+                reference / (reference + smoothed.as_secs_f64())
+            })
+        };
+        let factors = [
+            (
+                weights.rtt,
+                latency_factor(self.smoothed_rtt, weights.rtt_reference),
+            ),
+            (
+                weights.handshake,
+                latency_factor(self.smoothed_handshake, weights.handshake_reference),
+            ),
+            (
+                weights.disconnects,
+                1.0 / (1.0 + self.disconnects.len() as f64),
+            ),
+        ];
+
+        let total_weight: f64 = factors.iter().map(|(weight, _)| weight).sum();
+        if total_weight <= 0.0 {
+            return 1.0;
+        }
+        let weighted_sum: f64 = factors.iter().map(|(weight, factor)| weight * factor).sum();
+        (weighted_sum / total_weight).clamp(0.0, 1.0)
+    }
+}
+
+fn smooth(smoothed: &mut Option<Duration>, sample: Duration, smoothing: f64) {
+    let smoothing = smoothing.clamp(0.0, 1.0);
+    *smoothed = Some(match *smoothed {
+        None => sample,
+        Some(previous) => previous.mul_f64(1.0 - smoothing) + sample.mul_f64(smoothing),
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn score_follows_observations() {
+        let quality = ConnectionQuality::default();
+        let mut score = quality.subscribe();
+        assert_eq!(*score.borrow_and_update(), 1.0);
+
+        for _ in 0..10 {
+            quality.record_rtt(Duration::from_millis(20));
+        }
+        quality.record_handshake(Duration::from_millis(100));
+        let good = *score.borrow_and_update();
+        assert!(good > 0.9, "{good}");
+
+        for _ in 0..10 {
+            quality.record_rtt(Duration::from_secs(2));
+        }
+        let slow = *score.borrow_and_update();
+        assert!(slow < good, "{slow} < {good}");
+
+        quality.record_disconnect(Instant::now());
+        quality.record_disconnect(Instant::now());
+        let flaky = *score.borrow_and_update();
+        assert!(flaky < slow, "{flaky} < {slow}");
+
+        // Once the disconnects age out and the round-trip time improves, the
+        // score recovers.
+        tokio::time::advance(QualityWeights::default().disconnect_window * 2).await;
+        for _ in 0..20 {
+            quality.record_rtt(Duration::from_millis(20));
+        }
+        let recovered = *score.borrow_and_update();
+        assert!(recovered > flaky, "{recovered} > {flaky}");
+        assert!((recovered - good).abs() < 0.05, "{recovered} ~ {good}");
+    }
+
+    #[test]
+    fn weights_can_be_overridden() {
+        // With only the disconnect factor counting, latency doesn't matter.
+        let weights = QualityWeights {
+            rtt: 0.0,
+            handshake: 0.0,
+            disconnects: 1.0,
+            ..Default::default()
+        };
+        let quality = ConnectionQuality::new(weights);
+        quality.record_rtt(Duration::from_secs(10));
+        quality.record_handshake(Duration::from_secs(10));
+        assert_eq!(quality.score(), 1.0);
+
+        quality.record_disconnect(Instant::now());
+        assert_eq!(quality.score(), 0.5);
+    }
+}
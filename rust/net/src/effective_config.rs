@@ -0,0 +1,255 @@
+//
+// Copyright 2025 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! A snapshot of the settings connections are made with, for support bundles.
+//!
+//! How a connection behaves depends on the routes it can take, the websocket
+//! configuration, timeouts, backoff, and DNS settings, which are all kept in
+//! different places. [`effective_config`] collects them into one value that
+//! can be logged or attached to a bug report. Header values are left out, so
+//! credentials and cookies don't end up in the snapshot.
+
+use std::time::Duration;
+
+use libsignal_net_infra::dns::DnsResolver;
+use libsignal_net_infra::errors::LogSafeDisplay;
+use libsignal_net_infra::route::ConnectionOutcomeParams;
+use libsignal_net_infra::ws::WebSocketConfig;
+use libsignal_net_infra::ConnectionParams;
+use serde_with::{serde_as, DurationMilliSeconds};
+
+use crate::connect_state;
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct EffectiveConfig {
+    pub routes: Vec<RouteSettings>,
+    pub websocket: WebSocketSettings,
+    pub connect: ConnectSettings,
+    pub dns: DnsSettings,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct RouteSettings {
+    pub route_type: String,
+    pub http_host: String,
+    pub sni: String,
+    pub tcp_host: String,
+    pub port: u16,
+    pub alpn: Option<String>,
+    pub confirmation_header: Option<String>,
+    /// See [`HttpRequestDecoratorSeq::redacted_descriptions`].
+    ///
+    /// [`HttpRequestDecoratorSeq::redacted_descriptions`]: libsignal_net_infra::HttpRequestDecoratorSeq::redacted_descriptions
+    pub decorators: Vec<String>,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct WebSocketSettings {
+    pub endpoint: String,
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub max_connection_time: Duration,
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub keep_alive_interval: Duration,
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub max_idle_time: Duration,
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub upgrade_timeout: Duration,
+    pub max_message_size: Option<usize>,
+    pub max_frame_size: Option<usize>,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct ConnectSettings {
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub connect_timeout: Duration,
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub post_route_change_connect_timeout: Duration,
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub network_interface_poll_interval: Duration,
+    pub backoff: BackoffSettings,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct BackoffSettings {
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub age_cutoff: Duration,
+    pub cooldown_growth_factor: f32,
+    pub count_growth_factor: f32,
+    pub max_count: u8,
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub max_delay: Duration,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct DnsSettings {
+    pub ipv6_enabled: bool,
+    #[serde_as(as = "Vec<DurationMilliSeconds>")]
+    pub lookup_timeouts: Vec<Duration>,
+}
+
+/// Collects the settings that connections made with these arguments will use.
+pub fn effective_config(
+    routes: &[ConnectionParams],
+    websocket: &WebSocketConfig,
+    connect: &connect_state::Config,
+    dns_resolver: &DnsResolver,
+) -> EffectiveConfig {
+    let connect_state::Config {
+        connect_params:
+            ConnectionOutcomeParams {
+                age_cutoff,
+                cooldown_growth_factor,
+                count_growth_factor,
+                max_count,
+                max_delay,
+            },
+        connect_timeout,
+        network_interface_poll_interval,
+        post_route_change_connect_timeout,
+    } = *connect;
+
+    EffectiveConfig {
+        routes: routes.iter().map(RouteSettings::from).collect(),
+        websocket: WebSocketSettings {
+            endpoint: websocket.endpoint.to_string(),
+            max_connection_time: websocket.max_connection_time,
+            keep_alive_interval: websocket.keep_alive_interval,
+            max_idle_time: websocket.max_idle_time,
+            upgrade_timeout: websocket.upgrade_timeout,
+            max_message_size: websocket.ws_config.max_message_size,
+            max_frame_size: websocket.ws_config.max_frame_size,
+        },
+        connect: ConnectSettings {
+            connect_timeout,
+            post_route_change_connect_timeout,
+            network_interface_poll_interval,
+            backoff: BackoffSettings {
+                age_cutoff,
+                cooldown_growth_factor,
+                count_growth_factor,
+                max_count,
+                max_delay,
+            },
+        },
+        dns: DnsSettings {
+            ipv6_enabled: dns_resolver.ipv6_enabled(),
+            lookup_timeouts: dns_resolver.lookup_timeouts(),
+        },
+    }
+}
+
+impl From<&ConnectionParams> for RouteSettings {
+    fn from(params: &ConnectionParams) -> Self {
+        let ConnectionParams {
+            route_type,
+            http_host,
+            http_request_decorator,
+            connection_confirmation_header,
+            transport,
+        } = params;
+        Self {
+            route_type: route_type.to_string(),
+            http_host: http_host.to_string(),
+            sni: transport.sni.to_string(),
+            tcp_host: transport.tcp_host.to_string(),
+            port: transport.port.get(),
+            alpn: transport.alpn.map(|alpn| format!("{alpn:?}")),
+            confirmation_header: connection_confirmation_header
+                .as_ref()
+                .map(ToString::to_string),
+            decorators: http_request_decorator.redacted_descriptions(),
+        }
+    }
+}
+
+/// Formats the snapshot as JSON.
+impl std::fmt::Display for EffectiveConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| std::fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+/// Header values, which is where secrets would be, are never included.
+impl LogSafeDisplay for EffectiveConfig {}
+
+#[cfg(test)]
+mod test {
+    use http::{HeaderName, HeaderValue};
+    use libsignal_net_infra::certs::RootCertificates;
+    use libsignal_net_infra::host::Host;
+    use libsignal_net_infra::timeouts::{WS_KEEP_ALIVE_INTERVAL, WS_UPGRADE_TIMEOUT};
+    use libsignal_net_infra::utils::basic_authorization;
+    use libsignal_net_infra::{
+        make_ws_config, Alpn, HttpRequestDecorator, RouteType, TransportConnectionParams,
+    };
+    use nonzero_ext::nonzero;
+
+    use super::*;
+    use crate::connect_state::SUGGESTED_CONNECT_CONFIG;
+
+    #[test]
+    fn snapshot_has_settings_but_not_secrets() {
+        let route = ConnectionParams {
+            route_type: RouteType::Direct,
+            http_host: "chat.signal.org".into(),
+            http_request_decorator: Default::default(),
+            connection_confirmation_header: Some(HeaderName::from_static("x-signal-timestamp")),
+            transport: TransportConnectionParams {
+                sni: "chat.signal.org".into(),
+                tcp_host: Host::Domain("chat.signal.org".into()),
+                port: nonzero!(443u16),
+                certs: RootCertificates::Native,
+                dns_resolver: None,
+                alpn: Some(Alpn::Http1_1),
+            },
+        }
+        .with_decorator(HttpRequestDecorator::header(
+            http::header::AUTHORIZATION,
+            basic_authorization("username", "secret-password"),
+        ))
+        .with_decorator(HttpRequestDecorator::header(
+            http::header::COOKIE,
+            HeaderValue::from_static("session=secret-cookie"),
+        ));
+        let websocket = make_ws_config("/v1/websocket/".parse().unwrap(), Duration::from_secs(2));
+        let dns_resolver = DnsResolver::new_from_static_map(Default::default());
+
+        let config = effective_config(
+            &[route],
+            &websocket,
+            &SUGGESTED_CONNECT_CONFIG,
+            &dns_resolver,
+        );
+        assert_eq!(
+            config.routes[0].decorators,
+            ["header authorization", "header cookie"]
+        );
+        assert_eq!(config.websocket.keep_alive_interval, WS_KEEP_ALIVE_INTERVAL);
+        assert_eq!(
+            config.connect.backoff.max_delay,
+            SUGGESTED_CONNECT_CONFIG.connect_params.max_delay
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&config.to_string()).expect("valid JSON");
+        assert_eq!(json["routes"][0]["tcp_host"], "chat.signal.org");
+        assert_eq!(json["routes"][0]["port"], 443);
+        assert_eq!(
+            json["websocket"]["upgrade_timeout"],
+            u64::try_from(WS_UPGRADE_TIMEOUT.as_millis()).expect("small")
+        );
+        assert_eq!(json["dns"]["lookup_timeouts"], serde_json::json!([1]));
+
+        let rendered = config.to_string();
+        for secret in ["secret-password", "secret-cookie", "dXNlcm5hbWU6"] {
+            assert!(!rendered.contains(secret), "{secret} in {rendered}");
+        }
+    }
+}
@@ -16,8 +16,9 @@ use libsignal_net_infra::connection_manager::{
 use libsignal_net_infra::errors::LogSafeDisplay;
 use libsignal_net_infra::route::{
     DirectTcpRouteProvider, DomainFrontRouteProvider, HttpsProvider, TlsRouteProvider,
-    WebSocketProvider, WebSocketRouteFragment,
+    WebSocketProvider,
 };
+use libsignal_net_infra::timeouts::ONE_ROUTE_CONNECTION_TIMEOUT;
 use libsignal_net_infra::utils::NetworkChangeEvent;
 use libsignal_net_infra::ws::WebSocketServiceError;
 use libsignal_net_infra::ws2::attested::{
@@ -248,11 +249,11 @@ impl<E: EnclaveKind> EnclaveEndpoint<'_, E> {
         } = self;
         let http_provider = domain_config.connect.route_provider(enable_domain_fronting);
 
-        let ws_fragment = WebSocketRouteFragment {
-            ws_config: Default::default(),
-            endpoint: E::url_path(params.mr_enclave.as_ref()),
-            headers: Default::default(),
-        };
+        let ws_fragment = make_ws_config(
+            E::url_path(params.mr_enclave.as_ref()),
+            ONE_ROUTE_CONNECTION_TIMEOUT,
+        )
+        .route_fragment(Default::default());
 
         WebSocketProvider::new(ws_fragment, http_provider)
     }
@@ -20,13 +20,12 @@ use libsignal_net_infra::route::{
     DirectTcpRouteProvider, DomainFrontConfig, DomainFrontRouteProvider, HttpVersion,
     HttpsProvider, TlsRouteProvider,
 };
+use libsignal_net_infra::utils::rng::RngProvider;
 use libsignal_net_infra::{
-    AsHttpHeader, ConnectionParams, DnsSource, EnableDomainFronting, HttpRequestDecorator,
+    Alpn, AsHttpHeader, ConnectionParams, DnsSource, EnableDomainFronting, HttpRequestDecorator,
     HttpRequestDecoratorSeq, RouteType, TransportConnectionParams,
 };
 use nonzero_ext::nonzero;
-use rand::seq::SliceRandom;
-use rand::{rng, Rng};
 
 use crate::certs::{PROXY_G_ROOT_CERTIFICATES, SIGNAL_ROOT_CERTIFICATES};
 use crate::enclave::{Cdsi, EnclaveEndpoint, EndpointParams, MrEnclave, Svr2};
@@ -154,6 +153,7 @@ pub const PROXY_CONFIG_F_PROD: ProxyConfig = ProxyConfig {
         "www.redditstatic.com",
     ],
     certs: RootCertificates::Native,
+    alpn: None,
 };
 
 pub const PROXY_CONFIG_F_STAGING: ProxyConfig = ProxyConfig {
@@ -165,6 +165,7 @@ pub const PROXY_CONFIG_F_STAGING: ProxyConfig = ProxyConfig {
         "www.redditstatic.com",
     ],
     certs: RootCertificates::Native,
+    alpn: None,
 };
 
 pub const PROXY_CONFIG_G: ProxyConfig = ProxyConfig {
@@ -178,6 +179,7 @@ pub const PROXY_CONFIG_G: ProxyConfig = ProxyConfig {
         "googlemail.com",
     ],
     certs: PROXY_G_ROOT_CERTIFICATES,
+    alpn: None,
 };
 
 pub(crate) const ENDPOINT_PARAMS_CDSI_STAGING: EndpointParams<'static, Cdsi> = EndpointParams {
@@ -284,6 +286,8 @@ impl ConnectionConfig {
                     tcp_host: Host::Domain(Arc::clone(&hostname)),
                     port: self.port,
                     certs: self.cert.clone(),
+                    dns_resolver: None,
+                    alpn: None,
                 },
                 http_host: hostname,
                 http_request_decorator: HttpRequestDecoratorSeq::default(),
@@ -296,15 +300,16 @@ impl ConnectionConfig {
         result
     }
 
-    pub fn connection_params_with_fallback(&self) -> Vec<ConnectionParams> {
+    /// Returns the direct route followed by the proxies' routes, with each
+    /// proxy's SNIs shuffled using `rng`.
+    pub fn connection_params_with_fallback(&self, rng: &RngProvider) -> Vec<ConnectionParams> {
         let direct = self.direct_connection_params();
         if let Some(proxy) = &self.proxy {
-            let mut rng = rng();
             let [params_a, params_b] = proxy.configs.each_ref().map(|config| {
                 config.shuffled_connection_params(
                     proxy.path_prefix,
                     self.confirmation_header_name,
-                    &mut rng,
+                    rng,
                 )
             });
 
@@ -342,6 +347,7 @@ impl ConnectionConfig {
                             http_host,
                             sni_list,
                             certs,
+                            alpn,
                         } = config;
                         DomainFrontConfig {
                             root_certs: certs.clone(),
@@ -353,6 +359,7 @@ impl ConnectionConfig {
                                 enable_domain_fronting,
                                 EnableDomainFronting::AllDomains
                             ),
+                            alpn: *alpn,
                         }
                     };
                     configs.iter().map(make_proxy_config)
@@ -422,6 +429,9 @@ pub struct ProxyConfig {
     sni_list: &'static [&'static str],
     /// TLS root certificates to use.
     certs: RootCertificates,
+    /// If present, the ALPN protocol to offer on this proxy's routes, instead of the one the
+    /// caller asks for.
+    alpn: Option<Alpn>,
 }
 
 impl ProxyConfig {
@@ -429,14 +439,15 @@ impl ProxyConfig {
         &self,
         proxy_path: &'static str,
         confirmation_header_name: Option<&'static str>,
-        rng: &mut impl Rng,
+        rng: &RngProvider,
     ) -> impl Iterator<Item = ConnectionParams> {
         let route_type = self.route_type;
         let http_host = Arc::from(self.http_host);
         let certs = self.certs.clone();
+        let alpn = self.alpn;
 
         let mut sni_list = self.sni_list.to_vec();
-        sni_list.shuffle(rng);
+        rng.shuffle(&mut sni_list);
 
         sni_list.into_iter().map(move |sni| {
             // We want to use the SNI name as the hostname for DNS lookup and
@@ -451,6 +462,8 @@ impl ProxyConfig {
                     tcp_host: Host::Domain(sni_and_dns_host),
                     port: nonzero!(443u16),
                     certs: certs.clone(),
+                    dns_resolver: None,
+                    alpn,
                 },
                 http_host: Arc::clone(&http_host),
                 http_request_decorator: HttpRequestDecorator::PathPrefix(proxy_path).into(),
@@ -544,6 +557,37 @@ pub const PROD: Env<'static> = Env {
     keytrans_config: None,
 };
 
+/// The environments a client can connect to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Environment {
+    Staging,
+    Production,
+}
+
+impl Environment {
+    pub fn env(self) -> Env<'static> {
+        match self {
+            Self::Staging => STAGING,
+            Self::Production => PROD,
+        }
+    }
+}
+
+/// Returns the [`ConnectionParams`] for reaching the chat server in `environment`.
+///
+/// The direct route comes first, followed by the domain-fronted routes through each of the
+/// environment's proxies, ready to be passed to `new_multi`. No User-Agent is set; see
+/// [`add_user_agent_header`].
+///
+/// The order of each proxy's routes is shuffled using `rng`.
+pub fn connection_params_for(environment: Environment, rng: &RngProvider) -> Vec<ConnectionParams> {
+    environment
+        .env()
+        .chat_domain_config
+        .connect
+        .connection_params_with_fallback(rng)
+}
+
 pub mod constants {
     pub const WEB_SOCKET_PATH: &str = "/v1/websocket/";
 }
@@ -561,7 +605,6 @@ mod test {
         UnresolvedHost,
     };
     use libsignal_net_infra::testutil::no_network_change_events;
-    use libsignal_net_infra::Alpn;
     use test_case::test_matrix;
 
     use super::*;
@@ -577,7 +620,10 @@ mod test {
                 .as_ref()
                 .map(|header| header.as_str())
         );
-        for params in config.connect.connection_params_with_fallback() {
+        for params in config
+            .connect
+            .connection_params_with_fallback(&RngProvider::default())
+        {
             assert_eq!(
                 Some(TIMESTAMP_HEADER_NAME),
                 params
@@ -601,7 +647,10 @@ mod test {
                 .as_ref()
                 .map(|header| header.as_str())
         );
-        for params in config.connect.connection_params_with_fallback() {
+        for params in config
+            .connect
+            .connection_params_with_fallback(&RngProvider::default())
+        {
             assert_eq!(
                 None,
                 params
@@ -631,12 +680,14 @@ mod test {
                         http_host: "proxy-host-1",
                         sni_list: &["sni-1-a", "sni-1-b"],
                         certs: RootCertificates::Native,
+                        alpn: None,
                     },
                     ProxyConfig {
                         route_type: RouteType::ProxyF,
                         http_host: "proxy-host0",
                         sni_list: &["sni-2-a", "sni-2-b"],
                         certs: RootCertificates::Native,
+                        alpn: None,
                     },
                 ],
             }),
@@ -676,6 +727,65 @@ mod test {
         };
     }
 
+    #[test]
+    fn connection_params_for_environment() {
+        let hosts = |params: &[ConnectionParams]| {
+            params
+                .iter()
+                .map(|params| {
+                    (
+                        params.transport.tcp_host.to_string(),
+                        params.transport.port.get(),
+                        params.http_host.to_string(),
+                    )
+                })
+                .collect::<HashSet<_>>()
+        };
+
+        let production = connection_params_for(Environment::Production, &RngProvider::seeded(0));
+        let direct = production.first().expect("has a direct route");
+        assert_eq!(direct.route_type, RouteType::Direct);
+        assert_eq!(&*direct.http_host, "chat.signal.org");
+
+        let fronted = &production[1..];
+        assert_eq!(
+            fronted.len(),
+            PROXY_CONFIG_F_PROD.sni_list.len() + PROXY_CONFIG_G.sni_list.len()
+        );
+        let mut expected = HashSet::from([(
+            "chat.signal.org".to_owned(),
+            443,
+            "chat.signal.org".to_owned(),
+        )]);
+        for proxy in [PROXY_CONFIG_F_PROD, PROXY_CONFIG_G] {
+            expected.extend(
+                proxy
+                    .sni_list
+                    .iter()
+                    .map(|sni| (sni.to_string(), 443, proxy.http_host.to_owned())),
+            );
+        }
+        assert_eq!(hosts(&production), expected);
+        for params in fronted {
+            assert_ne!(params.route_type, RouteType::Direct);
+            let expected_decorator: HttpRequestDecoratorSeq =
+                HttpRequestDecorator::PathPrefix("/service").into();
+            assert_eq!(
+                format!("{:?}", params.http_request_decorator),
+                format!("{expected_decorator:?}"),
+                "{}",
+                params.transport.sni
+            );
+        }
+
+        let staging = connection_params_for(Environment::Staging, &RngProvider::seeded(0));
+        assert_eq!(&*staging[0].http_host, "chat.staging.signal.org");
+        assert_ne!(hosts(&staging), hosts(&production));
+        assert!(staging
+            .iter()
+            .any(|params| *params.http_host == *PROXY_CONFIG_F_STAGING.http_host));
+    }
+
     #[tokio::test]
     #[test_matrix([&DOMAIN_CONFIG_CHAT, &DOMAIN_CONFIG_CHAT_STAGING, &DOMAIN_CONFIG_CDSI, &DOMAIN_CONFIG_CDSI_STAGING])]
     async fn live_resolve_eq_static_resolution(config: &DomainConfig) {
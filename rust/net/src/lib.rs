@@ -8,6 +8,9 @@ pub mod cdsi;
 pub mod certs;
 pub mod chat;
 pub mod connect_state;
+pub mod connection_group;
+pub mod connection_quality;
+pub mod effective_config;
 pub mod enclave;
 pub mod env;
 pub mod keytrans;
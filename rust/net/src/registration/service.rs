@@ -184,7 +184,7 @@ async fn spawn_connected_chat(
                         return Err(FatalConnectError::RetryLater(retry_later));
                     }
                     err @ (ChatConnectError::Timeout
-                    | ChatConnectError::AllAttemptsFailed
+                    | ChatConnectError::AllAttemptsFailed(_)
                     | ChatConnectError::WebSocket(_)) => {
                         log::warn!("retryable error: {}", (&err as &dyn LogSafeDisplay));
                         let now = Instant::now();
@@ -207,6 +207,11 @@ async fn spawn_connected_chat(
                             "unauthenticated socket signaled deregistration",
                         ));
                     }
+                    ChatConnectError::Cancelled => {
+                        return Err(FatalConnectError::Unexpected(
+                            "connection attempt was cancelled",
+                        ));
+                    }
                 }
             }
         };
@@ -262,6 +267,11 @@ async fn send_request_to_connected_chat(
         match err {
             ChatSendError::RequestTimedOut => SendRequestError::RequestTimedOut,
             ChatSendError::Disconnected => SendRequestError::ConnectionLost,
+            // Don't treat this like a lost connection, which would cause the
+            // request to be sent again after reconnecting.
+            ChatSendError::RequestOutcomeUnknown => {
+                SendRequestError::Unknown("connection lost with request in flight".into())
+            }
             ChatSendError::ConnectionInvalidated | ChatSendError::ConnectedElsewhere => {
                 SendRequestError::Unknown(
                     "registration connection unexpectedly closed by server".into(),
@@ -277,6 +287,9 @@ async fn send_request_to_connected_chat(
             ChatSendError::RequestHasInvalidHeader => {
                 SendRequestError::Unknown("request had invalid header".into())
             }
+            // The request was never sent, so it's safe to send again on a new
+            // connection.
+            ChatSendError::Draining => SendRequestError::ConnectionLost,
         }
     })?;
 
@@ -7,7 +7,7 @@ use std::fmt::Display;
 
 use http::HeaderName;
 use libsignal_net_infra::connection_manager::{ErrorClass, ErrorClassifier};
-use libsignal_net_infra::errors::{LogSafeDisplay, TransportConnectError};
+use libsignal_net_infra::errors::{ConnectErrorCategory, LogSafeDisplay, TransportConnectError};
 use libsignal_net_infra::extract_retry_later;
 use libsignal_net_infra::ws::WebSocketConnectError;
 use tokio::time::Instant;
@@ -59,6 +59,13 @@ impl WebSocketServiceConnectError {
         }
     }
 
+    pub fn category(&self) -> ConnectErrorCategory {
+        match self {
+            Self::RejectedByServer { .. } => ConnectErrorCategory::Server,
+            Self::Connect(e, _) => e.category(),
+        }
+    }
+
     pub fn timeout() -> Self {
         Self::Connect(
             WebSocketConnectError::Timeout,
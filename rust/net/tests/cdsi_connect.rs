@@ -58,6 +58,7 @@ async fn can_connect_to_cdsi_staging() {
         dns_resolver: &resolver,
         network_change_event: &network_changed,
         confirmation_header_name,
+        cancellation: None,
     };
 
     CdsiConnection::connect_with(
@@ -337,7 +337,7 @@ async fn custom_dns_failure(lookup: impl DnsLookup + 'static, expected_duration:
     let (elapsed, outcome) = timed(deps.connect_chat().map_ok(|_| ())).await;
 
     assert_eq!(elapsed, expected_duration);
-    assert_matches!(outcome, Err(chat::ConnectError::AllAttemptsFailed));
+    assert_matches!(outcome, Err(chat::ConnectError::AllAttemptsFailed(_)));
 }
 
 #[test_case(false, Duration::from_secs(60))]
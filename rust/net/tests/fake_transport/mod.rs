@@ -28,6 +28,7 @@ use libsignal_net::infra::{
 };
 use libsignal_net_infra::route::{Connector, TransportRoute, UsePreconnect};
 use libsignal_net_infra::testutil::no_network_change_events;
+use libsignal_net_infra::utils::rng::RngProvider;
 use tokio::time::Duration;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use warp::Filter as _;
@@ -179,10 +180,13 @@ impl FakeDeps {
             true,
             &chat::EnforceMinimumTls::Yes,
             &no_network_change_events(),
+            &RngProvider::default(),
         );
 
-        let connector_factory =
-            ReplacingConnectorFactory(transport_connector.clone(), DefaultConnectorFactory);
+        let connector_factory = ReplacingConnectorFactory(
+            transport_connector.clone(),
+            DefaultConnectorFactory::default(),
+        );
         let connect_state =
             ConnectState::new_with_transport_connector(SUGGESTED_CONNECT_CONFIG, connector_factory);
         let resolved_names = fake_ips_for_names(chat_domain_config);
@@ -225,6 +229,7 @@ impl FakeDeps {
             dns_resolver,
             network_change_event: &no_network_change_events(),
             confirmation_header_name: None,
+            cancellation: None,
         };
 
         ChatConnection::start_connect_with_transport(
@@ -242,6 +247,7 @@ impl FakeDeps {
                 initial_request_id: 0,
             },
             None,
+            None,
             "fake chat",
         )
         .await
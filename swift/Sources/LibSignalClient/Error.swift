@@ -54,6 +54,8 @@ public enum SignalError: Error {
     case connectionTimeoutError(String)
     case requestTimeoutError(String)
     case connectionFailed(String)
+    case tlsRenegotiationRejected(String)
+    case operationTimeout(String)
     case networkProtocolError(String)
     case cdsiInvalidToken(String)
     case rateLimitedError(retryAfter: TimeInterval, message: String)
@@ -65,6 +67,7 @@ public enum SignalError: Error {
     case deviceDeregistered(String)
     case connectionInvalidated(String)
     case connectedElsewhere(String)
+    case connectionClosedByServer(code: UInt16, reason: String, message: String)
 
     case unknown(UInt32, String)
 }
@@ -198,6 +201,10 @@ internal func checkError(_ error: SignalFfiErrorRef?) throws {
         throw SignalError.requestTimeoutError(errStr)
     case SignalErrorCodeConnectionFailed:
         throw SignalError.connectionFailed(errStr)
+    case SignalErrorCodeTlsRenegotiationRejected:
+        throw SignalError.tlsRenegotiationRejected(errStr)
+    case SignalErrorCodeOperationTimeout:
+        throw SignalError.operationTimeout(errStr)
     case SignalErrorCodeNetworkProtocol:
         throw SignalError.networkProtocolError(errStr)
     case SignalErrorCodeCdsiInvalidToken:
@@ -226,6 +233,14 @@ internal func checkError(_ error: SignalFfiErrorRef?) throws {
         throw SignalError.connectionInvalidated(errStr)
     case SignalErrorCodeConnectedElsewhere:
         throw SignalError.connectedElsewhere(errStr)
+    case SignalErrorCodeConnectionClosedByServer:
+        let code = try invokeFnReturningInteger {
+            signal_error_get_close_code(error, $0)
+        }
+        let reason = try invokeFnReturningString {
+            signal_error_get_close_reason(error, $0)
+        }
+        throw SignalError.connectionClosedByServer(code: UInt16(code), reason: reason, message: errStr)
     case SignalErrorCodeBackupValidation:
         let unknownFields = try invokeFnReturningStringArray {
             signal_error_get_unknown_fields(error, $0)
@@ -130,6 +130,18 @@ public class Net {
         }
     }
 
+    /// Abandons any chat connection attempts in progress.
+    ///
+    /// The attempts fail with `CancellationError`; ones started afterwards are unaffected.
+    ///
+    /// No errors are expected to be thrown; this is only to make programmer errors
+    /// recoverable for this particular call.
+    public func cancelConnectionAttempts() throws {
+        try self.connectionManager.withNativeHandle { connectionManager in
+            try checkError(signal_connection_manager_cancel_connection_attempts(connectionManager.const()))
+        }
+    }
+
     /// Like ``cdsiLookup(auth:request:)`` but with the parameters to ``CdsiLookupRequest`` broken out.
     public func cdsiLookup(
         auth: Auth,
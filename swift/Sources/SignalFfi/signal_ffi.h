@@ -219,6 +219,8 @@ typedef enum {
   SignalErrorCodeConnectionFailed = 148,
   SignalErrorCodeChatServiceInactive = 149,
   SignalErrorCodeRequestTimedOut = 150,
+  SignalErrorCodeTlsRenegotiationRejected = 151,
+  SignalErrorCodeOperationTimeout = 152,
   SignalErrorCodeSvrDataMissing = 160,
   SignalErrorCodeSvrRestoreFailed = 161,
   SignalErrorCodeSvrRotationMachineTooManySteps = 162,
@@ -226,6 +228,7 @@ typedef enum {
   SignalErrorCodeDeviceDeregistered = 171,
   SignalErrorCodeConnectionInvalidated = 172,
   SignalErrorCodeConnectedElsewhere = 173,
+  SignalErrorCodeConnectionClosedByServer = 174,
   SignalErrorCodeBackupValidation = 180,
 } SignalErrorCode;
 
@@ -1279,6 +1282,8 @@ SignalFfiError *signal_ciphertext_message_type(uint8_t *out, SignalConstPointerC
 
 SignalFfiError *signal_connection_info_destroy(SignalMutPointerConnectionInfo p);
 
+SignalFfiError *signal_connection_manager_cancel_connection_attempts(SignalConstPointerConnectionManager connection_manager);
+
 SignalFfiError *signal_connection_manager_clear_proxy(SignalConstPointerConnectionManager connection_manager);
 
 SignalFfiError *signal_connection_manager_destroy(SignalMutPointerConnectionManager p);
@@ -1361,6 +1366,18 @@ void signal_error_free(SignalFfiError *err);
 
 SignalFfiError *signal_error_get_address(const SignalFfiError *err, SignalMutPointerProtocolAddress *out);
 
+SignalFfiError *signal_error_get_close_code(const SignalFfiError *err, uint32_t *out);
+
+SignalFfiError *signal_error_get_close_reason(const SignalFfiError *err, const char **out);
+
+SignalFfiError *signal_error_get_connect_category(const SignalFfiError *err, const char **out);
+
+SignalFfiError *signal_error_get_connect_elapsed_millis(const SignalFfiError *err, uint64_t *out);
+
+SignalFfiError *signal_error_get_connect_retryable(const SignalFfiError *err, bool *out);
+
+SignalFfiError *signal_error_get_connect_route(const SignalFfiError *err, const char **out);
+
 SignalFfiError *signal_error_get_message(const SignalFfiError *err, const char **out);
 
 SignalFfiError *signal_error_get_retry_after_seconds(const SignalFfiError *err, uint32_t *out);
@@ -1371,6 +1388,8 @@ uint32_t signal_error_get_type(const SignalFfiError *err);
 
 SignalFfiError *signal_error_get_unknown_fields(const SignalFfiError *err, SignalStringArray *out);
 
+SignalFfiError *signal_error_get_user_hint(const SignalFfiError *err, const char **out);
+
 SignalFfiError *signal_error_get_uuid(const SignalFfiError *err, uint8_t (*out)[16]);
 
 SignalFfiError *signal_expiring_profile_key_credential_check_valid_contents(SignalBorrowedBuffer buffer);